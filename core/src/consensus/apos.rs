@@ -1,5 +1,6 @@
 use crate::types::{Block, BlockId};
-use crate::consensus::{Validator, ValidatorSet, BlockProducer, FinalityProvider};
+use crate::consensus::{Validator, ValidatorSet, BlockProducer, FinalityProvider, FinalityProof, Vote, CommitteeConfig, CommitteeSchedule, CommitteeAssignment};
+use crate::sharding::ShardId;
 use ed25519_dalek::VerifyingKey;
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
@@ -20,6 +21,9 @@ pub struct APoSConfig {
     pub block_reward: u64,
     /// Percentage of transaction fees that go to the validator
     pub validator_fee_percentage: u8,
+    /// Shard committee sampling
+    #[serde(default)]
+    pub committee: CommitteeConfig,
 }
 
 impl Default for APoSConfig {
@@ -31,6 +35,7 @@ impl Default for APoSConfig {
             epoch_length: 10_000, // ~3 hours with 1s blocks
             block_reward: 100_000_000, // 100 tokens
             validator_fee_percentage: 70, // 70%
+            committee: CommitteeConfig::default(),
         }
     }
 }
@@ -47,6 +52,10 @@ pub struct APoS {
     block_producer: BlockProducer,
     /// Finality provider
     finality_provider: FinalityProvider,
+    /// Per-shard validator committees
+    committees: CommitteeSchedule,
+    /// Counter bumped whenever the validator set or epoch changes
+    validator_set_version: u64,
     /// Performance metrics for validators
     validator_performance: HashMap<VerifyingKey, ValidatorPerformance>,
     /// Whether the consensus is running
@@ -72,6 +81,7 @@ impl APoS {
         let validators = ValidatorSet::new();
         let block_producer = BlockProducer::new();
         let finality_provider = FinalityProvider::new();
+        let committees = CommitteeSchedule::new(config.committee.clone());
         
         APoS {
             config,
@@ -79,6 +89,8 @@ impl APoS {
             current_epoch: 0,
             block_producer,
             finality_provider,
+            committees,
+            validator_set_version: 0,
             validator_performance: HashMap::new(),
             running: false,
         }
@@ -124,6 +136,27 @@ impl APoS {
         &self.validators
     }
     
+    /// Get a counter that changes whenever the validator set or epoch changes
+    pub fn validator_set_version(&self) -> u64 {
+        self.validator_set_version
+    }
+    
+    /// Get the shard committees
+    pub fn committees(&self) -> &CommitteeSchedule {
+        &self.committees
+    }
+    
+    /// Sample shard committees for the epoch started by a finalized beacon block
+    pub fn reshuffle_committees(
+        &mut self,
+        beacon_height: u64,
+        beacon_block: &BlockId,
+        shards: &[ShardId],
+    ) -> Result<&CommitteeAssignment, String> {
+        let epoch = self.committees.epoch_of(beacon_height);
+        self.committees.reshuffle(epoch, beacon_block, &self.validators, shards)
+    }
+    
     /// Add a validator to the set
     pub fn add_validator(&mut self, validator: Validator) -> Result<(), String> {
         if validator.stake() < self.config.min_stake {
@@ -147,6 +180,7 @@ impl APoS {
         let public_key = validator.public_key();
         self.validators.add_validator(validator);
         self.validator_performance.insert(public_key, ValidatorPerformance::default());
+        self.validator_set_version += 1;
         
         Ok(())
     }
@@ -155,6 +189,43 @@ impl APoS {
     pub fn remove_validator(&mut self, public_key: &VerifyingKey) {
         self.validators.remove_validator(public_key);
         self.validator_performance.remove(public_key);
+        self.validator_set_version += 1;
+    }
+    
+    /// Track an imported block until validators vote it final
+    pub fn track_finality(&mut self, block: &Block) {
+        self.finality_provider.process_block(block.clone());
+    }
+    
+    /// Count a validator's precommit towards the finality of the block it votes for
+    ///
+    /// Returns the finality proof if the vote finalized the block. Nil votes and
+    /// precommits without a finality signature do not count. Shard blocks
+    /// produced by a committee member are finalized by that committee rather
+    /// than the whole validator set.
+    pub fn add_finality_vote(&mut self, validator: &[u8; 32], vote: &Vote) -> Result<Option<FinalityProof>, String> {
+        let (block_id, signature) = match (&vote.block_id, &vote.finality_signature) {
+            (Some(block_id), Some(signature)) => (block_id, signature.clone()),
+            _ => return Ok(None),
+        };
+        
+        let key = VerifyingKey::from_bytes(validator)
+            .map_err(|e| format!("Invalid validator key: {}", e))?;
+        let committee = self.finality_provider.pending_block(block_id)
+            .and_then(|block| self.committees.producer_committee(&ShardId(block.shard_id), &block.header));
+        let validators: Vec<[u8; 32]> = match committee {
+            Some(committee) => committee.members.clone(),
+            None => self.validators.validators().iter()
+                .map(|validator| validator.public_key().to_bytes())
+                .collect(),
+        };
+        
+        self.finality_provider.add_signed_vote(block_id, key, signature, &validators)
+    }
+    
+    /// Get the finality threshold as a fraction of the validator set
+    pub fn finality_threshold(&self) -> f64 {
+        self.finality_provider.finality_threshold()
     }
     
     /// Get the next validator to produce a block
@@ -213,6 +284,7 @@ impl APoS {
     /// Start a new epoch
     fn start_new_epoch(&mut self) {
         self.current_epoch += 1;
+        self.validator_set_version += 1;
         
         // Recalculate validator weights based on performance
         for (public_key, performance) in &self.validator_performance {
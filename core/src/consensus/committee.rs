@@ -0,0 +1,417 @@
+use crate::consensus::{FinalityProof, ValidatorSet};
+use crate::sharding::ShardId;
+use crate::types::{Block, BlockHeader, BlockId};
+use serde::{Serialize, Deserialize};
+use sha3::{Sha3_256, Digest};
+use std::collections::BTreeMap;
+
+/// Domain separator for epoch seeds
+const SEED_DOMAIN: &[u8] = b"optimachain-committee-seed";
+
+/// Domain separator for sampling priorities
+const PRIORITY_DOMAIN: &[u8] = b"optimachain-committee-priority";
+
+/// Configuration for shard committees
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitteeConfig {
+    /// Number of beacon blocks per committee epoch
+    pub epoch_length: u64,
+    /// Smallest committee a shard may have
+    pub min_committee_size: usize,
+    /// Largest committee a shard may have
+    pub max_committee_size: usize,
+    /// Fraction of a committee that must sign to finalize a shard block
+    pub finality_threshold: f64,
+}
+
+impl Default for CommitteeConfig {
+    fn default() -> Self {
+        CommitteeConfig {
+            epoch_length: 32,
+            min_committee_size: 4,
+            max_committee_size: 128,
+            finality_threshold: 0.67,
+        }
+    }
+}
+
+/// Validators responsible for a shard during an epoch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Committee {
+    /// Shard validated by the committee
+    pub shard_id: ShardId,
+    /// Epoch the committee serves in
+    pub epoch: u64,
+    /// Public keys of the members, in sampling order
+    pub members: Vec<[u8; 32]>,
+}
+
+impl Committee {
+    /// Check if a validator is a member
+    pub fn contains(&self, public_key: &[u8; 32]) -> bool {
+        self.members.contains(public_key)
+    }
+    
+    /// Check that a shard block was produced and signed by a member
+    pub fn verify_block(&self, block: &Block) -> Result<(), String> {
+        if ShardId(block.shard_id) != self.shard_id {
+            return Err(format!("Block is for shard {}, committee is for shard {:?}", block.shard_id, self.shard_id));
+        }
+        
+        self.verify_header(&block.header)
+    }
+    
+    /// Check that a shard block header was produced and signed by a member
+    pub fn verify_header(&self, header: &BlockHeader) -> Result<(), String> {
+        let producer = header.validator.to_bytes();
+        if !self.contains(&producer) {
+            return Err(format!(
+                "Block producer {} is not in the committee of shard {:?} for epoch {}",
+                hex::encode(producer), self.shard_id, self.epoch
+            ));
+        }
+        
+        if !header.has_valid_signature() {
+            return Err(format!("Invalid block signature from {}", hex::encode(producer)));
+        }
+        
+        Ok(())
+    }
+    
+    /// Check that enough members signed a shard block's finality
+    pub fn verify_finality(&self, proof: &FinalityProof, threshold: f64) -> Result<(), String> {
+        proof.verify(&self.members, threshold)
+    }
+}
+
+/// Committees of all shards for an epoch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitteeAssignment {
+    /// Epoch of the assignment
+    pub epoch: u64,
+    /// Randomness the committees were sampled from
+    pub seed: [u8; 32],
+    /// Committees by shard
+    committees: BTreeMap<ShardId, Committee>,
+}
+
+impl CommitteeAssignment {
+    /// Sample committees for an epoch, weighted by stake
+    ///
+    /// When there are enough validators every shard gets its own disjoint
+    /// committee, so shards are validated in parallel. Otherwise each shard
+    /// samples the minimum committee size on its own and validators serve
+    /// several shards.
+    pub fn sample(
+        epoch: u64,
+        seed: [u8; 32],
+        validators: &ValidatorSet,
+        shards: &[ShardId],
+        config: &CommitteeConfig,
+    ) -> Result<Self, String> {
+        let candidates: Vec<([u8; 32], u64)> = validators.validators().iter()
+            .filter(|validator| validator.total_stake() > 0)
+            .map(|validator| (validator.public_key().to_bytes(), validator.total_stake()))
+            .collect();
+        
+        if shards.is_empty() {
+            return Err("No shards to assign committees to".to_string());
+        }
+        if candidates.len() < config.min_committee_size {
+            return Err(format!(
+                "Only {} staked validators, committees need at least {}",
+                candidates.len(), config.min_committee_size
+            ));
+        }
+        
+        let mut shards = shards.to_vec();
+        shards.sort();
+        shards.dedup();
+        
+        let size = (candidates.len() / shards.len())
+            .max(config.min_committee_size)
+            .min(config.max_committee_size.max(config.min_committee_size));
+        
+        let mut members: BTreeMap<ShardId, Vec<[u8; 32]>> = BTreeMap::new();
+        if size * shards.len() <= candidates.len() {
+            // Deal the sampled validators out in turn
+            let sampled = weighted_sample(&candidates, &seed, size * shards.len());
+            for (index, public_key) in sampled.into_iter().enumerate() {
+                members.entry(shards[index % shards.len()]).or_default().push(public_key);
+            }
+        } else {
+            for shard_id in &shards {
+                let mut hasher = Sha3_256::new();
+                hasher.update(seed);
+                hasher.update(shard_id.0.to_be_bytes());
+                let shard_seed: [u8; 32] = hasher.finalize().into();
+                members.insert(*shard_id, weighted_sample(&candidates, &shard_seed, size));
+            }
+        }
+        
+        let committees = members.into_iter()
+            .map(|(shard_id, members)| (shard_id, Committee { shard_id, epoch, members }))
+            .collect();
+        
+        Ok(CommitteeAssignment { epoch, seed, committees })
+    }
+    
+    /// Get the committee of a shard
+    pub fn committee(&self, shard_id: &ShardId) -> Option<&Committee> {
+        self.committees.get(shard_id)
+    }
+    
+    /// Get the shards a validator serves
+    pub fn shards_of(&self, public_key: &[u8; 32]) -> Vec<ShardId> {
+        self.committees.values()
+            .filter(|committee| committee.contains(public_key))
+            .map(|committee| committee.shard_id)
+            .collect()
+    }
+}
+
+/// Committee assignments of the current and previous epoch
+///
+/// The previous epoch is kept so that shard blocks produced just before a
+/// reshuffle can still be checked and finalized.
+#[derive(Debug, Clone)]
+pub struct CommitteeSchedule {
+    /// Configuration
+    config: CommitteeConfig,
+    /// Assignments by epoch
+    assignments: BTreeMap<u64, CommitteeAssignment>,
+}
+
+impl CommitteeSchedule {
+    /// Create a schedule without assignments
+    pub fn new(config: CommitteeConfig) -> Self {
+        CommitteeSchedule {
+            config,
+            assignments: BTreeMap::new(),
+        }
+    }
+    
+    /// Get the configuration
+    pub fn config(&self) -> &CommitteeConfig {
+        &self.config
+    }
+    
+    /// Get the epoch a beacon height belongs to
+    pub fn epoch_of(&self, beacon_height: u64) -> u64 {
+        beacon_height / self.config.epoch_length.max(1)
+    }
+    
+    /// Check if a beacon height starts an epoch
+    pub fn is_epoch_start(&self, beacon_height: u64) -> bool {
+        beacon_height.is_multiple_of(self.config.epoch_length.max(1))
+    }
+    
+    /// Get the latest epoch with an assignment
+    pub fn current_epoch(&self) -> Option<u64> {
+        self.assignments.keys().next_back().copied()
+    }
+    
+    /// Get the assignment of an epoch
+    pub fn assignment(&self, epoch: u64) -> Option<&CommitteeAssignment> {
+        self.assignments.get(&epoch)
+    }
+    
+    /// Get the committee of a shard in an epoch
+    pub fn committee(&self, epoch: u64, shard_id: &ShardId) -> Result<&Committee, String> {
+        self.assignment(epoch)
+            .ok_or_else(|| format!("No committees for epoch {}", epoch))?
+            .committee(shard_id)
+            .ok_or_else(|| format!("No committee for shard {:?} in epoch {}", shard_id, epoch))
+    }
+    
+    /// Sample the committees of a new epoch
+    ///
+    /// The seed is taken from the finalized beacon block that starts the
+    /// epoch, which commits to the whole beacon history, so nodes with the
+    /// same validator set derive the same committees. Assignments are not
+    /// stored; a restarted node derives them again once it knows the
+    /// validators. Epochs must be assigned in ascending order.
+    pub fn reshuffle(
+        &mut self,
+        epoch: u64,
+        beacon_block: &BlockId,
+        validators: &ValidatorSet,
+        shards: &[ShardId],
+    ) -> Result<&CommitteeAssignment, String> {
+        if self.current_epoch().is_some_and(|current| epoch <= current) {
+            return Err(format!("Committees for epoch {} are already assigned", epoch));
+        }
+        
+        let seed = epoch_seed(epoch, beacon_block);
+        let assignment = CommitteeAssignment::sample(epoch, seed, validators, shards, &self.config)?;
+        
+        log::info!("Assigned committees for epoch {} to {} shards", epoch, shards.len());
+        
+        self.assignments.insert(epoch, assignment);
+        while self.assignments.len() > 2 {
+            self.assignments.pop_first();
+        }
+        
+        Ok(&self.assignments[&epoch])
+    }
+    
+    /// Get the newest retained committee of a shard that the producer of a header belongs to
+    pub fn producer_committee(&self, shard_id: &ShardId, header: &BlockHeader) -> Option<&Committee> {
+        let producer = header.validator.to_bytes();
+        self.assignments.values()
+            .rev()
+            .filter_map(|assignment| assignment.committee(shard_id))
+            .find(|committee| committee.contains(&producer))
+    }
+    
+    /// Check a shard block against its committee for an epoch
+    pub fn verify_block(&self, epoch: u64, block: &Block) -> Result<(), String> {
+        self.committee(epoch, &ShardId(block.shard_id))?.verify_block(block)
+    }
+    
+    /// Check a shard block header against the shard's committee in any retained epoch
+    pub fn verify_header(&self, shard_id: &ShardId, header: &BlockHeader) -> Result<(), String> {
+        if self.assignments.is_empty() {
+            return Err("No committees assigned".to_string());
+        }
+        
+        let mut last_error = None;
+        for assignment in self.assignments.values().rev() {
+            match assignment.committee(shard_id).map(|committee| committee.verify_header(header)) {
+                Some(Ok(())) => return Ok(()),
+                Some(Err(e)) => last_error = Some(e),
+                None => {}
+            }
+        }
+        
+        Err(last_error.unwrap_or_else(|| format!("No committee for shard {:?}", shard_id)))
+    }
+    
+    /// Check a shard block's finality proof against its committee for an epoch
+    pub fn verify_finality(&self, epoch: u64, shard_id: &ShardId, proof: &FinalityProof) -> Result<(), String> {
+        self.committee(epoch, shard_id)?.verify_finality(proof, self.config.finality_threshold)
+    }
+}
+
+/// Derive the seed of an epoch from the beacon block starting it
+pub fn epoch_seed(epoch: u64, beacon_block: &BlockId) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(SEED_DOMAIN);
+    hasher.update(epoch.to_be_bytes());
+    hasher.update(beacon_block.0);
+    hasher.finalize().into()
+}
+
+/// Sample validators without replacement, with probability proportional to stake
+///
+/// Each validator draws `-ln(u) / stake` for a seeded uniform `u`, and the
+/// lowest draws win.
+fn weighted_sample(candidates: &[([u8; 32], u64)], seed: &[u8; 32], count: usize) -> Vec<[u8; 32]> {
+    let mut draws: Vec<(f64, [u8; 32])> = candidates.iter()
+        .map(|(public_key, stake)| {
+            let mut hasher = Sha3_256::new();
+            hasher.update(PRIORITY_DOMAIN);
+            hasher.update(seed);
+            hasher.update(public_key);
+            let hash = hasher.finalize();
+            
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&hash[..8]);
+            // Uniform in (0, 1]
+            let uniform = ((u64::from_be_bytes(bytes) >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+            
+            (-uniform.ln() / *stake as f64, *public_key)
+        })
+        .collect();
+    
+    draws.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+    draws.into_iter().take(count).map(|(_, public_key)| public_key).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::Validator;
+    use crate::utils::crypto::KeyPair;
+    use ed25519_dalek::VerifyingKey;
+    
+    /// Key pairs of validators with equal stake, and the set they form
+    fn validators(count: usize) -> (Vec<KeyPair>, ValidatorSet) {
+        let keypairs: Vec<KeyPair> = (0..count).map(|_| KeyPair::generate()).collect();
+        let mut validators = ValidatorSet::new();
+        for keypair in &keypairs {
+            let public_key = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+            validators.add_validator(Validator::new(public_key, 100, "validator".to_string(), None, None, None));
+        }
+        (keypairs, validators)
+    }
+    
+    /// A configuration with small committees
+    fn config(min_committee_size: usize) -> CommitteeConfig {
+        CommitteeConfig { epoch_length: 4, min_committee_size, ..CommitteeConfig::default() }
+    }
+    
+    /// A signed header of a shard block produced by the key pair
+    fn header(keypair: &KeyPair, shard_id: ShardId) -> BlockHeader {
+        let validator = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+        let mut block = Block::new(1, BlockId([0; 32]), Vec::new(), crate::types::StateRoot([0; 32]), validator, shard_id.0);
+        block.header.sign(keypair).unwrap();
+        block.header
+    }
+    
+    #[test]
+    fn samples_the_same_committees_from_the_same_beacon_block() {
+        let (_, validators) = validators(8);
+        let shards = [ShardId(0), ShardId(1)];
+        let sample = |beacon_block: &BlockId| {
+            CommitteeAssignment::sample(1, epoch_seed(1, beacon_block), &validators, &shards, &config(2)).unwrap()
+        };
+        
+        let (first, again) = (sample(&BlockId([1; 32])), sample(&BlockId([1; 32])));
+        for shard_id in &shards {
+            assert_eq!(first.committee(shard_id).unwrap().members, again.committee(shard_id).unwrap().members);
+        }
+        assert_ne!(sample(&BlockId([2; 32])).seed, first.seed);
+    }
+    
+    #[test]
+    fn gives_shards_disjoint_committees_when_there_are_enough_validators() {
+        let shards = [ShardId(0), ShardId(1)];
+        
+        let (_, many) = validators(8);
+        let assignment = CommitteeAssignment::sample(0, [0; 32], &many, &shards, &config(2)).unwrap();
+        let (first, second) = (assignment.committee(&shards[0]).unwrap(), assignment.committee(&shards[1]).unwrap());
+        assert_eq!(first.members.len(), 4);
+        assert_eq!(second.members.len(), 4);
+        assert!(first.members.iter().all(|member| !second.contains(member)));
+        
+        // Too few for disjoint committees, so validators serve several shards
+        let (_, few) = validators(3);
+        let assignment = CommitteeAssignment::sample(0, [0; 32], &few, &shards, &config(2)).unwrap();
+        assert!(shards.iter().all(|shard_id| assignment.committee(shard_id).unwrap().members.len() == 2));
+        
+        assert!(CommitteeAssignment::sample(0, [0; 32], &few, &shards, &config(4)).is_err());
+    }
+    
+    #[test]
+    fn reshuffles_in_order_and_keeps_the_previous_epoch() {
+        let (keypairs, validators) = validators(4);
+        let shards = [ShardId(0)];
+        let mut schedule = CommitteeSchedule::new(config(4));
+        assert_eq!(schedule.epoch_of(7), 1);
+        assert!(schedule.is_epoch_start(8));
+        assert!(!schedule.is_epoch_start(9));
+        
+        for epoch in 0..3 {
+            schedule.reshuffle(epoch, &BlockId([epoch as u8; 32]), &validators, &shards).unwrap();
+        }
+        assert!(schedule.reshuffle(1, &BlockId([1; 32]), &validators, &shards).is_err());
+        assert_eq!(schedule.current_epoch(), Some(2));
+        assert!(schedule.assignment(0).is_none());
+        assert!(schedule.assignment(1).is_some());
+        
+        assert!(schedule.verify_header(&shards[0], &header(&keypairs[0], shards[0])).is_ok());
+        assert!(schedule.verify_header(&shards[0], &header(&KeyPair::generate(), shards[0])).is_err());
+        assert!(schedule.verify_header(&ShardId(1), &header(&keypairs[0], ShardId(1))).is_err());
+    }
+}
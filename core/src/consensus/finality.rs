@@ -1,9 +1,15 @@
 use crate::types::{Block, BlockId};
 use ed25519_dalek::VerifyingKey;
-use crate::utils::crypto::Signature;
+use crate::utils::crypto::{verify_signature, Signature};
 use serde::{Serialize, Deserialize, Deserializer};
 use std::collections::{HashMap, HashSet};
 
+/// Domain separator for finality votes
+const FINALITY_DOMAIN: &[u8] = b"optimachain-finality";
+
+/// Domain separator for validator set changes
+const VALIDATOR_SET_DOMAIN: &[u8] = b"optimachain-validator-set";
+
 /// Proof of finality for a block
 #[derive(Debug, Clone)]
 pub struct FinalityProof {
@@ -118,6 +124,79 @@ impl FinalityProof {
     pub fn has_signature_from(&self, validator: &VerifyingKey) -> bool {
         self.signatures.iter().any(|(v, _)| v == validator)
     }
+    
+    /// Get the bytes a validator signs to vote for the finality of a block
+    pub fn signing_bytes(block_id: &BlockId, height: u64) -> Vec<u8> {
+        let mut bytes = FINALITY_DOMAIN.to_vec();
+        bytes.extend_from_slice(&block_id.0);
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes
+    }
+    
+    /// Check that at least `threshold` of the given validators signed the block
+    pub fn verify(&self, validators: &[[u8; 32]], threshold: f64) -> Result<(), String> {
+        let message = FinalityProof::signing_bytes(&self.block_id, self.height);
+        let signatures = self.signatures.iter().map(|(key, signature)| (key.to_bytes(), signature));
+        
+        verify_quorum(&message, signatures, validators, threshold)
+    }
+}
+
+/// A new validator set, signed by the set it replaces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidatorSetChange {
+    /// Height from which the new set signs blocks
+    pub height: u64,
+    /// Block at which the change was decided
+    pub block_id: BlockId,
+    /// Keys of the new validator set
+    pub validators: Vec<[u8; 32]>,
+    /// Signatures of the previous validator set
+    pub signatures: Vec<([u8; 32], Signature)>,
+}
+
+impl ValidatorSetChange {
+    /// Get the bytes the previous validators sign
+    pub fn signing_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = VALIDATOR_SET_DOMAIN.to_vec();
+        bytes.extend(bincode::serialize(&(self.height, &self.block_id, &self.validators))
+            .map_err(|e| format!("Failed to encode validator set: {}", e))?);
+        Ok(bytes)
+    }
+    
+    /// Check that at least `threshold` of the previous validators signed the change
+    pub fn verify(&self, previous: &[[u8; 32]], threshold: f64) -> Result<(), String> {
+        let message = self.signing_bytes()?;
+        let signatures = self.signatures.iter().map(|(key, signature)| (*key, signature));
+        
+        verify_quorum(&message, signatures, previous, threshold)
+    }
+}
+
+/// Check that enough distinct validators produced valid signatures over a message
+fn verify_quorum<'a, I>(message: &[u8], signatures: I, validators: &[[u8; 32]], threshold: f64) -> Result<(), String>
+where
+    I: Iterator<Item = ([u8; 32], &'a Signature)>,
+{
+    let validator_set: HashSet<&[u8; 32]> = validators.iter().collect();
+    let mut signers = HashSet::new();
+    
+    for (key, signature) in signatures {
+        if !validator_set.contains(&key) {
+            continue;
+        }
+        if !verify_signature(&key, message, signature) {
+            return Err(format!("Invalid signature from validator {}", hex::encode(key)));
+        }
+        signers.insert(key);
+    }
+    
+    let required = (validator_set.len() as f64 * threshold).ceil() as usize;
+    if validator_set.is_empty() || signers.len() < required {
+        return Err(format!("Only {} of {} required validators signed", signers.len(), required));
+    }
+    
+    Ok(())
 }
 
 /// Provider of finality for the blockchain
@@ -127,7 +206,9 @@ pub struct FinalityProvider {
     /// Latest finalized height
     latest_finalized_height: u64,
     /// Blocks waiting for finality
-    pending_blocks: HashMap<BlockId, (Block, HashSet<VerifyingKey>)>,
+    pending_blocks: HashMap<BlockId, Block>,
+    /// Signed votes received for pending blocks
+    vote_signatures: HashMap<BlockId, HashMap<VerifyingKey, Signature>>,
     /// Threshold for finality (percentage of validators required)
     finality_threshold: f64,
 }
@@ -139,6 +220,7 @@ impl FinalityProvider {
             finalized_blocks: HashMap::new(),
             latest_finalized_height: 0,
             pending_blocks: HashMap::new(),
+            vote_signatures: HashMap::new(),
             finality_threshold: 0.67, // 2/3 of validators required for finality
         }
     }
@@ -148,72 +230,95 @@ impl FinalityProvider {
         self.finality_threshold = threshold.max(0.5).min(1.0); // Ensure threshold is between 0.5 and 1.0
     }
     
+    /// Get the finality threshold
+    pub fn finality_threshold(&self) -> f64 {
+        self.finality_threshold
+    }
+    
     /// Process a new block
-    pub fn process_block(&mut self, block: Block, total_validators: usize) {
+    ///
+    /// The block waits for signed votes; the producer's authorship is not a vote.
+    pub fn process_block(&mut self, block: Block) {
         let block_id = block.id();
         
-        // If block is already finalized, ignore
-        if self.finalized_blocks.contains_key(&block_id) {
+        // If block is already finalized or too old, ignore
+        if self.finalized_blocks.contains_key(&block_id) || block.header.height <= self.latest_finalized_height {
             return;
         }
         
-        // Add block to pending blocks
-        let mut signatures = HashSet::new();
-        signatures.insert(block.header.validator);
-        self.pending_blocks.insert(block_id.clone(), (block.clone(), signatures));
-        
-        // Check if block can be finalized immediately
-        self.check_finality(&block_id, total_validators);
+        self.pending_blocks.insert(block_id, block);
     }
     
-    /// Add a vote for a block
-    pub fn add_vote(&mut self, block_id: &BlockId, validator: VerifyingKey, total_validators: usize) {
-        if let Some((_, signatures)) = self.pending_blocks.get_mut(block_id) {
-            signatures.insert(validator);
-            
-            // Check if block can be finalized
-            self.check_finality(block_id, total_validators);
+    /// Get a block waiting for finality votes
+    pub fn pending_block(&self, block_id: &BlockId) -> Option<&Block> {
+        self.pending_blocks.get(block_id)
+    }
+    
+    /// Add a validator's signed finality vote for a pending block
+    ///
+    /// The signature must cover [`FinalityProof::signing_bytes`] of the block
+    /// and come from one of `validators`. Returns the finality proof if the
+    /// vote brought the block to finality. Votes for unknown blocks are ignored.
+    pub fn add_signed_vote(
+        &mut self,
+        block_id: &BlockId,
+        validator: VerifyingKey,
+        signature: Signature,
+        validators: &[[u8; 32]],
+    ) -> Result<Option<FinalityProof>, String> {
+        let block = match self.pending_blocks.get(block_id) {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+        
+        let key = validator.to_bytes();
+        if !validators.contains(&key) {
+            return Err(format!("Finality vote from {} who is not a validator", hex::encode(key)));
+        }
+        
+        let message = FinalityProof::signing_bytes(block_id, block.header.height);
+        if !verify_signature(&key, &message, &signature) {
+            return Err(format!("Invalid finality signature from validator {}", hex::encode(key)));
         }
+        
+        self.vote_signatures.entry(block_id.clone()).or_default().insert(validator, signature);
+        Ok(self.check_finality(block_id, validators.len()))
     }
     
-    /// Check if a block can be finalized
-    fn check_finality(&mut self, block_id: &BlockId, total_validators: usize) {
-        if let Some((block, signatures)) = self.pending_blocks.get(block_id) {
-            let signature_count = signatures.len();
-            let threshold = (total_validators as f64 * self.finality_threshold).ceil() as usize;
-            
-            if signature_count >= threshold {
-                // Block has reached finality
-                let mut proof = FinalityProof::new(block_id.clone(), block.header.height);
-                
-                // Add signatures to proof (in a real implementation, we would have actual signatures)
-                for validator in signatures {
-                    // Create a dummy signature for now
-                    let signature = Signature { bytes: [0; 64] };
-                    proof.add_signature(*validator, signature);
-                }
-                
-                // Add to finalized blocks
-                self.finalized_blocks.insert(block_id.clone(), proof);
-                
-                // Update latest finalized height if this block is higher
-                if block.header.height > self.latest_finalized_height {
-                    self.latest_finalized_height = block.header.height;
-                }
-                
-                // Remove from pending blocks
-                self.pending_blocks.remove(block_id);
-                
-                // Prune older pending blocks
-                self.prune_pending_blocks();
-            }
+    /// Finalize a block once enough validators signed it
+    fn check_finality(&mut self, block_id: &BlockId, total_validators: usize) -> Option<FinalityProof> {
+        let signature_count = self.vote_signatures.get(block_id).map_or(0, HashMap::len);
+        let threshold = (total_validators as f64 * self.finality_threshold).ceil() as usize;
+        if total_validators == 0 || signature_count < threshold {
+            return None;
         }
+        
+        let block = self.pending_blocks.remove(block_id)?;
+        let mut proof = FinalityProof::new(block_id.clone(), block.header.height);
+        for (validator, signature) in self.vote_signatures.remove(block_id).unwrap_or_default() {
+            proof.add_signature(validator, signature);
+        }
+        
+        self.finalized_blocks.insert(block_id.clone(), proof.clone());
+        
+        // Update latest finalized height if this block is higher
+        if block.header.height > self.latest_finalized_height {
+            self.latest_finalized_height = block.header.height;
+        }
+        
+        // Prune older pending blocks
+        self.prune_pending_blocks();
+        
+        Some(proof)
     }
     
     /// Prune pending blocks that are older than the latest finalized height
     fn prune_pending_blocks(&mut self) {
         let latest_height = self.latest_finalized_height;
-        self.pending_blocks.retain(|_, (block, _)| block.header.height > latest_height);
+        self.pending_blocks.retain(|_, block| block.header.height > latest_height);
+        
+        let pending_blocks = &self.pending_blocks;
+        self.vote_signatures.retain(|block_id, _| pending_blocks.contains_key(block_id));
     }
     
     /// Check if a block is finalized
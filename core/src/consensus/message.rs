@@ -0,0 +1,179 @@
+use crate::consensus::FinalityProof;
+use crate::types::BlockId;
+use crate::utils::crypto::{verify_signature, KeyPair, Signature};
+use libp2p::PeerId;
+use serde::{Serialize, Deserialize};
+
+/// Domain separator for consensus message signatures, followed by the chain ID
+const SIGNING_DOMAIN: &[u8] = b"optimachain-consensus";
+
+/// A block proposal for a height and round
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proposal {
+    /// Block height
+    pub height: u64,
+    /// Consensus round
+    pub round: u64,
+    /// Proposed block
+    pub block_id: BlockId,
+    /// Round in which the block was last locked, if any
+    pub valid_round: Option<u64>,
+}
+
+/// A prevote or precommit for a height and round
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vote {
+    /// Block height
+    pub height: u64,
+    /// Consensus round
+    pub round: u64,
+    /// Block voted for, or `None` for a nil vote
+    pub block_id: Option<BlockId>,
+    /// Signature over the finality signing bytes of the block, carried by
+    /// precommits so they can be aggregated into a finality proof
+    pub finality_signature: Option<Signature>,
+}
+
+impl Vote {
+    /// Sign the finality of the voted block, if any
+    pub fn sign_finality(&mut self, keypair: &KeyPair) {
+        if let Some(block_id) = &self.block_id {
+            self.finality_signature = Some(keypair.sign(&FinalityProof::signing_bytes(block_id, self.height)));
+        }
+    }
+}
+
+/// Proof that a validator misbehaved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Evidence {
+    /// Two conflicting votes of the same kind for the same height and round
+    DuplicateVote {
+        /// First vote
+        first: Box<SignedConsensusMessage>,
+        /// Conflicting vote
+        second: Box<SignedConsensusMessage>,
+    },
+}
+
+impl Evidence {
+    /// Get the validator the evidence is against
+    pub fn offender(&self) -> [u8; 32] {
+        match self {
+            Evidence::DuplicateVote { first, .. } => first.validator,
+        }
+    }
+    
+    /// Check that the evidence proves misbehavior on a chain
+    pub fn verify(&self, chain_id: &str) -> Result<(), String> {
+        match self {
+            Evidence::DuplicateVote { first, second } => {
+                first.verify(chain_id)?;
+                second.verify(chain_id)?;
+                
+                if first.validator != second.validator {
+                    return Err("Duplicate vote evidence from different validators".to_string());
+                }
+                
+                match (&first.payload, &second.payload) {
+                    (ConsensusPayload::Prevote(a), ConsensusPayload::Prevote(b))
+                    | (ConsensusPayload::Precommit(a), ConsensusPayload::Precommit(b)) => {
+                        if a.height != b.height || a.round != b.round {
+                            return Err("Duplicate votes are for different rounds".to_string());
+                        }
+                        if a.block_id == b.block_id {
+                            return Err("Duplicate votes do not conflict".to_string());
+                        }
+                        Ok(())
+                    }
+                    _ => Err("Duplicate vote evidence must hold two votes of the same kind".to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// Typed consensus payload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConsensusPayload {
+    /// Block proposal
+    Proposal(Proposal),
+    /// First-stage vote
+    Prevote(Vote),
+    /// Second-stage vote
+    Precommit(Vote),
+    /// Evidence of validator misbehavior
+    Evidence(Evidence),
+}
+
+impl ConsensusPayload {
+    /// Get the height and round the payload refers to, if any
+    pub fn height_and_round(&self) -> Option<(u64, u64)> {
+        match self {
+            ConsensusPayload::Proposal(proposal) => Some((proposal.height, proposal.round)),
+            ConsensusPayload::Prevote(vote) | ConsensusPayload::Precommit(vote) => Some((vote.height, vote.round)),
+            ConsensusPayload::Evidence(_) => None,
+        }
+    }
+}
+
+/// Consensus payload signed by a validator key
+///
+/// The signature covers the chain ID, the payload and, when present, the
+/// libp2p peer the validator sends from, so a message delivered directly by
+/// another peer can be told apart from a relayed one and a message cannot be
+/// replayed on another chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedConsensusMessage {
+    /// Signed payload
+    pub payload: ConsensusPayload,
+    /// Public key of the signing validator
+    pub validator: [u8; 32],
+    /// Encoded libp2p peer ID the validator is bound to, if any
+    pub peer_id: Option<Vec<u8>>,
+    /// Signature over the payload and peer binding
+    pub signature: Signature,
+}
+
+impl SignedConsensusMessage {
+    /// Sign a payload for a chain with a validator key, optionally binding it to our peer ID
+    pub fn sign(payload: ConsensusPayload, keypair: &KeyPair, peer_id: Option<&PeerId>, chain_id: &str) -> Result<Self, String> {
+        let peer_id = peer_id.map(|peer_id| peer_id.to_bytes());
+        let bytes = Self::signing_bytes(chain_id, &payload, &peer_id)?;
+        
+        Ok(SignedConsensusMessage {
+            signature: keypair.sign(&bytes),
+            validator: keypair.public_key(),
+            payload,
+            peer_id,
+        })
+    }
+    
+    /// Check the signature for a chain and any nested evidence
+    pub fn verify(&self, chain_id: &str) -> Result<(), String> {
+        let bytes = Self::signing_bytes(chain_id, &self.payload, &self.peer_id)?;
+        if !verify_signature(&self.validator, &bytes, &self.signature) {
+            return Err(format!("Invalid signature from validator {}", hex::encode(self.validator)));
+        }
+        
+        if let ConsensusPayload::Evidence(evidence) = &self.payload {
+            evidence.verify(chain_id)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the peer the message is bound to, if any
+    pub fn bound_peer(&self) -> Result<Option<PeerId>, String> {
+        self.peer_id.as_ref()
+            .map(|bytes| PeerId::from_bytes(bytes).map_err(|e| format!("Invalid bound peer ID: {}", e)))
+            .transpose()
+    }
+    
+    /// Bytes covered by the signature
+    fn signing_bytes(chain_id: &str, payload: &ConsensusPayload, peer_id: &Option<Vec<u8>>) -> Result<Vec<u8>, String> {
+        let mut bytes = SIGNING_DOMAIN.to_vec();
+        bytes.extend(bincode::serialize(&(chain_id, payload, peer_id))
+            .map_err(|e| format!("Failed to encode consensus payload: {}", e))?);
+        Ok(bytes)
+    }
+}
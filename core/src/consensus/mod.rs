@@ -6,8 +6,12 @@ mod apos;
 mod validator;
 mod block_production;
 mod finality;
+mod message;
+mod committee;
 
 pub use apos::{APoS, APoSConfig};
 pub use validator::{Validator, ValidatorSet, ValidatorInfo, StakeInfo};
 pub use block_production::{BlockProducer, BlockProductionSchedule};
-pub use finality::{FinalityProvider, FinalityProof};
+pub use finality::{FinalityProvider, FinalityProof, ValidatorSetChange};
+pub use committee::{Committee, CommitteeAssignment, CommitteeConfig, CommitteeSchedule, epoch_seed};
+pub use message::{ConsensusPayload, Proposal, Vote, Evidence, SignedConsensusMessage};
@@ -11,6 +11,7 @@ pub mod sharding;
 pub mod storage;
 pub mod wasm;
 pub mod utils;
+pub mod mempool;
 
 // Re-export commonly used types
 pub use types::{Block, BlockHeader, BlockId, Transaction, TransactionType, TransactionId, Account, AccountId, Balance, State};
@@ -21,6 +22,8 @@ pub use storage::{Database, DatabaseConfig, StorageError};
 pub use wasm::{WasmRuntime, RuntimeConfig, Contract, ContractInstance};
 pub use utils::{Result, Error, Config, KeyPair, Signature};
 
+use storage::StorageKey;
+
 /// Version of the OptimaChain blockchain
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -52,6 +55,114 @@ fn parse_log_level(level: &str) -> utils::LogLevel {
     }
 }
 
+/// Metadata key under which the genesis block ID is stored
+const GENESIS_KEY: &str = "genesis_block_id";
+
+/// Load the genesis block ID, if one has been recorded
+fn load_genesis_block_id(database: &storage::Database) -> utils::Result<Option<types::BlockId>> {
+    let stored = database.get(&storage::MetadataKey { key: GENESIS_KEY.to_string() })
+        .map_err(|e| utils::Error::database(e.to_string()))?;
+    
+    let bytes = match stored {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+    if bytes.len() != 32 {
+        return Err(utils::Error::database("Stored genesis block ID has the wrong length"));
+    }
+    
+    let mut block_id = [0u8; 32];
+    block_id.copy_from_slice(&bytes);
+    Ok(Some(types::BlockId(block_id)))
+}
+
+/// Metadata key under which the height and ID of the best imported block are stored
+const BEST_BLOCK_KEY: &str = "best_block";
+
+/// Load the best imported block, or the genesis block if none has been imported
+fn load_best_block(database: &storage::Database, genesis_block_id: Option<&types::BlockId>) -> utils::Result<(u64, types::BlockId)> {
+    let stored = database.get(&storage::MetadataKey { key: BEST_BLOCK_KEY.to_string() })
+        .map_err(|e| utils::Error::database(e.to_string()))?;
+    
+    match stored {
+        Some(bytes) => bincode::deserialize(&bytes)
+            .map_err(|e| utils::Error::database(format!("Failed to decode best block: {}", e))),
+        None => Ok((0, genesis_block_id.cloned().unwrap_or(types::BlockId([0; 32])))),
+    }
+}
+
+/// Metadata key under which the node's network keypair is stored
+const NODE_KEY_KEY: &str = "node_key";
+
+/// Load the node's network keypair, generating and storing one on first start
+///
+/// The peer ID is derived from the keypair, so it stays the same across
+/// restarts and peers that remember us can reconnect.
+fn load_node_keypair(database: &storage::Database) -> utils::Result<libp2p::identity::Keypair> {
+    let key = storage::MetadataKey { key: NODE_KEY_KEY.to_string() };
+    let stored = database.get(&key)
+        .map_err(|e| utils::Error::database(e.to_string()))?;
+    
+    if let Some(bytes) = stored {
+        return libp2p::identity::Keypair::from_protobuf_encoding(&bytes)
+            .map_err(|e| utils::Error::database(format!("Failed to decode node key: {}", e)));
+    }
+    
+    let keypair = libp2p::identity::Keypair::generate_ed25519();
+    let bytes = keypair.to_protobuf_encoding()
+        .map_err(|e| utils::Error::database(format!("Failed to encode node key: {}", e)))?;
+    database.put(&key, &bytes)
+        .map_err(|e| utils::Error::database(e.to_string()))?;
+    
+    Ok(keypair)
+}
+
+/// Metadata key prefix under which finality proofs are stored, followed by the block height
+const FINALITY_PROOF_KEY: &str = "finality_proof";
+
+/// Get the metadata key of the finality proof for a height
+fn finality_proof_key(height: u64) -> storage::MetadataKey {
+    storage::MetadataKey { key: format!("{}_{}", FINALITY_PROOF_KEY, height) }
+}
+
+/// Metadata key prefix under which the ID of each imported block is stored, followed by its height
+const BLOCK_HEIGHT_KEY: &str = "block_height";
+
+/// Get the metadata key of the ID of the block imported at a height
+fn block_height_key(height: u64) -> storage::MetadataKey {
+    storage::MetadataKey { key: format!("{}_{}", BLOCK_HEIGHT_KEY, height) }
+}
+
+/// Metadata key under which accepted validator set changes are stored
+const VALIDATOR_SET_CHANGES_KEY: &str = "validator_set_changes";
+
+/// Load the accepted validator set changes, in ascending height order
+fn load_validator_set_changes(database: &storage::Database) -> utils::Result<Vec<consensus::ValidatorSetChange>> {
+    let stored = database.get(&storage::MetadataKey { key: VALIDATOR_SET_CHANGES_KEY.to_string() })
+        .map_err(|e| utils::Error::database(e.to_string()))?;
+    
+    match stored {
+        Some(bytes) => bincode::deserialize(&bytes)
+            .map_err(|e| utils::Error::database(format!("Failed to decode validator set changes: {}", e))),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Most validator set changes served in one response
+const MAX_VALIDATOR_SET_CHANGES_PER_RESPONSE: usize = 64;
+
+/// Number of finalized shard states and snapshots kept for syncing peers and light clients
+const MAX_STATE_SNAPSHOTS: usize = 4;
+
+/// Most headers served in one response
+const MAX_HEADERS_PER_RESPONSE: u64 = 512;
+
+/// Most blocks served in one response
+const MAX_BLOCKS_PER_RESPONSE: usize = 64;
+
+/// Most account moves scheduled in one resharding evaluation
+const MAX_ACCOUNT_MOVES_PER_EVALUATION: usize = 1024;
+
 /// Main blockchain struct
 pub struct Blockchain {
     /// Configuration
@@ -60,12 +171,40 @@ pub struct Blockchain {
     database: storage::Database,
     /// Network protocol
     protocol: network::Protocol,
+    /// Peer discovery
+    discovery: network::Discovery,
+    /// Network keypair the local peer ID is derived from
+    node_keypair: libp2p::identity::Keypair,
     /// Consensus engine
     consensus: consensus::APoS,
     /// Shards
     shards: Vec<sharding::Shard>,
+    /// Cross-shard transfers
+    cross_shard: sharding::CrossShardCommunicator,
+    /// Resharding operations
+    resharding: sharding::ReshardingManager,
+    /// Routes transactions to shards
+    router: sharding::ShardRouter,
+    /// Coordination chain committing shard blocks
+    beacon: sharding::BeaconChain,
     /// WASM runtime
     wasm_runtime: wasm::WasmRuntime,
+    /// State snapshot sync and the shard it restores, while one is in progress
+    state_sync: Option<(sharding::ShardId, network::StateSync)>,
+    /// Snapshots of shard states at finalized blocks, served to syncing peers, oldest first
+    state_snapshots: Vec<network::StateSnapshot>,
+    /// Shard states at finalized blocks, so proofs can be served against roots the shards moved past, oldest first
+    finalized_states: Vec<types::State>,
+    /// Light client, when running as a light node
+    light_client: Option<network::LightClient>,
+    /// Height and ID of the best imported block
+    best_block: (u64, types::BlockId),
+    /// Validator set version whose keys the protocol checks consensus messages against
+    validator_keys_version: Option<u64>,
+    /// Accepted validator set changes, served to light clients
+    validator_set_changes: Vec<consensus::ValidatorSetChange>,
+    /// Committee epoch of the beacon head when resharding was last evaluated
+    resharding_epoch: u64,
 }
 
 impl Blockchain {
@@ -80,6 +219,7 @@ impl Blockchain {
                 "accounts".to_string(),
                 "state".to_string(),
                 "metadata".to_string(),
+                "sharding".to_string(),
             ],
             create_if_missing: true,
             create_missing_column_families: true,
@@ -90,6 +230,23 @@ impl Blockchain {
         let mut database = storage::Database::new(database_config);
         database.open().map_err(|e| utils::Error::database(e.to_string()))?;
         
+        let genesis_block_id = load_genesis_block_id(&database)?;
+        let best_block = load_best_block(&database, genesis_block_id.as_ref())?;
+        if genesis_block_id.is_none() {
+            log::warn!("No genesis block recorded; peers are refused until one is imported");
+        }
+        
+        // Shards retired by a merge are not created again
+        let retired_shards = sharding::ReshardingManager::load_retired(&database).map_err(utils::Error::sharding)?;
+        let shard_ids: Vec<sharding::ShardId> = if config.sharding.enable_sharding {
+            (0..config.sharding.shard_count as u32)
+                .map(sharding::ShardId)
+                .filter(|shard_id| !retired_shards.contains(shard_id))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        
         // Initialize network protocol
         let protocol_config = network::ProtocolConfig {
             protocol_name: "/optimachain/1.0.0".to_string(),
@@ -97,9 +254,67 @@ impl Blockchain {
             request_timeout: config.network.connection_timeout,
             max_message_size: 10 * 1024 * 1024, // 10 MB
             max_concurrent_requests: 100,
+            seen_cache_size: 10000,
+            seen_cache_ttl: 120,
+            gossip: network::GossipConfig::default(),
+            sync: network::SyncConfig::default(),
+            scoring: network::PeerScoreConfig::default(),
+            handshake: network::HandshakeConfig {
+                chain_id: config.network.chain_id.clone(),
+                genesis_block_id: genesis_block_id.clone(),
+                capabilities: network::Capabilities {
+                    role: network::NodeRole::from_config(&config.node.role),
+                    shards: shard_ids.clone(),
+                    compression: true,
+                },
+                ..network::HandshakeConfig::default()
+            },
+            rate_limit: network::RateLimitConfig::default(),
+            mempool: mempool::MempoolConfig::default(),
+            compact: network::CompactBlockConfig::default(),
+            dandelion: network::DandelionConfig {
+                enabled: config.network.enable_dandelion,
+                ..network::DandelionConfig::default()
+            },
+        };
+        
+        let mut protocol = network::Protocol::new(protocol_config);
+        protocol.scoring_mut().load_bans(&database).map_err(utils::Error::database)?;
+        protocol.set_local_head(best_block.0, best_block.1.clone());
+        
+        // Initialize peer discovery
+        let mut bootstrap_nodes = Vec::new();
+        for node in &config.network.bootstrap_nodes {
+            match node.parse() {
+                Ok(addr) => bootstrap_nodes.push(addr),
+                Err(e) => log::warn!("Invalid bootstrap node address {}: {}", node, e),
+            }
+        }
+        
+        let is_mainnet = config.network.network_type == utils::NetworkType::Mainnet;
+        if config.network.enable_mdns && is_mainnet {
+            log::warn!("mDNS discovery is not available on mainnet; ignoring enable_mdns");
+        }
+        
+        let discovery_config = network::DiscoveryConfig {
+            bootstrap_nodes,
+            enable_mdns: config.network.enable_mdns && !is_mainnet,
+            max_peers: config.network.max_peers,
+            min_peers: (config.network.max_peers / 5).max(1),
+            ..network::DiscoveryConfig::default()
+        };
+        
+        let transport_config = network::TransportConfig {
+            connection_timeout: config.network.connection_timeout,
+            enable_nat_traversal: config.network.enable_nat_traversal,
+            ..network::TransportConfig::default()
         };
         
-        let protocol = network::Protocol::new(protocol_config);
+        // Persisted peers are loaded first so they seed the routing table
+        let node_keypair = load_node_keypair(&database)?;
+        let mut discovery = network::Discovery::new(discovery_config);
+        discovery.load_peers(&database).map_err(utils::Error::database)?;
+        discovery.initialize(&libp2p::PeerId::from(node_keypair.public()), &transport_config);
         
         // Initialize consensus
         let consensus_config = consensus::APoSConfig {
@@ -109,20 +324,58 @@ impl Blockchain {
             epoch_length: 10_000, // ~3 hours with 1s blocks
             block_reward: 100_000_000, // 100 tokens
             validator_fee_percentage: 70, // 70%
+            committee: consensus::CommitteeConfig::default(),
         };
         
         let consensus = consensus::APoS::new(consensus_config);
+        let validator_set_changes = load_validator_set_changes(&database)?;
         
-        // Initialize shards
-        let mut shards = Vec::new();
+        // Initialize shards, resuming work interrupted by a restart
+        let mut shards: Vec<sharding::Shard> = Vec::new();
+        let cross_shard = sharding::CrossShardCommunicator::resume(sharding::CrossShardConfig::default(), &database)
+            .map_err(utils::Error::sharding)?;
+        let mut router = sharding::ShardRouter::new(
+            sharding::ShardAllocator::new(sharding::AllocationStrategy::from_config(&config.sharding.allocation_strategy)),
+        );
         
-        if config.sharding.enable_sharding {
-            for i in 0..config.sharding.shard_count {
-                let shard_config = sharding::ShardConfig::default();
-                let shard = sharding::Shard::new(sharding::ShardId(i as u32), shard_config);
-                shards.push(shard);
+        for shard_id in &shard_ids {
+            let shard_config = sharding::ShardConfig::default();
+            shard_config.validate().map_err(utils::Error::sharding)?;
+            router.add_shard(*shard_id, shard_config.clone());
+            shards.push(sharding::Shard::new(*shard_id, shard_config));
+        }
+        for shard_id in &retired_shards {
+            router.mark_retired(*shard_id);
+        }
+        
+        let mut resharding = sharding::ReshardingManager::resume(&database, &mut shards)
+            .map_err(utils::Error::sharding)?;
+        for shard in shards.iter_mut() {
+            resharding.add_shard_config(shard.id(), shard.config().clone());
+            // Shards outside an interrupted split or merge take transactions
+            if shard.state() == &sharding::ShardState::Creating {
+                shard.set_state(sharding::ShardState::Active);
             }
         }
+        router.place_from_states(&shards).map_err(utils::Error::sharding)?;
+        for account_id in resharding.relocated_accounts() {
+            router.allocator_mut().mark_relocated(account_id);
+        }
+        router.allocator_mut().stats_mut().load(&database).map_err(utils::Error::database)?;
+        
+        // Beacon blocks from before a merge link the retired shards, so they are replayed with them
+        let beacon_config = sharding::BeaconConfig {
+            genesis_block_id: genesis_block_id.clone().unwrap_or(types::BlockId([0; 32])),
+            ..sharding::BeaconConfig::default()
+        };
+        let beacon_shards: Vec<sharding::ShardId> = shard_ids.iter().chain(retired_shards.iter()).copied().collect();
+        let mut beacon = sharding::BeaconChain::resume(beacon_config, &beacon_shards, &database)
+            .map_err(utils::Error::sharding)?;
+        for shard_id in &retired_shards {
+            beacon.remove_shard(shard_id);
+        }
+        // The epoch under way was evaluated before the restart, if the node ran during it
+        let resharding_epoch = consensus.committees().epoch_of(beacon.head().height);
         
         // Initialize WASM runtime
         let runtime_type = match config.wasm.runtime_type.as_str() {
@@ -144,14 +397,84 @@ impl Blockchain {
         
         let wasm_runtime = wasm::WasmRuntime::new(runtime_config);
         
-        Ok(Blockchain {
+        let mut blockchain = Blockchain {
             config,
             database,
             protocol,
+            discovery,
+            node_keypair,
             consensus,
             shards,
+            cross_shard,
+            resharding,
+            router,
+            beacon,
             wasm_runtime,
-        })
+            state_sync: None,
+            state_snapshots: Vec::new(),
+            finalized_states: Vec::new(),
+            light_client: None,
+            best_block,
+            validator_keys_version: None,
+            validator_set_changes,
+            resharding_epoch,
+        };
+        blockchain.resume_resharding();
+        blockchain.derive_committees();
+        blockchain.refresh_frozen_accounts();
+        
+        Ok(blockchain)
+    }
+    
+    /// Run resharding interrupted by a restart to completion
+    ///
+    /// Each active operation is matched with the finalized beacon schedule
+    /// that started it and carried out like a freshly started one.
+    fn resume_resharding(&mut self) {
+        let finalized = self.beacon.finalized_height();
+        let resumed: Vec<(String, sharding::ScheduledResharding)> = self.resharding.get_active_operations().values()
+            .filter_map(|operation| {
+                let scheduled = self.beacon.due_resharding(0, finalized).into_iter()
+                    .rev()
+                    .find(|scheduled| scheduled.strategy == operation.strategy && scheduled.shards == operation.shards);
+                if scheduled.is_none() {
+                    log::warn!("No finalized schedule for resharding operation {}; it cannot be resumed", operation.id);
+                }
+                scheduled.map(|scheduled| (operation.id.clone(), scheduled.clone()))
+            })
+            .collect();
+        
+        for (operation_id, scheduled) in resumed {
+            self.run_resharding(&operation_id, &scheduled);
+        }
+    }
+    
+    /// Carry out a started resharding operation that only moves state
+    ///
+    /// Merges and account moves run to completion at the beacon height they
+    /// were scheduled for.
+    fn run_resharding(&mut self, operation_id: &str, scheduled: &sharding::ScheduledResharding) {
+        match (scheduled.strategy, scheduled.shards.as_slice()) {
+            (sharding::ReshardingStrategy::Merge, [surviving, retiring]) => {
+                if let Err(e) = self.merge_shards(operation_id, *retiring, *surviving) {
+                    log::warn!("Merge of shard {:?} into shard {:?} failed: {}", retiring, surviving, e);
+                }
+            }
+            (sharding::ReshardingStrategy::Rebalance, [source, destination]) if !scheduled.accounts.is_empty() => {
+                if let Err(e) = self.migrate_accounts(operation_id, *source, *destination, scheduled.accounts.clone()) {
+                    log::warn!("Moving {} accounts from shard {:?} to shard {:?} failed: {}", scheduled.accounts.len(), source, destination, e);
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    /// Freeze the accounts of finalized moves that have not activated yet
+    fn refresh_frozen_accounts(&mut self) {
+        let frozen = self.beacon.upcoming_resharding(self.beacon.finalized_height()).into_iter()
+            .flat_map(|scheduled| scheduled.accounts.iter().cloned())
+            .collect();
+        self.router.set_frozen_accounts(frozen);
     }
     
     /// Start the blockchain
@@ -164,6 +487,15 @@ impl Blockchain {
         // Start consensus
         self.consensus.start().map_err(|e| utils::Error::from(e))?;
         
+        // Only the active validators may sign consensus messages
+        self.refresh_validator_keys();
+        
+        // Validators known by now may complete the committees derived at startup
+        self.derive_committees();
+        
+        // Beacon blocks and cross-links are gossiped to every node
+        self.protocol.subscribe(network::Topic::beacon());
+        
         log::info!("OptimaChain blockchain started");
         
         Ok(())
@@ -178,6 +510,9 @@ impl Blockchain {
         
         // Stop network protocol
         self.protocol.stop().map_err(|e| utils::Error::from(e))?;
+        self.persist_peer_bans()?;
+        self.discovery.save_peers(&self.database).map_err(utils::Error::database)?;
+        self.router.allocator().stats().save(&self.database).map_err(utils::Error::database)?;
         
         // Close database
         self.database.close();
@@ -202,6 +537,26 @@ impl Blockchain {
         &self.protocol
     }
     
+    /// Get the peer discovery mechanism
+    pub fn discovery(&self) -> &network::Discovery {
+        &self.discovery
+    }
+    
+    /// Get the peer discovery mechanism mutably, e.g. to feed it swarm events
+    pub fn discovery_mut(&mut self) -> &mut network::Discovery {
+        &mut self.discovery
+    }
+    
+    /// Get the node's network keypair
+    pub fn node_keypair(&self) -> &libp2p::identity::Keypair {
+        &self.node_keypair
+    }
+    
+    /// Get the local peer ID
+    pub fn local_peer_id(&self) -> libp2p::PeerId {
+        libp2p::PeerId::from(self.node_keypair.public())
+    }
+    
     /// Get the consensus engine
     pub fn consensus(&self) -> &consensus::APoS {
         &self.consensus
@@ -216,4 +571,1592 @@ impl Blockchain {
     pub fn wasm_runtime(&self) -> &wasm::WasmRuntime {
         &self.wasm_runtime
     }
+    
+    /// Start syncing a shard's state at a finalized height, resuming an interrupted sync of the same state
+    ///
+    /// The sync is driven from `tick`, and the shard's state is replaced once it completes.
+    pub fn start_state_sync(&mut self, shard_id: sharding::ShardId, height: u64, state_root: types::StateRoot) -> utils::Result<()> {
+        if !self.shards.iter().any(|shard| shard.id() == shard_id) {
+            return Err(utils::Error::sharding(format!("Shard {} is not served by this node", shard_id.0)));
+        }
+        
+        let state_sync = network::StateSync::resume(
+            network::StateSyncConfig::default(),
+            height,
+            state_root,
+            &self.database,
+        ).map_err(utils::Error::network)?;
+        
+        self.state_sync = Some((shard_id, state_sync));
+        
+        Ok(())
+    }
+    
+    /// Ask connected peers for the snapshot and its chunks, and reschedule timed-out requests
+    fn drive_state_sync(&mut self) -> Vec<network::ProtocolEvent> {
+        let mut events = Vec::new();
+        let state_sync = match self.state_sync.as_mut() {
+            Some((_, state_sync)) => state_sync,
+            None => return events,
+        };
+        
+        for peer_id in self.protocol.handshake().established_peers() {
+            state_sync.add_peer(peer_id);
+        }
+        
+        let timed_out = state_sync.check_timeouts();
+        let requests = state_sync.next_requests();
+        
+        for peer_id in timed_out {
+            events.extend(self.protocol.report_peer(&peer_id, network::Misbehavior::Timeout, "State sync request timed out"));
+        }
+        for (peer_id, request) in requests {
+            self.protocol.send_message(peer_id, network::Message::new(request, 1));
+        }
+        
+        events
+    }
+    
+    /// Feed a state sync event from the network into the running state sync
+    ///
+    /// Once every chunk is in, the assembled state replaces the shard's state and is returned.
+    pub fn handle_state_sync_event(&mut self, event: network::ProtocolEvent) -> utils::Result<Option<types::State>> {
+        let (shard_id, state_sync) = match self.state_sync.as_mut() {
+            Some((shard_id, state_sync)) => (*shard_id, state_sync),
+            None => return Ok(None),
+        };
+        
+        let result = match event {
+            network::ProtocolEvent::StateManifestReceived { peer_id, manifest } => {
+                state_sync.on_manifest(&peer_id, manifest).map_err(|e| (peer_id, e))
+            }
+            network::ProtocolEvent::StateChunkReceived { peer_id, chunk } => {
+                state_sync.on_chunk(&peer_id, chunk, &self.database).map_err(|e| (peer_id, e))
+            }
+            _ => Ok(()),
+        };
+        
+        if let Err((peer_id, e)) = result {
+            log::warn!("State sync: {}", e);
+            if self.protocol.report_peer(&peer_id, network::Misbehavior::InvalidMessage, &e).is_some() {
+                self.persist_peer_bans()?;
+            }
+            return Ok(None);
+        }
+        
+        if !state_sync.is_complete() {
+            return Ok(None);
+        }
+        
+        let state = state_sync.finalize(&self.database).map_err(utils::Error::database)?;
+        self.state_sync = None;
+        
+        if let Some(shard) = self.shards.iter_mut().find(|shard| shard.id() == shard_id) {
+            *shard.account_state_mut() = state.clone();
+            log::info!("Restored the state of shard {} with {} accounts", shard_id.0, state.accounts.len());
+        }
+        
+        Ok(Some(state))
+    }
+    
+    /// Start following the chain as a light client from a trusted header and validator set
+    pub fn start_light_client(&mut self, trusted_header: types::BlockHeader, validators: Vec<[u8; 32]>) {
+        self.light_client = Some(network::LightClient::new(
+            network::LightClientConfig::default(),
+            trusted_header,
+            validators,
+        ));
+    }
+    
+    /// Feed a light client event from the network into the light client
+    ///
+    /// Account and storage proofs that verify against the finalized state root
+    /// are returned for the caller; everything else is consumed.
+    pub fn handle_light_client_event(&mut self, event: network::ProtocolEvent) -> utils::Result<Option<network::ProtocolEvent>> {
+        let light_client = match self.light_client.as_mut() {
+            Some(light_client) => light_client,
+            None => return Ok(None),
+        };
+        
+        let finalized_root = light_client.finalized_header().state_root.clone();
+        let mut verified = None;
+        let result = match event {
+            network::ProtocolEvent::HeadersReceived { peer_id, headers } => {
+                light_client.on_headers(headers).map(|_| ()).map_err(|e| (peer_id, e))
+            }
+            network::ProtocolEvent::FinalityProofReceived { peer_id, proof: Some(proof) } => {
+                light_client.on_finality_proof(&proof).map_err(|e| (peer_id, e))
+            }
+            network::ProtocolEvent::ValidatorSetChangesReceived { peer_id, changes } => {
+                changes.iter()
+                    .try_for_each(|change| light_client.on_validator_set_change(change))
+                    .map_err(|e| (peer_id, e))
+            }
+            // Proofs against an older finalized root answer a request we have moved past
+            network::ProtocolEvent::AccountProofReceived { state_root, .. }
+            | network::ProtocolEvent::StorageProofReceived { state_root, .. } if state_root != finalized_root => Ok(()),
+            network::ProtocolEvent::AccountProofReceived { peer_id, state_root, account_id, proof } => {
+                let check = proof.as_ref().map_or(Ok(()), |proof| light_client.verify_account(&account_id, proof).map(|_| ()));
+                if check.is_ok() {
+                    verified = Some(network::ProtocolEvent::AccountProofReceived { peer_id, state_root, account_id, proof });
+                }
+                check.map_err(|e| (peer_id, e))
+            }
+            network::ProtocolEvent::StorageProofReceived { peer_id, state_root, account_id, proof } => {
+                let check = proof.as_ref().map_or(Ok(()), |proof| light_client.verify_storage(&account_id, proof).map(|_| ()));
+                if check.is_ok() {
+                    verified = Some(network::ProtocolEvent::StorageProofReceived { peer_id, state_root, account_id, proof });
+                }
+                check.map_err(|e| (peer_id, e))
+            }
+            _ => Ok(()),
+        };
+        
+        if let Err((peer_id, e)) = result {
+            log::warn!("Light client: {}", e);
+            if self.protocol.report_peer(&peer_id, network::Misbehavior::InvalidMessage, &e).is_some() {
+                self.persist_peer_bans()?;
+            }
+        }
+        
+        Ok(verified)
+    }
+    
+    /// Ask a full node for an account proof against the light client's finalized state root
+    ///
+    /// The verified proof is returned from `tick` as an `AccountProofReceived` event.
+    pub fn request_account_proof(&mut self, account_id: types::AccountId) -> utils::Result<()> {
+        let request = self.light_client.as_ref()
+            .ok_or_else(|| utils::Error::network("Not running as a light client".to_string()))?
+            .account_proof_request(account_id);
+        self.send_light_client_request(request)
+    }
+    
+    /// Ask a full node for a storage proof against the light client's finalized state root
+    ///
+    /// The verified proof is returned from `tick` as a `StorageProofReceived` event.
+    pub fn request_storage_proof(&mut self, account_id: types::AccountId, key: Vec<u8>) -> utils::Result<()> {
+        let request = self.light_client.as_ref()
+            .ok_or_else(|| utils::Error::network("Not running as a light client".to_string()))?
+            .storage_proof_request(account_id, key);
+        self.send_light_client_request(request)
+    }
+    
+    /// Send the light client's periodic header, finality and validator set requests
+    fn drive_light_client(&mut self) {
+        if self.protocol.handshake().light_servers().is_empty() {
+            return;
+        }
+        
+        let requests = match self.light_client.as_mut() {
+            Some(light_client) => light_client.next_requests(),
+            None => return,
+        };
+        for request in requests {
+            // Requests are spread over random servers so no single peer can stall the client
+            if let Err(e) = self.send_light_client_request(request) {
+                log::debug!("Light client: {}", e);
+            }
+        }
+    }
+    
+    /// Send a light client request to a random full node serving light clients
+    fn send_light_client_request(&mut self, request: network::MessageType) -> utils::Result<()> {
+        use rand::seq::SliceRandom;
+        
+        let servers = self.protocol.handshake().light_servers();
+        let peer_id = servers.choose(&mut rand::thread_rng())
+            .ok_or_else(|| utils::Error::network("No peer serves light clients".to_string()))?;
+        self.protocol.send_message(*peer_id, network::Message::new(request, 1));
+        Ok(())
+    }
+    
+    /// Get the light client
+    pub fn light_client(&self) -> Option<&network::LightClient> {
+        self.light_client.as_ref()
+    }
+    
+    /// Get the running state sync
+    pub fn state_sync_mut(&mut self) -> Option<&mut network::StateSync> {
+        self.state_sync.as_mut().map(|(_, state_sync)| state_sync)
+    }
+    
+    /// Record the genesis block of the chain; peers on a different genesis are rejected
+    pub fn set_genesis(&mut self, genesis_block_id: types::BlockId) -> utils::Result<()> {
+        self.database.put(
+            &storage::MetadataKey { key: GENESIS_KEY.to_string() },
+            &genesis_block_id.0,
+        ).map_err(|e| utils::Error::database(e.to_string()))?;
+        
+        self.protocol.set_genesis(genesis_block_id.clone());
+        self.beacon.set_genesis(genesis_block_id.clone());
+        
+        // Sync starts from the genesis block until a block is imported
+        if self.best_block.0 == 0 {
+            self.best_block = (0, genesis_block_id);
+            self.protocol.set_local_head(0, self.best_block.1.clone());
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the height and ID of the best imported block
+    pub fn best_block(&self) -> &(u64, types::BlockId) {
+        &self.best_block
+    }
+    
+    /// Run periodic network maintenance, to be called from the node loop about once a second
+    ///
+    /// Sync requests are sent and rescheduled, and the events the node reacts
+    /// to itself are handled. The remaining events are returned.
+    pub fn tick(&mut self) -> utils::Result<Vec<network::ProtocolEvent>> {
+        let mut events = self.protocol.take_queued_events();
+        events.extend(self.protocol.check_handshake_timeouts());
+        events.extend(self.protocol.drive_sync());
+        events.extend(self.protocol.check_compact_blocks());
+        events.extend(self.drive_state_sync());
+        self.drive_light_client();
+        for transaction in self.protocol.check_dandelion() {
+            if let Err(e) = self.pool_transaction(transaction) {
+                log::debug!("Not pooling fluffed transaction: {}", e);
+            }
+        }
+        self.protocol.prune();
+        self.refresh_validator_keys();
+        
+        let unhandled = self.handle_protocol_events(events)?;
+        
+        // Bans issued while handling messages outside the node loop, or expired ones
+        if self.protocol.scoring().has_unsaved_bans() {
+            self.persist_peer_bans()?;
+        }
+        
+        // Peers learned since the last save survive a crash
+        if self.discovery.should_save_peers() {
+            self.discovery.save_peers(&self.database).map_err(utils::Error::database)?;
+        }
+        
+        if self.config.node.role == "validator" {
+            self.drive_beacon()?;
+        }
+        
+        // Resharding is evaluated once per committee epoch of the beacon chain
+        let epoch = self.consensus.committees().epoch_of(self.beacon.head().height);
+        if epoch > self.resharding_epoch {
+            self.resharding_epoch = epoch;
+            self.evaluate_resharding()?;
+            self.router.allocator().stats().save(&self.database).map_err(utils::Error::database)?;
+        }
+        
+        Ok(unhandled)
+    }
+    
+    /// Handle protocol events the node reacts to itself and return the rest
+    pub fn handle_protocol_events(&mut self, events: Vec<network::ProtocolEvent>) -> utils::Result<Vec<network::ProtocolEvent>> {
+        let mut unhandled = Vec::new();
+        
+        for event in events {
+            match event {
+                network::ProtocolEvent::BlocksSynced { blocks } => unhandled.extend(self.import_synced_blocks(blocks)?),
+                network::ProtocolEvent::BlockReceived { peer_id, block, transactions } => {
+                    let extends_head = block.header.height == self.best_block.0 + 1
+                        && block.header.prev_block == self.best_block.1;
+                    if !extends_head {
+                        // Gaps are filled by block sync, which learns of the block from peer status
+                        unhandled.push(network::ProtocolEvent::BlockReceived { peer_id, block, transactions });
+                    } else if !block.header.has_valid_signature() {
+                        log::warn!("Dropping block at height {} from {} with an invalid signature", block.header.height, peer_id);
+                    } else if let Err(e) = self.check_shard_block(&block) {
+                        log::warn!("Dropping block at height {} from {}: {}", block.header.height, peer_id, e);
+                    } else {
+                        self.import_blocks(vec![(block, transactions)])?;
+                    }
+                }
+                network::ProtocolEvent::BlockBodyRequested { peer_id, block_id } => self.serve_block_body(peer_id, &block_id)?,
+                network::ProtocolEvent::TransactionReceived { peer_id, transaction } => {
+                    if let Err(e) = self.pool_transaction(transaction) {
+                        log::debug!("Not pooling transaction from {}: {}", peer_id, e);
+                    }
+                }
+                network::ProtocolEvent::HeadersRequested { peer_id, start_height, count } => {
+                    self.serve_headers(peer_id, start_height, count)?;
+                }
+                network::ProtocolEvent::BlocksRequested { peer_id, block_ids } => self.serve_blocks(peer_id, &block_ids)?,
+                network::ProtocolEvent::ConsensusReceived { peer_id, message } => {
+                    if let consensus::ConsensusPayload::Precommit(vote) = &message.payload {
+                        self.add_finality_vote(&message.validator, vote)?;
+                    }
+                    unhandled.push(network::ProtocolEvent::ConsensusReceived { peer_id, message });
+                }
+                network::ProtocolEvent::BeaconBlockReceived { peer_id, block } => {
+                    let height = block.height;
+                    if let Err(e) = self.import_beacon_block(block) {
+                        log::debug!("Not importing beacon block {} from {}: {}", height, peer_id, e);
+                    }
+                }
+                network::ProtocolEvent::CrossLinkReceived { peer_id, link } => {
+                    if let Err(e) = self.submit_cross_link(link) {
+                        log::debug!("Not queuing cross-link from {}: {}", peer_id, e);
+                    }
+                }
+                network::ProtocolEvent::FinalityProofRequested { peer_id, height } => self.serve_finality_proof(peer_id, height)?,
+                network::ProtocolEvent::ValidatorSetChangesRequested { peer_id, from_height, count } => {
+                    self.serve_validator_set_changes(peer_id, from_height, count);
+                }
+                network::ProtocolEvent::AccountProofRequested { peer_id, state_root, account_id } => {
+                    let proof = self.proof_state(&state_root).map(|state| state.prove_account(&account_id));
+                    let response = network::MessageType::AccountProofResponse { state_root, account_id, proof };
+                    self.protocol.send_message(peer_id, network::Message::new(response, 1));
+                }
+                network::ProtocolEvent::StorageProofRequested { peer_id, state_root, account_id, key } => {
+                    let proof = self.proof_state(&state_root).map(|state| state.prove_storage(&account_id, key));
+                    let response = network::MessageType::StorageProofResponse { state_root, account_id, proof };
+                    self.protocol.send_message(peer_id, network::Message::new(response, 1));
+                }
+                network::ProtocolEvent::StateManifestRequested { peer_id, height, state_root } => {
+                    let manifest = self.state_snapshots.iter()
+                        .map(|snapshot| snapshot.manifest())
+                        .find(|manifest| manifest.height == height && manifest.state_root == state_root)
+                        .cloned();
+                    let response = network::MessageType::StateManifestResponse { manifest };
+                    self.protocol.send_message(peer_id, network::Message::new(response, 1));
+                }
+                network::ProtocolEvent::StateChunkRequested { peer_id, state_root, index } => {
+                    let chunk = self.state_snapshots.iter()
+                        .find(|snapshot| snapshot.manifest().state_root == state_root)
+                        .and_then(|snapshot| snapshot.chunk(index));
+                    match chunk {
+                        Some(chunk) => {
+                            let response = network::MessageType::StateChunkResponse { chunk };
+                            self.protocol.send_message(peer_id, network::Message::new(response, 1));
+                        }
+                        None => log::debug!("Peer {} requested chunk {} of a snapshot we do not have", peer_id, index),
+                    }
+                }
+                event @ (network::ProtocolEvent::StateManifestReceived { .. } | network::ProtocolEvent::StateChunkReceived { .. }) => {
+                    self.handle_state_sync_event(event)?;
+                }
+                event @ (network::ProtocolEvent::HeadersReceived { .. }
+                | network::ProtocolEvent::FinalityProofReceived { .. }
+                | network::ProtocolEvent::ValidatorSetChangesReceived { .. }
+                | network::ProtocolEvent::AccountProofReceived { .. }
+                | network::ProtocolEvent::StorageProofReceived { .. }) if self.light_client.is_some() => {
+                    unhandled.extend(self.handle_light_client_event(event)?);
+                }
+                network::ProtocolEvent::ConflictingChain { peer_id } => {
+                    log::warn!("Peer {} is on a chain that conflicts with our block at height {}", peer_id, self.best_block.0);
+                }
+                network::ProtocolEvent::PeerBanned { .. } => {
+                    // Persist right away so a crash does not lift the ban
+                    self.persist_peer_bans()?;
+                    unhandled.push(event);
+                }
+                event => unhandled.push(event),
+            }
+        }
+        
+        Ok(unhandled)
+    }
+    
+    /// Import blocks downloaded by block sync, in ascending height order
+    ///
+    /// Sync already checked the header chain and signatures; each block must
+    /// also come from its shard's committee. The first block that does not is
+    /// dropped with everything above it, and the peer that served it is
+    /// penalized. Returns the resulting ban, if any.
+    pub fn import_synced_blocks(&mut self, blocks: Vec<network::CompleteBlock>) -> utils::Result<Vec<network::ProtocolEvent>> {
+        let mut verified = Vec::with_capacity(blocks.len());
+        let mut events = Vec::new();
+        
+        for network::CompleteBlock { peer_id, block, transactions } in blocks {
+            let check = if block.header.has_valid_signature() {
+                self.check_shard_block(&block)
+            } else {
+                Err(utils::Error::network("Invalid block signature".to_string()))
+            };
+            
+            if let Err(e) = check {
+                events.extend(self.protocol.reject_synced_block(&peer_id, block.header.height, &e.to_string()));
+                break;
+            }
+            
+            verified.push((block, transactions));
+        }
+        
+        if !verified.is_empty() {
+            self.import_blocks(verified)?;
+        }
+        Ok(events)
+    }
+    
+    /// Import blocks with their transactions, in ascending height order
+    ///
+    /// Each block must extend the best block, carry exactly the transactions
+    /// it lists and be new to its shard. The whole batch is checked and
+    /// stored before anything changes in memory, so a bad block leaves the
+    /// node as it was. Then included transactions leave the mempool, blocks
+    /// are added to their shard, and sync continues from the new best block.
+    fn import_blocks(&mut self, blocks: Vec<(types::Block, Vec<types::Transaction>)>) -> utils::Result<()> {
+        let mut best_block = self.best_block.clone();
+        let mut batch = storage::Batch::new();
+        
+        for (block, transactions) in &blocks {
+            let block_id = block.id();
+            if block.header.height != best_block.0 + 1 || block.header.prev_block != best_block.1 {
+                return Err(utils::Error::network(format!(
+                    "Block at height {} does not extend the best block at height {}",
+                    block.header.height, best_block.0
+                )));
+            }
+            
+            let matches = transactions.len() == block.transactions.len()
+                && transactions.iter().zip(&block.transactions).all(|(transaction, transaction_id)| transaction.id() == *transaction_id);
+            if !matches {
+                return Err(utils::Error::network(format!("Transactions do not match the block at height {}", block.header.height)));
+            }
+            
+            if self.shards.iter().any(|shard| shard.id().0 == block.shard_id && shard.get_block(&block_id).is_some()) {
+                return Err(utils::Error::sharding(format!("Block at height {} is already in shard {}", block.header.height, block.shard_id)));
+            }
+            
+            let bytes = bincode::serialize(&block)
+                .map_err(|e| utils::Error::database(format!("Failed to encode block: {}", e)))?;
+            batch.put(storage::BlockKey::column_family(), storage::BlockKey { block_id: block_id.0 }.encode(), bytes);
+            batch.put(storage::MetadataKey::column_family(), block_height_key(block.header.height).encode(), block_id.0.to_vec());
+            
+            for transaction in transactions {
+                let bytes = bincode::serialize(transaction)
+                    .map_err(|e| utils::Error::database(format!("Failed to encode transaction: {}", e)))?;
+                let key = storage::TransactionKey { transaction_id: transaction.id().0 };
+                batch.put(storage::TransactionKey::column_family(), key.encode(), bytes);
+            }
+            
+            best_block = (block.header.height, block_id);
+        }
+        
+        let head = bincode::serialize(&best_block)
+            .map_err(|e| utils::Error::database(format!("Failed to encode best block: {}", e)))?;
+        batch.put(storage::MetadataKey::column_family(), storage::MetadataKey { key: BEST_BLOCK_KEY.to_string() }.encode(), head);
+        self.database.apply_batch(&batch).map_err(|e| utils::Error::database(e.to_string()))?;
+        
+        for (block, transactions) in blocks {
+            let block_id = block.id();
+            self.protocol.mempool_mut().remove_included(&block.transactions);
+            
+            if let Some(shard) = self.shards.iter_mut().find(|shard| shard.id().0 == block.shard_id) {
+                shard.add_block(block.clone()).map_err(utils::Error::sharding)?;
+                let execution = sharding::execute_block(shard, &transactions, &self.router, &self.database)
+                    .map_err(utils::Error::sharding)?;
+                for (transaction_id, reason) in &execution.failed {
+                    log::debug!("Transaction {} failed at height {}: {}", hex::encode(transaction_id.0), block.header.height, reason);
+                }
+                shard.record_block_execution(&block, execution.gas_used, execution.execution_time);
+                for transaction in &transactions {
+                    self.router.record_executed(transaction, shard.account_state());
+                }
+                self.apply_cross_shard(sharding::ShardId(block.shard_id), &block_id, block.header.height, &transactions)?;
+            }
+            self.consensus.track_finality(&block);
+        }
+        
+        log::info!("Imported blocks up to height {}", best_block.0);
+        
+        self.protocol.set_local_head(best_block.0, best_block.1.clone());
+        self.best_block = best_block;
+        
+        Ok(())
+    }
+    
+    /// Load a stored block by ID
+    fn load_block(&self, block_id: &types::BlockId) -> utils::Result<Option<types::Block>> {
+        let stored = self.database.get(&storage::BlockKey { block_id: block_id.0 })
+            .map_err(|e| utils::Error::database(e.to_string()))?;
+        stored
+            .map(|bytes| bincode::deserialize(&bytes))
+            .transpose()
+            .map_err(|e| utils::Error::database(format!("Failed to decode block: {}", e)))
+    }
+    
+    /// Answer a peer's request for a range of headers, up to our best block
+    ///
+    /// The response stops at the first height we have no block for.
+    fn serve_headers(&mut self, peer_id: libp2p::PeerId, start_height: u64, count: u32) -> utils::Result<()> {
+        let end_height = start_height
+            .saturating_add((count as u64).min(MAX_HEADERS_PER_RESPONSE))
+            .min(self.best_block.0.saturating_add(1));
+        let mut headers = Vec::new();
+        
+        for height in start_height..end_height {
+            let stored = self.database.get(&block_height_key(height))
+                .map_err(|e| utils::Error::database(e.to_string()))?;
+            let block_id = match stored.and_then(|bytes| <[u8; 32]>::try_from(bytes.as_slice()).ok()) {
+                Some(bytes) => types::BlockId(bytes),
+                None => break,
+            };
+            
+            match self.load_block(&block_id)? {
+                Some(block) => headers.push(block.header),
+                None => break,
+            }
+        }
+        
+        let response = network::MessageType::HeadersResponse { headers };
+        self.protocol.send_message(peer_id, network::Message::new(response, 1));
+        Ok(())
+    }
+    
+    /// Answer a peer's request for several blocks; unknown blocks are left out
+    fn serve_blocks(&mut self, peer_id: libp2p::PeerId, block_ids: &[types::BlockId]) -> utils::Result<()> {
+        let mut blocks = Vec::new();
+        for block_id in block_ids.iter().take(MAX_BLOCKS_PER_RESPONSE) {
+            blocks.extend(self.load_block(block_id)?);
+        }
+        
+        let response = network::MessageType::BlocksResponse { blocks };
+        self.protocol.send_message(peer_id, network::Message::new(response, 1));
+        Ok(())
+    }
+    
+    /// Answer a peer's request for the transactions of a block
+    ///
+    /// Transactions come from the mempool or the database. Requests for blocks
+    /// we do not have, or whose transactions we do not all hold, go unanswered.
+    fn serve_block_body(&mut self, peer_id: libp2p::PeerId, block_id: &types::BlockId) -> utils::Result<()> {
+        let block = match self.load_block(block_id)? {
+            Some(block) => block,
+            None => {
+                log::debug!("Peer {} requested the body of an unknown block", peer_id);
+                return Ok(());
+            }
+        };
+        
+        let mut transactions = Vec::with_capacity(block.transactions.len());
+        for transaction_id in &block.transactions {
+            if let Some(transaction) = self.protocol.mempool().get(transaction_id) {
+                transactions.push(transaction.clone());
+                continue;
+            }
+            
+            let stored = self.database.get(&storage::TransactionKey { transaction_id: transaction_id.0 })
+                .map_err(|e| utils::Error::database(e.to_string()))?;
+            match stored {
+                Some(bytes) => transactions.push(bincode::deserialize(&bytes)
+                    .map_err(|e| utils::Error::database(format!("Failed to decode transaction: {}", e)))?),
+                None => {
+                    log::debug!("Cannot serve the body of block at height {} to {}: transactions are missing", block.header.height, peer_id);
+                    return Ok(());
+                }
+            }
+        }
+        
+        let response = network::MessageType::BlockBodyResponse { block_id: block_id.clone(), transactions };
+        self.protocol.send_message(peer_id, network::Message::new(response, 1));
+        Ok(())
+    }
+    
+    /// Count a verified precommit towards finality and store the proof once the block is final
+    ///
+    /// Proofs are stored by height so they can be served to light clients.
+    fn add_finality_vote(&mut self, validator: &[u8; 32], vote: &consensus::Vote) -> utils::Result<()> {
+        // Beacon blocks are finalized by the whole validator set
+        if let (Some(block_id), Some(signature)) = (&vote.block_id, &vote.finality_signature) {
+            if self.beacon.block(vote.height).is_some_and(|block| block.id() == *block_id) {
+                return self.add_beacon_finality_vote(validator, block_id, vote.height, signature.clone());
+            }
+        }
+        
+        let proof = match self.consensus.add_finality_vote(validator, vote) {
+            Ok(Some(proof)) => proof,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                log::warn!("Ignoring finality vote at height {}: {}", vote.height, e);
+                return Ok(());
+            }
+        };
+        
+        // Shard blocks are final only once their committee signed them
+        if let Some(shard) = self.shards.iter().find(|shard| shard.get_block(&proof.block_id).is_some()) {
+            if let Err(e) = self.verify_shard_finality(&shard.id(), &proof) {
+                log::warn!("Finality proof at height {} is not backed by the shard committee: {}", proof.height, e);
+                return Ok(());
+            }
+        }
+        
+        let bytes = bincode::serialize(&proof)
+            .map_err(|e| utils::Error::database(format!("Failed to encode finality proof: {}", e)))?;
+        self.database.put(&finality_proof_key(proof.height), &bytes)
+            .map_err(|e| utils::Error::database(e.to_string()))?;
+        
+        log::info!("Block at height {} is final with {} signatures", proof.height, proof.signature_count());
+        
+        self.snapshot_finalized_state(&proof.block_id, proof.height);
+        self.finalize_cross_shard(&proof.block_id, proof.height)
+    }
+    
+    /// Count a precommit for a beacon block and finalize the block once the validator set signed it
+    fn add_beacon_finality_vote(
+        &mut self,
+        validator: &[u8; 32],
+        block_id: &types::BlockId,
+        height: u64,
+        signature: utils::crypto::Signature,
+    ) -> utils::Result<()> {
+        let validators: Vec<[u8; 32]> = self.consensus.validator_set().validators().iter()
+            .map(|validator| validator.public_key().to_bytes())
+            .collect();
+        let proof = match self.beacon.add_finality_vote(validator, block_id, height, signature, &validators) {
+            Ok(Some(proof)) => proof,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                log::warn!("Ignoring beacon finality vote at height {}: {}", height, e);
+                return Ok(());
+            }
+        };
+        
+        let finalized = self.finalize_beacon_block(&proof, &validators)?;
+        log::info!("Beacon block {} is final with {} signatures, committing {} shards", height, proof.signature_count(), finalized.len());
+        Ok(())
+    }
+    
+    /// Advance cross-shard transfers past a finalized block
+    ///
+    /// When the block is its shard's head, the shard's state root is recorded
+    /// as finalized, which makes the receipts it committed ready for their
+    /// destination. Credits made by the block become final.
+    fn finalize_cross_shard(&mut self, block_id: &types::BlockId, height: u64) -> utils::Result<()> {
+        if let Some(shard) = self.shards.iter().find(|shard| shard.latest_block_id() == Some(block_id)) {
+            self.cross_shard.on_shard_finalized(shard.id(), height, shard.state_root().clone(), &self.database)
+                .map_err(utils::Error::sharding)?;
+        }
+        
+        self.cross_shard.on_block_finalized(block_id, &self.database).map_err(utils::Error::sharding)?;
+        Ok(())
+    }
+    
+    /// Run the cross-shard receipt protocol for an imported block of one of our shards
+    ///
+    /// Receipts headed for the shard are credited once they can be proven
+    /// against the source shard's finalized state, receipts from the shard
+    /// that expired uncredited are refunded against the destination's
+    /// finalized state, and transfers in the block to accounts of other
+    /// shards debit their senders. Receipts whose other shard we do not hold
+    /// a finalized state of wait until we do.
+    fn apply_cross_shard(
+        &mut self,
+        shard_id: sharding::ShardId,
+        block_id: &types::BlockId,
+        height: u64,
+        transactions: &[types::Transaction],
+    ) -> utils::Result<()> {
+        let index = match self.shards.iter().position(|shard| shard.id() == shard_id) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        
+        for transaction in self.cross_shard.get_pending_for_destination(&shard_id) {
+            // Expired receipts are left for the source shard to refund
+            let Some(receipt) = transaction.receipt.filter(|receipt| height <= receipt.deadline) else {
+                continue;
+            };
+            let outbox = sharding::outbox_account(shard_id);
+            let Some((proof, proof_height)) = self.finalized_storage_proof(receipt.source_shard, &outbox, receipt.key()) else {
+                continue;
+            };
+            
+            let state = self.shards[index].account_state_mut();
+            if let Err(e) = self.cross_shard.commit_in_destination(&receipt, &proof, proof_height, block_id.clone(), height, state, &self.database) {
+                log::warn!("Could not credit cross-shard receipt {}: {}", hex::encode(receipt.transaction_id.0), e);
+            }
+        }
+        
+        let destinations: std::collections::HashSet<sharding::ShardId> = self.cross_shard.in_flight().into_iter()
+            .filter(|transaction| transaction.source_shard == shard_id)
+            .map(|transaction| transaction.destination_shard)
+            .collect();
+        for destination in destinations {
+            for receipt in self.cross_shard.expired_receipts(&destination) {
+                if receipt.source_shard != shard_id {
+                    continue;
+                }
+                let inbox = sharding::inbox_account(shard_id);
+                let Some((proof, proof_height)) = self.finalized_storage_proof(destination, &inbox, receipt.key()) else {
+                    continue;
+                };
+                
+                let state = self.shards[index].account_state_mut();
+                if let Err(e) = self.cross_shard.refund(&receipt, &proof, proof_height, state, &self.database) {
+                    log::warn!("Could not refund cross-shard receipt {}: {}", hex::encode(receipt.transaction_id.0), e);
+                }
+            }
+        }
+        
+        for transaction in transactions {
+            let Ok(sharding::Route::CrossShard { source_shard, destination_shard, transfer }) = self.router.route(transaction) else {
+                continue;
+            };
+            if source_shard != shard_id {
+                continue;
+            }
+            
+            // Transfers we did not route ourselves arrive with the block
+            let transaction_id = transaction.id();
+            match self.cross_shard.get_transaction(&transaction_id) {
+                None => {
+                    self.cross_shard.submit_transaction(transaction_id.clone(), source_shard, destination_shard, transfer, &self.database)
+                        .map_err(utils::Error::sharding)?;
+                }
+                Some(tracked) if tracked.status != sharding::CrossShardTransactionStatus::PendingSource => continue,
+                Some(_) => {}
+            }
+            
+            let state = self.shards[index].account_state_mut();
+            if let Err(e) = self.cross_shard.commit_in_source(&transaction_id, block_id.clone(), height, state, &self.database) {
+                log::warn!("Cross-shard transfer {} failed: {}", hex::encode(transaction_id.0), e);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Prove a storage value against the latest finalized state of a shard, if we hold that state
+    fn finalized_storage_proof(
+        &self,
+        shard_id: sharding::ShardId,
+        account_id: &types::AccountId,
+        key: Vec<u8>,
+    ) -> Option<(types::StorageProof, u64)> {
+        let height = self.cross_shard.finalized_height(&shard_id);
+        let state_root = self.cross_shard.finalized_root(&shard_id, height)?;
+        let state = self.proof_state(state_root)?;
+        Some((state.prove_storage(account_id, key), height))
+    }
+    
+    /// Keep the state of the shard whose head was just finalized, with a snapshot for syncing peers
+    fn snapshot_finalized_state(&mut self, block_id: &types::BlockId, height: u64) {
+        let shard = match self.shards.iter().find(|shard| shard.latest_block_id() == Some(block_id)) {
+            Some(shard) => shard,
+            None => return,
+        };
+        if self.finalized_states.iter().any(|state| state.root == *shard.state_root()) {
+            return;
+        }
+        
+        self.finalized_states.push(shard.account_state().clone());
+        if self.finalized_states.len() > MAX_STATE_SNAPSHOTS {
+            self.finalized_states.remove(0);
+        }
+        
+        let accounts_per_chunk = network::StateSyncConfig::default().accounts_per_chunk;
+        match network::StateSnapshot::new(shard.account_state(), height, block_id.clone(), accounts_per_chunk) {
+            Ok(snapshot) => {
+                self.state_snapshots.push(snapshot);
+                if self.state_snapshots.len() > MAX_STATE_SNAPSHOTS {
+                    self.state_snapshots.remove(0);
+                }
+            }
+            Err(e) => log::warn!("Could not snapshot the state of shard {} at height {}: {}", shard.id().0, height, e),
+        }
+    }
+    
+    /// Find the state with a root, among current shard states and recent finalized ones
+    fn proof_state(&self, state_root: &types::StateRoot) -> Option<&types::State> {
+        self.shards.iter()
+            .map(|shard| shard.account_state())
+            .chain(self.finalized_states.iter().rev())
+            .find(|state| state.root == *state_root)
+    }
+    
+    /// Answer a light client's request for the finality proof at a height
+    fn serve_finality_proof(&mut self, peer_id: libp2p::PeerId, height: u64) -> utils::Result<()> {
+        let stored = self.database.get(&finality_proof_key(height))
+            .map_err(|e| utils::Error::database(e.to_string()))?;
+        let proof = stored
+            .map(|bytes| bincode::deserialize(&bytes))
+            .transpose()
+            .map_err(|e| utils::Error::database(format!("Failed to decode finality proof: {}", e)))?;
+        
+        let response = network::MessageType::FinalityProofResponse { proof };
+        self.protocol.send_message(peer_id, network::Message::new(response, 1));
+        Ok(())
+    }
+    
+    /// Answer a light client's request for validator set changes from a height
+    fn serve_validator_set_changes(&mut self, peer_id: libp2p::PeerId, from_height: u64, count: u32) {
+        let changes = self.validator_set_changes.iter()
+            .filter(|change| change.height >= from_height)
+            .take((count as usize).min(MAX_VALIDATOR_SET_CHANGES_PER_RESPONSE))
+            .cloned()
+            .collect();
+        
+        let response = network::MessageType::ValidatorSetChangesResponse { changes };
+        self.protocol.send_message(peer_id, network::Message::new(response, 1));
+    }
+    
+    /// Accept a validator set change signed by the current validator set
+    ///
+    /// The change is stored so it can be served to light clients; applying the
+    /// new set to consensus is left to the caller.
+    pub fn record_validator_set_change(&mut self, change: consensus::ValidatorSetChange) -> utils::Result<()> {
+        if self.validator_set_changes.last().is_some_and(|last| last.height >= change.height) {
+            return Err(utils::Error::from(format!("Validator set change at height {} is not newer than the last one", change.height)));
+        }
+        
+        let previous: Vec<[u8; 32]> = self.consensus.validator_set().validators().iter()
+            .map(|validator| validator.public_key().to_bytes())
+            .collect();
+        change.verify(&previous, self.consensus.finality_threshold()).map_err(utils::Error::from)?;
+        
+        let mut changes = self.validator_set_changes.clone();
+        changes.push(change);
+        let bytes = bincode::serialize(&changes)
+            .map_err(|e| utils::Error::database(format!("Failed to encode validator set changes: {}", e)))?;
+        self.database.put(&storage::MetadataKey { key: VALIDATOR_SET_CHANGES_KEY.to_string() }, &bytes)
+            .map_err(|e| utils::Error::database(e.to_string()))?;
+        
+        self.validator_set_changes = changes;
+        Ok(())
+    }
+    
+    /// Get the peer score table
+    pub fn peer_scores(&self) -> &std::collections::HashMap<libp2p::PeerId, network::PeerScoreEntry> {
+        self.protocol.scoring().scores()
+    }
+    
+    /// Get the active peer bans
+    pub fn peer_bans(&self) -> &std::collections::HashMap<libp2p::PeerId, network::PeerBan> {
+        self.protocol.scoring().bans()
+    }
+    
+    /// Ban a peer manually
+    pub fn ban_peer(&mut self, peer_id: libp2p::PeerId, duration_secs: u64, reason: String) -> utils::Result<()> {
+        self.protocol.scoring_mut().ban(peer_id, std::time::Duration::from_secs(duration_secs), reason);
+        self.protocol.remove_peer(&peer_id);
+        self.persist_peer_bans()
+    }
+    
+    /// Lift a peer ban
+    pub fn unban_peer(&mut self, peer_id: &libp2p::PeerId) -> utils::Result<bool> {
+        let unbanned = self.protocol.scoring_mut().unban(peer_id);
+        self.persist_peer_bans()?;
+        Ok(unbanned)
+    }
+    
+    /// Get the cross-shard communicator
+    pub fn cross_shard(&self) -> &sharding::CrossShardCommunicator {
+        &self.cross_shard
+    }
+    
+    /// Get mutable access to the cross-shard communicator, with the database its steps are journaled to
+    pub fn cross_shard_mut(&mut self) -> (&mut sharding::CrossShardCommunicator, &storage::Database) {
+        (&mut self.cross_shard, &self.database)
+    }
+    
+    /// Get the resharding manager
+    pub fn resharding(&self) -> &sharding::ReshardingManager {
+        &self.resharding
+    }
+    
+    /// Get the shard router
+    pub fn router(&self) -> &sharding::ShardRouter {
+        &self.router
+    }
+    
+    /// Get the beacon chain
+    pub fn beacon(&self) -> &sharding::BeaconChain {
+        &self.beacon
+    }
+    
+    /// Validate a shard's cross-link and queue it for the next beacon block
+    ///
+    /// Every header must come from the shard's committee.
+    pub fn submit_cross_link(&mut self, link: sharding::CrossLink) -> utils::Result<()> {
+        self.beacon.submit_cross_link(link, self.consensus.committees()).map_err(utils::Error::sharding)
+    }
+    
+    /// Link this node's new shard blocks and produce a beacon block once one is due
+    ///
+    /// Cross-links are queued locally and gossiped so every validator can
+    /// include them. A block is produced when something is queued and the
+    /// beacon block interval has passed since the head.
+    fn drive_beacon(&mut self) -> utils::Result<()> {
+        let links: Vec<sharding::CrossLink> = self.shards.iter()
+            .filter_map(|shard| self.new_cross_link(shard))
+            .collect();
+        for link in links {
+            match self.submit_cross_link(link.clone()) {
+                Ok(()) => {
+                    self.protocol.publish(network::Topic::beacon(), network::MessageType::CrossLinkAnnounce { link });
+                }
+                Err(e) => log::debug!("Not linking shard {:?}: {}", link.shard_id, e),
+            }
+        }
+        
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        let due = self.beacon.head().timestamp.saturating_add(self.beacon.config().block_interval) <= now;
+        if due && self.beacon.has_pending() {
+            let block = self.produce_beacon_block()?;
+            log::info!("Produced beacon block {} linking {} shards", block.height, block.cross_links.len());
+            self.protocol.publish(network::Topic::beacon(), network::MessageType::BeaconBlockAnnounce { block });
+        }
+        
+        Ok(())
+    }
+    
+    /// Build a cross-link for a shard's blocks above its beacon commitment, unless a queued link already covers them
+    fn new_cross_link(&self, shard: &sharding::Shard) -> Option<sharding::CrossLink> {
+        let shard_id = shard.id();
+        let committed = self.beacon.shard_commitment(self.beacon.head().height, &shard_id)
+            .map_or(0, |commitment| commitment.height);
+        let queued = self.beacon.pending_link(&shard_id)
+            .and_then(|link| link.headers.last())
+            .map_or(committed, |header| header.height);
+        if shard.latest_block_height() <= committed.max(queued) {
+            return None;
+        }
+        
+        let mut headers = Vec::new();
+        let mut block = shard.latest_block();
+        while let Some(current) = block.filter(|current| current.header.height > committed) {
+            headers.push(current.header.clone());
+            block = shard.get_block(&current.header.prev_block);
+        }
+        headers.reverse();
+        
+        // The oldest headers extend the commitment; the rest follow in a later link
+        headers.truncate(self.beacon.config().max_cross_link_headers);
+        Some(sharding::CrossLink { shard_id, headers })
+    }
+    
+    /// Check a block of one of this node's shards against the shard's committee
+    fn check_shard_block(&self, block: &types::Block) -> utils::Result<()> {
+        if self.shards.iter().any(|shard| shard.id().0 == block.shard_id) {
+            self.verify_shard_block(block)?;
+        }
+        Ok(())
+    }
+    
+    /// Check a shard block against its committee
+    pub fn verify_shard_block(&self, block: &types::Block) -> utils::Result<()> {
+        let committees = self.consensus.committees();
+        let epoch = committees.current_epoch()
+            .ok_or_else(|| utils::Error::sharding("No committees assigned".to_string()))?;
+        
+        committees.verify_block(epoch, block)
+            .or_else(|_| committees.verify_block(epoch.saturating_sub(1), block))
+            .map_err(utils::Error::sharding)
+    }
+    
+    /// Check a shard block's finality proof against its committee
+    pub fn verify_shard_finality(&self, shard_id: &sharding::ShardId, proof: &consensus::FinalityProof) -> utils::Result<()> {
+        let committees = self.consensus.committees();
+        let epoch = committees.current_epoch()
+            .ok_or_else(|| utils::Error::sharding("No committees assigned".to_string()))?;
+        
+        committees.verify_finality(epoch, shard_id, proof)
+            .or_else(|_| committees.verify_finality(epoch.saturating_sub(1), shard_id, proof))
+            .map_err(utils::Error::sharding)
+    }
+    
+    /// Derive the committees of the finalized epoch and the one before it, if not assigned yet
+    ///
+    /// Committees are derived from the finalized beacon chain rather than
+    /// stored, so this runs at startup and again as validators become known.
+    /// Without committees, shard blocks and cross-links are rejected.
+    fn derive_committees(&mut self) {
+        if self.shards.is_empty() || self.consensus.validator_set().is_empty() {
+            return;
+        }
+        
+        let committees = self.consensus.committees();
+        let epoch = committees.epoch_of(self.beacon.finalized_height());
+        let epoch_length = committees.config().epoch_length;
+        for epoch in epoch.saturating_sub(1)..=epoch {
+            if self.consensus.committees().current_epoch().is_none_or(|current| epoch > current) {
+                self.reshuffle_committees(epoch * epoch_length);
+            }
+        }
+    }
+    
+    /// Sample shard committees from the beacon block starting an epoch
+    ///
+    /// Failures are logged, and the previous committees stay in charge.
+    fn reshuffle_committees(&mut self, beacon_height: u64) {
+        let beacon_block = match self.beacon.block(beacon_height) {
+            Some(block) => block.id(),
+            None => return,
+        };
+        let shard_ids: Vec<sharding::ShardId> = self.shards.iter().map(|shard| shard.id()).collect();
+        
+        if let Err(e) = self.consensus.reshuffle_committees(beacon_height, &beacon_block, &shard_ids) {
+            log::warn!("Could not assign committees at beacon height {}: {}", beacon_height, e);
+        }
+    }
+    
+    /// Schedule resharding at a beacon height, to be agreed on in the next beacon block
+    pub fn schedule_resharding(
+        &mut self,
+        strategy: sharding::ReshardingStrategy,
+        shards: Vec<sharding::ShardId>,
+        activation_height: u64,
+    ) -> utils::Result<()> {
+        self.beacon.schedule_resharding(sharding::ScheduledResharding { strategy, shards, activation_height, accounts: Vec::new() })
+            .map_err(utils::Error::sharding)
+    }
+    
+    /// Schedule moving accounts between shards at a beacon height, to be agreed on in the next beacon block
+    ///
+    /// Once the schedule is finalized the accounts take no new transactions
+    /// until they have moved at the activation height.
+    pub fn schedule_account_migration(
+        &mut self,
+        source_shard: sharding::ShardId,
+        destination_shard: sharding::ShardId,
+        accounts: Vec<types::AccountId>,
+        activation_height: u64,
+    ) -> utils::Result<()> {
+        if accounts.is_empty() {
+            return Err(utils::Error::sharding("No accounts to move"));
+        }
+        
+        self.beacon.schedule_resharding(sharding::ScheduledResharding {
+            strategy: sharding::ReshardingStrategy::Rebalance,
+            shards: vec![source_shard, destination_shard],
+            activation_height,
+            accounts,
+        })
+        .map_err(utils::Error::sharding)
+    }
+    
+    /// Produce the next beacon block from the queued cross-links and resharding
+    pub fn produce_beacon_block(&mut self) -> utils::Result<sharding::BeaconBlock> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        self.beacon.produce_block(timestamp, self.consensus.committees(), &self.database).map_err(utils::Error::sharding)
+    }
+    
+    /// Import a beacon block produced by another node
+    pub fn import_beacon_block(&mut self, block: sharding::BeaconBlock) -> utils::Result<()> {
+        self.beacon.import_block(block, self.consensus.committees(), &self.database).map_err(utils::Error::sharding)
+    }
+    
+    /// Finalize a beacon block
+    ///
+    /// The shard state roots it commits to become the roots cross-shard
+    /// receipts are verified against, and resharding scheduled up to its
+    /// height is started.
+    pub fn finalize_beacon_block(
+        &mut self,
+        proof: &consensus::FinalityProof,
+        validators: &[[u8; 32]],
+    ) -> utils::Result<Vec<sharding::ShardCommitment>> {
+        let previous = self.beacon.finalized_height();
+        let finalized = self.beacon.finalize(proof, validators, &self.database).map_err(utils::Error::sharding)?;
+        let state_commitment = self.beacon.state_commitment(proof.height)
+            .expect("Finalized beacon block exists");
+        
+        for commitment in &finalized {
+            let root_proof = self.beacon.prove_shard_root(proof.height, &commitment.shard_id)
+                .expect("Finalized commitment is part of the beacon block");
+            self.cross_shard.on_beacon_finalized(&root_proof, &state_commitment, &self.database)
+                .map_err(utils::Error::sharding)?;
+        }
+        
+        // Reshuffle committees if an epoch started since the last finalized block
+        let epoch = self.consensus.committees().epoch_of(proof.height);
+        if epoch > self.consensus.committees().epoch_of(previous) {
+            self.reshuffle_committees(epoch * self.consensus.committees().config().epoch_length);
+        }
+        
+        let due: Vec<sharding::ScheduledResharding> = self.beacon.due_resharding(previous, proof.height)
+            .into_iter()
+            .cloned()
+            .collect();
+        for scheduled in due {
+            let operation_id = match self.resharding.start_scheduled(&scheduled, &self.database) {
+                Ok(operation_id) => operation_id,
+                Err(e) => {
+                    log::warn!("Could not start resharding scheduled for beacon height {}: {}", scheduled.activation_height, e);
+                    continue;
+                }
+            };
+            self.run_resharding(&operation_id, &scheduled);
+        }
+        self.refresh_frozen_accounts();
+        
+        Ok(finalized)
+    }
+    
+    /// Get the shard an account is on or goes to, if there are any shards
+    pub fn shard_of_account(&self, account_id: &types::AccountId) -> Option<sharding::ShardId> {
+        self.router.shard_of(account_id)
+    }
+    
+    /// Refresh shard load metrics and collect resharding recommendations, for periodic evaluation
+    ///
+    /// Called from `tick` once per committee epoch, so a merge is only
+    /// recommended after a shard stayed underloaded for several epochs. Each
+    /// recommended merge is scheduled on the beacon chain for the start of
+    /// the next epoch, into the least loaded shard that can take it. Under
+    /// consistent hashing, accounts left away from their ring owner by a
+    /// change of the shard set are scheduled to move there as well; under
+    /// data-driven strategies, the moves `propose_shard_migrations` finds are.
+    pub fn evaluate_resharding(&mut self) -> utils::Result<Vec<(sharding::ShardId, sharding::ReshardingStrategy)>> {
+        let mut recommendations = Vec::new();
+        for shard in self.shards.iter_mut() {
+            shard.record_mempool_backlog(self.protocol.mempool().shard_len(&shard.id()));
+            shard.measure_state_usage();
+            
+            if let Some(strategy) = self.resharding.recommend_strategy(shard) {
+                log::info!("Recommending {:?} for shard {:?} (load {:.2})", strategy, shard.id(), shard.load_score());
+                recommendations.push((shard.id(), strategy));
+            }
+        }
+        
+        let committees = self.consensus.committees().config();
+        let next_epoch_start = (self.beacon.head().height / committees.epoch_length.max(1) + 1) * committees.epoch_length.max(1);
+        let mut merging = std::collections::HashSet::new();
+        for (shard_id, strategy) in &recommendations {
+            if *strategy != sharding::ReshardingStrategy::Merge || merging.contains(shard_id) {
+                continue;
+            }
+            
+            let shard = self.shards.iter().find(|shard| shard.id() == *shard_id).expect("Recommended shard exists");
+            let partner = match self.resharding.merge_partner(shard, &self.shards) {
+                Some(partner) if !merging.contains(&partner) => partner,
+                _ => continue,
+            };
+            
+            let scheduled = sharding::ScheduledResharding {
+                strategy: sharding::ReshardingStrategy::Merge,
+                shards: vec![partner, *shard_id],
+                activation_height: next_epoch_start,
+                accounts: Vec::new(),
+            };
+            match self.beacon.schedule_resharding(scheduled) {
+                Ok(()) => {
+                    merging.insert(*shard_id);
+                    merging.insert(partner);
+                }
+                Err(e) => log::warn!("Could not schedule merge of shard {:?}: {}", shard_id, e),
+            }
+        }
+        
+        let moves = match self.router.allocator().strategy() {
+            sharding::AllocationStrategy::ConsistentHash => self.router.allocator().plan_rebalance().map_err(utils::Error::sharding)?,
+            sharding::AllocationStrategy::Balance
+            | sharding::AllocationStrategy::Activity
+            | sharding::AllocationStrategy::ContractDependency => self.propose_shard_migrations(MAX_ACCOUNT_MOVES_PER_EVALUATION),
+            sharding::AllocationStrategy::Hash | sharding::AllocationStrategy::Geographic => Vec::new(),
+        };
+        self.schedule_account_moves(moves, &merging, next_epoch_start);
+        
+        Ok(recommendations)
+    }
+    
+    /// Schedule account moves on the beacon chain, one migration per pair of shards
+    ///
+    /// Accounts already due to move and shards taking part in a merge are
+    /// left out, and at most `MAX_ACCOUNT_MOVES_PER_EVALUATION` accounts are
+    /// scheduled.
+    fn schedule_account_moves(
+        &mut self,
+        moves: Vec<sharding::AccountMove>,
+        merging: &std::collections::HashSet<sharding::ShardId>,
+        activation_height: u64,
+    ) {
+        let moves: Vec<sharding::AccountMove> = moves.into_iter()
+            .filter(|account_move| !self.router.is_frozen(&account_move.account_id))
+            .filter(|account_move| !merging.contains(&account_move.from) && !merging.contains(&account_move.to))
+            .take(MAX_ACCOUNT_MOVES_PER_EVALUATION)
+            .collect();
+        
+        let mut groups: Vec<((sharding::ShardId, sharding::ShardId), Vec<types::AccountId>)> =
+            sharding::AccountMove::group(&moves).into_iter().collect();
+        groups.sort_by_key(|(pair, _)| *pair);
+        for ((source_shard, destination_shard), accounts) in groups {
+            log::info!("Scheduling {} accounts to move from shard {:?} to {:?}", accounts.len(), source_shard, destination_shard);
+            if let Err(e) = self.schedule_account_migration(source_shard, destination_shard, accounts, activation_height) {
+                log::warn!("Could not schedule account move from shard {:?} to {:?}: {}", source_shard, destination_shard, e);
+            }
+        }
+    }
+    
+    /// Propose account moves that cut cross-shard transactions, for periodic re-evaluation
+    ///
+    /// Shards may end up to 20% above the average load if that keeps
+    /// interacting accounts together.
+    pub fn propose_shard_migrations(&self, max_moves: usize) -> Vec<sharding::AccountMove> {
+        self.router.allocator().propose_migrations(max_moves, 0.2)
+    }
+    
+    /// Route a transaction submitted to this node and propagate it to peers
+    ///
+    /// The transaction is pooled with the shard that executes it, then
+    /// relayed on its source shard, privately first when Dandelion++ is
+    /// enabled.
+    pub fn route_transaction(&mut self, transaction: types::Transaction) -> utils::Result<sharding::Route> {
+        transaction.validate().map_err(utils::Error::network)?;
+        
+        let route = self.pool_transaction(transaction.clone())?;
+        let source_shard = match &route {
+            sharding::Route::IntraShard(shard_id) => *shard_id,
+            sharding::Route::CrossShard { source_shard, .. } => *source_shard,
+        };
+        self.protocol.submit_transaction(transaction, source_shard);
+        
+        Ok(route)
+    }
+    
+    /// Route a transaction into the mempool, whether submitted locally or received from peers
+    fn pool_transaction(&mut self, transaction: types::Transaction) -> utils::Result<sharding::Route> {
+        self.router.submit(transaction, &self.shards, self.protocol.mempool_mut(), &mut self.cross_shard, &self.database)
+            .map_err(utils::Error::sharding)
+    }
+    
+    /// Move accounts between shards as part of a scheduled rebalance and complete the operation
+    ///
+    /// The accounts have taken no new transactions since the move was
+    /// finalized on the beacon chain. Afterwards the router sends them to
+    /// their new shard and their pooled transactions are routed again. A move
+    /// that fails is recorded as a failed operation.
+    fn migrate_accounts(
+        &mut self,
+        operation_id: &str,
+        source_shard: sharding::ShardId,
+        destination_shard: sharding::ShardId,
+        accounts: Vec<types::AccountId>,
+    ) -> utils::Result<()> {
+        let source_index = self.shards.iter().position(|shard| shard.id() == source_shard)
+            .ok_or_else(|| utils::Error::sharding(format!("Unknown shard {:?}", source_shard)))?;
+        let destination_index = self.shards.iter().position(|shard| shard.id() == destination_shard)
+            .ok_or_else(|| utils::Error::sharding(format!("Unknown shard {:?}", destination_shard)))?;
+        if source_index == destination_index {
+            return Err(utils::Error::sharding("Accounts must move to a different shard"));
+        }
+        
+        // Borrow both shards mutably
+        let (low, high) = self.shards.split_at_mut(source_index.max(destination_index));
+        let (source, destination) = if source_index < destination_index {
+            (&mut low[source_index], &mut high[0])
+        } else {
+            (&mut high[0], &mut low[destination_index])
+        };
+        
+        let migrated = self.resharding.get_active_operation(operation_id)
+            .is_some_and(|operation| operation.events.iter().any(|event| matches!(event, sharding::ReshardingEvent::AccountsMigrated { .. })));
+        if !migrated {
+            let result = self.resharding.migrate_accounts(
+                operation_id,
+                accounts.clone(),
+                source,
+                destination,
+                &mut self.cross_shard,
+                &self.database,
+            );
+            if let Err(e) = result {
+                self.resharding.fail_resharding(operation_id, e.clone(), &self.database).map_err(utils::Error::sharding)?;
+                return Err(utils::Error::sharding(e));
+            }
+        }
+        
+        for account_id in &accounts {
+            self.router.relocate(account_id, destination_shard).map_err(utils::Error::sharding)?;
+        }
+        let moved: std::collections::HashSet<types::AccountId> = accounts.into_iter().collect();
+        self.router.reroute_pooled(&moved, self.protocol.mempool_mut(), &mut self.cross_shard, &self.database)
+            .map_err(utils::Error::sharding)?;
+        
+        self.resharding.complete_resharding(operation_id, vec![source_shard, destination_shard], &self.database)
+            .map_err(utils::Error::sharding)
+    }
+    
+    /// Merge a shard into another as part of a merge operation, retiring its ID
+    ///
+    /// The retiring shard is dropped from the shard list and the beacon
+    /// chain, and the operation completes with the surviving shard.
+    pub fn merge_shards(
+        &mut self,
+        operation_id: &str,
+        retiring_shard: sharding::ShardId,
+        surviving_shard: sharding::ShardId,
+    ) -> utils::Result<sharding::AccountMigration> {
+        let retiring_index = self.shards.iter().position(|shard| shard.id() == retiring_shard)
+            .ok_or_else(|| utils::Error::sharding(format!("Unknown shard {:?}", retiring_shard)))?;
+        let surviving_index = self.shards.iter().position(|shard| shard.id() == surviving_shard)
+            .ok_or_else(|| utils::Error::sharding(format!("Unknown shard {:?}", surviving_shard)))?;
+        if retiring_index == surviving_index {
+            return Err(utils::Error::sharding("A shard cannot be merged into itself"));
+        }
+        
+        // Pin accounts to the shard holding them before the shard set changes their default
+        self.router.place_from_states(&self.shards).map_err(utils::Error::sharding)?;
+        
+        // Borrow both shards mutably
+        let (low, high) = self.shards.split_at_mut(retiring_index.max(surviving_index));
+        let (retiring, surviving) = if retiring_index < surviving_index {
+            (&mut low[retiring_index], &mut high[0])
+        } else {
+            (&mut high[0], &mut low[surviving_index])
+        };
+        
+        self.resharding.begin_move(operation_id, retiring, surviving).map_err(utils::Error::sharding)?;
+        let result = self.resharding.merge_shards(
+            operation_id,
+            retiring,
+            surviving,
+            &mut self.cross_shard,
+            &self.database,
+        );
+        
+        let migration = match result {
+            Ok(migration) => migration,
+            Err(e) => {
+                self.resharding.finish_move(retiring, surviving);
+                return Err(utils::Error::sharding(e));
+            }
+        };
+        surviving.set_state(sharding::ShardState::Active);
+        self.router.retire_shard(&retiring_shard, surviving_shard, self.protocol.mempool_mut()).map_err(utils::Error::sharding)?;
+        
+        self.shards.remove(retiring_index);
+        self.beacon.remove_shard(&retiring_shard);
+        self.resharding.complete_resharding(operation_id, vec![surviving_shard], &self.database)
+            .map_err(utils::Error::sharding)?;
+        
+        Ok(migration)
+    }
+    
+    /// List cross-shard transfers that are still in flight
+    pub fn in_flight_cross_shard_transactions(&self) -> Vec<sharding::CrossShardTransaction> {
+        self.cross_shard.in_flight()
+    }
+    
+    /// Fail a stuck cross-shard transfer manually
+    pub fn force_fail_cross_shard_transaction(
+        &mut self,
+        transaction_id: &types::TransactionId,
+        reason: String,
+    ) -> utils::Result<sharding::CrossShardTransaction> {
+        log::warn!("Force-failing cross-shard transaction {:?}: {}", transaction_id, reason);
+        self.cross_shard.force_fail(transaction_id, reason, &self.database).map_err(utils::Error::sharding)
+    }
+    
+    /// List resharding operations running for longer than `max_age_secs`
+    pub fn stuck_resharding_operations(&self, max_age_secs: u64) -> Vec<&sharding::ReshardingOperation> {
+        self.resharding.stuck_operations(std::time::Duration::from_secs(max_age_secs))
+    }
+    
+    /// Fail a stuck resharding operation manually
+    pub fn force_fail_resharding_operation(&mut self, operation_id: &str, reason: String) -> utils::Result<()> {
+        log::warn!("Force-failing resharding operation {}: {}", operation_id, reason);
+        self.resharding.fail_resharding(operation_id, reason, &self.database).map_err(utils::Error::sharding)
+    }
+    
+    /// Add a validator to the consensus set
+    pub fn add_validator(&mut self, validator: consensus::Validator) -> utils::Result<()> {
+        self.consensus.add_validator(validator).map_err(utils::Error::from)?;
+        self.refresh_validator_keys();
+        self.derive_committees();
+        Ok(())
+    }
+    
+    /// Remove a validator from the consensus set
+    pub fn remove_validator(&mut self, public_key: &ed25519_dalek::VerifyingKey) {
+        self.consensus.remove_validator(public_key);
+        self.refresh_validator_keys();
+    }
+    
+    /// Give the protocol the current validator keys if the set or epoch changed since it last got them
+    fn refresh_validator_keys(&mut self) {
+        let version = self.consensus.validator_set_version();
+        if self.validator_keys_version == Some(version) {
+            return;
+        }
+        
+        let validator_keys = self.consensus.validator_set().validators().iter()
+            .map(|validator| validator.public_key().to_bytes());
+        self.protocol.set_validators(validator_keys);
+        self.validator_keys_version = Some(version);
+        
+        if self.consensus.validator_set().is_empty() {
+            log::warn!("Validator set is empty; consensus messages are rejected until validators are added");
+        }
+    }
+    
+    /// Persist the active peer bans, e.g. after a `PeerBanned` event
+    pub fn persist_peer_bans(&mut self) -> utils::Result<()> {
+        self.protocol.scoring_mut().save_bans(&self.database).map_err(utils::Error::database)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::VerifyingKey;
+    use utils::crypto::KeyPair;
+    
+    /// A node storing its data in a temporary directory
+    fn blockchain() -> (tempfile::TempDir, Blockchain) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = utils::Config::default();
+        config.storage.db_path = dir.path().to_path_buf();
+        (dir, Blockchain::new(config).unwrap())
+    }
+    
+    /// A signed block of shard 0 following a parent, with one transaction
+    fn block(keypair: &KeyPair, height: u64, prev_block: types::BlockId) -> (types::Block, Vec<types::Transaction>) {
+        let validator = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+        let transaction = types::Transaction::new(types::TransactionType::Stake { amount: height }, validator, height, 1, 1);
+        let mut block = types::Block::new(height, prev_block, vec![transaction.id()], types::StateRoot([0; 32]), validator, 0);
+        block.header.sign(keypair).unwrap();
+        (block, vec![transaction])
+    }
+    
+    #[test]
+    fn imports_blocks_that_extend_the_best_block() {
+        let (_dir, mut blockchain) = blockchain();
+        let keypair = KeyPair::generate();
+        let first = block(&keypair, 1, blockchain.best_block.1.clone());
+        let second = block(&keypair, 2, first.0.id());
+        let head = second.0.id();
+        
+        blockchain.import_blocks(vec![first.clone(), second]).unwrap();
+        
+        assert_eq!(blockchain.best_block, (2, head));
+        assert!(blockchain.load_block(&first.0.id()).unwrap().is_some());
+        assert!(blockchain.shards[0].get_block(&first.0.id()).is_some());
+    }
+    
+    #[test]
+    fn leaves_no_trace_of_a_batch_with_a_bad_block() {
+        let (_dir, mut blockchain) = blockchain();
+        let keypair = KeyPair::generate();
+        let genesis = blockchain.best_block.clone();
+        let first = block(&keypair, 1, genesis.1.clone());
+        let unlinked = block(&keypair, 2, types::BlockId([9; 32]));
+        let (mut mismatched, _) = block(&keypair, 2, first.0.id());
+        mismatched.transactions.clear();
+        let mismatched = (mismatched, first.1.clone());
+        
+        for bad in [unlinked, mismatched] {
+            assert!(blockchain.import_blocks(vec![first.clone(), bad]).is_err());
+            assert_eq!(blockchain.best_block, genesis);
+            assert!(blockchain.load_block(&first.0.id()).unwrap().is_none());
+            assert!(blockchain.shards[0].get_block(&first.0.id()).is_none());
+        }
+    }
+    
+    #[test]
+    fn refuses_blocks_already_in_their_shard() {
+        let (_dir, mut blockchain) = blockchain();
+        let keypair = KeyPair::generate();
+        let genesis = blockchain.best_block.clone();
+        let first = block(&keypair, 1, genesis.1.clone());
+        blockchain.shards[0].add_block(first.0.clone()).unwrap();
+        
+        assert!(blockchain.import_blocks(vec![first]).is_err());
+        assert_eq!(blockchain.best_block, genesis);
+    }
+    
+    #[test]
+    fn routes_received_transactions_into_a_single_pool() {
+        let (_dir, mut blockchain) = blockchain();
+        let keypair = KeyPair::generate();
+        let (_, transactions) = block(&keypair, 1, blockchain.best_block.1.clone());
+        let transaction = transactions[0].clone();
+        let sharding::Route::IntraShard(shard_id) = blockchain.router.route(&transaction).unwrap() else {
+            panic!("Staking stays within the sender's shard");
+        };
+        
+        let received = || network::ProtocolEvent::TransactionReceived {
+            peer_id: libp2p::PeerId::random(),
+            transaction: transaction.clone(),
+        };
+        blockchain.handle_protocol_events(vec![received(), received()]).unwrap();
+        
+        let mempool = blockchain.protocol.mempool();
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.shard_of(&transaction.id()), Some(shard_id));
+        assert_eq!(mempool.shard_len(&shard_id), 1);
+    }
+    
+    #[test]
+    fn finalizes_beacon_blocks_from_precommits_and_reshuffles_at_epoch_start() {
+        let (_dir, mut blockchain) = blockchain();
+        let keypairs: Vec<KeyPair> = (0..4).map(|_| KeyPair::generate()).collect();
+        for keypair in &keypairs {
+            let public_key = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+            let validator = consensus::Validator::new(public_key, 1_000_000, "validator".to_string(), None, None, None);
+            blockchain.consensus.add_validator(validator).unwrap();
+        }
+        blockchain.derive_committees();
+        assert_eq!(blockchain.consensus.committees().current_epoch(), Some(0));
+        
+        let epoch_length = blockchain.consensus.committees().config().epoch_length;
+        let mut block = blockchain.beacon.head().clone();
+        while block.height < epoch_length {
+            block = blockchain.produce_beacon_block().unwrap();
+        }
+        
+        for (index, keypair) in keypairs.iter().enumerate().take(3) {
+            assert_eq!(blockchain.beacon.finalized_height(), 0, "finalized after {} votes", index);
+            let mut vote = consensus::Vote { height: block.height, round: 0, block_id: Some(block.id()), finality_signature: None };
+            vote.sign_finality(keypair);
+            blockchain.add_finality_vote(&keypair.public_key(), &vote).unwrap();
+        }
+        
+        assert_eq!(blockchain.beacon.finalized_height(), epoch_length);
+        assert_eq!(blockchain.consensus.committees().current_epoch(), Some(1));
+    }
+    
+    #[test]
+    fn merges_retire_a_shard_and_move_its_inbox_to_the_survivor() {
+        let (_dir, mut blockchain) = blockchain();
+        let keypair = KeyPair::generate();
+        let public_key = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+        blockchain.consensus.add_validator(consensus::Validator::new(public_key, 1_000_000, "validator".to_string(), None, None, None)).unwrap();
+        
+        // Empty shards stay underloaded until merges are scheduled for the next epoch
+        for _ in 0..10 {
+            if blockchain.beacon.has_pending() {
+                break;
+            }
+            blockchain.evaluate_resharding().unwrap();
+        }
+        let scheduled = blockchain.produce_beacon_block().unwrap().resharding;
+        let merge = scheduled.first().expect("Idle shards are merged").clone();
+        let (surviving, retiring) = (merge.shards[0], merge.shards[1]);
+        let source = blockchain.shards.iter()
+            .map(|shard| shard.id())
+            .find(|shard_id| scheduled.iter().all(|scheduled| !scheduled.shards.contains(shard_id)))
+            .expect("A shard stays out of the merges");
+        
+        // The retiring shard credited a receipt from a shard that stays live
+        let inbox = sharding::inbox_account(source);
+        let mut credited = types::Account::new_user(inbox.clone());
+        credited.storage.push((b"receipt".to_vec(), vec![1]));
+        let retiring_shard = blockchain.shards.iter_mut().find(|shard| shard.id() == retiring).unwrap();
+        retiring_shard.account_state_mut().apply_update(types::StateUpdate::CreateAccount(credited));
+        
+        let mut block = blockchain.beacon.head().clone();
+        while block.height < merge.activation_height {
+            block = blockchain.produce_beacon_block().unwrap();
+        }
+        let mut vote = consensus::Vote { height: block.height, round: 0, block_id: Some(block.id()), finality_signature: None };
+        vote.sign_finality(&keypair);
+        blockchain.add_finality_vote(&keypair.public_key(), &vote).unwrap();
+        
+        assert!(blockchain.shards.iter().all(|shard| shard.id() != retiring));
+        assert!(!blockchain.router.allocator().has_shard(&retiring));
+        assert!(blockchain.resharding.retired_shards().contains(&retiring));
+        
+        let survivor = blockchain.shards.iter().find(|shard| shard.id() == surviving).unwrap();
+        let record = survivor.account_state().accounts.iter().find(|account| account.id == inbox).unwrap();
+        assert!(record.storage.contains(&(b"receipt".to_vec(), vec![1])));
+    }
 }
@@ -4,7 +4,7 @@
 //! It parses command-line arguments and starts the blockchain node.
 
 use clap::{Command, Arg};
-use optimachain::{init, utils, VERSION};
+use optimachain::{init, utils, Block, VERSION};
 use std::path::PathBuf;
 use std::process;
 
@@ -221,8 +221,11 @@ fn run_blockchain(config: utils::Config) -> utils::Result<()> {
     })
     .map_err(|e| utils::Error::other(format!("Failed to set Ctrl+C handler: {}", e)))?;
 
-    // Keep the main thread alive
+    // Drive the node until shutdown
     loop {
+        if let Err(err) = blockchain.tick() {
+            eprintln!("Node maintenance failed: {}", err);
+        }
         std::thread::sleep(std::time::Duration::from_secs(1));
     }
 }
@@ -267,12 +270,18 @@ fn import_genesis(config: &utils::Config, input: &PathBuf) -> utils::Result<()>
     // Read the genesis block
     let genesis_block = std::fs::read_to_string(input)
         .map_err(|e| utils::Error::io(e))?;
+    let genesis_block: Block = serde_json::from_str(&genesis_block)
+        .map_err(|e| utils::Error::config(format!("Invalid genesis block: {}", e)))?;
+    if genesis_block.header.height != 0 {
+        return Err(utils::Error::config("Genesis block must be at height 0"));
+    }
 
     // Initialize the blockchain
-    let blockchain = init(config.clone())?;
+    let mut blockchain = init(config.clone())?;
 
-    // In a real implementation, we would import the genesis block into the blockchain
-    println!("Genesis block imported successfully.");
+    // Record the genesis block; until then the node refuses all peers
+    blockchain.set_genesis(genesis_block.id())?;
+    println!("Genesis block {} imported successfully.", hex::encode(genesis_block.id().0));
 
     Ok(())
 }
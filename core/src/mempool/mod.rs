@@ -0,0 +1,7 @@
+//! Mempool module for OptimaChain
+//!
+//! This module holds transactions that were received but not yet included in a block.
+
+mod pool;
+
+pub use pool::{Mempool, MempoolConfig};
@@ -0,0 +1,166 @@
+use crate::sharding::ShardId;
+use crate::types::{Transaction, TransactionId};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Configuration for the mempool
+#[derive(Debug, Clone)]
+pub struct MempoolConfig {
+    /// Maximum number of pending transactions
+    pub max_transactions: usize,
+    /// Time after which a pending transaction is dropped, in seconds
+    pub max_age: u64,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        MempoolConfig {
+            max_transactions: 10_000,
+            max_age: 3600,
+        }
+    }
+}
+
+/// Pool of transactions waiting to be included in a block
+///
+/// Each transaction is kept with the shard it was routed to, which executes
+/// it; cross-shard transfers are kept with their source shard.
+pub struct Mempool {
+    /// Configuration
+    config: MempoolConfig,
+    /// Pending transactions with their shard and the time they were added
+    transactions: HashMap<TransactionId, (Transaction, ShardId, Instant)>,
+    /// IDs in insertion order; entries whose time no longer matches are stale
+    order: VecDeque<(TransactionId, Instant)>,
+}
+
+impl Mempool {
+    /// Create a new mempool
+    pub fn new(config: MempoolConfig) -> Self {
+        Mempool {
+            config,
+            transactions: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+    
+    /// Get the configuration
+    pub fn config(&self) -> &MempoolConfig {
+        &self.config
+    }
+    
+    /// Add a transaction routed to a shard, returning false if it was not added
+    ///
+    /// When the pool is full the transaction replaces the one with the lowest
+    /// gas price if it pays more, and is refused otherwise, so flooding the
+    /// pool cannot push out better-paying transactions.
+    pub fn insert(&mut self, transaction: Transaction, shard_id: ShardId) -> bool {
+        let transaction_id = transaction.id();
+        if self.transactions.contains_key(&transaction_id) {
+            return false;
+        }
+        
+        if self.transactions.len() >= self.config.max_transactions.max(1) {
+            let cheapest = self.transactions.iter()
+                .min_by_key(|(_, (pending, _, added_at))| (pending.gas_price, std::cmp::Reverse(*added_at)))
+                .map(|(id, (pending, _, _))| (id.clone(), pending.gas_price));
+            
+            match cheapest {
+                Some((cheapest_id, gas_price)) if gas_price < transaction.gas_price => {
+                    self.remove(&cheapest_id);
+                }
+                _ => return false,
+            }
+        }
+        
+        let now = Instant::now();
+        self.transactions.insert(transaction_id.clone(), (transaction, shard_id, now));
+        self.order.push_back((transaction_id, now));
+        
+        // Removed IDs leave stale entries behind; compact before they pile up
+        if self.order.len() > 2 * self.config.max_transactions.max(1) {
+            let transactions = &self.transactions;
+            self.order.retain(|(id, added_at)| transactions.get(id).map(|(_, _, time)| time) == Some(added_at));
+        }
+        
+        true
+    }
+    
+    /// Get a pending transaction
+    pub fn get(&self, transaction_id: &TransactionId) -> Option<&Transaction> {
+        self.transactions.get(transaction_id).map(|(transaction, _, _)| transaction)
+    }
+    
+    /// Get the shard a pending transaction was routed to
+    pub fn shard_of(&self, transaction_id: &TransactionId) -> Option<ShardId> {
+        self.transactions.get(transaction_id).map(|(_, shard_id, _)| *shard_id)
+    }
+    
+    /// Check if a transaction is pending
+    pub fn contains(&self, transaction_id: &TransactionId) -> bool {
+        self.transactions.contains_key(transaction_id)
+    }
+    
+    /// Remove a pending transaction
+    pub fn remove(&mut self, transaction_id: &TransactionId) -> Option<Transaction> {
+        self.transactions.remove(transaction_id).map(|(transaction, _, _)| transaction)
+    }
+    
+    /// Remove the transactions included in a block
+    pub fn remove_included(&mut self, transaction_ids: &[TransactionId]) {
+        for transaction_id in transaction_ids {
+            self.transactions.remove(transaction_id);
+        }
+    }
+    
+    /// Get the pending transactions in insertion order
+    pub fn transactions(&self) -> Vec<&Transaction> {
+        self.order.iter()
+            .filter_map(|(id, added_at)| match self.transactions.get(id) {
+                Some((transaction, _, time)) if time == added_at => Some(transaction),
+                _ => None,
+            })
+            .collect()
+    }
+    
+    /// Get the number of pending transactions of a shard
+    pub fn shard_len(&self, shard_id: &ShardId) -> usize {
+        self.transactions.values().filter(|(_, pending_shard, _)| pending_shard == shard_id).count()
+    }
+    
+    /// Hand the pending transactions of one shard to another, e.g. when shards merge
+    pub fn move_shard(&mut self, from: &ShardId, to: ShardId) {
+        for (_, shard_id, _) in self.transactions.values_mut() {
+            if shard_id == from {
+                *shard_id = to;
+            }
+        }
+    }
+    
+    /// Get the number of pending transactions
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+    
+    /// Check if the pool is empty
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+    
+    /// Drop transactions older than the maximum age
+    pub fn prune(&mut self) {
+        let max_age = Duration::from_secs(self.config.max_age);
+        
+        while let Some((_, added_at)) = self.order.front() {
+            if added_at.elapsed() < max_age {
+                break;
+            }
+            
+            if let Some((transaction_id, added_at)) = self.order.pop_front() {
+                if self.transactions.get(&transaction_id).map(|(_, _, time)| time) == Some(&added_at) {
+                    self.transactions.remove(&transaction_id);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use crate::network::Message;
+use bincode::Options;
+use std::fmt;
+use std::io::Read;
+
+/// Version of the frame format
+pub const WIRE_VERSION: u8 = 1;
+
+/// Size of the frame header: version, flags and a big-endian u32 body length
+pub const FRAME_HEADER_SIZE: usize = 6;
+
+/// Flag set when the frame body is zstd-compressed
+const FLAG_COMPRESSED: u8 = 0x01;
+
+/// Bodies smaller than this are never worth compressing
+const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// zstd compression level
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Errors produced while encoding or decoding frames
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    /// Frame uses a wire version we do not understand
+    UnsupportedVersion(u8),
+    /// Frame has flags we do not understand
+    UnknownFlags(u8),
+    /// Compressed frame from a peer that did not negotiate compression
+    CompressionNotNegotiated,
+    /// Message exceeds the maximum size
+    TooLarge {
+        /// Size of the message
+        size: usize,
+        /// Maximum allowed size
+        max: usize,
+    },
+    /// Decoded body would exceed the maximum size
+    SizeLimitExceeded {
+        /// Maximum allowed size
+        max: usize,
+    },
+    /// Compression or decompression failed
+    Compression(String),
+    /// Message could not be serialized or deserialized
+    Serialization(String),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::UnsupportedVersion(version) => write!(f, "Unsupported wire version {}", version),
+            CodecError::UnknownFlags(flags) => write!(f, "Unknown frame flags {:#04x}", flags),
+            CodecError::CompressionNotNegotiated => write!(f, "Compressed frame without negotiated compression"),
+            CodecError::TooLarge { size, max } => write!(f, "Message of {} bytes exceeds maximum of {}", size, max),
+            CodecError::SizeLimitExceeded { max } => write!(f, "Message exceeds maximum of {} bytes", max),
+            CodecError::Compression(e) => write!(f, "Compression error: {}", e),
+            CodecError::Serialization(e) => write!(f, "Serialization error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Protocol codec for serializing and deserializing messages
+///
+/// Each message is sent as a frame: a version byte, a flags byte, the body
+/// length as a big-endian u32 and the bincode-encoded body, optionally
+/// compressed with zstd. All sizes are checked against `max_size` before
+/// any buffer is allocated.
+#[derive(Debug, Clone)]
+pub struct ProtocolCodec {
+    /// Maximum message size
+    max_size: usize,
+}
+
+impl ProtocolCodec {
+    /// Create a new codec
+    pub fn new(max_size: usize) -> Self {
+        ProtocolCodec { max_size }
+    }
+    
+    /// Get the maximum message size
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+    
+    /// Encode a message into a frame, compressing large bodies if allowed
+    pub fn encode(&self, message: &Message, compress: bool) -> Result<Vec<u8>, CodecError> {
+        let body = self.options()
+            .serialize(message)
+            .map_err(|e| self.map_bincode_error(*e))?;
+        
+        let (flags, body) = if compress && body.len() >= COMPRESSION_THRESHOLD {
+            let compressed = zstd::bulk::compress(&body, COMPRESSION_LEVEL)
+                .map_err(|e| CodecError::Compression(e.to_string()))?;
+            
+            if compressed.len() < body.len() {
+                (FLAG_COMPRESSED, compressed)
+            } else {
+                (0, body)
+            }
+        } else {
+            (0, body)
+        };
+        
+        let length = u32::try_from(body.len())
+            .map_err(|_| CodecError::TooLarge { size: body.len(), max: self.max_size })?;
+        
+        let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + body.len());
+        frame.push(WIRE_VERSION);
+        frame.push(flags);
+        frame.extend_from_slice(&length.to_be_bytes());
+        frame.extend_from_slice(&body);
+        
+        Ok(frame)
+    }
+    
+    /// Decode the first frame in a buffer
+    ///
+    /// Returns `Ok(None)` if the buffer does not yet hold a complete frame,
+    /// otherwise the message and the number of bytes consumed.
+    pub fn decode(&self, buffer: &[u8], allow_compression: bool) -> Result<Option<(Message, usize)>, CodecError> {
+        if buffer.len() < FRAME_HEADER_SIZE {
+            return Ok(None);
+        }
+        
+        let version = buffer[0];
+        if version != WIRE_VERSION {
+            return Err(CodecError::UnsupportedVersion(version));
+        }
+        
+        let flags = buffer[1];
+        if flags & !FLAG_COMPRESSED != 0 {
+            return Err(CodecError::UnknownFlags(flags));
+        }
+        
+        let length = u32::from_be_bytes([buffer[2], buffer[3], buffer[4], buffer[5]]) as usize;
+        if length > self.max_size {
+            return Err(CodecError::TooLarge { size: length, max: self.max_size });
+        }
+        
+        let end = FRAME_HEADER_SIZE + length;
+        if buffer.len() < end {
+            return Ok(None);
+        }
+        
+        let body = &buffer[FRAME_HEADER_SIZE..end];
+        
+        let message = if flags & FLAG_COMPRESSED != 0 {
+            if !allow_compression {
+                return Err(CodecError::CompressionNotNegotiated);
+            }
+            
+            // Decompress incrementally and stop past the maximum size, so a
+            // compression bomb fails without a large up-front allocation
+            let decoder = zstd::stream::read::Decoder::new(body)
+                .map_err(|e| CodecError::Compression(e.to_string()))?;
+            
+            let mut decompressed = Vec::new();
+            decoder.take(self.max_size as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| CodecError::Compression(e.to_string()))?;
+            
+            if decompressed.len() > self.max_size {
+                return Err(CodecError::SizeLimitExceeded { max: self.max_size });
+            }
+            
+            self.deserialize(&decompressed)?
+        } else {
+            self.deserialize(body)?
+        };
+        
+        Ok(Some((message, end)))
+    }
+    
+    /// Deserialize a frame body
+    fn deserialize(&self, body: &[u8]) -> Result<Message, CodecError> {
+        self.options()
+            .deserialize(body)
+            .map_err(|e| self.map_bincode_error(*e))
+    }
+    
+    /// Bincode options bounded by the maximum message size
+    fn options(&self) -> impl Options {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(self.max_size as u64)
+    }
+    
+    /// Map a bincode error, keeping size limit violations distinct
+    fn map_bincode_error(&self, error: bincode::ErrorKind) -> CodecError {
+        match error {
+            bincode::ErrorKind::SizeLimit => CodecError::SizeLimitExceeded { max: self.max_size },
+            other => CodecError::Serialization(other.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::MessageType;
+    
+    const MAX_SIZE: usize = 64 * 1024;
+    
+    /// A message whose body is large and compresses well
+    fn large_message() -> Message {
+        let addresses = (0..200).map(|i| format!("/ip4/10.0.0.{}/tcp/30333", i % 8)).collect();
+        Message::new(MessageType::DiscoveryMessage { addresses }, 1)
+    }
+    
+    /// A frame header announcing a body of the given length
+    fn header(flags: u8, length: u32) -> Vec<u8> {
+        let mut frame = vec![WIRE_VERSION, flags];
+        frame.extend_from_slice(&length.to_be_bytes());
+        frame
+    }
+    
+    #[test]
+    fn round_trips_plain_and_compressed_frames() {
+        let codec = ProtocolCodec::new(MAX_SIZE);
+        let message = large_message();
+        
+        for compress in [false, true] {
+            let frame = codec.encode(&message, compress).unwrap();
+            assert_eq!(frame[1] & FLAG_COMPRESSED != 0, compress);
+            
+            let (decoded, consumed) = codec.decode(&frame, true).unwrap().unwrap();
+            assert_eq!(consumed, frame.len());
+            assert_eq!(decoded.id, message.id);
+            assert!(decoded.has_valid_id());
+        }
+    }
+    
+    #[test]
+    fn decodes_frames_one_at_a_time() {
+        let codec = ProtocolCodec::new(MAX_SIZE);
+        let first = Message::new(MessageType::DiscoveryMessage { addresses: vec!["a".to_string()] }, 1);
+        let second = Message::new(MessageType::DiscoveryMessage { addresses: vec!["b".to_string()] }, 1);
+        
+        let mut buffer = codec.encode(&first, false).unwrap();
+        buffer.extend(codec.encode(&second, false).unwrap());
+        
+        let (decoded, consumed) = codec.decode(&buffer, false).unwrap().unwrap();
+        assert_eq!(decoded.id, first.id);
+        let (decoded, _) = codec.decode(&buffer[consumed..], false).unwrap().unwrap();
+        assert_eq!(decoded.id, second.id);
+    }
+    
+    #[test]
+    fn waits_for_truncated_frames() {
+        let codec = ProtocolCodec::new(MAX_SIZE);
+        let frame = codec.encode(&large_message(), true).unwrap();
+        
+        assert!(matches!(codec.decode(&frame[..FRAME_HEADER_SIZE - 1], true), Ok(None)));
+        assert!(matches!(codec.decode(&frame[..FRAME_HEADER_SIZE], true), Ok(None)));
+        assert!(matches!(codec.decode(&frame[..frame.len() - 1], true), Ok(None)));
+    }
+    
+    #[test]
+    fn rejects_oversize_length_headers_before_the_body_arrives() {
+        let codec = ProtocolCodec::new(MAX_SIZE);
+        
+        let frame = header(0, MAX_SIZE as u32 + 1);
+        assert_eq!(codec.decode(&frame, true).unwrap_err(), CodecError::TooLarge { size: MAX_SIZE + 1, max: MAX_SIZE });
+        
+        let frame = header(0, u32::MAX);
+        assert!(matches!(codec.decode(&frame, true), Err(CodecError::TooLarge { .. })));
+    }
+    
+    #[test]
+    fn rejects_oversize_messages_on_encode() {
+        let codec = ProtocolCodec::new(1024);
+        assert_eq!(codec.encode(&large_message(), false).unwrap_err(), CodecError::SizeLimitExceeded { max: 1024 });
+    }
+    
+    #[test]
+    fn stops_decompression_bombs_at_the_size_limit() {
+        let codec = ProtocolCodec::new(MAX_SIZE);
+        
+        // A few hundred bytes that expand to many times the limit
+        let bomb = zstd::bulk::compress(&vec![0u8; MAX_SIZE * 16], COMPRESSION_LEVEL).unwrap();
+        assert!(bomb.len() < MAX_SIZE);
+        
+        let mut frame = header(FLAG_COMPRESSED, bomb.len() as u32);
+        frame.extend_from_slice(&bomb);
+        
+        assert_eq!(codec.decode(&frame, true).unwrap_err(), CodecError::SizeLimitExceeded { max: MAX_SIZE });
+    }
+    
+    #[test]
+    fn rejects_compression_that_was_not_negotiated() {
+        let codec = ProtocolCodec::new(MAX_SIZE);
+        let frame = codec.encode(&large_message(), true).unwrap();
+        assert_eq!(frame[1], FLAG_COMPRESSED);
+        
+        assert_eq!(codec.decode(&frame, false).unwrap_err(), CodecError::CompressionNotNegotiated);
+    }
+    
+    #[test]
+    fn rejects_unknown_versions_and_flags() {
+        let codec = ProtocolCodec::new(MAX_SIZE);
+        let frame = codec.encode(&large_message(), false).unwrap();
+        
+        let mut wrong_version = frame.clone();
+        wrong_version[0] = WIRE_VERSION + 1;
+        assert_eq!(codec.decode(&wrong_version, true).unwrap_err(), CodecError::UnsupportedVersion(WIRE_VERSION + 1));
+        
+        let mut wrong_flags = frame;
+        wrong_flags[1] = 0x80;
+        assert_eq!(codec.decode(&wrong_flags, true).unwrap_err(), CodecError::UnknownFlags(0x80));
+    }
+    
+    #[test]
+    fn rejects_corrupt_bodies() {
+        let codec = ProtocolCodec::new(MAX_SIZE);
+        
+        let mut frame = header(0, 4);
+        frame.extend_from_slice(&[0xff; 4]);
+        assert!(matches!(codec.decode(&frame, true), Err(CodecError::Serialization(_))));
+        
+        let mut frame = header(FLAG_COMPRESSED, 4);
+        frame.extend_from_slice(&[0xff; 4]);
+        assert!(matches!(codec.decode(&frame, true), Err(CodecError::Compression(_))));
+    }
+}
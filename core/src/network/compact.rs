@@ -0,0 +1,371 @@
+use crate::mempool::Mempool;
+use crate::types::{Block, BlockId, Transaction, TransactionId};
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Configuration for compact block relay
+#[derive(Debug, Clone)]
+pub struct CompactBlockConfig {
+    /// Time to wait for missing transactions before asking for the full body, in seconds
+    pub request_timeout: u64,
+    /// Maximum number of blocks awaiting transactions
+    pub max_pending_blocks: usize,
+    /// Maximum number of transactions served for one transaction request
+    pub max_transactions_per_request: usize,
+}
+
+impl Default for CompactBlockConfig {
+    fn default() -> Self {
+        CompactBlockConfig {
+            request_timeout: 5,
+            max_pending_blocks: 64,
+            max_transactions_per_request: 1024,
+        }
+    }
+}
+
+/// Result of reconstructing an announced block
+#[derive(Debug, Clone)]
+pub enum Reconstruction {
+    /// All transactions were found
+    Complete {
+        /// The block
+        block: Box<Block>,
+        /// Its transactions, in block order
+        transactions: Vec<Transaction>,
+    },
+    /// Some transactions must be requested from the announcing peer
+    Missing(Vec<TransactionId>),
+    /// The block is already being reconstructed
+    Pending,
+}
+
+/// Follow-up needed for a block whose transactions did not arrive in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompactAction {
+    /// Ask the peer for the full block body
+    RequestBody {
+        /// Peer that announced the block
+        peer_id: PeerId,
+        /// Block ID
+        block_id: BlockId,
+    },
+    /// The peer did not deliver the body either; the block is dropped
+    Failed {
+        /// Peer that announced the block
+        peer_id: PeerId,
+        /// Block ID
+        block_id: BlockId,
+    },
+}
+
+/// A reconstructed block with its transactions
+#[derive(Debug, Clone)]
+pub struct CompleteBlock {
+    /// Peer that announced the block
+    pub peer_id: PeerId,
+    /// The block
+    pub block: Block,
+    /// Its transactions, in block order
+    pub transactions: Vec<Transaction>,
+}
+
+/// A block waiting for transactions
+struct PendingBlock {
+    /// Peer that announced the block
+    peer_id: PeerId,
+    /// The block
+    block: Block,
+    /// Transactions found so far, in block order
+    transactions: Vec<Option<Transaction>>,
+    /// Transactions still missing
+    missing: HashSet<TransactionId>,
+    /// When the last request for the block was sent
+    requested_at: Instant,
+    /// Whether the full body was already requested
+    body_requested: bool,
+}
+
+impl PendingBlock {
+    /// Take the block and its transactions once nothing is missing
+    fn into_complete(self) -> CompleteBlock {
+        CompleteBlock {
+            peer_id: self.peer_id,
+            block: self.block,
+            transactions: self.transactions.into_iter().flatten().collect(),
+        }
+    }
+}
+
+/// Rebuilds announced blocks from the mempool and tracks missing transactions
+///
+/// Announcements carry only transaction IDs. Whatever the mempool already
+/// holds is filled in locally and only the rest is requested; if that does
+/// not arrive in time, the full body is requested instead.
+pub struct BlockReconstructor {
+    /// Configuration
+    config: CompactBlockConfig,
+    /// Blocks waiting for transactions
+    pending: HashMap<BlockId, PendingBlock>,
+}
+
+impl BlockReconstructor {
+    /// Create a new reconstructor
+    pub fn new(config: CompactBlockConfig) -> Self {
+        BlockReconstructor {
+            config,
+            pending: HashMap::new(),
+        }
+    }
+    
+    /// Reconstruct an announced block from the mempool
+    pub fn reconstruct(&mut self, peer_id: PeerId, block: Block, mempool: &Mempool) -> Reconstruction {
+        let block_id = block.id();
+        if self.pending.contains_key(&block_id) {
+            return Reconstruction::Pending;
+        }
+        
+        let transactions: Vec<Option<Transaction>> = block.transactions.iter()
+            .map(|transaction_id| mempool.get(transaction_id).cloned())
+            .collect();
+        
+        let missing: HashSet<TransactionId> = block.transactions.iter()
+            .zip(&transactions)
+            .filter(|(_, transaction)| transaction.is_none())
+            .map(|(transaction_id, _)| transaction_id.clone())
+            .collect();
+        
+        if missing.is_empty() {
+            return Reconstruction::Complete {
+                block: Box::new(block),
+                transactions: transactions.into_iter().flatten().collect(),
+            };
+        }
+        
+        // Make room by dropping the oldest pending block
+        if self.pending.len() >= self.config.max_pending_blocks {
+            let oldest = self.pending.iter()
+                .min_by_key(|(_, pending)| pending.requested_at)
+                .map(|(block_id, _)| block_id.clone());
+            if let Some(oldest) = oldest {
+                self.pending.remove(&oldest);
+            }
+        }
+        
+        let request = missing.iter().cloned().collect();
+        self.pending.insert(block_id, PendingBlock {
+            peer_id,
+            block,
+            transactions,
+            missing,
+            requested_at: Instant::now(),
+            body_requested: false,
+        });
+        
+        Reconstruction::Missing(request)
+    }
+    
+    /// Fill in received transactions, returning the blocks they completed
+    pub fn on_transactions(&mut self, transactions: &[Transaction]) -> Vec<CompleteBlock> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+        
+        let by_id: HashMap<TransactionId, &Transaction> = transactions.iter()
+            .map(|transaction| (transaction.id(), transaction))
+            .collect();
+        
+        let mut completed = Vec::new();
+        for (block_id, pending) in self.pending.iter_mut() {
+            if !pending.missing.iter().any(|transaction_id| by_id.contains_key(transaction_id)) {
+                continue;
+            }
+            
+            for (transaction_id, slot) in pending.block.transactions.iter().zip(pending.transactions.iter_mut()) {
+                if slot.is_none() {
+                    if let Some(transaction) = by_id.get(transaction_id) {
+                        *slot = Some((*transaction).clone());
+                        pending.missing.remove(transaction_id);
+                    }
+                }
+            }
+            
+            if pending.missing.is_empty() {
+                completed.push(block_id.clone());
+            }
+        }
+        
+        completed.into_iter()
+            .filter_map(|block_id| self.pending.remove(&block_id))
+            .map(PendingBlock::into_complete)
+            .collect()
+    }
+    
+    /// Handle a full block body
+    ///
+    /// Returns `Ok(None)` if the block was not awaited and an error if the
+    /// body does not match the block's transaction list.
+    pub fn on_block_body(
+        &mut self,
+        peer_id: &PeerId,
+        block_id: &BlockId,
+        transactions: Vec<Transaction>,
+    ) -> Result<Option<CompleteBlock>, String> {
+        let matches = match self.pending.get(block_id) {
+            Some(pending) if pending.peer_id == *peer_id => {
+                pending.block.transactions.len() == transactions.len()
+                    && pending.block.transactions.iter()
+                        .zip(&transactions)
+                        .all(|(transaction_id, transaction)| transaction.id() == *transaction_id)
+            }
+            _ => return Ok(None),
+        };
+        
+        if !matches {
+            return Err("Block body does not match the block's transactions".to_string());
+        }
+        
+        Ok(self.pending.remove(block_id).map(|pending| CompleteBlock {
+            peer_id: pending.peer_id,
+            block: pending.block,
+            transactions,
+        }))
+    }
+    
+    /// Check if a block is waiting for transactions
+    pub fn is_pending(&self, block_id: &BlockId) -> bool {
+        self.pending.contains_key(block_id)
+    }
+    
+    /// Find blocks whose requests timed out
+    ///
+    /// The first timeout falls back to requesting the full body; a second one
+    /// drops the block.
+    pub fn check_timeouts(&mut self) -> Vec<CompactAction> {
+        let timeout = Duration::from_secs(self.config.request_timeout);
+        let mut actions = Vec::new();
+        
+        for (block_id, pending) in self.pending.iter_mut() {
+            if pending.requested_at.elapsed() < timeout {
+                continue;
+            }
+            
+            if pending.body_requested {
+                actions.push(CompactAction::Failed {
+                    peer_id: pending.peer_id,
+                    block_id: block_id.clone(),
+                });
+            } else {
+                pending.body_requested = true;
+                pending.requested_at = Instant::now();
+                actions.push(CompactAction::RequestBody {
+                    peer_id: pending.peer_id,
+                    block_id: block_id.clone(),
+                });
+            }
+        }
+        
+        for action in &actions {
+            if let CompactAction::Failed { block_id, .. } = action {
+                self.pending.remove(block_id);
+            }
+        }
+        
+        actions
+    }
+    
+    /// Forget blocks announced by a disconnected peer
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.pending.retain(|_, pending| pending.peer_id != *peer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::MempoolConfig;
+    use crate::sharding::ShardId;
+    use crate::types::{StateRoot, TransactionType};
+    use crate::utils::crypto::KeyPair;
+    use ed25519_dalek::VerifyingKey;
+    
+    /// A block with `count` transactions
+    fn block(count: u64) -> (Block, Vec<Transaction>) {
+        let validator = VerifyingKey::from_bytes(&KeyPair::generate().public_key()).unwrap();
+        let transactions: Vec<Transaction> = (0..count)
+            .map(|nonce| Transaction::new(TransactionType::Stake { amount: 1 }, validator, nonce, 1, 1))
+            .collect();
+        let block = Block::new(1, BlockId([0; 32]), transactions.iter().map(Transaction::id).collect(), StateRoot([0; 32]), validator, 0);
+        (block, transactions)
+    }
+    
+    #[test]
+    fn reconstructs_blocks_from_the_mempool() {
+        let (block, transactions) = block(2);
+        let mut mempool = Mempool::new(MempoolConfig::default());
+        for transaction in &transactions {
+            mempool.insert(transaction.clone(), ShardId(0));
+        }
+        
+        let mut reconstructor = BlockReconstructor::new(CompactBlockConfig::default());
+        match reconstructor.reconstruct(PeerId::random(), block, &mempool) {
+            Reconstruction::Complete { transactions: found, .. } => {
+                assert_eq!(found.iter().map(Transaction::id).collect::<Vec<_>>(), transactions.iter().map(Transaction::id).collect::<Vec<_>>());
+            }
+            other => panic!("unexpected reconstruction {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn completes_blocks_as_missing_transactions_arrive() {
+        let (block, transactions) = block(2);
+        let block_id = block.id();
+        let mut mempool = Mempool::new(MempoolConfig::default());
+        mempool.insert(transactions[0].clone(), ShardId(0));
+        
+        let mut reconstructor = BlockReconstructor::new(CompactBlockConfig::default());
+        let missing = reconstructor.reconstruct(PeerId::random(), block.clone(), &mempool);
+        assert!(matches!(missing, Reconstruction::Missing(ref ids) if *ids == vec![transactions[1].id()]));
+        assert!(matches!(reconstructor.reconstruct(PeerId::random(), block, &mempool), Reconstruction::Pending));
+        
+        let completed = reconstructor.on_transactions(&transactions[1..]);
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].transactions.len(), 2);
+        assert!(!reconstructor.is_pending(&block_id));
+    }
+    
+    #[test]
+    fn accepts_only_matching_bodies_from_the_announcing_peer() {
+        let (block, transactions) = block(2);
+        let block_id = block.id();
+        let peer_id = PeerId::random();
+        let mempool = Mempool::new(MempoolConfig::default());
+        
+        let mut reconstructor = BlockReconstructor::new(CompactBlockConfig::default());
+        reconstructor.reconstruct(peer_id, block, &mempool);
+        
+        assert!(matches!(reconstructor.on_block_body(&PeerId::random(), &block_id, transactions.clone()), Ok(None)));
+        assert!(reconstructor.on_block_body(&peer_id, &block_id, transactions[..1].to_vec()).is_err());
+        assert!(reconstructor.on_block_body(&peer_id, &block_id, transactions).unwrap().is_some());
+        assert!(!reconstructor.is_pending(&block_id));
+    }
+    
+    #[test]
+    fn falls_back_to_the_body_and_then_gives_up() {
+        let (block, _) = block(1);
+        let block_id = block.id();
+        let peer_id = PeerId::random();
+        let mempool = Mempool::new(MempoolConfig::default());
+        
+        let mut reconstructor = BlockReconstructor::new(CompactBlockConfig {
+            request_timeout: 0,
+            ..CompactBlockConfig::default()
+        });
+        reconstructor.reconstruct(peer_id, block, &mempool);
+        
+        assert_eq!(reconstructor.check_timeouts(), vec![CompactAction::RequestBody { peer_id, block_id: block_id.clone() }]);
+        assert_eq!(reconstructor.check_timeouts(), vec![CompactAction::Failed { peer_id, block_id: block_id.clone() }]);
+        assert!(!reconstructor.is_pending(&block_id));
+    }
+}
@@ -0,0 +1,332 @@
+use crate::network::{BucketConfig, TokenBucket};
+use crate::sharding::ShardId;
+use crate::types::{Transaction, TransactionId};
+use libp2p::PeerId;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Configuration for Dandelion++ transaction propagation
+#[derive(Debug, Clone)]
+pub struct DandelionConfig {
+    /// Whether transactions are stemmed before being fluffed
+    pub enabled: bool,
+    /// Length of a routing epoch, in seconds
+    pub epoch_duration: u64,
+    /// Probability that the node fluffs every stem transaction during an epoch
+    pub fluff_probability: f64,
+    /// Number of peers stem transactions are relayed to during an epoch
+    pub stem_relays: usize,
+    /// Minimum time a stem transaction is embargoed before we fluff it ourselves, in seconds
+    pub embargo: u64,
+    /// Maximum random time added to the embargo, in seconds
+    pub embargo_jitter: u64,
+    /// Maximum number of embargoed transactions; beyond it transactions are fluffed
+    pub max_embargoes: usize,
+    /// Limit on stem transactions accepted from each peer
+    pub stem_rate: BucketConfig,
+}
+
+impl Default for DandelionConfig {
+    fn default() -> Self {
+        DandelionConfig {
+            enabled: false,
+            epoch_duration: 600,
+            fluff_probability: 0.1,
+            stem_relays: 2,
+            embargo: 30,
+            embargo_jitter: 15,
+            max_embargoes: 10_000,
+            stem_rate: BucketConfig { rate: 5.0, burst: 20.0 },
+        }
+    }
+}
+
+/// Where a transaction should go next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DandelionRoute {
+    /// Relay privately to a single peer
+    Stem(PeerId),
+    /// Broadcast to the network
+    Fluff,
+}
+
+/// A stem transaction waiting to be seen on the network
+struct Embargo {
+    /// The transaction
+    transaction: Transaction,
+    /// Shard the transaction is submitted to
+    shard_id: ShardId,
+    /// Time at which we fluff it ourselves
+    expires_at: Instant,
+}
+
+/// Dandelion++ stem routing and embargo tracking
+///
+/// Each epoch the node either fluffs every stem transaction it receives or
+/// relays them along fixed routes: all transactions from one inbound peer go
+/// to the same stem relay, and our own transactions to a single relay. Stem
+/// transactions stay out of the mempool; if one is not seen fluffed before
+/// its embargo expires, we fluff it ourselves so a black-holing relay cannot
+/// censor it.
+pub struct Dandelion {
+    /// Configuration
+    config: DandelionConfig,
+    /// Start of the current epoch
+    epoch_started: Instant,
+    /// Whether we fluff everything during this epoch
+    fluff_epoch: bool,
+    /// Stem relays chosen for this epoch
+    relays: Vec<PeerId>,
+    /// Relay used for our own transactions this epoch
+    local_route: Option<PeerId>,
+    /// Relay used for each inbound peer this epoch
+    routes: HashMap<PeerId, PeerId>,
+    /// Stem transactions we relayed or originated
+    embargoes: HashMap<TransactionId, Embargo>,
+    /// Stem rate limits per inbound peer
+    stem_buckets: HashMap<PeerId, TokenBucket>,
+}
+
+impl Dandelion {
+    /// Create a new router
+    pub fn new(config: DandelionConfig) -> Self {
+        Dandelion {
+            config,
+            epoch_started: Instant::now(),
+            fluff_epoch: false,
+            relays: Vec::new(),
+            local_route: None,
+            routes: HashMap::new(),
+            embargoes: HashMap::new(),
+            stem_buckets: HashMap::new(),
+        }
+    }
+    
+    /// Get the configuration
+    pub fn config(&self) -> &DandelionConfig {
+        &self.config
+    }
+    
+    /// Check if stem propagation is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+    
+    /// Start a new epoch if the current one is over or its relays are gone
+    pub fn maybe_new_epoch(&mut self, peers: &[PeerId]) {
+        let expired = self.epoch_started.elapsed() >= Duration::from_secs(self.config.epoch_duration);
+        if expired || self.relays.is_empty() {
+            self.new_epoch(peers);
+        }
+    }
+    
+    /// Pick new relays and decide whether this epoch fluffs
+    pub fn new_epoch(&mut self, peers: &[PeerId]) {
+        let mut rng = rand::thread_rng();
+        
+        self.epoch_started = Instant::now();
+        self.fluff_epoch = rng.gen_bool(self.config.fluff_probability.clamp(0.0, 1.0));
+        self.relays = peers.choose_multiple(&mut rng, self.config.stem_relays).copied().collect();
+        self.local_route = self.relays.choose(&mut rng).copied();
+        self.routes.clear();
+    }
+    
+    /// Route a transaction we originated; it is stemmed when a relay is available and the embargo table has room
+    pub fn route_local(&mut self, transaction: &Transaction, shard_id: ShardId) -> DandelionRoute {
+        match self.local_route {
+            Some(relay) if !self.embargoes_full() => {
+                self.embargo(transaction, shard_id);
+                DandelionRoute::Stem(relay)
+            }
+            _ => DandelionRoute::Fluff,
+        }
+    }
+    
+    /// Check whether a peer may send another stem transaction
+    pub fn allow_stem(&mut self, source: &PeerId) -> bool {
+        let rate = self.config.stem_rate;
+        self.stem_buckets.entry(*source)
+            .or_insert_with(|| TokenBucket::new(rate))
+            .try_take()
+    }
+    
+    /// Route a stem transaction received from a peer
+    pub fn route_stem(&mut self, source: &PeerId, transaction: &Transaction, shard_id: ShardId) -> DandelionRoute {
+        if self.fluff_epoch || self.embargoes_full() {
+            return DandelionRoute::Fluff;
+        }
+        
+        // Never send a transaction straight back to where it came from
+        let candidates: Vec<PeerId> = self.relays.iter().filter(|relay| *relay != source).copied().collect();
+        let relay = match self.routes.get(source) {
+            Some(relay) if candidates.contains(relay) => *relay,
+            _ => match candidates.choose(&mut rand::thread_rng()) {
+                Some(relay) => {
+                    self.routes.insert(*source, *relay);
+                    *relay
+                }
+                None => return DandelionRoute::Fluff,
+            },
+        };
+        
+        self.embargo(transaction, shard_id);
+        DandelionRoute::Stem(relay)
+    }
+    
+    /// Check if a transaction is in the stem phase here
+    pub fn is_embargoed(&self, transaction_id: &TransactionId) -> bool {
+        self.embargoes.contains_key(transaction_id)
+    }
+    
+    /// Note that a transaction was seen fluffed, lifting its embargo
+    pub fn on_fluffed(&mut self, transaction_id: &TransactionId) {
+        self.embargoes.remove(transaction_id);
+    }
+    
+    /// Take the stem transactions whose embargo expired; they must be fluffed
+    pub fn expired_embargoes(&mut self) -> Vec<(Transaction, ShardId)> {
+        let now = Instant::now();
+        let expired: Vec<TransactionId> = self.embargoes.iter()
+            .filter(|(_, embargo)| embargo.expires_at <= now)
+            .map(|(transaction_id, _)| transaction_id.clone())
+            .collect();
+        
+        expired.iter()
+            .filter_map(|transaction_id| self.embargoes.remove(transaction_id))
+            .map(|embargo| (embargo.transaction, embargo.shard_id))
+            .collect()
+    }
+    
+    /// Forget a disconnected peer, choosing new relays if it was one
+    pub fn remove_peer(&mut self, peer_id: &PeerId, peers: &[PeerId]) {
+        self.routes.remove(peer_id);
+        self.stem_buckets.remove(peer_id);
+        
+        if self.relays.contains(peer_id) {
+            let remaining: Vec<PeerId> = peers.iter().filter(|peer| *peer != peer_id).copied().collect();
+            self.new_epoch(&remaining);
+        }
+    }
+    
+    /// Check if no more transactions can be embargoed
+    fn embargoes_full(&self) -> bool {
+        self.embargoes.len() >= self.config.max_embargoes
+    }
+    
+    /// Hold a stem transaction until it is seen fluffed or the embargo expires
+    fn embargo(&mut self, transaction: &Transaction, shard_id: ShardId) {
+        let jitter = rand::thread_rng().gen_range(0..=self.config.embargo_jitter);
+        let expires_at = Instant::now() + Duration::from_secs(self.config.embargo + jitter);
+        
+        self.embargoes.entry(transaction.id()).or_insert_with(|| Embargo {
+            transaction: transaction.clone(),
+            shard_id,
+            expires_at,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TransactionType;
+    use crate::utils::crypto::KeyPair;
+    use ed25519_dalek::VerifyingKey;
+    
+    fn transaction() -> Transaction {
+        let sender = VerifyingKey::from_bytes(&KeyPair::generate().public_key()).unwrap();
+        Transaction::new(TransactionType::Stake { amount: 1 }, sender, 0, 1, 1)
+    }
+    
+    /// A router that never fluffs on its own
+    fn stemming(config: DandelionConfig) -> Dandelion {
+        Dandelion::new(DandelionConfig {
+            enabled: true,
+            fluff_probability: 0.0,
+            ..config
+        })
+    }
+    
+    #[test]
+    fn stems_along_fixed_routes_and_never_back_to_the_source() {
+        let peers: Vec<PeerId> = (0..2).map(|_| PeerId::random()).collect();
+        let mut dandelion = stemming(DandelionConfig::default());
+        dandelion.new_epoch(&peers);
+        
+        let first = dandelion.route_stem(&peers[0], &transaction(), ShardId(0));
+        assert_eq!(first, DandelionRoute::Stem(peers[1]));
+        assert_eq!(dandelion.route_stem(&peers[0], &transaction(), ShardId(0)), first);
+        
+        let source = PeerId::random();
+        let route = dandelion.route_stem(&source, &transaction(), ShardId(0));
+        assert_eq!(dandelion.route_stem(&source, &transaction(), ShardId(0)), route);
+    }
+    
+    #[test]
+    fn fluffs_without_relays_or_during_fluff_epochs() {
+        let transaction = transaction();
+        let mut dandelion = stemming(DandelionConfig::default());
+        assert_eq!(dandelion.route_local(&transaction, ShardId(0)), DandelionRoute::Fluff);
+        
+        let mut fluffing = Dandelion::new(DandelionConfig {
+            enabled: true,
+            fluff_probability: 1.0,
+            ..DandelionConfig::default()
+        });
+        fluffing.new_epoch(&[PeerId::random(), PeerId::random()]);
+        assert_eq!(fluffing.route_stem(&PeerId::random(), &transaction, ShardId(0)), DandelionRoute::Fluff);
+        assert!(!fluffing.is_embargoed(&transaction.id()));
+    }
+    
+    #[test]
+    fn fluffs_embargoed_transactions_when_the_embargo_expires() {
+        let transaction = transaction();
+        let mut dandelion = stemming(DandelionConfig {
+            embargo: 0,
+            embargo_jitter: 0,
+            ..DandelionConfig::default()
+        });
+        dandelion.new_epoch(&[PeerId::random()]);
+        
+        assert!(matches!(dandelion.route_local(&transaction, ShardId(3)), DandelionRoute::Stem(_)));
+        assert!(dandelion.is_embargoed(&transaction.id()));
+        
+        let expired = dandelion.expired_embargoes();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].1, ShardId(3));
+        assert!(!dandelion.is_embargoed(&transaction.id()));
+    }
+    
+    #[test]
+    fn lifts_embargoes_seen_fluffed_and_limits_stem_senders() {
+        let transaction = transaction();
+        let source = PeerId::random();
+        let mut dandelion = stemming(DandelionConfig {
+            stem_rate: BucketConfig { rate: 0.0, burst: 1.0 },
+            ..DandelionConfig::default()
+        });
+        dandelion.new_epoch(&[PeerId::random()]);
+        
+        dandelion.route_stem(&source, &transaction, ShardId(0));
+        dandelion.on_fluffed(&transaction.id());
+        assert!(dandelion.expired_embargoes().is_empty());
+        
+        assert!(dandelion.allow_stem(&source));
+        assert!(!dandelion.allow_stem(&source));
+    }
+    
+    #[test]
+    fn replaces_relays_that_disconnect() {
+        let (relay, other) = (PeerId::random(), PeerId::random());
+        let mut dandelion = stemming(DandelionConfig {
+            stem_relays: 1,
+            ..DandelionConfig::default()
+        });
+        dandelion.new_epoch(&[relay]);
+        
+        dandelion.remove_peer(&relay, &[relay, other]);
+        assert_eq!(dandelion.route_local(&transaction(), ShardId(0)), DandelionRoute::Stem(other));
+    }
+}
@@ -1,12 +1,22 @@
 use crate::network::TransportConfig;
+use crate::storage::{Database, MetadataKey};
 use libp2p::{
     core::Multiaddr,
+    identify,
     kad::{self, store::MemoryStore, QueryId},
+    mdns,
+    multiaddr::Protocol,
+    PeerId,
 };
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 use std::time::{Duration, Instant};
 
+/// Metadata key under which known peers are persisted
+const KNOWN_PEERS_KEY: &str = "known_peers";
+
 /// Configuration for the discovery mechanism
 #[derive(Debug, Clone)]
 pub struct DiscoveryConfig {
@@ -20,6 +30,12 @@ pub struct DiscoveryConfig {
     pub min_peers: usize,
     /// Time to live for peer records in seconds
     pub peer_ttl: u64,
+    /// Maximum number of peer records to keep
+    pub max_known_peers: usize,
+    /// Discover peers on the local network with mDNS
+    pub enable_mdns: bool,
+    /// Minimum interval between saves of changed peer records in seconds
+    pub save_interval: u64,
 }
 
 impl Default for DiscoveryConfig {
@@ -30,6 +46,9 @@ impl Default for DiscoveryConfig {
             max_peers: 50,
             min_peers: 10,
             peer_ttl: 3600,
+            max_known_peers: 1000,
+            enable_mdns: false,
+            save_interval: 60,
         }
     }
 }
@@ -51,63 +70,193 @@ pub struct PeerInfo {
     pub capabilities: Vec<String>,
 }
 
+impl PeerInfo {
+    /// Create a record for a peer known only by its addresses
+    fn new(peer_id: &PeerId, addresses: Vec<Multiaddr>) -> Self {
+        PeerInfo {
+            peer_id: peer_id.to_string(),
+            addresses,
+            last_seen: now(),
+            protocol_version: String::new(),
+            client_version: String::new(),
+            capabilities: Vec::new(),
+        }
+    }
+}
+
 /// Discovery mechanism for finding peers
 pub struct Discovery {
     /// Configuration
     config: DiscoveryConfig,
     /// Known peers
     peers: HashMap<String, PeerInfo>,
+    /// Currently connected peers
+    connected: HashSet<PeerId>,
     /// Active queries
     active_queries: HashMap<QueryId, Instant>,
     /// Last discovery time
     last_discovery: Instant,
+    /// Whether peer records changed since they were last saved
+    unsaved_peers: bool,
+    /// Last time peer records were saved
+    last_save: Instant,
+    /// Local peer ID, set on initialization
+    local_peer_id: Option<PeerId>,
     /// Kademlia DHT for peer discovery
     kademlia: Option<kad::Behaviour<MemoryStore>>,
+    /// mDNS for local network discovery, if enabled
+    mdns: Option<mdns::async_io::Behaviour>,
 }
 
-// Using libp2p's MemoryStore instead of defining our own
-// This was causing a name conflict with the imported MemoryStore
-
 impl Discovery {
     /// Create a new discovery mechanism
     pub fn new(config: DiscoveryConfig) -> Self {
         Discovery {
             config,
             peers: HashMap::new(),
+            connected: HashSet::new(),
             active_queries: HashMap::new(),
             last_discovery: Instant::now(),
+            unsaved_peers: false,
+            last_save: Instant::now(),
+            local_peer_id: None,
             kademlia: None,
+            mdns: None,
         }
     }
     
     /// Initialize the discovery mechanism
-    pub fn initialize(&mut self, local_peer_id: &str, transport_config: &TransportConfig) {
-        // In a real implementation, this would initialize Kademlia with the local peer ID
-        // and bootstrap nodes from the config
-        
-        // For now, just log the initialization
+    ///
+    /// Seeds the Kademlia routing table with bootstrap nodes and persisted peers
+    /// and starts a bootstrap query.
+    pub fn initialize(&mut self, local_peer_id: &PeerId, transport_config: &TransportConfig) {
         log::info!("Initializing discovery mechanism with peer ID: {}", local_peer_id);
         
-        // Connect to bootstrap nodes
+        let mut kad_config = kad::Config::default();
+        kad_config.set_query_timeout(Duration::from_secs(transport_config.connection_timeout));
+        
+        let mut kademlia = kad::Behaviour::with_config(*local_peer_id, MemoryStore::new(*local_peer_id), kad_config);
+        kademlia.set_mode(Some(kad::Mode::Server));
+        
+        // Bootstrap nodes must carry a /p2p/<peer id> suffix to be usable
         for addr in &self.config.bootstrap_nodes {
-            log::info!("Connecting to bootstrap node: {}", addr);
+            match peer_id_from_addr(addr) {
+                Some(peer_id) => {
+                    log::info!("Adding bootstrap node: {}", addr);
+                    kademlia.add_address(&peer_id, addr.clone());
+                }
+                None => log::warn!("Bootstrap node {} has no peer ID, skipping", addr),
+            }
+        }
+        
+        // Persisted peers let us rejoin even if every bootstrap node is down
+        for info in self.peers.values() {
+            if let Ok(peer_id) = PeerId::from_str(&info.peer_id) {
+                for addr in &info.addresses {
+                    kademlia.add_address(&peer_id, addr.clone());
+                }
+            }
+        }
+        
+        match kademlia.bootstrap() {
+            Ok(query_id) => {
+                self.active_queries.insert(query_id, Instant::now());
+            }
+            Err(_) => log::warn!("No bootstrap nodes or known peers; waiting for inbound connections"),
+        }
+        
+        if self.config.enable_mdns {
+            match mdns::async_io::Behaviour::new(mdns::Config::default(), *local_peer_id) {
+                Ok(behaviour) => {
+                    log::info!("mDNS local peer discovery enabled");
+                    self.mdns = Some(behaviour);
+                }
+                Err(e) => log::warn!("Failed to start mDNS discovery: {}", e),
+            }
         }
+        
+        self.local_peer_id = Some(*local_peer_id);
+        self.kademlia = Some(kademlia);
+    }
+    
+    /// Get the Kademlia behaviour, to be driven by the swarm
+    pub fn kademlia_mut(&mut self) -> Option<&mut kad::Behaviour<MemoryStore>> {
+        self.kademlia.as_mut()
+    }
+    
+    /// Get the mDNS behaviour, to be driven by the swarm, if mDNS is enabled
+    pub fn mdns_mut(&mut self) -> Option<&mut mdns::async_io::Behaviour> {
+        self.mdns.as_mut()
     }
     
     /// Start peer discovery
     pub fn start_discovery(&mut self) -> Option<QueryId> {
         self.last_discovery = Instant::now();
         
-        // In a real implementation, this would start a Kademlia random walk
-        // to discover new peers
+        if self.connected.len() >= self.config.max_peers {
+            return None;
+        }
+        
+        // Random walk: looking up a random key fills the routing table with
+        // peers from all over the keyspace
+        let kademlia = self.kademlia.as_mut()?;
+        let query_id = kademlia.get_closest_peers(PeerId::random());
+        self.active_queries.insert(query_id, Instant::now());
         
         log::info!("Starting peer discovery");
-        None
+        Some(query_id)
+    }
+    
+    /// Load persisted peers
+    pub fn load_peers(&mut self, database: &Database) -> Result<(), String> {
+        let stored = database.get(&MetadataKey { key: KNOWN_PEERS_KEY.to_string() })
+            .map_err(|e| e.to_string())?;
+        
+        if let Some(bytes) = stored {
+            let peers: Vec<PeerInfo> = bincode::deserialize(&bytes)
+                .map_err(|e| format!("Failed to decode known peers: {}", e))?;
+            
+            log::info!("Loaded {} known peers", peers.len());
+            
+            for info in peers {
+                self.peers.insert(info.peer_id.clone(), info);
+            }
+            
+            self.prune_peers();
+        }
+        
+        Ok(())
+    }
+    
+    /// Persist known peers
+    pub fn save_peers(&mut self, database: &Database) -> Result<(), String> {
+        let peers: Vec<&PeerInfo> = self.peers.values().collect();
+        let bytes = bincode::serialize(&peers)
+            .map_err(|e| format!("Failed to encode known peers: {}", e))?;
+        
+        database.put(&MetadataKey { key: KNOWN_PEERS_KEY.to_string() }, &bytes)
+            .map_err(|e| e.to_string())?;
+        
+        self.unsaved_peers = false;
+        self.last_save = Instant::now();
+        Ok(())
+    }
+    
+    /// Check if peer records changed and the save interval has passed
+    pub fn should_save_peers(&self) -> bool {
+        self.unsaved_peers && self.last_save.elapsed() >= Duration::from_secs(self.config.save_interval)
     }
     
     /// Add a peer to the known peers
     pub fn add_peer(&mut self, peer_info: PeerInfo) {
+        if let (Ok(peer_id), Some(kademlia)) = (PeerId::from_str(&peer_info.peer_id), self.kademlia.as_mut()) {
+            for addr in &peer_info.addresses {
+                kademlia.add_address(&peer_id, addr.clone());
+            }
+        }
+        
         self.peers.insert(peer_info.peer_id.clone(), peer_info);
+        self.unsaved_peers = true;
         
         // Prune peers if we have too many
         self.prune_peers();
@@ -115,7 +264,13 @@ impl Discovery {
     
     /// Remove a peer from the known peers
     pub fn remove_peer(&mut self, peer_id: &str) {
-        self.peers.remove(peer_id);
+        if self.peers.remove(peer_id).is_some() {
+            self.unsaved_peers = true;
+        }
+        
+        if let (Ok(peer_id), Some(kademlia)) = (PeerId::from_str(peer_id), self.kademlia.as_mut()) {
+            kademlia.remove_peer(&peer_id);
+        }
     }
     
     /// Get a peer by ID
@@ -128,30 +283,83 @@ impl Discovery {
         &self.peers
     }
     
+    /// Record a new connection
+    pub fn peer_connected(&mut self, peer_id: PeerId) {
+        self.connected.insert(peer_id);
+        self.update_peer_last_seen(&peer_id.to_string());
+    }
+    
+    /// Record a closed connection
+    pub fn peer_disconnected(&mut self, peer_id: &PeerId) {
+        self.connected.remove(peer_id);
+    }
+    
+    /// Get the number of connected peers
+    pub fn connected_count(&self) -> usize {
+        self.connected.len()
+    }
+    
     /// Check if we need to discover more peers
     pub fn should_discover(&self) -> bool {
-        self.peers.len() < self.config.min_peers
+        if self.connected.len() >= self.config.max_peers {
+            return false;
+        }
+        
+        (self.connected.len() < self.config.min_peers && self.active_queries.is_empty())
             || self.last_discovery.elapsed() > Duration::from_secs(self.config.discovery_interval)
     }
     
+    /// Select known, unconnected peers to dial to get back to `min_peers`
+    pub fn peers_to_dial(&self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let wanted = self.config.min_peers.saturating_sub(self.connected.len());
+        
+        let mut candidates: Vec<&PeerInfo> = self.peers.values()
+            .filter(|info| !info.addresses.is_empty())
+            .collect();
+        candidates.sort_by_key(|info| Reverse(info.last_seen));
+        
+        candidates.into_iter()
+            .filter_map(|info| {
+                let peer_id = PeerId::from_str(&info.peer_id).ok()?;
+                let is_local = self.local_peer_id == Some(peer_id);
+                (!is_local && !self.connected.contains(&peer_id)).then(|| (peer_id, info.addresses.clone()))
+            })
+            .take(wanted)
+            .collect()
+    }
+    
+    /// Select connected peers to disconnect to get back to `max_peers`, least recently seen first
+    pub fn excess_peers(&self) -> Vec<PeerId> {
+        let excess = self.connected.len().saturating_sub(self.config.max_peers);
+        
+        let mut connected: Vec<(u64, PeerId)> = self.connected.iter()
+            .map(|peer_id| {
+                let last_seen = self.peers.get(&peer_id.to_string()).map_or(0, |info| info.last_seen);
+                (last_seen, *peer_id)
+            })
+            .collect();
+        connected.sort();
+        
+        connected.into_iter().take(excess).map(|(_, peer_id)| peer_id).collect()
+    }
+    
     /// Prune old or excess peers
     fn prune_peers(&mut self) {
-        // Remove old peers
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
-        
+        // Remove old peers, keeping the ones we are connected to
+        let now = now();
         let ttl = self.config.peer_ttl;
-        self.peers.retain(|_, info| now - info.last_seen < ttl);
+        let connected: HashSet<String> = self.connected.iter().map(|peer_id| peer_id.to_string()).collect();
+        self.peers.retain(|peer_id, info| connected.contains(peer_id) || now.saturating_sub(info.last_seen) < ttl);
         
         // If we still have too many peers, remove the oldest ones
-        if self.peers.len() > self.config.max_peers {
+        if self.peers.len() > self.config.max_known_peers {
             // Collect peer IDs to remove
-            let mut peers: Vec<_> = self.peers.iter().collect();
+            let mut peers: Vec<_> = self.peers.iter()
+                .filter(|(peer_id, _)| !connected.contains(*peer_id))
+                .collect();
             peers.sort_by_key(|(_, info)| info.last_seen);
             
-            let to_remove = peers.len() - self.config.max_peers;
+            let to_remove = self.peers.len() - self.config.max_known_peers;
             let peers_to_remove: Vec<String> = peers.iter()
                 .take(to_remove)
                 .map(|(peer_id, _)| (*peer_id).clone())
@@ -167,32 +375,221 @@ impl Discovery {
     /// Handle a Kademlia event
     pub fn handle_kademlia_event(&mut self, event: kad::Event) {
         match event {
-            kad::Event::OutboundQueryProgressed { id, result, .. } => {
-                self.active_queries.remove(&id);
+            kad::Event::OutboundQueryProgressed { id, result, step, .. } => {
+                if step.last {
+                    self.active_queries.remove(&id);
+                }
                 
                 match result {
                     kad::QueryResult::GetClosestPeers(Ok(peers)) => {
                         log::info!("Found {} closest peers", peers.peers.len());
-                        
-                        // In a real implementation, we would add these peers to our known peers
                     }
                     kad::QueryResult::GetClosestPeers(Err(err)) => {
                         log::error!("Failed to get closest peers: {:?}", err);
                     }
+                    kad::QueryResult::Bootstrap(Ok(result)) => {
+                        log::debug!("Bootstrap progressed, {} buckets remaining", result.num_remaining);
+                    }
+                    kad::QueryResult::Bootstrap(Err(err)) => {
+                        log::warn!("Bootstrap failed: {:?}", err);
+                    }
                     _ => {}
                 }
             }
+            kad::Event::RoutingUpdated { peer, addresses, .. } => {
+                self.record_addresses(&peer, addresses.iter().cloned().collect());
+            }
+            kad::Event::RoutablePeer { peer, address } | kad::Event::PendingRoutablePeer { peer, address } => {
+                self.record_addresses(&peer, vec![address]);
+            }
             _ => {}
         }
     }
     
+    /// Handle an mDNS event, adding discovered peers to the known peers
+    ///
+    /// mDNS only queries non-loopback interfaces, so nodes on the same host
+    /// are not found this way; list them as bootstrap nodes instead.
+    pub fn handle_mdns_event(&mut self, event: mdns::Event) {
+        match event {
+            mdns::Event::Discovered(discovered) => {
+                let mut by_peer: HashMap<PeerId, Vec<Multiaddr>> = HashMap::new();
+                for (peer_id, addr) in discovered {
+                    by_peer.entry(peer_id).or_default().push(addr);
+                }
+                
+                for (peer_id, addresses) in by_peer {
+                    if self.local_peer_id == Some(peer_id) {
+                        continue;
+                    }
+                    
+                    log::debug!("mDNS discovered {} at {:?}", peer_id, addresses);
+                    
+                    let mut info = self.peers.get(&peer_id.to_string())
+                        .cloned()
+                        .unwrap_or_else(|| PeerInfo::new(&peer_id, Vec::new()));
+                    
+                    for addr in addresses {
+                        if !info.addresses.contains(&addr) {
+                            info.addresses.push(addr);
+                        }
+                    }
+                    info.last_seen = now();
+                    
+                    self.add_peer(info);
+                }
+            }
+            mdns::Event::Expired(expired) => {
+                for (peer_id, addr) in expired {
+                    if let Some(info) = self.peers.get_mut(&peer_id.to_string()) {
+                        info.addresses.retain(|known| *known != addr);
+                        self.unsaved_peers = true;
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Handle an identify event, filling in the peer's record
+    pub fn handle_identify_event(&mut self, event: identify::Event) {
+        if let identify::Event::Received { peer_id, info } = event {
+            if let Some(kademlia) = self.kademlia.as_mut() {
+                for addr in &info.listen_addrs {
+                    kademlia.add_address(&peer_id, addr.clone());
+                }
+            }
+            
+            let record = self.peers.entry(peer_id.to_string())
+                .or_insert_with(|| PeerInfo::new(&peer_id, Vec::new()));
+            
+            record.addresses = info.listen_addrs;
+            record.protocol_version = info.protocol_version;
+            record.client_version = info.agent_version;
+            record.capabilities = info.protocols.iter().map(|protocol| protocol.to_string()).collect();
+            record.last_seen = now();
+            self.unsaved_peers = true;
+        }
+    }
+    
+    /// Merge newly learned addresses into a peer's record
+    fn record_addresses(&mut self, peer_id: &PeerId, addresses: Vec<Multiaddr>) {
+        if self.local_peer_id == Some(*peer_id) {
+            return;
+        }
+        
+        let record = self.peers.entry(peer_id.to_string())
+            .or_insert_with(|| PeerInfo::new(peer_id, Vec::new()));
+        
+        for addr in addresses {
+            if !record.addresses.contains(&addr) {
+                record.addresses.push(addr);
+            }
+        }
+        self.unsaved_peers = true;
+        
+        self.prune_peers();
+    }
+    
     /// Update a peer's last seen time
     pub fn update_peer_last_seen(&mut self, peer_id: &str) {
         if let Some(peer) = self.peers.get_mut(peer_id) {
-            peer.last_seen = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_secs();
+            peer.last_seen = now();
+            self.unsaved_peers = true;
+        }
+    }
+}
+
+/// Extract the peer ID from a `/p2p/<peer id>` address suffix
+pub fn peer_id_from_addr(addr: &Multiaddr) -> Option<PeerId> {
+    addr.iter().find_map(|protocol| match protocol {
+        Protocol::P2p(peer_id) => Some(peer_id),
+        _ => None,
+    })
+}
+
+/// Get the current Unix time in seconds
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// A peer record last seen `age` seconds ago
+    fn peer(peer_id: &PeerId, age: u64) -> PeerInfo {
+        PeerInfo {
+            last_seen: now() - age,
+            ..PeerInfo::new(peer_id, vec!["/ip4/127.0.0.1/tcp/30333".parse().unwrap()])
         }
     }
+    
+    #[test]
+    fn prunes_expired_and_excess_peers() {
+        let mut discovery = Discovery::new(DiscoveryConfig {
+            peer_ttl: 100,
+            max_known_peers: 2,
+            ..DiscoveryConfig::default()
+        });
+        let (stale, old, recent, newest) = (PeerId::random(), PeerId::random(), PeerId::random(), PeerId::random());
+        
+        discovery.add_peer(peer(&stale, 200));
+        assert!(discovery.get_peer(&stale.to_string()).is_none());
+        
+        discovery.add_peer(peer(&old, 50));
+        discovery.add_peer(peer(&recent, 10));
+        discovery.add_peer(peer(&newest, 0));
+        
+        assert_eq!(discovery.get_peers().len(), 2);
+        assert!(discovery.get_peer(&old.to_string()).is_none());
+    }
+    
+    #[test]
+    fn dials_unconnected_peers_up_to_the_minimum() {
+        let mut discovery = Discovery::new(DiscoveryConfig {
+            min_peers: 2,
+            ..DiscoveryConfig::default()
+        });
+        let peers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        for (age, peer_id) in peers.iter().enumerate() {
+            discovery.add_peer(peer(peer_id, age as u64));
+        }
+        discovery.peer_connected(peers[0]);
+        
+        let dial: Vec<PeerId> = discovery.peers_to_dial().into_iter().map(|(peer_id, _)| peer_id).collect();
+        assert_eq!(dial, vec![peers[1]]);
+    }
+    
+    #[test]
+    fn disconnects_the_least_recently_seen_excess_peers() {
+        let mut discovery = Discovery::new(DiscoveryConfig {
+            max_peers: 1,
+            ..DiscoveryConfig::default()
+        });
+        let (old, recent) = (PeerId::random(), PeerId::random());
+        discovery.peer_connected(old);
+        discovery.peer_connected(recent);
+        discovery.add_peer(peer(&old, 50));
+        discovery.add_peer(peer(&recent, 0));
+        
+        assert_eq!(discovery.excess_peers(), vec![old]);
+    }
+    
+    #[test]
+    fn persists_known_peers() {
+        let (_dir, database) = crate::storage::temporary_database();
+        let peer_id = PeerId::random();
+        
+        let mut discovery = Discovery::new(DiscoveryConfig::default());
+        discovery.add_peer(peer(&peer_id, 0));
+        discovery.save_peers(&database).unwrap();
+        assert!(!discovery.should_save_peers());
+        
+        let mut restored = Discovery::new(DiscoveryConfig::default());
+        restored.load_peers(&database).unwrap();
+        assert_eq!(restored.get_peer(&peer_id.to_string()).unwrap().addresses, discovery.get_peer(&peer_id.to_string()).unwrap().addresses);
+    }
 }
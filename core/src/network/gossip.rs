@@ -0,0 +1,496 @@
+use crate::network::{MessageId, MessageType, SeenCache};
+use crate::sharding::ShardId;
+use libp2p::PeerId;
+use rand::seq::SliceRandom;
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::Duration;
+
+/// Class of messages carried on a gossip topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TopicKind {
+    /// Block announcements
+    Blocks,
+    /// Transaction announcements
+    Transactions,
+    /// Consensus and finality votes
+    Consensus,
+    /// Beacon blocks and the cross-links they collect
+    Beacon,
+}
+
+/// A gossip topic, scoped to a message class and optionally to a shard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Topic {
+    /// Message class carried on this topic
+    pub kind: TopicKind,
+    /// Shard this topic belongs to (`None` for the global topic)
+    pub shard_id: Option<ShardId>,
+}
+
+impl Topic {
+    /// Create a new topic
+    pub fn new(kind: TopicKind, shard_id: Option<ShardId>) -> Self {
+        Topic { kind, shard_id }
+    }
+    
+    /// Topic for blocks of a shard
+    pub fn blocks(shard_id: ShardId) -> Self {
+        Topic::new(TopicKind::Blocks, Some(shard_id))
+    }
+    
+    /// Topic for transactions of a shard
+    pub fn transactions(shard_id: ShardId) -> Self {
+        Topic::new(TopicKind::Transactions, Some(shard_id))
+    }
+    
+    /// Topic for consensus votes of a shard
+    pub fn consensus(shard_id: ShardId) -> Self {
+        Topic::new(TopicKind::Consensus, Some(shard_id))
+    }
+    
+    /// Topic for the beacon chain, shared by all shards
+    pub fn beacon() -> Self {
+        Topic::new(TopicKind::Beacon, None)
+    }
+    
+    /// Derive the topic a message should be published on, if it is gossiped at all
+    pub fn for_message(message_type: &MessageType) -> Option<Self> {
+        match message_type {
+            MessageType::BlockAnnounce { block } => Some(Topic::blocks(ShardId(block.shard_id))),
+            MessageType::TransactionAnnounce { shard_id, .. } => Some(Topic::transactions(*shard_id)),
+            MessageType::ConsensusMessage { shard_id, .. } => Some(Topic::consensus(*shard_id)),
+            MessageType::BeaconBlockAnnounce { .. } | MessageType::CrossLinkAnnounce { .. } => Some(Topic::beacon()),
+            _ => None,
+        }
+    }
+    
+    /// Check whether a payload belongs on this topic
+    fn accepts(&self, payload: &MessageType) -> bool {
+        let shard_id = match (self.kind, payload) {
+            (TopicKind::Blocks, MessageType::BlockAnnounce { block }) => ShardId(block.shard_id),
+            (TopicKind::Transactions, MessageType::TransactionAnnounce { shard_id, .. }) => *shard_id,
+            (TopicKind::Consensus, MessageType::ConsensusMessage { shard_id, .. }) => *shard_id,
+            (TopicKind::Beacon, MessageType::BeaconBlockAnnounce { .. } | MessageType::CrossLinkAnnounce { .. }) => {
+                return self.shard_id.is_none();
+            }
+            _ => return false,
+        };
+        
+        self.shard_id.is_none_or(|topic_shard| topic_shard == shard_id)
+    }
+}
+
+impl fmt::Display for Topic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            TopicKind::Blocks => "blocks",
+            TopicKind::Transactions => "transactions",
+            TopicKind::Consensus => "consensus",
+            TopicKind::Beacon => "beacon",
+        };
+        
+        match self.shard_id {
+            Some(shard_id) => write!(f, "/optimachain/{}/shard-{}", kind, shard_id.0),
+            None => write!(f, "/optimachain/{}", kind),
+        }
+    }
+}
+
+/// Configuration for gossip propagation
+#[derive(Debug, Clone)]
+pub struct GossipConfig {
+    /// Target number of mesh peers per topic
+    pub mesh_size: usize,
+    /// Lower bound of mesh peers before more are grafted
+    pub mesh_size_low: usize,
+    /// Upper bound of mesh peers before some are pruned
+    pub mesh_size_high: usize,
+    /// Number of recently seen message IDs to remember for deduplication
+    pub duplicate_cache_size: usize,
+    /// How long a seen message ID is remembered, in seconds
+    pub duplicate_cache_ttl: u64,
+    /// Number of hops a published message may travel
+    pub max_hops: u8,
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        GossipConfig {
+            mesh_size: 6,
+            mesh_size_low: 4,
+            mesh_size_high: 12,
+            duplicate_cache_size: 10000,
+            duplicate_cache_ttl: 120,
+            max_hops: 8,
+        }
+    }
+}
+
+/// Result of validating a gossiped message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationResult {
+    /// Message is valid and should be delivered and forwarded
+    Accept,
+    /// Message is invalid; the sender should be penalized
+    Reject,
+    /// Message should be dropped without penalizing the sender
+    Ignore,
+}
+
+/// Validation hook for messages on a topic kind
+pub type MessageValidator = Box<dyn Fn(&PeerId, &MessageType) -> ValidationResult + Send + Sync>;
+
+/// Outcome of handling an inbound gossip message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GossipOutcome {
+    /// Message was already seen
+    Duplicate,
+    /// Message was dropped by a validator without penalty
+    Ignored,
+    /// Message failed validation and must not be forwarded
+    Rejected {
+        /// Reason for rejection
+        reason: String,
+    },
+    /// Message was accepted
+    Accepted {
+        /// Mesh peers the message should be forwarded to
+        forward_to: Vec<PeerId>,
+    },
+}
+
+/// Topic-based gossip router
+pub struct Gossip {
+    /// Configuration
+    config: GossipConfig,
+    /// Topics we are subscribed to
+    subscriptions: HashSet<Topic>,
+    /// Topics each connected peer is subscribed to
+    peer_topics: HashMap<PeerId, HashSet<Topic>>,
+    /// Mesh peers we forward to, per subscribed topic
+    mesh: HashMap<Topic, HashSet<PeerId>>,
+    /// Recently seen message IDs
+    seen: SeenCache,
+    /// Validation hooks per topic kind
+    validators: HashMap<TopicKind, MessageValidator>,
+}
+
+impl Gossip {
+    /// Create a new gossip router
+    pub fn new(config: GossipConfig) -> Self {
+        Gossip {
+            subscriptions: HashSet::new(),
+            peer_topics: HashMap::new(),
+            mesh: HashMap::new(),
+            seen: SeenCache::new(
+                config.duplicate_cache_size,
+                Duration::from_secs(config.duplicate_cache_ttl),
+            ),
+            validators: HashMap::new(),
+            config,
+        }
+    }
+    
+    /// Get the configuration
+    pub fn config(&self) -> &GossipConfig {
+        &self.config
+    }
+    
+    /// Set the validation hook for a topic kind
+    pub fn set_validator<F>(&mut self, kind: TopicKind, validator: F)
+    where
+        F: Fn(&PeerId, &MessageType) -> ValidationResult + Send + Sync + 'static,
+    {
+        self.validators.insert(kind, Box::new(validator));
+    }
+    
+    /// Subscribe to a topic, returning the peers that should be told about it
+    pub fn subscribe(&mut self, topic: Topic) -> Vec<PeerId> {
+        if !self.subscriptions.insert(topic) {
+            return Vec::new();
+        }
+        
+        self.mesh.entry(topic).or_default();
+        self.graft(&topic);
+        
+        self.peer_topics.keys().copied().collect()
+    }
+    
+    /// Unsubscribe from a topic, returning the peers that should be told about it
+    pub fn unsubscribe(&mut self, topic: &Topic) -> Vec<PeerId> {
+        if !self.subscriptions.remove(topic) {
+            return Vec::new();
+        }
+        
+        self.mesh.remove(topic);
+        
+        self.peer_topics.keys().copied().collect()
+    }
+    
+    /// Get the topics we are subscribed to
+    pub fn subscriptions(&self) -> Vec<Topic> {
+        self.subscriptions.iter().copied().collect()
+    }
+    
+    /// Check if we are subscribed to a topic
+    pub fn is_subscribed(&self, topic: &Topic) -> bool {
+        self.subscriptions.contains(topic)
+    }
+    
+    /// Register a newly connected peer
+    pub fn add_peer(&mut self, peer_id: PeerId) {
+        self.peer_topics.entry(peer_id).or_default();
+    }
+    
+    /// Forget a disconnected peer
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.peer_topics.remove(peer_id);
+        
+        for peers in self.mesh.values_mut() {
+            peers.remove(peer_id);
+        }
+    }
+    
+    /// Handle a subscription change announced by a peer
+    pub fn handle_subscription(&mut self, peer_id: PeerId, topics: Vec<Topic>, subscribe: bool) {
+        let peer_topics = self.peer_topics.entry(peer_id).or_default();
+        
+        for topic in topics {
+            if subscribe {
+                peer_topics.insert(topic);
+            } else {
+                peer_topics.remove(&topic);
+                
+                if let Some(peers) = self.mesh.get_mut(&topic) {
+                    peers.remove(&peer_id);
+                }
+            }
+        }
+        
+        if subscribe {
+            let subscribed: Vec<_> = self.subscriptions.iter().copied().collect();
+            for topic in subscribed {
+                self.graft(&topic);
+            }
+        }
+    }
+    
+    /// Select the peers a locally published message should be sent to
+    pub fn publish(&mut self, topic: &Topic, message_id: MessageId) -> Vec<PeerId> {
+        self.seen.insert(message_id);
+        
+        if let Some(peers) = self.mesh.get(topic) {
+            if !peers.is_empty() {
+                return peers.iter().copied().collect();
+            }
+        }
+        
+        // Not subscribed or mesh empty: fan out to a random sample of subscribers
+        let mut peers = self.topic_peers(topic);
+        peers.shuffle(&mut rand::thread_rng());
+        peers.truncate(self.config.mesh_size);
+        peers
+    }
+    
+    /// Handle a gossip message received from a peer
+    pub fn handle_message(
+        &mut self,
+        source: &PeerId,
+        topic: &Topic,
+        message_id: &MessageId,
+        payload: &MessageType,
+    ) -> GossipOutcome {
+        if !self.seen.insert(message_id.clone()) {
+            return GossipOutcome::Duplicate;
+        }
+        
+        if !topic.accepts(payload) {
+            return GossipOutcome::Rejected {
+                reason: format!("Payload does not belong on topic {}", topic),
+            };
+        }
+        
+        if let Some(validator) = self.validators.get(&topic.kind) {
+            match validator(source, payload) {
+                ValidationResult::Accept => {}
+                ValidationResult::Ignore => return GossipOutcome::Ignored,
+                ValidationResult::Reject => {
+                    return GossipOutcome::Rejected {
+                        reason: format!("Validation failed on topic {}", topic),
+                    };
+                }
+            }
+        }
+        
+        let forward_to = self.mesh.get(topic)
+            .map(|peers| peers.iter().filter(|peer_id| *peer_id != source).copied().collect())
+            .unwrap_or_default();
+        
+        GossipOutcome::Accepted { forward_to }
+    }
+    
+    /// Maintain mesh sizes for all subscribed topics
+    pub fn heartbeat(&mut self) {
+        self.seen.prune();
+        
+        let subscribed: Vec<_> = self.subscriptions.iter().copied().collect();
+        
+        for topic in subscribed {
+            // Drop mesh peers that are gone or no longer subscribed
+            let live: HashSet<PeerId> = self.topic_peers(&topic).into_iter().collect();
+            let mesh = self.mesh.entry(topic).or_default();
+            mesh.retain(|peer_id| live.contains(peer_id));
+            let mesh_len = mesh.len();
+            
+            if mesh_len < self.config.mesh_size_low {
+                self.graft(&topic);
+            } else if mesh_len > self.config.mesh_size_high {
+                let mut peers: Vec<_> = mesh.iter().copied().collect();
+                peers.shuffle(&mut rand::thread_rng());
+                
+                for peer_id in peers.into_iter().skip(self.config.mesh_size) {
+                    mesh.remove(&peer_id);
+                }
+            }
+        }
+    }
+    
+    /// Add subscribed peers to a topic mesh until it reaches the target size
+    fn graft(&mut self, topic: &Topic) {
+        let mut candidates = self.topic_peers(topic);
+        candidates.shuffle(&mut rand::thread_rng());
+        
+        let mesh_size = self.config.mesh_size;
+        let mesh = self.mesh.entry(*topic).or_default();
+        
+        for peer_id in candidates {
+            if mesh.len() >= mesh_size {
+                break;
+            }
+            mesh.insert(peer_id);
+        }
+    }
+    
+    /// Get the connected peers subscribed to a topic
+    fn topic_peers(&self, topic: &Topic) -> Vec<PeerId> {
+        self.peer_topics.iter()
+            .filter(|(_, topics)| topics.contains(topic))
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Message;
+    use crate::types::{Transaction, TransactionType};
+    use crate::utils::crypto::KeyPair;
+    use ed25519_dalek::VerifyingKey;
+    
+    /// A transaction announcement for a shard
+    fn announcement(shard_id: ShardId, nonce: u64) -> MessageType {
+        let keypair = KeyPair::generate();
+        let sender = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+        let transaction = Transaction::new(TransactionType::Stake { amount: 1 }, sender, nonce, 1, 1);
+        MessageType::TransactionAnnounce { shard_id, transaction }
+    }
+    
+    /// A router with the given peers all subscribed to a topic
+    fn router_with_peers(topic: Topic, count: usize) -> (Gossip, Vec<PeerId>) {
+        let mut gossip = Gossip::new(GossipConfig::default());
+        let peers: Vec<PeerId> = (0..count).map(|_| PeerId::random()).collect();
+        
+        for peer_id in &peers {
+            gossip.add_peer(*peer_id);
+            gossip.handle_subscription(*peer_id, vec![topic], true);
+        }
+        
+        (gossip, peers)
+    }
+    
+    #[test]
+    fn derives_topics_from_payloads() {
+        let payload = announcement(ShardId(3), 0);
+        assert_eq!(Topic::for_message(&payload), Some(Topic::transactions(ShardId(3))));
+        assert_eq!(Topic::for_message(&MessageType::Ping { data: 1 }), None);
+        assert_eq!(Topic::transactions(ShardId(3)).to_string(), "/optimachain/transactions/shard-3");
+    }
+    
+    #[test]
+    fn grafts_subscribers_into_a_bounded_mesh() {
+        let topic = Topic::transactions(ShardId(0));
+        let (mut gossip, peers) = router_with_peers(topic, 20);
+        
+        gossip.subscribe(topic);
+        let mesh = gossip.publish(&topic, Message::calculate_id(&announcement(ShardId(0), 0)));
+        
+        assert_eq!(mesh.len(), gossip.config().mesh_size);
+        assert!(mesh.iter().all(|peer_id| peers.contains(peer_id)));
+    }
+    
+    #[test]
+    fn forwards_accepted_messages_once_and_not_back_to_the_source() {
+        let topic = Topic::transactions(ShardId(0));
+        let (mut gossip, peers) = router_with_peers(topic, 3);
+        gossip.subscribe(topic);
+        
+        let payload = announcement(ShardId(0), 0);
+        let message_id = Message::calculate_id(&payload);
+        
+        match gossip.handle_message(&peers[0], &topic, &message_id, &payload) {
+            GossipOutcome::Accepted { forward_to } => {
+                assert_eq!(forward_to.len(), 2);
+                assert!(!forward_to.contains(&peers[0]));
+            }
+            outcome => panic!("Unexpected outcome {:?}", outcome),
+        }
+        
+        assert_eq!(gossip.handle_message(&peers[1], &topic, &message_id, &payload), GossipOutcome::Duplicate);
+    }
+    
+    #[test]
+    fn rejects_payloads_on_the_wrong_topic() {
+        let topic = Topic::transactions(ShardId(0));
+        let (mut gossip, peers) = router_with_peers(topic, 1);
+        
+        let payload = announcement(ShardId(1), 0);
+        let outcome = gossip.handle_message(&peers[0], &topic, &Message::calculate_id(&payload), &payload);
+        assert!(matches!(outcome, GossipOutcome::Rejected { .. }));
+    }
+    
+    #[test]
+    fn applies_topic_validators() {
+        let topic = Topic::transactions(ShardId(0));
+        let (mut gossip, peers) = router_with_peers(topic, 1);
+        gossip.set_validator(TopicKind::Transactions, |_, payload| match payload {
+            MessageType::TransactionAnnounce { transaction, .. } if transaction.nonce == 0 => ValidationResult::Ignore,
+            _ => ValidationResult::Reject,
+        });
+        
+        let ignored = announcement(ShardId(0), 0);
+        let outcome = gossip.handle_message(&peers[0], &topic, &Message::calculate_id(&ignored), &ignored);
+        assert_eq!(outcome, GossipOutcome::Ignored);
+        
+        let rejected = announcement(ShardId(0), 1);
+        let outcome = gossip.handle_message(&peers[0], &topic, &Message::calculate_id(&rejected), &rejected);
+        assert!(matches!(outcome, GossipOutcome::Rejected { .. }));
+    }
+    
+    #[test]
+    fn drops_peers_that_unsubscribe_from_the_mesh() {
+        let topic = Topic::transactions(ShardId(0));
+        let (mut gossip, peers) = router_with_peers(topic, 2);
+        gossip.subscribe(topic);
+        
+        gossip.handle_subscription(peers[0], vec![topic], false);
+        gossip.remove_peer(&peers[1]);
+        gossip.heartbeat();
+        
+        let payload = announcement(ShardId(0), 0);
+        let outcome = gossip.handle_message(&PeerId::random(), &topic, &Message::calculate_id(&payload), &payload);
+        assert_eq!(outcome, GossipOutcome::Accepted { forward_to: Vec::new() });
+    }
+}
@@ -0,0 +1,349 @@
+use crate::network::MessageType;
+use crate::sharding::ShardId;
+use crate::types::BlockId;
+use libp2p::PeerId;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Current version of the wire protocol
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest wire protocol version this node still speaks
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Role a node plays in the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NodeRole {
+    /// Keeps recent state and serves blocks
+    Full,
+    /// Keeps all history and serves any block or state
+    Archive,
+    /// Keeps headers only and relies on proofs from full nodes
+    Light,
+}
+
+impl NodeRole {
+    /// Parse a role from its configuration name, defaulting to a full node
+    pub fn from_config(role: &str) -> Self {
+        match role {
+            "archive" => NodeRole::Archive,
+            "light" => NodeRole::Light,
+            _ => NodeRole::Full,
+        }
+    }
+}
+
+/// What a node offers to its peers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Node role
+    pub role: NodeRole,
+    /// Shards the node serves
+    pub shards: Vec<ShardId>,
+    /// Whether the node accepts compressed frames
+    pub compression: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            role: NodeRole::Full,
+            shards: Vec::new(),
+            compression: true,
+        }
+    }
+}
+
+/// Configuration for the connection handshake
+#[derive(Debug, Clone)]
+pub struct HandshakeConfig {
+    /// Chain identifier, e.g. "optimachain-testnet"
+    pub chain_id: String,
+    /// Genesis block of the chain; handshakes fail until it is set
+    pub genesis_block_id: Option<BlockId>,
+    /// Highest protocol version we speak
+    pub protocol_version: u32,
+    /// Lowest protocol version we still accept
+    pub min_protocol_version: u32,
+    /// Capabilities we advertise
+    pub capabilities: Capabilities,
+    /// Time a peer has to complete the handshake, in seconds
+    pub timeout: u64,
+}
+
+impl Default for HandshakeConfig {
+    fn default() -> Self {
+        HandshakeConfig {
+            chain_id: "optimachain-devnet".to_string(),
+            genesis_block_id: None,
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            capabilities: Capabilities::default(),
+            timeout: 10,
+        }
+    }
+}
+
+/// A peer that completed the handshake
+#[derive(Debug, Clone)]
+pub struct HandshakePeer {
+    /// Protocol version negotiated with the peer
+    pub protocol_version: u32,
+    /// Capabilities the peer advertised
+    pub capabilities: Capabilities,
+    /// Whether frames to and from the peer may be compressed
+    pub compression: bool,
+}
+
+/// Handshake state of connected peers
+pub struct Handshake {
+    /// Configuration
+    config: HandshakeConfig,
+    /// Our best block height, advertised in the handshake
+    best_block_height: u64,
+    /// Our best block ID, advertised in the handshake
+    best_block_id: BlockId,
+    /// Peers we sent our handshake to, with the time it was sent
+    pending: HashMap<PeerId, Instant>,
+    /// Peers that completed the handshake
+    established: HashMap<PeerId, HandshakePeer>,
+}
+
+impl Handshake {
+    /// Create a new handshake tracker
+    pub fn new(config: HandshakeConfig) -> Self {
+        Handshake {
+            config,
+            best_block_height: 0,
+            best_block_id: BlockId([0; 32]),
+            pending: HashMap::new(),
+            established: HashMap::new(),
+        }
+    }
+    
+    /// Get the configuration
+    pub fn config(&self) -> &HandshakeConfig {
+        &self.config
+    }
+    
+    /// Set the genesis block of the chain
+    pub fn set_genesis(&mut self, genesis_block_id: BlockId) {
+        self.config.genesis_block_id = Some(genesis_block_id);
+    }
+    
+    /// Set the chain head advertised to new peers
+    pub fn set_local_head(&mut self, height: u64, block_id: BlockId) {
+        self.best_block_height = height;
+        self.best_block_id = block_id;
+    }
+    
+    /// Start a handshake with a new peer, returning the message to send
+    ///
+    /// Fails if the genesis block is not set, as we could not tell whether the peer is on our chain.
+    pub fn start(&mut self, peer_id: PeerId) -> Result<MessageType, String> {
+        let genesis_block_id = self.config.genesis_block_id.clone()
+            .ok_or_else(|| "Genesis block is not set".to_string())?;
+        
+        self.established.remove(&peer_id);
+        self.pending.insert(peer_id, Instant::now());
+        
+        Ok(MessageType::Handshake {
+            chain_id: self.config.chain_id.clone(),
+            genesis_block_id,
+            protocol_version: self.config.protocol_version,
+            min_protocol_version: self.config.min_protocol_version,
+            capabilities: self.config.capabilities.clone(),
+            best_block_id: self.best_block_id.clone(),
+            best_block_height: self.best_block_height,
+        })
+    }
+    
+    /// Check a peer's handshake and negotiate the protocol version
+    pub fn on_handshake(
+        &mut self,
+        peer_id: &PeerId,
+        chain_id: &str,
+        genesis_block_id: &BlockId,
+        protocol_version: u32,
+        min_protocol_version: u32,
+        capabilities: Capabilities,
+    ) -> Result<&HandshakePeer, String> {
+        self.pending.remove(peer_id);
+        
+        if chain_id != self.config.chain_id {
+            return Err(format!("Chain ID mismatch: ours {}, theirs {}", self.config.chain_id, chain_id));
+        }
+        
+        match &self.config.genesis_block_id {
+            Some(ours) if ours == genesis_block_id => {}
+            Some(_) => return Err("Genesis block mismatch".to_string()),
+            None => return Err("Genesis block is not set".to_string()),
+        }
+        
+        // Speak the highest version both sides support; during a rolling upgrade
+        // upgraded nodes keep accepting the previous version until it is retired
+        let version = self.config.protocol_version.min(protocol_version);
+        if version < self.config.min_protocol_version || version < min_protocol_version {
+            return Err(format!(
+                "No common protocol version: ours {}..={}, theirs {}..={}",
+                self.config.min_protocol_version,
+                self.config.protocol_version,
+                min_protocol_version,
+                protocol_version
+            ));
+        }
+        
+        let compression = self.config.capabilities.compression && capabilities.compression;
+        self.established.insert(*peer_id, HandshakePeer {
+            protocol_version: version,
+            capabilities,
+            compression,
+        });
+        
+        Ok(&self.established[peer_id])
+    }
+    
+    /// Check if a peer completed the handshake
+    pub fn is_established(&self, peer_id: &PeerId) -> bool {
+        self.established.contains_key(peer_id)
+    }
+    
+    /// Check if we are waiting for a peer's handshake
+    pub fn is_pending(&self, peer_id: &PeerId) -> bool {
+        self.pending.contains_key(peer_id)
+    }
+    
+    /// Get a peer that completed the handshake
+    pub fn peer(&self, peer_id: &PeerId) -> Option<&HandshakePeer> {
+        self.established.get(peer_id)
+    }
+    
+    /// Get the peers that completed the handshake
+    pub fn established_peers(&self) -> Vec<PeerId> {
+        self.established.keys().copied().collect()
+    }
+    
+    /// Get the peers serving a shard
+    pub fn peers_serving_shard(&self, shard_id: ShardId) -> Vec<PeerId> {
+        self.established.iter()
+            .filter(|(_, peer)| peer.capabilities.shards.contains(&shard_id))
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+    
+    /// Get the peers that serve headers, finality and state proofs to light clients
+    pub fn light_servers(&self) -> Vec<PeerId> {
+        self.established.iter()
+            .filter(|(_, peer)| peer.capabilities.role != NodeRole::Light)
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+    
+    /// Get the peers with a given role
+    pub fn peers_with_role(&self, role: NodeRole) -> Vec<PeerId> {
+        self.established.iter()
+            .filter(|(_, peer)| peer.capabilities.role == role)
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+    
+    /// Forget a disconnected peer
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.pending.remove(peer_id);
+        self.established.remove(peer_id);
+    }
+    
+    /// Get the peers that did not complete the handshake in time
+    pub fn check_timeouts(&mut self) -> Vec<PeerId> {
+        let timeout = Duration::from_secs(self.config.timeout);
+        let timed_out: Vec<PeerId> = self.pending.iter()
+            .filter(|(_, sent_at)| sent_at.elapsed() > timeout)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        
+        for peer_id in &timed_out {
+            self.pending.remove(peer_id);
+        }
+        
+        timed_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn config(version: u32, min_version: u32) -> HandshakeConfig {
+        HandshakeConfig {
+            genesis_block_id: Some(BlockId([7; 32])),
+            protocol_version: version,
+            min_protocol_version: min_version,
+            ..HandshakeConfig::default()
+        }
+    }
+    
+    fn answer(handshake: &mut Handshake, peer_id: &PeerId, chain_id: &str, genesis: u8, version: u32, min_version: u32) -> Result<HandshakePeer, String> {
+        handshake.on_handshake(peer_id, chain_id, &BlockId([genesis; 32]), version, min_version, Capabilities::default()).cloned()
+    }
+    
+    #[test]
+    fn refuses_to_start_without_a_genesis_block() {
+        let mut handshake = Handshake::new(HandshakeConfig::default());
+        assert!(handshake.start(PeerId::random()).is_err());
+    }
+    
+    #[test]
+    fn negotiates_the_highest_common_version() {
+        let mut handshake = Handshake::new(config(3, 1));
+        let peer_id = PeerId::random();
+        handshake.start(peer_id).unwrap();
+        assert!(handshake.is_pending(&peer_id));
+        
+        let peer = answer(&mut handshake, &peer_id, "optimachain-devnet", 7, 2, 1).unwrap();
+        assert_eq!(peer.protocol_version, 2);
+        assert!(peer.compression);
+        assert!(handshake.is_established(&peer_id));
+        assert!(!handshake.is_pending(&peer_id));
+    }
+    
+    #[test]
+    fn rejects_other_chains_and_incompatible_versions() {
+        let mut handshake = Handshake::new(config(3, 2));
+        let peer_id = PeerId::random();
+        
+        assert!(answer(&mut handshake, &peer_id, "optimachain-mainnet", 7, 3, 1).unwrap_err().contains("Chain ID"));
+        assert!(answer(&mut handshake, &peer_id, "optimachain-devnet", 8, 3, 1).unwrap_err().contains("Genesis"));
+        assert!(answer(&mut handshake, &peer_id, "optimachain-devnet", 7, 1, 1).unwrap_err().contains("No common"));
+        assert!(answer(&mut handshake, &peer_id, "optimachain-devnet", 7, 5, 4).unwrap_err().contains("No common"));
+        assert!(!handshake.is_established(&peer_id));
+    }
+    
+    #[test]
+    fn times_out_unanswered_handshakes() {
+        let mut handshake = Handshake::new(HandshakeConfig { timeout: 0, ..config(1, 1) });
+        let peer_id = PeerId::random();
+        handshake.start(peer_id).unwrap();
+        
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(handshake.check_timeouts(), vec![peer_id]);
+        assert!(!handshake.is_pending(&peer_id));
+    }
+    
+    #[test]
+    fn selects_peers_by_role_and_shard() {
+        let mut handshake = Handshake::new(config(1, 1));
+        let full = PeerId::random();
+        let light = PeerId::random();
+        
+        let capabilities = Capabilities { shards: vec![ShardId(2)], ..Capabilities::default() };
+        handshake.on_handshake(&full, "optimachain-devnet", &BlockId([7; 32]), 1, 1, capabilities).unwrap();
+        let capabilities = Capabilities { role: NodeRole::Light, compression: false, ..Capabilities::default() };
+        let peer = handshake.on_handshake(&light, "optimachain-devnet", &BlockId([7; 32]), 1, 1, capabilities).unwrap();
+        assert!(!peer.compression);
+        
+        assert_eq!(handshake.peers_serving_shard(ShardId(2)), vec![full]);
+        assert_eq!(handshake.light_servers(), vec![full]);
+        assert_eq!(handshake.peers_with_role(NodeRole::Light), vec![light]);
+    }
+}
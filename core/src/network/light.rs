@@ -0,0 +1,369 @@
+use crate::consensus::{FinalityProof, ValidatorSetChange};
+use crate::network::MessageType;
+use crate::types::{Account, AccountId, AccountProof, BlockHeader, BlockId, StorageProof};
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::time::{Duration, Instant};
+
+/// Configuration for the light client
+#[derive(Debug, Clone)]
+pub struct LightClientConfig {
+    /// Fraction of the validator set that must sign a finality proof
+    pub finality_threshold: f64,
+    /// Maximum number of headers requested at once
+    pub max_headers_per_request: u32,
+    /// Maximum number of unfinalized headers kept above the finalized one
+    pub max_unfinalized_headers: usize,
+    /// Maximum number of validator set changes requested at once
+    pub max_validator_set_changes_per_request: u32,
+    /// Time between rounds of requests to a full node, in seconds
+    pub request_interval: u64,
+}
+
+impl Default for LightClientConfig {
+    fn default() -> Self {
+        LightClientConfig {
+            finality_threshold: 0.67,
+            max_headers_per_request: 128,
+            max_unfinalized_headers: 4096,
+            max_validator_set_changes_per_request: 16,
+            request_interval: 5,
+        }
+    }
+}
+
+/// Header-only chain follower that verifies everything against finality proofs
+///
+/// The client starts from a trusted header and validator set. Headers must
+/// link to known headers, a header is only trusted once a finality proof
+/// signed by the current validator set covers it, and the validator set only
+/// changes through a change signed by the set it replaces. Account and
+/// storage proofs are checked against the state root of the latest finalized
+/// header.
+pub struct LightClient {
+    /// Configuration
+    config: LightClientConfig,
+    /// Current validator set
+    validators: Vec<[u8; 32]>,
+    /// Height from which the current validator set signs
+    validator_set_height: u64,
+    /// Known headers by height
+    headers: BTreeMap<u64, BlockHeader>,
+    /// Latest finalized header
+    finalized: BlockHeader,
+    /// When the last round of requests was sent
+    last_request: Option<Instant>,
+}
+
+impl LightClient {
+    /// Create a light client from a trusted header and the validator set at that header
+    pub fn new(config: LightClientConfig, trusted_header: BlockHeader, validators: Vec<[u8; 32]>) -> Self {
+        let mut headers = BTreeMap::new();
+        headers.insert(trusted_header.height, trusted_header.clone());
+        
+        LightClient {
+            config,
+            validators,
+            validator_set_height: trusted_header.height,
+            headers,
+            finalized: trusted_header,
+            last_request: None,
+        }
+    }
+    
+    /// Get the latest finalized header
+    pub fn finalized_header(&self) -> &BlockHeader {
+        &self.finalized
+    }
+    
+    /// Get the current validator set
+    pub fn validators(&self) -> &[[u8; 32]] {
+        &self.validators
+    }
+    
+    /// Get a known header, finalized or not
+    pub fn header(&self, height: u64) -> Option<&BlockHeader> {
+        self.headers.get(&height)
+    }
+    
+    /// Get the highest known header
+    pub fn best_header(&self) -> &BlockHeader {
+        self.headers.values().next_back().unwrap_or(&self.finalized)
+    }
+    
+    /// Build a request for the headers following the best known one
+    pub fn headers_request(&self) -> MessageType {
+        MessageType::HeadersRequest {
+            start_height: self.best_header().height + 1,
+            count: self.config.max_headers_per_request,
+        }
+    }
+    
+    /// Build a request for the finality proof of the best known header
+    pub fn finality_proof_request(&self) -> MessageType {
+        MessageType::FinalityProofRequest {
+            height: self.best_header().height,
+        }
+    }
+    
+    /// Build a request for validator set changes after the current set
+    pub fn validator_set_changes_request(&self, count: u32) -> MessageType {
+        MessageType::ValidatorSetChangesRequest {
+            from_height: self.validator_set_height + 1,
+            count,
+        }
+    }
+    
+    /// Build a request for an account proof against the latest finalized state root
+    pub fn account_proof_request(&self, account_id: AccountId) -> MessageType {
+        MessageType::AccountProofRequest {
+            state_root: self.finalized.state_root.clone(),
+            account_id,
+        }
+    }
+    
+    /// Build a request for a storage proof against the latest finalized state root
+    pub fn storage_proof_request(&self, account_id: AccountId, key: Vec<u8>) -> MessageType {
+        MessageType::StorageProofRequest {
+            state_root: self.finalized.state_root.clone(),
+            account_id,
+            key,
+        }
+    }
+    
+    /// Get the requests to send to a full node, once per request interval
+    ///
+    /// Validator set changes come first so that the finality proof in the
+    /// same round can be checked against the newest set.
+    pub fn next_requests(&mut self) -> Vec<MessageType> {
+        let interval = Duration::from_secs(self.config.request_interval);
+        if self.last_request.is_some_and(|sent_at| sent_at.elapsed() < interval) {
+            return Vec::new();
+        }
+        self.last_request = Some(Instant::now());
+        
+        vec![
+            self.validator_set_changes_request(self.config.max_validator_set_changes_per_request),
+            self.headers_request(),
+            self.finality_proof_request(),
+        ]
+    }
+    
+    /// Add headers that extend the known chain, returning how many were new
+    pub fn on_headers(&mut self, headers: Vec<BlockHeader>) -> Result<usize, String> {
+        let mut added = 0;
+        
+        for header in headers {
+            if header.height <= self.finalized.height {
+                if self.headers.get(&header.height).is_some_and(|known| known.id() != header.id()) {
+                    return Err(format!("Header at height {} conflicts with the finalized chain", header.height));
+                }
+                continue;
+            }
+            
+            let parent_id = match self.headers.get(&(header.height - 1)) {
+                Some(parent) => parent.id(),
+                None => return Err(format!("Header at height {} does not follow a known header", header.height)),
+            };
+            if header.prev_block != parent_id {
+                return Err(format!("Header at height {} does not link to its parent", header.height));
+            }
+            
+            // A different header at a known height replaces the unfinalized branch above it
+            if self.headers.get(&header.height).is_some_and(|known| known.id() != header.id()) {
+                self.headers.split_off(&header.height);
+            }
+            
+            if let Entry::Vacant(entry) = self.headers.entry(header.height) {
+                entry.insert(header);
+                added += 1;
+            }
+        }
+        
+        while self.headers.range(self.finalized.height + 1..).count() > self.config.max_unfinalized_headers {
+            let highest = *self.headers.keys().next_back().expect("Headers are not empty");
+            self.headers.remove(&highest);
+        }
+        
+        Ok(added)
+    }
+    
+    /// Finalize a known header with a proof signed by the current validator set
+    pub fn on_finality_proof(&mut self, proof: &FinalityProof) -> Result<(), String> {
+        let header = self.headers.get(&proof.height)
+            .ok_or_else(|| format!("No header at height {}", proof.height))?;
+        
+        if header.id() != proof.block_id {
+            return Err(format!("Finality proof is for a different block at height {}", proof.height));
+        }
+        
+        if proof.height < self.validator_set_height {
+            return Err("Finality proof predates the current validator set".to_string());
+        }
+        
+        proof.verify(&self.validators, self.config.finality_threshold)?;
+        
+        if proof.height > self.finalized.height {
+            self.finalized = header.clone();
+            
+            // Headers below the finalized one are only kept for the finalized chain
+            let finalized_height = self.finalized.height;
+            self.headers = self.headers.split_off(&finalized_height);
+        }
+        
+        Ok(())
+    }
+    
+    /// Move to a new validator set signed by the current one
+    pub fn on_validator_set_change(&mut self, change: &ValidatorSetChange) -> Result<(), String> {
+        if change.height <= self.validator_set_height {
+            return Err(format!("Validator set change at height {} is not newer than ours", change.height));
+        }
+        
+        if change.validators.is_empty() {
+            return Err("Validator set change to an empty set".to_string());
+        }
+        
+        change.verify(&self.validators, self.config.finality_threshold)?;
+        
+        self.validators = change.validators.clone();
+        self.validator_set_height = change.height;
+        
+        Ok(())
+    }
+    
+    /// Verify an account proof against the latest finalized state root
+    pub fn verify_account(&self, account_id: &AccountId, proof: &AccountProof) -> Result<Option<Account>, String> {
+        proof.verify(account_id, &self.finalized.state_root)
+    }
+    
+    /// Verify a storage proof against the latest finalized state root
+    pub fn verify_storage(&self, account_id: &AccountId, proof: &StorageProof) -> Result<Option<Vec<u8>>, String> {
+        proof.verify(account_id, &self.finalized.state_root)
+    }
+    
+    /// Get the ID of the latest finalized block
+    pub fn finalized_block_id(&self) -> BlockId {
+        self.finalized.id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Block, State, StateRoot};
+    use crate::utils::crypto::KeyPair;
+    use ed25519_dalek::VerifyingKey;
+    
+    /// Signed headers following a parent, all with the same state root
+    fn headers(keypair: &KeyPair, parent: &BlockHeader, count: u64, state_root: &StateRoot) -> Vec<BlockHeader> {
+        let validator = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+        let mut prev = parent.clone();
+        
+        (0..count).map(|_| {
+            let mut block = Block::new(prev.height + 1, prev.id(), Vec::new(), state_root.clone(), validator, 0);
+            block.header.sign(keypair).unwrap();
+            prev = block.header.clone();
+            block.header
+        }).collect()
+    }
+    
+    /// A finality proof for a header signed by the key pairs
+    fn finality_proof(keypairs: &[&KeyPair], header: &BlockHeader) -> FinalityProof {
+        let mut proof = FinalityProof::new(header.id(), header.height);
+        for keypair in keypairs {
+            let validator = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+            proof.add_signature(validator, keypair.sign(&FinalityProof::signing_bytes(&header.id(), header.height)));
+        }
+        proof
+    }
+    
+    /// A light client trusting a genesis header produced by the key pair
+    fn light_client(keypair: &KeyPair, state_root: &StateRoot) -> LightClient {
+        let validator = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+        let mut genesis = Block::new(0, BlockId([0; 32]), Vec::new(), state_root.clone(), validator, 0);
+        genesis.header.sign(keypair).unwrap();
+        LightClient::new(LightClientConfig::default(), genesis.header, vec![keypair.public_key()])
+    }
+    
+    #[test]
+    fn finalizes_linked_headers_with_proofs_from_the_validator_set() {
+        let keypair = KeyPair::generate();
+        let mut client = light_client(&keypair, &StateRoot([0; 32]));
+        let headers = headers(&keypair, client.finalized_header(), 3, &StateRoot([0; 32]));
+        
+        assert_eq!(client.on_headers(headers.clone()).unwrap(), 3);
+        assert!(matches!(client.headers_request(), MessageType::HeadersRequest { start_height: 4, .. }));
+        
+        assert!(client.on_finality_proof(&finality_proof(&[&KeyPair::generate()], &headers[1])).is_err());
+        client.on_finality_proof(&finality_proof(&[&keypair], &headers[1])).unwrap();
+        assert_eq!(client.finalized_block_id(), headers[1].id());
+        assert!(client.header(0).is_none());
+    }
+    
+    #[test]
+    fn rejects_unlinked_and_conflicting_headers() {
+        let keypair = KeyPair::generate();
+        let mut client = light_client(&keypair, &StateRoot([0; 32]));
+        let genesis = client.finalized_header().clone();
+        let headers = headers(&keypair, &genesis, 2, &StateRoot([0; 32]));
+        
+        assert!(client.on_headers(headers[1..].to_vec()).is_err());
+        
+        client.on_headers(headers.clone()).unwrap();
+        client.on_finality_proof(&finality_proof(&[&keypair], &headers[1])).unwrap();
+        
+        let conflicting = self::headers(&keypair, &headers[0], 1, &StateRoot([1; 32]));
+        assert!(client.on_headers(conflicting).is_err());
+    }
+    
+    #[test]
+    fn follows_validator_set_changes_signed_by_the_previous_set() {
+        let (keypair, next) = (KeyPair::generate(), KeyPair::generate());
+        let mut client = light_client(&keypair, &StateRoot([0; 32]));
+        
+        let mut change = ValidatorSetChange {
+            height: 5,
+            block_id: BlockId([5; 32]),
+            validators: vec![next.public_key()],
+            signatures: Vec::new(),
+        };
+        let bytes = change.signing_bytes().unwrap();
+        change.signatures.push((next.public_key(), next.sign(&bytes)));
+        assert!(client.on_validator_set_change(&change).is_err());
+        
+        change.signatures = vec![(keypair.public_key(), keypair.sign(&bytes))];
+        client.on_validator_set_change(&change).unwrap();
+        assert_eq!(client.validators(), [next.public_key()]);
+        assert!(client.on_validator_set_change(&change).is_err());
+        assert!(matches!(client.validator_set_changes_request(1), MessageType::ValidatorSetChangesRequest { from_height: 6, .. }));
+    }
+    
+    #[test]
+    fn verifies_account_proofs_against_the_finalized_root() {
+        let keypair = KeyPair::generate();
+        let account_id = AccountId([7; 32]);
+        let state = State::from_accounts(vec![Account::new_user(account_id.clone())]);
+        let client = light_client(&keypair, &state.root);
+        
+        let account = client.verify_account(&account_id, &state.prove_account(&account_id)).unwrap();
+        assert_eq!(account.map(|account| account.id), Some(account_id.clone()));
+        
+        let other = State::from_accounts(vec![Account::new_user(account_id.clone()), Account::new_user(AccountId([8; 32]))]);
+        assert!(client.verify_account(&account_id, &other.prove_account(&account_id)).is_err());
+        assert!(matches!(client.account_proof_request(account_id), MessageType::AccountProofRequest { state_root, .. } if state_root == state.root));
+    }
+    
+    #[test]
+    fn sends_requests_once_per_interval() {
+        let keypair = KeyPair::generate();
+        let mut client = light_client(&keypair, &StateRoot([0; 32]));
+        
+        let requests = client.next_requests();
+        assert!(matches!(requests[..], [
+            MessageType::ValidatorSetChangesRequest { .. },
+            MessageType::HeadersRequest { .. },
+            MessageType::FinalityProofRequest { .. },
+        ]));
+        assert!(client.next_requests().is_empty());
+    }
+}
@@ -1,4 +1,7 @@
-use crate::types::{Block, BlockId, Transaction, TransactionId};
+use crate::consensus::{FinalityProof, SignedConsensusMessage, ValidatorSetChange};
+use crate::network::{Capabilities, StateChunk, StateManifest, Topic};
+use crate::sharding::{BeaconBlock, CrossLink, ShardId};
+use crate::types::{AccountId, AccountProof, Block, BlockHeader, BlockId, StateRoot, StorageProof, Transaction, TransactionId};
 use serde::{Serialize, Deserialize};
 use sha3::{Sha3_256, Digest};
 
@@ -26,25 +29,56 @@ pub enum MessageType {
     },
     /// Announce a new transaction
     TransactionAnnounce {
+        /// Shard the transaction is submitted to
+        shard_id: ShardId,
         /// The transaction being announced
         transaction: Transaction,
     },
-    /// Request a transaction by ID
+    /// Transaction in the Dandelion++ stem phase, relayed to a single peer
+    StemTransaction {
+        /// Shard the transaction is submitted to
+        shard_id: ShardId,
+        /// The transaction
+        transaction: Transaction,
+    },
+    /// Request several transactions by ID
     TransactionRequest {
-        /// ID of the transaction being requested
-        transaction_id: TransactionId,
+        /// IDs of the transactions being requested
+        transaction_ids: Vec<TransactionId>,
     },
     /// Response to a transaction request
     TransactionResponse {
-        /// The requested transaction
-        transaction: Transaction,
+        /// The requested transactions that were found
+        transactions: Vec<Transaction>,
+    },
+    /// Request the full list of transactions of a block
+    BlockBodyRequest {
+        /// ID of the block
+        block_id: BlockId,
+    },
+    /// Response to a block body request
+    BlockBodyResponse {
+        /// ID of the block
+        block_id: BlockId,
+        /// The block's transactions, in block order
+        transactions: Vec<Transaction>,
     },
     /// Consensus message
     ConsensusMessage {
-        /// Round number
-        round: u64,
-        /// Consensus data
-        data: Vec<u8>,
+        /// Shard whose consensus the message belongs to
+        shard_id: ShardId,
+        /// Signed consensus payload
+        message: SignedConsensusMessage,
+    },
+    /// Announce a new beacon block
+    BeaconBlockAnnounce {
+        /// The beacon block being announced
+        block: BeaconBlock,
+    },
+    /// Announce shard headers for the next beacon block
+    CrossLinkAnnounce {
+        /// The cross-link being announced
+        link: CrossLink,
     },
     /// Peer discovery message
     DiscoveryMessage {
@@ -62,6 +96,28 @@ pub enum MessageType {
         /// Genesis block ID
         genesis_block_id: BlockId,
     },
+    /// Handshake sent on every new connection
+    Handshake {
+        /// Chain identifier
+        chain_id: String,
+        /// Genesis block ID
+        genesis_block_id: BlockId,
+        /// Highest protocol version the sender speaks
+        protocol_version: u32,
+        /// Lowest protocol version the sender accepts
+        min_protocol_version: u32,
+        /// Capabilities of the sender
+        capabilities: Capabilities,
+        /// Best block ID
+        best_block_id: BlockId,
+        /// Best block height
+        best_block_height: u64,
+    },
+    /// Rejection of a peer's handshake
+    HandshakeReject {
+        /// Reason for the rejection
+        reason: String,
+    },
     /// Ping message
     Ping {
         /// Ping data
@@ -72,6 +128,138 @@ pub enum MessageType {
         /// Pong data (should match the ping data)
         data: u64,
     },
+    /// Gossip subscription change
+    Subscription {
+        /// Whether the topics are being subscribed to or unsubscribed from
+        subscribe: bool,
+        /// Topics affected
+        topics: Vec<Topic>,
+    },
+    /// Message published on a gossip topic
+    Gossip {
+        /// Topic the message was published on
+        topic: Topic,
+        /// Published payload
+        payload: Box<MessageType>,
+    },
+    /// Request a range of block headers by height
+    HeadersRequest {
+        /// Height of the first requested header
+        start_height: u64,
+        /// Number of headers requested
+        count: u32,
+    },
+    /// Response to a header range request
+    HeadersResponse {
+        /// Headers in ascending height order
+        headers: Vec<BlockHeader>,
+    },
+    /// Request several blocks by ID
+    BlocksRequest {
+        /// IDs of the blocks being requested
+        block_ids: Vec<BlockId>,
+    },
+    /// Response to a multi-block request
+    BlocksResponse {
+        /// The requested blocks that were found
+        blocks: Vec<Block>,
+    },
+    /// Request the manifest of a state snapshot
+    StateManifestRequest {
+        /// Height of the snapshot
+        height: u64,
+        /// Expected state root of the snapshot
+        state_root: StateRoot,
+    },
+    /// Response to a state manifest request
+    StateManifestResponse {
+        /// The manifest, if the snapshot is available
+        manifest: Option<StateManifest>,
+    },
+    /// Request a chunk of a state snapshot
+    StateChunkRequest {
+        /// State root of the snapshot
+        state_root: StateRoot,
+        /// Index of the chunk
+        index: u32,
+    },
+    /// Response to a state chunk request
+    StateChunkResponse {
+        /// The requested chunk
+        chunk: StateChunk,
+    },
+    /// Request the finality proof of the block at a height
+    FinalityProofRequest {
+        /// Block height
+        height: u64,
+    },
+    /// Response to a finality proof request
+    FinalityProofResponse {
+        /// The proof, if the block is finalized
+        proof: Option<FinalityProof>,
+    },
+    /// Request validator set changes
+    ValidatorSetChangesRequest {
+        /// Lowest height of a change to return
+        from_height: u64,
+        /// Maximum number of changes
+        count: u32,
+    },
+    /// Response to a validator set changes request
+    ValidatorSetChangesResponse {
+        /// Changes in ascending height order
+        changes: Vec<ValidatorSetChange>,
+    },
+    /// Request a proof of an account against a state root
+    AccountProofRequest {
+        /// State root to prove against
+        state_root: StateRoot,
+        /// Account to prove
+        account_id: AccountId,
+    },
+    /// Response to an account proof request
+    AccountProofResponse {
+        /// State root the proof is against
+        state_root: StateRoot,
+        /// Proven account
+        account_id: AccountId,
+        /// The proof, if the state is available
+        proof: Option<AccountProof>,
+    },
+    /// Request a proof of a contract storage value against a state root
+    StorageProofRequest {
+        /// State root to prove against
+        state_root: StateRoot,
+        /// Account holding the storage
+        account_id: AccountId,
+        /// Storage key
+        key: Vec<u8>,
+    },
+    /// Response to a storage proof request
+    StorageProofResponse {
+        /// State root the proof is against
+        state_root: StateRoot,
+        /// Account holding the storage
+        account_id: AccountId,
+        /// The proof, if the state is available
+        proof: Option<StorageProof>,
+    },
+}
+
+impl MessageType {
+    /// Check if this payload is relayed between peers rather than answering a request
+    ///
+    /// Only relayed payloads are deduplicated; a peer may legitimately repeat a request.
+    pub fn is_announcement(&self) -> bool {
+        matches!(
+            self,
+            MessageType::BlockAnnounce { .. }
+                | MessageType::TransactionAnnounce { .. }
+                | MessageType::ConsensusMessage { .. }
+                | MessageType::BeaconBlockAnnounce { .. }
+                | MessageType::CrossLinkAnnounce { .. }
+        )
+    }
 }
 
 /// A message that can be sent over the network
@@ -83,7 +271,7 @@ pub struct Message {
     pub message_type: MessageType,
     /// Timestamp when the message was created
     pub timestamp: u64,
-    /// TTL (time to live) for the message
+    /// TTL (time to live) for the message, as the number of hops it may still travel
     pub ttl: u8,
 }
 
@@ -95,85 +283,24 @@ impl Message {
             .expect("Time went backwards")
             .as_secs();
         
-        let mut message = Message {
-            id: MessageId([0; 32]), // Placeholder, will be calculated below
+        Message {
+            id: Message::calculate_id(&message_type),
             message_type,
             timestamp,
             ttl,
-        };
-        
-        // Calculate message ID
-        message.id = message.calculate_id();
-        
-        message
+        }
     }
     
-    /// Calculate the ID of this message
-    fn calculate_id(&self) -> MessageId {
-        // In a real implementation, we would serialize the message and hash it
-        // For now, just use a simple hash of the timestamp and message type
-        let mut hasher = Sha3_256::new();
-        
-        // Add timestamp to hash
-        hasher.update(self.timestamp.to_be_bytes());
-        
-        // Add TTL to hash
-        hasher.update([self.ttl]);
-        
-        // Add a type-specific value to the hash
-        match &self.message_type {
-            MessageType::BlockAnnounce { block } => {
-                hasher.update(b"BlockAnnounce");
-                hasher.update(block.id().0);
-            }
-            MessageType::BlockRequest { block_id } => {
-                hasher.update(b"BlockRequest");
-                hasher.update(block_id.0);
-            }
-            MessageType::BlockResponse { block } => {
-                hasher.update(b"BlockResponse");
-                hasher.update(block.id().0);
-            }
-            MessageType::TransactionAnnounce { transaction } => {
-                hasher.update(b"TransactionAnnounce");
-                hasher.update(transaction.id().0);
-            }
-            MessageType::TransactionRequest { transaction_id } => {
-                hasher.update(b"TransactionRequest");
-                hasher.update(transaction_id.0);
-            }
-            MessageType::TransactionResponse { transaction } => {
-                hasher.update(b"TransactionResponse");
-                hasher.update(transaction.id().0);
-            }
-            MessageType::ConsensusMessage { round, data } => {
-                hasher.update(b"ConsensusMessage");
-                hasher.update(round.to_be_bytes());
-                hasher.update(data);
-            }
-            MessageType::DiscoveryMessage { addresses } => {
-                hasher.update(b"DiscoveryMessage");
-                for address in addresses {
-                    hasher.update(address.as_bytes());
-                }
-            }
-            MessageType::StatusMessage { protocol_version, best_block_id, best_block_height, genesis_block_id } => {
-                hasher.update(b"StatusMessage");
-                hasher.update(protocol_version.as_bytes());
-                hasher.update(best_block_id.0);
-                hasher.update(best_block_height.to_be_bytes());
-                hasher.update(genesis_block_id.0);
-            }
-            MessageType::Ping { data } => {
-                hasher.update(b"Ping");
-                hasher.update(data.to_be_bytes());
-            }
-            MessageType::Pong { data } => {
-                hasher.update(b"Pong");
-                hasher.update(data.to_be_bytes());
-            }
-        }
+    /// Calculate the ID of a message payload
+    ///
+    /// The ID is the hash of the canonical encoding of the full payload, so
+    /// identical payloads share an ID regardless of when or how far they were sent.
+    pub fn calculate_id(message_type: &MessageType) -> MessageId {
+        let encoded = bincode::serialize(message_type).expect("Message payloads are always serializable");
         
+        let mut hasher = Sha3_256::new();
+        hasher.update(b"optimachain-message");
+        hasher.update(&encoded);
         let result = hasher.finalize();
         
         let mut id = [0u8; 32];
@@ -181,25 +308,18 @@ impl Message {
         MessageId(id)
     }
     
-    /// Check if the message has expired
+    /// Check that the message ID matches its payload
+    pub fn has_valid_id(&self) -> bool {
+        self.id == Message::calculate_id(&self.message_type)
+    }
+    
+    /// Check if the message has no hops left
     pub fn is_expired(&self) -> bool {
-        if self.ttl == 0 {
-            return true;
-        }
-        
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
-        
-        // TTL is in seconds
-        now > self.timestamp + self.ttl as u64
+        self.ttl == 0
     }
     
-    /// Decrement the TTL of the message
+    /// Consume one hop of the message's TTL
     pub fn decrement_ttl(&mut self) {
-        if self.ttl > 0 {
-            self.ttl -= 1;
-        }
+        self.ttl = self.ttl.saturating_sub(1);
     }
 }
@@ -6,8 +6,30 @@ mod discovery;
 mod transport;
 mod protocol;
 mod message;
+mod gossip;
+mod sync;
+mod state_sync;
+mod peer_score;
+mod seen_cache;
+mod handshake;
+mod codec;
+mod rate_limit;
+mod compact;
+mod dandelion;
+mod light;
 
-pub use discovery::{Discovery, DiscoveryConfig, PeerInfo};
-pub use transport::{Transport, TransportConfig};
+pub use discovery::{Discovery, DiscoveryConfig, PeerInfo, peer_id_from_addr};
+pub use transport::{Transport, TransportConfig, TransportKind, LinkConditions, ConditionedStream, memory_address};
 pub use protocol::{Protocol, ProtocolConfig, ProtocolEvent};
 pub use message::{Message, MessageType, MessageId};
+pub use gossip::{Gossip, GossipConfig, GossipOutcome, Topic, TopicKind, ValidationResult, MessageValidator};
+pub use sync::{BlockSync, SyncConfig, SyncEvent, SyncProgress, SyncState};
+pub use seen_cache::SeenCache;
+pub use light::{LightClient, LightClientConfig};
+pub use dandelion::{Dandelion, DandelionConfig, DandelionRoute};
+pub use compact::{BlockReconstructor, CompactBlockConfig, CompactAction, CompleteBlock, Reconstruction};
+pub use rate_limit::{RateLimiter, RateLimitConfig, BucketConfig, TokenBucket, OutboundQueue, OverflowPolicy, MessageClass, TrafficCounters};
+pub use codec::{ProtocolCodec, CodecError, WIRE_VERSION, FRAME_HEADER_SIZE};
+pub use handshake::{Handshake, HandshakeConfig, HandshakePeer, Capabilities, NodeRole, PROTOCOL_VERSION, MIN_PROTOCOL_VERSION};
+pub use peer_score::{PeerScoring, PeerScoreConfig, PeerScoreEntry, PeerBan, Misbehavior};
+pub use state_sync::{StateSync, StateSyncConfig, StateSyncProgress, StateManifest, StateChunk, StateSnapshot};
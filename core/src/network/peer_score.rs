@@ -0,0 +1,398 @@
+use crate::storage::{Database, MetadataKey};
+use libp2p::PeerId;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Metadata key under which active bans are persisted
+const BANS_KEY: &str = "peer_bans";
+
+/// Scores closer to zero than this are forgotten at the next decay step
+const NEUTRAL_SCORE: f64 = 0.01;
+
+/// Configuration for peer scoring
+#[derive(Debug, Clone)]
+pub struct PeerScoreConfig {
+    /// Score below which a peer is disconnected and banned
+    pub ban_threshold: f64,
+    /// Upper bound on a peer's score
+    pub max_score: f64,
+    /// Ban duration in seconds
+    pub ban_duration: u64,
+    /// Fraction of the score kept at every decay step
+    pub decay_factor: f64,
+    /// Reward for a useful response or valid gossip
+    pub useful_reward: f64,
+    /// Penalty for an invalid block
+    pub invalid_block_penalty: f64,
+    /// Penalty for an invalid transaction
+    pub invalid_transaction_penalty: f64,
+    /// Penalty for a malformed, invalid or unrequested message
+    pub invalid_message_penalty: f64,
+    /// Penalty for a timed-out request
+    pub timeout_penalty: f64,
+    /// Penalty for exceeding the message rate
+    pub spam_penalty: f64,
+    /// Penalty for a forged or misattributed signature
+    pub invalid_signature_penalty: f64,
+    /// Seconds between decay steps
+    pub decay_interval: u64,
+    /// Maximum number of peers whose score is remembered
+    pub max_tracked_peers: usize,
+}
+
+impl Default for PeerScoreConfig {
+    fn default() -> Self {
+        PeerScoreConfig {
+            ban_threshold: -100.0,
+            max_score: 100.0,
+            ban_duration: 3600,
+            decay_factor: 0.9,
+            useful_reward: 1.0,
+            invalid_block_penalty: 50.0,
+            invalid_transaction_penalty: 10.0,
+            invalid_message_penalty: 20.0,
+            timeout_penalty: 5.0,
+            spam_penalty: 10.0,
+            invalid_signature_penalty: 100.0,
+            decay_interval: 60,
+            max_tracked_peers: 10_000,
+        }
+    }
+}
+
+/// Kinds of peer misbehavior
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// Peer relayed an invalid block
+    InvalidBlock,
+    /// Peer relayed an invalid transaction
+    InvalidTransaction,
+    /// Peer sent a malformed, invalid or unrequested message
+    InvalidMessage,
+    /// Peer did not answer a request in time
+    Timeout,
+    /// Peer exceeded the message rate
+    Spam,
+    /// Peer sent a message with a forged or misattributed signature
+    InvalidSignature,
+}
+
+/// Score table entry for a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerScoreEntry {
+    /// Current score
+    pub score: f64,
+    /// Number of useful responses
+    pub useful_responses: u64,
+    /// Number of recorded misbehaviors
+    pub violations: u64,
+    /// Last recorded misbehavior
+    pub last_violation: Option<String>,
+}
+
+/// An active ban
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerBan {
+    /// Banned peer
+    pub peer_id: String,
+    /// Unix time at which the ban expires
+    pub until: u64,
+    /// Reason for the ban
+    pub reason: String,
+}
+
+/// Tracks peer quality and bans peers that fall below the threshold
+pub struct PeerScoring {
+    /// Configuration
+    config: PeerScoreConfig,
+    /// Scores of known peers
+    scores: HashMap<PeerId, PeerScoreEntry>,
+    /// Active bans
+    bans: HashMap<PeerId, PeerBan>,
+    /// Whether bans changed since they were last persisted
+    unsaved_bans: bool,
+    /// Time of the last decay step
+    last_decay: Instant,
+}
+
+impl PeerScoring {
+    /// Create a new peer scoring component
+    pub fn new(config: PeerScoreConfig) -> Self {
+        PeerScoring {
+            config,
+            scores: HashMap::new(),
+            bans: HashMap::new(),
+            unsaved_bans: false,
+            last_decay: Instant::now(),
+        }
+    }
+    
+    /// Load persisted bans, dropping those that have expired
+    pub fn load_bans(&mut self, database: &Database) -> Result<(), String> {
+        let stored = database.get(&MetadataKey { key: BANS_KEY.to_string() })
+            .map_err(|e| e.to_string())?;
+        
+        if let Some(bytes) = stored {
+            let bans: Vec<PeerBan> = bincode::deserialize(&bytes)
+                .map_err(|e| format!("Failed to decode peer bans: {}", e))?;
+            
+            let now = now();
+            for ban in bans.into_iter().filter(|ban| ban.until > now) {
+                match PeerId::from_str(&ban.peer_id) {
+                    Ok(peer_id) => {
+                        self.bans.insert(peer_id, ban);
+                    }
+                    Err(e) => log::warn!("Skipping stored ban of invalid peer {}: {}", ban.peer_id, e),
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Persist the active bans
+    pub fn save_bans(&mut self, database: &Database) -> Result<(), String> {
+        let bans: Vec<&PeerBan> = self.bans.values().collect();
+        let bytes = bincode::serialize(&bans)
+            .map_err(|e| format!("Failed to encode peer bans: {}", e))?;
+        
+        database.put(&MetadataKey { key: BANS_KEY.to_string() }, &bytes)
+            .map_err(|e| e.to_string())?;
+        self.unsaved_bans = false;
+        
+        Ok(())
+    }
+    
+    /// Check if bans were issued, lifted or expired since they were last persisted
+    pub fn has_unsaved_bans(&self) -> bool {
+        self.unsaved_bans
+    }
+    
+    /// Record a useful response or valid gossip message from a peer
+    pub fn record_useful(&mut self, peer_id: &PeerId) {
+        let max_score = self.config.max_score;
+        let reward = self.config.useful_reward;
+        let entry = self.entry(peer_id);
+        
+        entry.score = (entry.score + reward).min(max_score);
+        entry.useful_responses += 1;
+    }
+    
+    /// Record a misbehavior, returning the new ban if the peer fell below the threshold
+    pub fn record_misbehavior(&mut self, peer_id: &PeerId, misbehavior: Misbehavior, reason: &str) -> Option<PeerBan> {
+        let penalty = match misbehavior {
+            Misbehavior::InvalidBlock => self.config.invalid_block_penalty,
+            Misbehavior::InvalidTransaction => self.config.invalid_transaction_penalty,
+            Misbehavior::InvalidMessage => self.config.invalid_message_penalty,
+            Misbehavior::Timeout => self.config.timeout_penalty,
+            Misbehavior::Spam => self.config.spam_penalty,
+            Misbehavior::InvalidSignature => self.config.invalid_signature_penalty,
+        };
+        
+        let entry = self.entry(peer_id);
+        entry.score -= penalty;
+        entry.violations += 1;
+        entry.last_violation = Some(reason.to_string());
+        
+        let score = entry.score;
+        if score >= self.config.ban_threshold || self.is_banned(peer_id) {
+            return None;
+        }
+        
+        let reason = format!("Score {:.1} below threshold: {}", score, reason);
+        Some(self.ban(*peer_id, Duration::from_secs(self.config.ban_duration), reason))
+    }
+    
+    /// Ban a peer for a duration
+    pub fn ban(&mut self, peer_id: PeerId, duration: Duration, reason: String) -> PeerBan {
+        let ban = PeerBan {
+            peer_id: peer_id.to_string(),
+            until: now() + duration.as_secs(),
+            reason,
+        };
+        
+        log::warn!("Banning peer {} until {}: {}", peer_id, ban.until, ban.reason);
+        
+        self.bans.insert(peer_id, ban.clone());
+        self.unsaved_bans = true;
+        ban
+    }
+    
+    /// Lift a ban and reset the peer's score
+    pub fn unban(&mut self, peer_id: &PeerId) -> bool {
+        self.scores.remove(peer_id);
+        let unbanned = self.bans.remove(peer_id).is_some();
+        self.unsaved_bans |= unbanned;
+        unbanned
+    }
+    
+    /// Check if a peer is currently banned
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.bans.get(peer_id).is_some_and(|ban| ban.until > now())
+    }
+    
+    /// Get a peer's score
+    pub fn score(&self, peer_id: &PeerId) -> f64 {
+        self.scores.get(peer_id).map_or(0.0, |entry| entry.score)
+    }
+    
+    /// Get the score table
+    pub fn scores(&self) -> &HashMap<PeerId, PeerScoreEntry> {
+        &self.scores
+    }
+    
+    /// Get the active bans
+    pub fn bans(&self) -> &HashMap<PeerId, PeerBan> {
+        &self.bans
+    }
+    
+    /// Decay scores towards zero once per decay interval, and drop expired bans
+    ///
+    /// Peers whose score is back to neutral are forgotten.
+    pub fn decay(&mut self) {
+        if self.last_decay.elapsed() < Duration::from_secs(self.config.decay_interval) {
+            return;
+        }
+        self.last_decay = Instant::now();
+        
+        let factor = self.config.decay_factor;
+        for entry in self.scores.values_mut() {
+            entry.score *= factor;
+        }
+        
+        let bans = &self.bans;
+        self.scores.retain(|peer_id, entry| entry.score.abs() >= NEUTRAL_SCORE || bans.contains_key(peer_id));
+        
+        let now = now();
+        let expired: Vec<PeerId> = self.bans.iter()
+            .filter(|(_, ban)| ban.until <= now)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        
+        for peer_id in expired {
+            log::info!("Ban of peer {} expired", peer_id);
+            self.bans.remove(&peer_id);
+            self.scores.remove(&peer_id);
+            self.unsaved_bans = true;
+        }
+    }
+    
+    /// Get or create a peer's score entry
+    ///
+    /// At the tracking limit, the unbanned peer closest to neutral is forgotten to make room.
+    fn entry(&mut self, peer_id: &PeerId) -> &mut PeerScoreEntry {
+        if !self.scores.contains_key(peer_id) && self.scores.len() >= self.config.max_tracked_peers {
+            let bans = &self.bans;
+            let closest = self.scores.iter()
+                .filter(|(tracked, _)| !bans.contains_key(tracked))
+                .min_by(|a, b| a.1.score.abs().total_cmp(&b.1.score.abs()))
+                .map(|(tracked, _)| *tracked);
+            
+            if let Some(tracked) = closest {
+                self.scores.remove(&tracked);
+            }
+        }
+        
+        self.scores.entry(*peer_id).or_insert(PeerScoreEntry {
+            score: 0.0,
+            useful_responses: 0,
+            violations: 0,
+            last_violation: None,
+        })
+    }
+}
+
+/// Get the current Unix time in seconds
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::temporary_database;
+    
+    #[test]
+    fn bans_peers_below_the_threshold() {
+        let mut scoring = PeerScoring::new(PeerScoreConfig::default());
+        let peer_id = PeerId::random();
+        
+        for _ in 0..2 {
+            assert!(scoring.record_misbehavior(&peer_id, Misbehavior::InvalidBlock, "bad block").is_none());
+        }
+        let ban = scoring.record_misbehavior(&peer_id, Misbehavior::InvalidBlock, "bad block").unwrap();
+        
+        assert!(ban.reason.contains("bad block"));
+        assert!(scoring.is_banned(&peer_id));
+        assert!(scoring.has_unsaved_bans());
+        assert_eq!(scoring.scores()[&peer_id].violations, 3);
+    }
+    
+    #[test]
+    fn caps_rewards_at_the_maximum_score() {
+        let config = PeerScoreConfig { max_score: 2.0, ..PeerScoreConfig::default() };
+        let mut scoring = PeerScoring::new(config);
+        let peer_id = PeerId::random();
+        
+        for _ in 0..5 {
+            scoring.record_useful(&peer_id);
+        }
+        
+        assert_eq!(scoring.score(&peer_id), 2.0);
+    }
+    
+    #[test]
+    fn enforces_the_tracking_limit_on_insert() {
+        let config = PeerScoreConfig { max_tracked_peers: 3, ..PeerScoreConfig::default() };
+        let mut scoring = PeerScoring::new(config);
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        
+        scoring.record_misbehavior(&peers[0], Misbehavior::Timeout, "slow");
+        scoring.record_useful(&peers[1]);
+        scoring.record_misbehavior(&peers[2], Misbehavior::InvalidMessage, "junk");
+        
+        let newcomer = PeerId::random();
+        scoring.record_useful(&newcomer);
+        
+        assert_eq!(scoring.scores().len(), 3);
+        assert!(!scoring.scores().contains_key(&peers[1]));
+        assert!(scoring.scores().contains_key(&newcomer));
+    }
+    
+    #[test]
+    fn keeps_banned_peers_when_making_room() {
+        let config = PeerScoreConfig { max_tracked_peers: 1, ..PeerScoreConfig::default() };
+        let mut scoring = PeerScoring::new(config);
+        let banned = PeerId::random();
+        scoring.record_misbehavior(&banned, Misbehavior::InvalidSignature, "forged");
+        scoring.record_misbehavior(&banned, Misbehavior::InvalidSignature, "forged");
+        assert!(scoring.is_banned(&banned));
+        
+        scoring.record_useful(&PeerId::random());
+        assert!(scoring.scores().contains_key(&banned));
+    }
+    
+    #[test]
+    fn persists_and_lifts_bans() {
+        let (_directory, database) = temporary_database();
+        let peer_id = PeerId::random();
+        
+        let mut scoring = PeerScoring::new(PeerScoreConfig::default());
+        scoring.ban(peer_id, Duration::from_secs(60), "manual".to_string());
+        scoring.save_bans(&database).unwrap();
+        assert!(!scoring.has_unsaved_bans());
+        
+        let mut restored = PeerScoring::new(PeerScoreConfig::default());
+        restored.load_bans(&database).unwrap();
+        assert!(restored.is_banned(&peer_id));
+        
+        assert!(restored.unban(&peer_id));
+        assert!(!restored.is_banned(&peer_id));
+        assert!(restored.has_unsaved_bans());
+    }
+}
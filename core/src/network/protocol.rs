@@ -1,9 +1,19 @@
-use crate::network::{Message, MessageId, MessageType};
-use crate::types::{Block, Transaction};
+use crate::consensus::{FinalityProof, SignedConsensusMessage, ValidatorSetChange};
+use crate::mempool::{Mempool, MempoolConfig};
+use crate::network::{Message, MessageId, MessageType, Gossip, GossipConfig, GossipOutcome, Topic, TopicKind, ValidationResult};
+use crate::network::{BlockSync, SyncConfig, SyncEvent, SyncProgress, StateChunk, StateManifest};
+use crate::network::{Misbehavior, PeerScoreConfig, PeerScoring, SeenCache};
+use crate::network::{Capabilities, Handshake, HandshakeConfig, NodeRole, ProtocolCodec};
+use crate::network::{Dandelion, DandelionConfig, DandelionRoute};
+use crate::network::{BlockReconstructor, CompactAction, CompactBlockConfig, CompleteBlock, Reconstruction};
+use crate::network::{MessageClass, OutboundQueue, OverflowPolicy, RateLimitConfig, RateLimiter, TrafficCounters};
+use crate::sharding::{BeaconBlock, CrossLink, ShardId};
+use crate::types::{AccountId, AccountProof, Block, BlockHeader, BlockId, StateRoot, StorageProof, Transaction};
 use libp2p::PeerId;
 use serde::{Serialize, Deserialize};
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::time::{Duration, Instant};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 /// Name of the protocol
 #[derive(Debug, Clone)]
@@ -29,6 +39,26 @@ pub struct ProtocolConfig {
     pub max_message_size: usize,
     /// Maximum number of concurrent requests
     pub max_concurrent_requests: usize,
+    /// Number of recently seen announcement IDs to remember
+    pub seen_cache_size: usize,
+    /// How long a seen announcement ID is remembered, in seconds
+    pub seen_cache_ttl: u64,
+    /// Gossip configuration
+    pub gossip: GossipConfig,
+    /// Block synchronization configuration
+    pub sync: SyncConfig,
+    /// Peer scoring configuration
+    pub scoring: PeerScoreConfig,
+    /// Connection handshake configuration
+    pub handshake: HandshakeConfig,
+    /// Inbound rate limits and outbound queue bounds
+    pub rate_limit: RateLimitConfig,
+    /// Mempool configuration
+    pub mempool: MempoolConfig,
+    /// Compact block relay configuration
+    pub compact: CompactBlockConfig,
+    /// Dandelion++ transaction propagation configuration
+    pub dandelion: DandelionConfig,
 }
 
 impl Default for ProtocolConfig {
@@ -39,6 +69,16 @@ impl Default for ProtocolConfig {
             request_timeout: 30,
             max_message_size: 10 * 1024 * 1024, // 10 MB
             max_concurrent_requests: 100,
+            seen_cache_size: 10000,
+            seen_cache_ttl: 120,
+            gossip: GossipConfig::default(),
+            sync: SyncConfig::default(),
+            scoring: PeerScoreConfig::default(),
+            handshake: HandshakeConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            mempool: MempoolConfig::default(),
+            compact: CompactBlockConfig::default(),
+            dandelion: DandelionConfig::default(),
         }
     }
 }
@@ -75,14 +115,236 @@ pub enum ProtocolEvent {
         peer_id: PeerId,
         /// Block that was received
         block: Block,
+        /// The block's transactions, in block order
+        transactions: Vec<Transaction>,
     },
-    /// Received a transaction
+    /// A peer requested the transactions of a block we could not serve from the mempool
+    BlockBodyRequested {
+        /// Requesting peer
+        peer_id: PeerId,
+        /// ID of the block
+        block_id: BlockId,
+    },
+    /// Received a transaction, which the node routes into the mempool
     TransactionReceived {
         /// Peer that sent the transaction
         peer_id: PeerId,
         /// Transaction that was received
         transaction: Transaction,
     },
+    /// A gossip message failed validation and was not forwarded
+    GossipRejected {
+        /// Peer that sent the message
+        peer_id: PeerId,
+        /// Topic the message was published on
+        topic: Topic,
+        /// ID of the rejected message
+        message_id: MessageId,
+        /// Reason for rejection
+        reason: String,
+    },
+    /// A light node received headers
+    HeadersReceived {
+        /// Responding peer
+        peer_id: PeerId,
+        /// Headers in ascending height order, not yet verified
+        headers: Vec<BlockHeader>,
+    },
+    /// A light client requested the finality proof of a block
+    FinalityProofRequested {
+        /// Requesting peer
+        peer_id: PeerId,
+        /// Block height
+        height: u64,
+    },
+    /// A peer sent a finality proof
+    FinalityProofReceived {
+        /// Responding peer
+        peer_id: PeerId,
+        /// The proof, not yet verified
+        proof: Option<FinalityProof>,
+    },
+    /// A light client requested validator set changes
+    ValidatorSetChangesRequested {
+        /// Requesting peer
+        peer_id: PeerId,
+        /// Lowest height of a change to return
+        from_height: u64,
+        /// Maximum number of changes
+        count: u32,
+    },
+    /// A peer sent validator set changes
+    ValidatorSetChangesReceived {
+        /// Responding peer
+        peer_id: PeerId,
+        /// Changes in ascending height order, not yet verified
+        changes: Vec<ValidatorSetChange>,
+    },
+    /// A light client requested an account proof
+    AccountProofRequested {
+        /// Requesting peer
+        peer_id: PeerId,
+        /// State root to prove against
+        state_root: StateRoot,
+        /// Account to prove
+        account_id: AccountId,
+    },
+    /// A peer sent an account proof
+    AccountProofReceived {
+        /// Responding peer
+        peer_id: PeerId,
+        /// State root the proof is against
+        state_root: StateRoot,
+        /// Proven account
+        account_id: AccountId,
+        /// The proof, not yet verified
+        proof: Option<AccountProof>,
+    },
+    /// A light client requested a storage proof
+    StorageProofRequested {
+        /// Requesting peer
+        peer_id: PeerId,
+        /// State root to prove against
+        state_root: StateRoot,
+        /// Account holding the storage
+        account_id: AccountId,
+        /// Storage key
+        key: Vec<u8>,
+    },
+    /// A peer sent a storage proof
+    StorageProofReceived {
+        /// Responding peer
+        peer_id: PeerId,
+        /// State root the proof is against
+        state_root: StateRoot,
+        /// Account holding the storage
+        account_id: AccountId,
+        /// The proof, not yet verified
+        proof: Option<StorageProof>,
+    },
+    /// A peer requested a range of headers
+    HeadersRequested {
+        /// Requesting peer
+        peer_id: PeerId,
+        /// Height of the first requested header
+        start_height: u64,
+        /// Number of headers requested
+        count: u32,
+    },
+    /// A peer requested several blocks
+    BlocksRequested {
+        /// Requesting peer
+        peer_id: PeerId,
+        /// IDs of the requested blocks
+        block_ids: Vec<BlockId>,
+    },
+    /// A peer requested the manifest of a state snapshot
+    StateManifestRequested {
+        /// Requesting peer
+        peer_id: PeerId,
+        /// Height of the snapshot
+        height: u64,
+        /// Expected state root of the snapshot
+        state_root: StateRoot,
+    },
+    /// A peer requested a chunk of a state snapshot
+    StateChunkRequested {
+        /// Requesting peer
+        peer_id: PeerId,
+        /// State root of the snapshot
+        state_root: StateRoot,
+        /// Index of the chunk
+        index: u32,
+    },
+    /// A peer answered a state manifest request
+    StateManifestReceived {
+        /// Responding peer
+        peer_id: PeerId,
+        /// The manifest, if the peer has the snapshot
+        manifest: Option<StateManifest>,
+    },
+    /// A peer sent a state chunk
+    StateChunkReceived {
+        /// Responding peer
+        peer_id: PeerId,
+        /// The chunk, not yet verified
+        chunk: StateChunk,
+    },
+    /// A peer sent a consensus message with a valid validator signature
+    ConsensusReceived {
+        /// Peer that delivered the message
+        peer_id: PeerId,
+        /// The verified message
+        message: SignedConsensusMessage,
+    },
+    /// A peer announced a beacon block, not yet validated
+    BeaconBlockReceived {
+        /// Peer that delivered the block
+        peer_id: PeerId,
+        /// The beacon block
+        block: BeaconBlock,
+    },
+    /// A peer announced a cross-link, not yet validated
+    CrossLinkReceived {
+        /// Peer that delivered the cross-link
+        peer_id: PeerId,
+        /// The cross-link
+        link: CrossLink,
+    },
+    /// A peer completed the handshake
+    PeerHandshaked {
+        /// Peer ID
+        peer_id: PeerId,
+        /// Negotiated protocol version
+        protocol_version: u32,
+        /// Capabilities the peer advertised
+        capabilities: Capabilities,
+    },
+    /// A handshake failed or timed out; the peer should be disconnected
+    HandshakeFailed {
+        /// Peer ID
+        peer_id: PeerId,
+        /// Reason for the failure
+        reason: String,
+    },
+    /// A peer exceeded a rate limit or its outbound queue overflowed; it should be disconnected
+    PeerOverloaded {
+        /// Peer ID
+        peer_id: PeerId,
+        /// Traffic class that overflowed
+        class: MessageClass,
+    },
+    /// A peer fell below the score threshold and was banned; it should be disconnected
+    PeerBanned {
+        /// Banned peer
+        peer_id: PeerId,
+        /// Unix time at which the ban expires
+        until: u64,
+        /// Reason for the ban
+        reason: String,
+    },
+    /// Synced blocks are ready to be imported, in ascending height order
+    BlocksSynced {
+        /// Downloaded blocks with their transactions and the peers that served them
+        blocks: Vec<CompleteBlock>,
+    },
+    /// A peer sent an invalid or unrequested response
+    PeerMisbehaved {
+        /// Offending peer
+        peer_id: PeerId,
+        /// Description of the problem
+        reason: String,
+    },
+    /// A peer did not answer a request in time
+    PeerTimedOut {
+        /// Peer that timed out
+        peer_id: PeerId,
+    },
+    /// A sync peer follows a chain that does not contain our local head
+    ConflictingChain {
+        /// Peer on the conflicting chain
+        peer_id: PeerId,
+    },
 }
 
 /// Protocol implementation
@@ -90,33 +352,109 @@ pub struct Protocol {
     /// Configuration
     config: ProtocolConfig,
     /// Pending outbound messages
-    pending_messages: VecDeque<(PeerId, Message)>,
+    pending_messages: OutboundQueue,
+    /// Per-peer inbound rate limits
+    rate_limiter: RateLimiter,
+    /// Counters of traffic dropped by limits
+    traffic: TrafficCounters,
+    /// Events raised while queueing messages, returned with the next batch of sends
+    queue_events: Vec<ProtocolEvent>,
     /// Recently seen message IDs to avoid duplicates
-    seen_messages: HashSet<MessageId>,
-    /// Active requests
-    active_requests: HashMap<MessageId, Instant>,
+    seen_messages: SeenCache,
     /// Request-response protocol (placeholder for actual implementation)
     request_response: Option<()>,
+    /// Topic-based gossip router
+    gossip: Gossip,
+    /// Headers-first block synchronizer
+    sync: BlockSync,
+    /// Peer reputation and bans
+    scoring: PeerScoring,
+    /// Connection handshakes
+    handshake: Handshake,
+    /// Wire codec
+    codec: ProtocolCodec,
+    /// Transactions not yet included in a block
+    mempool: Mempool,
+    /// Announced blocks waiting for transactions
+    reconstructor: BlockReconstructor,
+    /// Stem routing for private transaction propagation
+    dandelion: Dandelion,
+    /// Keys of the validators allowed to sign consensus messages; empty rejects every signer
+    validator_keys: Arc<RwLock<HashSet<[u8; 32]>>>,
     /// Whether the protocol is running
     running: bool,
 }
 
-/// Protocol codec for serializing and deserializing messages
-#[derive(Clone)]
-pub struct ProtocolCodec {
-    /// Maximum message size
-    max_size: usize,
-}
-
 impl Protocol {
     /// Create a new protocol
     pub fn new(config: ProtocolConfig) -> Self {
+        let validator_keys = Arc::new(RwLock::new(HashSet::new()));
+        
+        // Consensus gossip is verified before it is forwarded
+        let mut gossip = Gossip::new(config.gossip.clone());
+        let keys = Arc::clone(&validator_keys);
+        let chain_id = config.handshake.chain_id.clone();
+        gossip.set_validator(TopicKind::Consensus, move |source, payload| {
+            let MessageType::ConsensusMessage { message, .. } = payload else {
+                return ValidationResult::Reject;
+            };
+            
+            let keys = keys.read().expect("Validator key lock poisoned");
+            match verify_consensus_message(&keys, &chain_id, message) {
+                Ok(()) => ValidationResult::Accept,
+                Err((ValidationResult::Reject, e)) => {
+                    log::warn!("Rejected consensus gossip from {}: {}", source, e);
+                    ValidationResult::Reject
+                }
+                Err((result, e)) => {
+                    log::debug!("Ignored consensus gossip from {}: {}", source, e);
+                    result
+                }
+            }
+        });
+        
+        // Transactions are checked before they are forwarded or reach the mempool
+        gossip.set_validator(TopicKind::Transactions, |source, payload| {
+            let MessageType::TransactionAnnounce { transaction, .. } = payload else {
+                return ValidationResult::Reject;
+            };
+            
+            match transaction.validate() {
+                Ok(()) => ValidationResult::Accept,
+                Err(e) => {
+                    log::warn!("Rejected transaction gossip from {}: {}", source, e);
+                    ValidationResult::Reject
+                }
+            }
+        });
+        let sync = BlockSync::new(config.sync.clone(), 0, BlockId([0; 32]));
+        let scoring = PeerScoring::new(config.scoring.clone());
+        let handshake = Handshake::new(config.handshake.clone());
+        let seen_messages = SeenCache::new(config.seen_cache_size, Duration::from_secs(config.seen_cache_ttl));
+        let codec = ProtocolCodec::new(config.max_message_size);
+        let pending_messages = OutboundQueue::new(config.rate_limit.max_queued_per_peer, config.rate_limit.max_queued_total);
+        let rate_limiter = RateLimiter::new(config.rate_limit.clone());
+        let mempool = Mempool::new(config.mempool.clone());
+        let reconstructor = BlockReconstructor::new(config.compact.clone());
+        let dandelion = Dandelion::new(config.dandelion.clone());
+        
         Protocol {
             config,
-            pending_messages: VecDeque::new(),
-            seen_messages: HashSet::new(),
-            active_requests: HashMap::new(),
+            pending_messages,
+            rate_limiter,
+            traffic: TrafficCounters::default(),
+            queue_events: Vec::new(),
+            seen_messages,
             request_response: None,
+            gossip,
+            sync,
+            scoring,
+            handshake,
+            codec,
+            mempool,
+            reconstructor,
+            dandelion,
+            validator_keys,
             running: false,
         }
     }
@@ -153,10 +491,6 @@ impl Protocol {
         // Commented out due to missing ProtocolName trait
         // let protocol_name = ProtocolNameImpl(self.config.protocol_name.clone());
         
-        let codec = ProtocolCodec {
-            max_size: self.config.max_message_size,
-        };
-        
         // Placeholder for request-response configuration
         // In a real implementation, this would configure the request-response protocol
         log::info!("Configuring request-response with timeout: {} seconds", self.config.request_timeout);
@@ -171,10 +505,64 @@ impl Protocol {
         // self.request_response = Some(request_response);
     }
     
+    /// Encode a message into a wire frame for a peer
+    pub fn encode_frame(&self, peer_id: &PeerId, message: &Message) -> Result<Vec<u8>, String> {
+        let compress = self.handshake.peer(peer_id).is_some_and(|peer| peer.compression);
+        self.codec.encode(message, compress).map_err(|e| e.to_string())
+    }
+    
+    /// Decode wire frames received from a peer and handle the messages in them
+    ///
+    /// Returns the number of bytes consumed; a trailing partial frame is left for the next call.
+    /// Undecodable frames are reported to peer scoring and the rest of the buffer is discarded.
+    pub fn handle_frames(&mut self, peer_id: PeerId, buffer: &[u8]) -> (usize, Vec<ProtocolEvent>) {
+        let allow_compression = self.handshake.peer(&peer_id).is_some_and(|peer| peer.compression);
+        let mut consumed = 0;
+        let mut events = Vec::new();
+        
+        loop {
+            match self.codec.decode(&buffer[consumed..], allow_compression) {
+                Ok(Some((message, length))) => {
+                    consumed += length;
+                    self.handle_message(peer_id, message, &mut events);
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    let reason = format!("Undecodable frame: {}", e);
+                    log::warn!("{} from {}", reason, peer_id);
+                    events.extend(self.report_peer(&peer_id, Misbehavior::InvalidMessage, &reason));
+                    events.push(ProtocolEvent::PeerMisbehaved { peer_id, reason });
+                    consumed = buffer.len();
+                    break;
+                }
+            }
+        }
+        
+        (consumed, events)
+    }
+    
     /// Send a message to a peer
     pub fn send_message(&mut self, peer_id: PeerId, message: Message) {
-        // Add message to pending queue
-        self.pending_messages.push_back((peer_id, message));
+        // Add message to pending queue, lower-priority traffic gives way when it is full
+        let dropped = match self.pending_messages.push(peer_id, message) {
+            Some(class) => class,
+            None => return,
+        };
+        
+        *self.traffic.outbound_dropped.entry(dropped).or_insert(0) += 1;
+        
+        let peer_full = self.pending_messages.queued_for(&peer_id) >= self.config.rate_limit.max_queued_per_peer;
+        if peer_full && self.config.rate_limit.outbound_policy == OverflowPolicy::Disconnect {
+            log::warn!("Outbound queue to {} overflowed; disconnecting", peer_id);
+            self.traffic.disconnects += 1;
+            self.remove_peer(&peer_id);
+            self.queue_events.push(ProtocolEvent::PeerOverloaded { peer_id, class: dropped });
+        }
+    }
+    
+    /// Get the counters of traffic dropped by rate limits and full queues
+    pub fn traffic_counters(&self) -> &TrafficCounters {
+        &self.traffic
     }
     
     /// Broadcast a message to multiple peers
@@ -184,51 +572,281 @@ impl Protocol {
         }
     }
     
-    /// Process pending messages
-    pub fn process_pending_messages(&mut self) -> Vec<ProtocolEvent> {
+    /// Register a newly connected peer and send it our handshake
+    ///
+    /// Returns false if the peer is banned or the genesis block is not set,
+    /// and the peer should be disconnected.
+    pub fn add_peer(&mut self, peer_id: PeerId) -> bool {
+        if self.scoring.is_banned(&peer_id) {
+            log::info!("Refusing banned peer {}", peer_id);
+            return false;
+        }
+        
+        match self.handshake.start(peer_id) {
+            Ok(handshake) => {
+                self.send_message(peer_id, Message::new(handshake, 1));
+                true
+            }
+            Err(e) => {
+                log::warn!("Refusing peer {}: {}", peer_id, e);
+                false
+            }
+        }
+    }
+    
+    /// Start gossiping with a peer that completed the handshake and tell it our subscriptions
+    fn activate_peer(&mut self, peer_id: PeerId) {
+        self.gossip.add_peer(peer_id);
+        
+        let topics = self.gossip.subscriptions();
+        if !topics.is_empty() {
+            let message = Message::new(MessageType::Subscription { subscribe: true, topics }, 1);
+            self.send_message(peer_id, message);
+        }
+    }
+    
+    /// Forget a disconnected peer
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.gossip.remove_peer(peer_id);
+        self.sync.remove_peer(peer_id);
+        self.handshake.remove_peer(peer_id);
+        self.rate_limiter.remove_peer(peer_id);
+        self.pending_messages.remove_peer(peer_id);
+        self.reconstructor.remove_peer(peer_id);
+        
+        let peers = self.handshake.established_peers();
+        self.dandelion.remove_peer(peer_id, &peers);
+    }
+    
+    /// Get the handshake state of connected peers
+    pub fn handshake(&self) -> &Handshake {
+        &self.handshake
+    }
+    
+    /// Set the genesis block peers must share
+    pub fn set_genesis(&mut self, genesis_block_id: BlockId) {
+        self.handshake.set_genesis(genesis_block_id);
+    }
+    
+    /// Set the validator keys allowed to sign consensus messages
+    pub fn set_validators<I: IntoIterator<Item = [u8; 32]>>(&mut self, keys: I) {
+        let keys: HashSet<[u8; 32]> = keys.into_iter().collect();
+        self.sync.set_validators(keys.clone());
+        
+        let mut validator_keys = self.validator_keys.write().expect("Validator key lock poisoned");
+        *validator_keys = keys;
+    }
+    
+    /// Fail handshakes that did not complete in time
+    pub fn check_handshake_timeouts(&mut self) -> Vec<ProtocolEvent> {
+        let mut events = Vec::new();
+        
+        for peer_id in self.handshake.check_timeouts() {
+            self.remove_peer(&peer_id);
+            events.push(ProtocolEvent::HandshakeFailed {
+                peer_id,
+                reason: "Handshake timed out".to_string(),
+            });
+        }
+        
+        events
+    }
+    
+    /// Penalize a peer, e.g. for relaying a block that failed validation
+    ///
+    /// Returns a `PeerBanned` event if the peer fell below the ban threshold.
+    pub fn report_peer(&mut self, peer_id: &PeerId, misbehavior: Misbehavior, reason: &str) -> Option<ProtocolEvent> {
+        let ban = self.scoring.record_misbehavior(peer_id, misbehavior, reason)?;
+        self.remove_peer(peer_id);
+        
+        Some(ProtocolEvent::PeerBanned {
+            peer_id: *peer_id,
+            until: ban.until,
+            reason: ban.reason,
+        })
+    }
+    
+    /// Reward a peer for a useful response
+    pub fn reward_peer(&mut self, peer_id: &PeerId) {
+        self.scoring.record_useful(peer_id);
+    }
+    
+    /// Get the mempool
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+    
+    /// Get the mempool for modification, e.g. to drop transactions included in a block
+    pub fn mempool_mut(&mut self) -> &mut Mempool {
+        &mut self.mempool
+    }
+    
+    /// Propagate a transaction that originated on this node to a shard
+    ///
+    /// The node routes the transaction into the mempool first. With
+    /// Dandelion++ enabled it is then relayed privately to a single stem peer;
+    /// otherwise it is gossiped on the shard's transaction topic.
+    pub fn submit_transaction(&mut self, transaction: Transaction, shard_id: ShardId) {
+        if self.dandelion.is_enabled() {
+            let peers = self.handshake.established_peers();
+            self.dandelion.maybe_new_epoch(&peers);
+            
+            if let DandelionRoute::Stem(relay) = self.dandelion.route_local(&transaction, shard_id) {
+                self.send_message(relay, Message::new(MessageType::StemTransaction { shard_id, transaction }, 1));
+                return;
+            }
+        }
+        
+        self.fluff_transaction(transaction, shard_id);
+    }
+    
+    /// Rotate stem routes at epoch boundaries and fluff transactions whose embargo expired
+    ///
+    /// The fluffed transactions are returned for the node to route into the mempool.
+    pub fn check_dandelion(&mut self) -> Vec<Transaction> {
+        if !self.dandelion.is_enabled() {
+            return Vec::new();
+        }
+        
+        let peers = self.handshake.established_peers();
+        self.dandelion.maybe_new_epoch(&peers);
+        
+        let mut fluffed = Vec::new();
+        for (transaction, shard_id) in self.dandelion.expired_embargoes() {
+            log::debug!("Embargo expired; fluffing stem transaction");
+            self.fluff_transaction(transaction.clone(), shard_id);
+            fluffed.push(transaction);
+        }
+        fluffed
+    }
+    
+    /// Gossip a transaction on its shard's topic
+    fn fluff_transaction(&mut self, transaction: Transaction, shard_id: ShardId) {
+        self.dandelion.on_fluffed(&transaction.id());
+        
+        let payload = MessageType::TransactionAnnounce { shard_id, transaction };
+        if let Some(topic) = Topic::for_message(&payload) {
+            self.publish(topic, payload);
+        }
+    }
+    
+    /// Request the full body of blocks whose missing transactions did not arrive in time
+    pub fn check_compact_blocks(&mut self) -> Vec<ProtocolEvent> {
         let mut events = Vec::new();
         
-        // Process up to 10 messages at a time
-        for _ in 0..10 {
-            if let Some((peer_id, message)) = self.pending_messages.pop_front() {
-                // Check if we've already seen this message
-                if self.seen_messages.contains(&message.id) {
-                    continue;
+        for action in self.reconstructor.check_timeouts() {
+            match action {
+                CompactAction::RequestBody { peer_id, block_id } => {
+                    log::debug!("Missing transactions not received; requesting body of block from {}", peer_id);
+                    self.send_message(peer_id, Message::new(MessageType::BlockBodyRequest { block_id }, 1));
                 }
-                
-                // Add message to seen messages
-                self.seen_messages.insert(message.id.clone());
-                
-                // Send message
-                if let Some(request_response) = &mut self.request_response {
-                    // In a real implementation, we would serialize the message and send it
-                    // For now, just log the message
-                    log::info!("Sending message to {}: {:?}", peer_id, message);
-                    
-                    // Add to active requests
-                    self.active_requests.insert(message.id.clone(), Instant::now());
-                    
-                    // Add event
-                    events.push(ProtocolEvent::MessageSent {
-                        peer_id,
-                        message_id: message.id,
-                    });
-                } else {
-                    // Protocol not initialized
-                    events.push(ProtocolEvent::MessageSendFailed {
-                        peer_id,
-                        message_id: message.id,
-                        error: "Protocol not initialized".to_string(),
-                    });
+                CompactAction::Failed { peer_id, .. } => {
+                    events.extend(self.report_peer(&peer_id, Misbehavior::Timeout, "Block body request timed out"));
+                    events.push(ProtocolEvent::PeerTimedOut { peer_id });
                 }
-            } else {
-                break;
             }
         }
         
         events
     }
     
+    /// Get the peer scoring component
+    pub fn scoring(&self) -> &PeerScoring {
+        &self.scoring
+    }
+    
+    /// Get the peer scoring component mutably, e.g. to load bans or ban peers manually
+    pub fn scoring_mut(&mut self) -> &mut PeerScoring {
+        &mut self.scoring
+    }
+    
+    /// Subscribe to a gossip topic
+    pub fn subscribe(&mut self, topic: Topic) {
+        let peers = self.gossip.subscribe(topic);
+        let message = Message::new(MessageType::Subscription { subscribe: true, topics: vec![topic] }, 1);
+        self.broadcast_message(&peers, message);
+    }
+    
+    /// Unsubscribe from a gossip topic
+    pub fn unsubscribe(&mut self, topic: &Topic) {
+        let peers = self.gossip.unsubscribe(topic);
+        let message = Message::new(MessageType::Subscription { subscribe: false, topics: vec![*topic] }, 1);
+        self.broadcast_message(&peers, message);
+    }
+    
+    /// Publish a payload on a gossip topic
+    pub fn publish(&mut self, topic: Topic, payload: MessageType) -> MessageId {
+        let message = Message::new(
+            MessageType::Gossip { topic, payload: Box::new(payload) },
+            self.config.gossip.max_hops,
+        );
+        
+        let peers = self.gossip.publish(&topic, message.id.clone());
+        let message_id = message.id.clone();
+        self.broadcast_message(&peers, message);
+        
+        message_id
+    }
+    
+    /// Get the gossip router, e.g. to install validation hooks
+    pub fn gossip_mut(&mut self) -> &mut Gossip {
+        &mut self.gossip
+    }
+    
+    /// Set the local chain head used as the starting point for sync
+    pub fn set_local_head(&mut self, height: u64, block_id: BlockId) {
+        self.handshake.set_local_head(height, block_id.clone());
+        self.sync.set_local_head(height, block_id);
+    }
+    
+    /// Penalize the peer that served a synced block which failed to import, and download the range again
+    pub fn reject_synced_block(&mut self, peer_id: &PeerId, height: u64, reason: &str) -> Option<ProtocolEvent> {
+        log::warn!("Synced block at height {} from {} was rejected: {}", height, peer_id, reason);
+        self.sync.discard_from(height);
+        self.report_peer(peer_id, Misbehavior::InvalidBlock, reason)
+    }
+    
+    /// Get the current block synchronization progress
+    pub fn sync_progress(&self) -> SyncProgress {
+        self.sync.progress()
+    }
+    
+    /// Reschedule timed-out sync requests and send new ones
+    pub fn drive_sync(&mut self) -> Vec<ProtocolEvent> {
+        let mut events = Vec::new();
+        let sync_events = self.sync.check_timeouts();
+        self.push_sync_events(sync_events, &mut events);
+        
+        for (peer_id, request) in self.sync.next_requests() {
+            self.send_message(peer_id, Message::new(request, 1));
+        }
+        
+        events
+    }
+    
+    /// Take the events raised while queueing or encoding outbound messages
+    pub fn take_queued_events(&mut self) -> Vec<ProtocolEvent> {
+        std::mem::take(&mut self.queue_events)
+    }
+    
+    /// Take the next queued message as a wire frame to write to the peer's stream
+    ///
+    /// Messages that cannot be encoded are reported with the next batch of events.
+    pub fn next_outbound_frame(&mut self) -> Option<(PeerId, Vec<u8>)> {
+        while let Some((peer_id, message)) = self.pending_messages.pop() {
+            match self.encode_frame(&peer_id, &message) {
+                Ok(frame) => return Some((peer_id, frame)),
+                Err(error) => self.queue_events.push(ProtocolEvent::MessageSendFailed {
+                    peer_id,
+                    message_id: message.id,
+                    error,
+                }),
+            }
+        }
+        
+        None
+    }
+    
     /// Handle a request-response event
     pub fn handle_request_response_event(&mut self) -> Vec<ProtocolEvent> {
         // Placeholder implementation
@@ -247,23 +865,7 @@ impl Protocol {
             match event {
                 ProtocolEvent::MessageReceived { peer_id, message } => {
                     // Process received message
-                    match message.message_type {
-                        MessageType::BlockAnnounce { block } => {
-                            new_events.push(ProtocolEvent::BlockReceived {
-                                peer_id,
-                                block,
-                            });
-                        }
-                        MessageType::TransactionAnnounce { transaction } => {
-                            new_events.push(ProtocolEvent::TransactionReceived {
-                                peer_id,
-                                transaction,
-                            });
-                        }
-                        _ => {
-                            // Other message types
-                        }
-                    }
+                    self.handle_message(peer_id, message, &mut new_events);
                 }
                 _ => {
                     // Pass through other events
@@ -275,19 +877,604 @@ impl Protocol {
         new_events
     }
     
+    /// Handle a message received from a peer
+    fn handle_message(&mut self, peer_id: PeerId, message: Message, events: &mut Vec<ProtocolEvent>) {
+        if self.scoring.is_banned(&peer_id) {
+            return;
+        }
+        
+        let class = MessageClass::of(&message.message_type);
+        if !self.rate_limiter.allow(&peer_id, class) {
+            *self.traffic.inbound_dropped.entry(class).or_insert(0) += 1;
+            
+            if self.config.rate_limit.inbound_policy == OverflowPolicy::Disconnect {
+                log::warn!("Peer {} exceeded the {:?} rate limit; disconnecting", peer_id, class);
+                self.traffic.disconnects += 1;
+                self.remove_peer(&peer_id);
+                events.push(ProtocolEvent::PeerOverloaded { peer_id, class });
+            }
+            return;
+        }
+        
+        if message.is_expired() {
+            return;
+        }
+        
+        if !message.has_valid_id() {
+            events.extend(self.report_peer(&peer_id, Misbehavior::InvalidMessage, "Message ID does not match payload"));
+            return;
+        }
+        
+        let Message { id, message_type, timestamp, ttl } = message;
+        
+        // Nothing but the handshake is accepted from a peer until it completes
+        let message_type = match message_type {
+            MessageType::Handshake {
+                chain_id,
+                genesis_block_id,
+                protocol_version,
+                min_protocol_version,
+                capabilities,
+                best_block_id,
+                best_block_height,
+            } => {
+                let result = self.handshake.on_handshake(
+                    &peer_id,
+                    &chain_id,
+                    &genesis_block_id,
+                    protocol_version,
+                    min_protocol_version,
+                    capabilities,
+                ).cloned();
+                
+                match result {
+                    Ok(peer) => {
+                        log::info!("Handshake with {} completed at protocol version {}", peer_id, peer.protocol_version);
+                        self.activate_peer(peer_id);
+                        self.sync.update_peer_status(peer_id, best_block_height, best_block_id);
+                        events.push(ProtocolEvent::PeerHandshaked {
+                            peer_id,
+                            protocol_version: peer.protocol_version,
+                            capabilities: peer.capabilities,
+                        });
+                    }
+                    Err(reason) => {
+                        log::warn!("Handshake with {} failed: {}", peer_id, reason);
+                        // Removing the peer drops its queue, so the rejection is queued afterwards
+                        self.remove_peer(&peer_id);
+                        self.send_message(peer_id, Message::new(MessageType::HandshakeReject { reason: reason.clone() }, 1));
+                        events.push(ProtocolEvent::HandshakeFailed { peer_id, reason });
+                    }
+                }
+                return;
+            }
+            MessageType::HandshakeReject { reason } => {
+                log::warn!("Peer {} rejected our handshake: {}", peer_id, reason);
+                self.remove_peer(&peer_id);
+                events.push(ProtocolEvent::HandshakeFailed { peer_id, reason });
+                return;
+            }
+            _ if !self.handshake.is_established(&peer_id) => {
+                log::debug!("Dropping message from {} before handshake", peer_id);
+                return;
+            }
+            message_type => message_type,
+        };
+        
+        match message_type {
+            MessageType::Subscription { subscribe, topics } => {
+                self.gossip.handle_subscription(peer_id, topics, subscribe);
+            }
+            MessageType::Gossip { topic, payload } => {
+                match self.gossip.handle_message(&peer_id, &topic, &id, &payload) {
+                    GossipOutcome::Accepted { forward_to } => {
+                        // Forward to our mesh while hops remain
+                        if ttl > 1 {
+                            let mut forwarded = Message {
+                                id: id.clone(),
+                                message_type: MessageType::Gossip { topic, payload: payload.clone() },
+                                timestamp,
+                                ttl,
+                            };
+                            forwarded.decrement_ttl();
+                            self.broadcast_message(&forward_to, forwarded);
+                        }
+                        
+                        self.scoring.record_useful(&peer_id);
+                        
+                        self.dispatch_payload(peer_id, *payload, events);
+                    }
+                    GossipOutcome::Rejected { reason } => {
+                        log::warn!("Rejected gossip message from {} on {}: {}", peer_id, topic, reason);
+                        let ban = self.report_peer(&peer_id, Misbehavior::InvalidMessage, &reason);
+                        events.push(ProtocolEvent::GossipRejected {
+                            peer_id,
+                            topic,
+                            message_id: id,
+                            reason,
+                        });
+                        events.extend(ban);
+                    }
+                    GossipOutcome::Duplicate | GossipOutcome::Ignored => {}
+                }
+            }
+            message_type => {
+                // Drop announcements we've already seen
+                if message_type.is_announcement() && !self.seen_messages.insert(id) {
+                    return;
+                }
+                
+                // A consensus message sent to us directly must come from the peer it is bound to
+                if let MessageType::ConsensusMessage { message, .. } = &message_type {
+                    let result = {
+                        let keys = self.validator_keys.read().expect("Validator key lock poisoned");
+                        verify_consensus_message(&keys, &self.config.handshake.chain_id, message)
+                    }.and_then(|()| match message.bound_peer() {
+                        Ok(Some(bound)) if bound != peer_id => Err((ValidationResult::Reject, format!("Message is bound to peer {}", bound))),
+                        Ok(_) => Ok(()),
+                        Err(e) => Err((ValidationResult::Reject, e)),
+                    });
+                    
+                    match result {
+                        Ok(()) => {}
+                        Err((ValidationResult::Reject, reason)) => {
+                            log::warn!("Rejected consensus message from {}: {}", peer_id, reason);
+                            events.extend(self.report_peer(&peer_id, Misbehavior::InvalidSignature, &reason));
+                            return;
+                        }
+                        Err((_, reason)) => {
+                            log::debug!("Ignored consensus message from {}: {}", peer_id, reason);
+                            return;
+                        }
+                    }
+                }
+                
+                self.dispatch_payload(peer_id, message_type, events);
+            }
+        }
+    }
+    
+    /// Turn a received payload into the corresponding protocol event
+    fn dispatch_payload(&mut self, peer_id: PeerId, message_type: MessageType, events: &mut Vec<ProtocolEvent>) {
+        match message_type {
+            MessageType::ConsensusMessage { message, .. } => {
+                events.push(ProtocolEvent::ConsensusReceived {
+                    peer_id,
+                    message,
+                });
+            }
+            MessageType::BeaconBlockAnnounce { block } => {
+                events.push(ProtocolEvent::BeaconBlockReceived {
+                    peer_id,
+                    block,
+                });
+            }
+            MessageType::CrossLinkAnnounce { link } => {
+                events.push(ProtocolEvent::CrossLinkReceived {
+                    peer_id,
+                    link,
+                });
+            }
+            MessageType::BlockAnnounce { block } => {
+                if !block.has_valid_transactions_root() {
+                    events.extend(self.report_peer(&peer_id, Misbehavior::InvalidBlock, "Transactions root does not match"));
+                    return;
+                }
+                
+                // Fill the block from the mempool and ask the announcer for the rest
+                match self.reconstructor.reconstruct(peer_id, block, &self.mempool) {
+                    Reconstruction::Complete { block, transactions } => {
+                        events.push(ProtocolEvent::BlockReceived {
+                            peer_id,
+                            block: *block,
+                            transactions,
+                        });
+                    }
+                    Reconstruction::Missing(transaction_ids) => {
+                        self.send_message(peer_id, Message::new(MessageType::TransactionRequest { transaction_ids }, 1));
+                    }
+                    Reconstruction::Pending => {}
+                }
+            }
+            MessageType::StemTransaction { shard_id, transaction } => {
+                if !self.dandelion.allow_stem(&peer_id) {
+                    events.extend(self.report_peer(&peer_id, Misbehavior::Spam, "Stem transaction rate exceeded"));
+                    return;
+                }
+                
+                // Stem transactions are relayed without gossip validation, so they are checked here
+                if let Err(reason) = transaction.validate() {
+                    events.extend(self.report_peer(&peer_id, Misbehavior::InvalidTransaction, &reason));
+                    return;
+                }
+                
+                // Drop transactions we already stemmed or fluffed to break routing loops
+                let transaction_id = transaction.id();
+                if self.mempool.contains(&transaction_id) || self.dandelion.is_embargoed(&transaction_id) {
+                    return;
+                }
+                
+                if self.dandelion.is_enabled() {
+                    let peers = self.handshake.established_peers();
+                    self.dandelion.maybe_new_epoch(&peers);
+                    
+                    if let DandelionRoute::Stem(relay) = self.dandelion.route_stem(&peer_id, &transaction, shard_id) {
+                        self.send_message(relay, Message::new(MessageType::StemTransaction { shard_id, transaction }, 1));
+                        return;
+                    }
+                }
+                
+                self.fluff_transaction(transaction.clone(), shard_id);
+                events.push(ProtocolEvent::TransactionReceived {
+                    peer_id,
+                    transaction,
+                });
+            }
+            MessageType::TransactionAnnounce { transaction, .. } => {
+                if let Err(reason) = transaction.validate() {
+                    events.extend(self.report_peer(&peer_id, Misbehavior::InvalidTransaction, &reason));
+                    return;
+                }
+                
+                self.dandelion.on_fluffed(&transaction.id());
+                let completed = self.reconstructor.on_transactions(std::slice::from_ref(&transaction));
+                push_completed_blocks(completed, events);
+                
+                events.push(ProtocolEvent::TransactionReceived {
+                    peer_id,
+                    transaction,
+                });
+            }
+            MessageType::TransactionRequest { transaction_ids } => {
+                // Larger requests are answered in part; the requester falls back to the block body.
+                // Our own transactions still in the stem phase are not revealed.
+                let transactions: Vec<Transaction> = transaction_ids.iter()
+                    .take(self.config.compact.max_transactions_per_request)
+                    .filter(|transaction_id| !self.dandelion.is_embargoed(transaction_id))
+                    .filter_map(|transaction_id| self.mempool.get(transaction_id).cloned())
+                    .collect();
+                self.send_message(peer_id, Message::new(MessageType::TransactionResponse { transactions }, 1));
+            }
+            MessageType::TransactionResponse { transactions } => {
+                let completed = self.reconstructor.on_transactions(&transactions);
+                if !completed.is_empty() {
+                    self.scoring.record_useful(&peer_id);
+                }
+                push_completed_blocks(completed, events);
+            }
+            MessageType::BlockBodyRequest { block_id } => {
+                events.push(ProtocolEvent::BlockBodyRequested {
+                    peer_id,
+                    block_id,
+                });
+            }
+            MessageType::BlockBodyResponse { block_id, transactions } if self.sync.is_awaiting_body(&peer_id, &block_id) => {
+                let sync_events = self.sync.on_block_body(&peer_id, &block_id, transactions);
+                self.reward_if_clean(&peer_id, &sync_events);
+                self.push_sync_events(sync_events, events);
+            }
+            MessageType::BlockBodyResponse { block_id, transactions } => {
+                match self.reconstructor.on_block_body(&peer_id, &block_id, transactions) {
+                    Ok(Some(complete)) => {
+                        self.scoring.record_useful(&peer_id);
+                        push_completed_blocks(vec![complete], events);
+                    }
+                    Ok(None) => {}
+                    Err(reason) => {
+                        events.extend(self.report_peer(&peer_id, Misbehavior::InvalidBlock, &reason));
+                    }
+                }
+            }
+            MessageType::StatusMessage { best_block_id, best_block_height, genesis_block_id, .. } => {
+                if self.handshake.config().genesis_block_id.as_ref() != Some(&genesis_block_id) {
+                    events.extend(self.report_peer(&peer_id, Misbehavior::InvalidMessage, "Status on a different genesis"));
+                    return;
+                }
+                
+                self.sync.update_peer_status(peer_id, best_block_height, best_block_id);
+            }
+            MessageType::HeadersRequest { start_height, count } => {
+                events.push(ProtocolEvent::HeadersRequested {
+                    peer_id,
+                    start_height,
+                    count,
+                });
+            }
+            MessageType::HeadersResponse { headers } if self.is_light() => {
+                events.push(ProtocolEvent::HeadersReceived {
+                    peer_id,
+                    headers,
+                });
+            }
+            MessageType::HeadersResponse { headers } => {
+                let sync_events = self.sync.on_headers(&peer_id, headers);
+                self.reward_if_clean(&peer_id, &sync_events);
+                self.push_sync_events(sync_events, events);
+            }
+            MessageType::BlocksRequest { block_ids } => {
+                events.push(ProtocolEvent::BlocksRequested {
+                    peer_id,
+                    block_ids,
+                });
+            }
+            MessageType::BlocksResponse { blocks } => {
+                let sync_events = self.sync.on_blocks(&peer_id, blocks);
+                self.reward_if_clean(&peer_id, &sync_events);
+                self.push_sync_events(sync_events, events);
+            }
+            MessageType::StateManifestRequest { height, state_root } => {
+                events.push(ProtocolEvent::StateManifestRequested {
+                    peer_id,
+                    height,
+                    state_root,
+                });
+            }
+            MessageType::StateManifestResponse { manifest } => {
+                events.push(ProtocolEvent::StateManifestReceived {
+                    peer_id,
+                    manifest,
+                });
+            }
+            MessageType::StateChunkRequest { state_root, index } => {
+                events.push(ProtocolEvent::StateChunkRequested {
+                    peer_id,
+                    state_root,
+                    index,
+                });
+            }
+            MessageType::StateChunkResponse { chunk } => {
+                events.push(ProtocolEvent::StateChunkReceived {
+                    peer_id,
+                    chunk,
+                });
+            }
+            // Light nodes keep no finality or state data to serve
+            MessageType::FinalityProofRequest { .. }
+            | MessageType::ValidatorSetChangesRequest { .. }
+            | MessageType::AccountProofRequest { .. }
+            | MessageType::StorageProofRequest { .. } if self.is_light() => {
+                log::debug!("Ignoring light client request from {}: we are a light node", peer_id);
+            }
+            MessageType::FinalityProofRequest { height } => {
+                events.push(ProtocolEvent::FinalityProofRequested {
+                    peer_id,
+                    height,
+                });
+            }
+            MessageType::FinalityProofResponse { proof } => {
+                events.push(ProtocolEvent::FinalityProofReceived {
+                    peer_id,
+                    proof,
+                });
+            }
+            MessageType::ValidatorSetChangesRequest { from_height, count } => {
+                events.push(ProtocolEvent::ValidatorSetChangesRequested {
+                    peer_id,
+                    from_height,
+                    count,
+                });
+            }
+            MessageType::ValidatorSetChangesResponse { changes } => {
+                events.push(ProtocolEvent::ValidatorSetChangesReceived {
+                    peer_id,
+                    changes,
+                });
+            }
+            MessageType::AccountProofRequest { state_root, account_id } => {
+                events.push(ProtocolEvent::AccountProofRequested {
+                    peer_id,
+                    state_root,
+                    account_id,
+                });
+            }
+            MessageType::AccountProofResponse { state_root, account_id, proof } => {
+                events.push(ProtocolEvent::AccountProofReceived {
+                    peer_id,
+                    state_root,
+                    account_id,
+                    proof,
+                });
+            }
+            MessageType::StorageProofRequest { state_root, account_id, key } => {
+                events.push(ProtocolEvent::StorageProofRequested {
+                    peer_id,
+                    state_root,
+                    account_id,
+                    key,
+                });
+            }
+            MessageType::StorageProofResponse { state_root, account_id, proof } => {
+                events.push(ProtocolEvent::StorageProofReceived {
+                    peer_id,
+                    state_root,
+                    account_id,
+                    proof,
+                });
+            }
+            _ => {
+                // Other message types
+            }
+        }
+    }
+    
+    /// Check if this node runs as a light client
+    fn is_light(&self) -> bool {
+        self.handshake.config().capabilities.role == NodeRole::Light
+    }
+    
+    /// Reward a peer whose sync response raised no complaint
+    fn reward_if_clean(&mut self, peer_id: &PeerId, sync_events: &[SyncEvent]) {
+        let misbehaved = sync_events.iter().any(|event| matches!(
+            event,
+            SyncEvent::PeerMisbehaved { peer_id: offender, .. } if offender == peer_id
+        ));
+        
+        if !misbehaved {
+            self.scoring.record_useful(peer_id);
+        }
+    }
+    
+    /// Convert sync events into protocol events, penalizing misbehaving and slow peers
+    fn push_sync_events(&mut self, sync_events: Vec<SyncEvent>, events: &mut Vec<ProtocolEvent>) {
+        for event in sync_events {
+            let ban = match &event {
+                SyncEvent::PeerMisbehaved { peer_id, reason } => {
+                    self.report_peer(peer_id, Misbehavior::InvalidMessage, reason)
+                }
+                SyncEvent::PeerTimedOut { peer_id } => {
+                    self.report_peer(peer_id, Misbehavior::Timeout, "Sync request timed out")
+                }
+                SyncEvent::BlocksReady { .. } | SyncEvent::ConflictingChain { .. } => None,
+            };
+            
+            events.push(ProtocolEvent::from(event));
+            events.extend(ban);
+        }
+    }
+    
     /// Prune old seen messages and active requests
     pub fn prune(&mut self) {
-        // Prune seen messages (keep only the last 10000)
-        if self.seen_messages.len() > 10000 {
-            let to_remove = self.seen_messages.len() - 10000;
-            let to_remove: Vec<_> = self.seen_messages.iter().take(to_remove).cloned().collect();
-            for message_id in to_remove {
-                self.seen_messages.remove(&message_id);
-            }
+        // Forget expired seen messages
+        self.seen_messages.prune();
+        
+        // Drop stale pending transactions
+        self.mempool.prune();
+        
+        // Maintain gossip meshes
+        self.gossip.heartbeat();
+        
+        // Let old scores fade and lift expired bans
+        self.scoring.decay();
+    }
+}
+
+impl From<SyncEvent> for ProtocolEvent {
+    fn from(event: SyncEvent) -> Self {
+        match event {
+            SyncEvent::BlocksReady { blocks } => ProtocolEvent::BlocksSynced { blocks },
+            SyncEvent::PeerMisbehaved { peer_id, reason } => ProtocolEvent::PeerMisbehaved { peer_id, reason },
+            SyncEvent::PeerTimedOut { peer_id } => ProtocolEvent::PeerTimedOut { peer_id },
+            SyncEvent::ConflictingChain { peer_id } => ProtocolEvent::ConflictingChain { peer_id },
         }
+    }
+}
+
+/// Turn reconstructed blocks into events
+fn push_completed_blocks(completed: Vec<CompleteBlock>, events: &mut Vec<ProtocolEvent>) {
+    for CompleteBlock { peer_id, block, transactions } in completed {
+        events.push(ProtocolEvent::BlockReceived {
+            peer_id,
+            block,
+            transactions,
+        });
+    }
+}
+
+/// Check that a consensus message's signer is a known validator and its signature is valid
+///
+/// Messages are ignored while the validator set is unknown or when the signer
+/// is not in it, since our view of the set may simply be behind the sender's;
+/// only an invalid signature is grounds for rejecting the sender.
+fn verify_consensus_message(
+    validator_keys: &HashSet<[u8; 32]>,
+    chain_id: &str,
+    message: &SignedConsensusMessage,
+) -> Result<(), (ValidationResult, String)> {
+    if validator_keys.is_empty() {
+        return Err((ValidationResult::Ignore, "Validator set is not known yet".to_string()));
+    }
+    
+    if !validator_keys.contains(&message.validator) {
+        return Err((ValidationResult::Ignore, format!("Signer {} is not a validator", hex::encode(message.validator))));
+    }
+    
+    message.verify(chain_id).map_err(|e| (ValidationResult::Reject, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::{ConsensusPayload, Vote};
+    use crate::utils::crypto::KeyPair;
+    
+    /// A protocol with its genesis set and an established peer
+    fn connected_protocol() -> (Protocol, PeerId) {
+        let mut config = ProtocolConfig::default();
+        config.handshake.genesis_block_id = Some(BlockId([1; 32]));
+        let mut protocol = Protocol::new(config.clone());
+        
+        let peer_id = PeerId::random();
+        assert!(protocol.add_peer(peer_id));
+        let handshake = Handshake::new(config.handshake).start(PeerId::random()).unwrap();
+        protocol.process_events(vec![ProtocolEvent::MessageReceived { peer_id, message: Message::new(handshake, 1) }]);
+        assert!(protocol.handshake().is_established(&peer_id));
+        
+        (protocol, peer_id)
+    }
+    
+    /// A prevote signed by a key pair
+    fn prevote(keypair: &KeyPair) -> SignedConsensusMessage {
+        let vote = Vote { height: 1, round: 0, block_id: Some(BlockId([2; 32])), finality_signature: None };
+        SignedConsensusMessage::sign(ConsensusPayload::Prevote(vote), keypair, None, &ProtocolConfig::default().handshake.chain_id).unwrap()
+    }
+    
+    /// Deliver a consensus message, directly or over gossip
+    fn deliver(protocol: &mut Protocol, peer_id: PeerId, message: SignedConsensusMessage, gossip: bool) -> Vec<ProtocolEvent> {
+        let payload = MessageType::ConsensusMessage { shard_id: ShardId(0), message };
+        let message_type = if gossip {
+            MessageType::Gossip { topic: Topic::consensus(ShardId(0)), payload: Box::new(payload) }
+        } else {
+            payload
+        };
+        protocol.process_events(vec![ProtocolEvent::MessageReceived { peer_id, message: Message::new(message_type, 1) }])
+    }
+    
+    #[test]
+    fn ignores_consensus_messages_from_unknown_signers_without_penalty() {
+        let keypair = KeyPair::generate();
         
-        // Prune active requests (remove those older than the timeout)
-        let timeout = Duration::from_secs(self.config.request_timeout);
-        self.active_requests.retain(|_, time| time.elapsed() < timeout);
+        for gossip in [false, true] {
+            let (mut protocol, peer_id) = connected_protocol();
+            
+            // Validator set not known yet
+            assert!(deliver(&mut protocol, peer_id, prevote(&keypair), gossip).is_empty());
+            
+            // Signer not in the set
+            protocol.set_validators([KeyPair::generate().public_key()]);
+            assert!(deliver(&mut protocol, peer_id, prevote(&keypair), gossip).is_empty());
+            
+            assert_eq!(protocol.scoring().score(&peer_id), 0.0);
+        }
+    }
+    
+    #[test]
+    fn accepts_consensus_messages_from_validators() {
+        let keypair = KeyPair::generate();
+        
+        for gossip in [false, true] {
+            let (mut protocol, peer_id) = connected_protocol();
+            protocol.set_validators([keypair.public_key()]);
+            
+            let events = deliver(&mut protocol, peer_id, prevote(&keypair), gossip);
+            assert!(matches!(events[..], [ProtocolEvent::ConsensusReceived { .. }]));
+        }
+    }
+    
+    #[test]
+    fn penalizes_invalid_consensus_signatures() {
+        let keypair = KeyPair::generate();
+        let mut forged = prevote(&keypair);
+        if let ConsensusPayload::Prevote(vote) = &mut forged.payload {
+            vote.round = 1;
+        }
+        
+        for gossip in [false, true] {
+            let (mut protocol, peer_id) = connected_protocol();
+            protocol.set_validators([keypair.public_key()]);
+            
+            let events = deliver(&mut protocol, peer_id, forged.clone(), gossip);
+            assert!(!events.iter().any(|event| matches!(event, ProtocolEvent::ConsensusReceived { .. })));
+            assert!(protocol.scoring().score(&peer_id) < 0.0);
+        }
     }
 }
@@ -0,0 +1,385 @@
+use crate::network::{Message, MessageType};
+use libp2p::PeerId;
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+/// Traffic class of a message, in descending outbound priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MessageClass {
+    /// Consensus votes and proposals
+    Consensus,
+    /// Handshakes, subscriptions, status and pings
+    Control,
+    /// Blocks, headers and state sync
+    Blocks,
+    /// Transactions
+    Transactions,
+}
+
+impl MessageClass {
+    /// All classes, highest priority first
+    pub const ALL: [MessageClass; 4] = [
+        MessageClass::Consensus,
+        MessageClass::Control,
+        MessageClass::Blocks,
+        MessageClass::Transactions,
+    ];
+    
+    /// Classify a message payload; gossip is classified by what it carries
+    pub fn of(message_type: &MessageType) -> Self {
+        match message_type {
+            MessageType::ConsensusMessage { .. } => MessageClass::Consensus,
+            MessageType::BlockAnnounce { .. }
+            | MessageType::BeaconBlockAnnounce { .. }
+            | MessageType::CrossLinkAnnounce { .. }
+            | MessageType::BlockRequest { .. }
+            | MessageType::BlockResponse { .. }
+            | MessageType::BlockBodyRequest { .. }
+            | MessageType::BlockBodyResponse { .. }
+            | MessageType::HeadersRequest { .. }
+            | MessageType::HeadersResponse { .. }
+            | MessageType::BlocksRequest { .. }
+            | MessageType::BlocksResponse { .. }
+            | MessageType::StateManifestRequest { .. }
+            | MessageType::StateManifestResponse { .. }
+            | MessageType::StateChunkRequest { .. }
+            | MessageType::StateChunkResponse { .. }
+            | MessageType::FinalityProofRequest { .. }
+            | MessageType::FinalityProofResponse { .. }
+            | MessageType::ValidatorSetChangesRequest { .. }
+            | MessageType::ValidatorSetChangesResponse { .. }
+            | MessageType::AccountProofRequest { .. }
+            | MessageType::AccountProofResponse { .. }
+            | MessageType::StorageProofRequest { .. }
+            | MessageType::StorageProofResponse { .. } => MessageClass::Blocks,
+            MessageType::TransactionAnnounce { .. }
+            | MessageType::StemTransaction { .. }
+            | MessageType::TransactionRequest { .. }
+            | MessageType::TransactionResponse { .. } => MessageClass::Transactions,
+            MessageType::Gossip { payload, .. } => MessageClass::of(payload),
+            _ => MessageClass::Control,
+        }
+    }
+    
+    /// Index of the class in `ALL`
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// What to do when a limit is exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the offending message and count it
+    Drop,
+    /// Drop the message and disconnect the peer
+    Disconnect,
+}
+
+/// Token bucket parameters
+#[derive(Debug, Clone, Copy)]
+pub struct BucketConfig {
+    /// Messages per second added to the bucket
+    pub rate: f64,
+    /// Maximum burst size
+    pub burst: f64,
+}
+
+/// Configuration for inbound rate limits and outbound queues
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Inbound limit for consensus messages
+    pub consensus: BucketConfig,
+    /// Inbound limit for control messages
+    pub control: BucketConfig,
+    /// Inbound limit for block and state sync messages
+    pub blocks: BucketConfig,
+    /// Inbound limit for transaction messages
+    pub transactions: BucketConfig,
+    /// What to do with a peer exceeding an inbound limit
+    pub inbound_policy: OverflowPolicy,
+    /// Maximum number of queued outbound messages per peer
+    pub max_queued_per_peer: usize,
+    /// Maximum number of queued outbound messages in total
+    pub max_queued_total: usize,
+    /// What to do with a peer whose outbound queue overflows
+    pub outbound_policy: OverflowPolicy,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            consensus: BucketConfig { rate: 100.0, burst: 200.0 },
+            control: BucketConfig { rate: 20.0, burst: 50.0 },
+            blocks: BucketConfig { rate: 20.0, burst: 50.0 },
+            transactions: BucketConfig { rate: 200.0, burst: 500.0 },
+            inbound_policy: OverflowPolicy::Drop,
+            max_queued_per_peer: 1024,
+            max_queued_total: 16 * 1024,
+            outbound_policy: OverflowPolicy::Drop,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Get the bucket parameters for a class
+    fn bucket(&self, class: MessageClass) -> BucketConfig {
+        match class {
+            MessageClass::Consensus => self.consensus,
+            MessageClass::Control => self.control,
+            MessageClass::Blocks => self.blocks,
+            MessageClass::Transactions => self.transactions,
+        }
+    }
+}
+
+/// Token bucket
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    /// Bucket parameters
+    config: BucketConfig,
+    /// Available tokens
+    tokens: f64,
+    /// Last refill time
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Create a full bucket
+    pub fn new(config: BucketConfig) -> Self {
+        TokenBucket {
+            config,
+            tokens: config.burst,
+            last_refill: Instant::now(),
+        }
+    }
+    
+    /// Take one token, returning false if the bucket is empty
+    pub fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.config.rate).min(self.config.burst);
+        self.last_refill = now;
+        
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Counters of dropped traffic
+#[derive(Debug, Clone, Default)]
+pub struct TrafficCounters {
+    /// Inbound messages dropped by rate limits, per class
+    pub inbound_dropped: HashMap<MessageClass, u64>,
+    /// Outbound messages dropped from full queues, per class
+    pub outbound_dropped: HashMap<MessageClass, u64>,
+    /// Peers disconnected for exceeding limits
+    pub disconnects: u64,
+}
+
+/// Per-peer, per-class inbound token buckets
+pub struct RateLimiter {
+    /// Configuration
+    config: RateLimitConfig,
+    /// Buckets per peer, indexed by class
+    buckets: HashMap<PeerId, Vec<TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// Create a new rate limiter
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: HashMap::new(),
+        }
+    }
+    
+    /// Check whether an inbound message of a class from a peer is within its limit
+    pub fn allow(&mut self, peer_id: &PeerId, class: MessageClass) -> bool {
+        let config = &self.config;
+        let buckets = self.buckets.entry(*peer_id).or_insert_with(|| {
+            MessageClass::ALL.iter().map(|class| TokenBucket::new(config.bucket(*class))).collect()
+        });
+        
+        buckets[class.index()].try_take()
+    }
+    
+    /// Forget a disconnected peer
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.buckets.remove(peer_id);
+    }
+}
+
+/// Bounded outbound queue that sends higher-priority classes first
+pub struct OutboundQueue {
+    /// Maximum number of queued messages per peer
+    max_per_peer: usize,
+    /// Maximum number of queued messages in total
+    max_total: usize,
+    /// Queued messages per class, indexed by class
+    queues: Vec<VecDeque<(PeerId, Message)>>,
+    /// Number of queued messages per peer
+    per_peer: HashMap<PeerId, usize>,
+}
+
+impl OutboundQueue {
+    /// Create a new queue
+    pub fn new(max_per_peer: usize, max_total: usize) -> Self {
+        OutboundQueue {
+            max_per_peer,
+            max_total,
+            queues: MessageClass::ALL.iter().map(|_| VecDeque::new()).collect(),
+            per_peer: HashMap::new(),
+        }
+    }
+    
+    /// Queue a message
+    ///
+    /// When a bound is hit, the newest message of the lowest class below the new
+    /// message's class is evicted to make room; if there is none, the new message
+    /// is dropped. Returns the class of the dropped message, if any.
+    pub fn push(&mut self, peer_id: PeerId, message: Message) -> Option<MessageClass> {
+        let class = MessageClass::of(&message.message_type);
+        
+        let peer_full = self.per_peer.get(&peer_id).copied().unwrap_or(0) >= self.max_per_peer;
+        let total_full = self.len() >= self.max_total;
+        
+        let mut dropped = None;
+        if peer_full || total_full {
+            let evict_from = if peer_full { Some(peer_id) } else { None };
+            match self.evict_below(class, evict_from) {
+                Some(evicted) => dropped = Some(evicted),
+                None => return Some(class),
+            }
+        }
+        
+        self.queues[class.index()].push_back((peer_id, message));
+        *self.per_peer.entry(peer_id).or_insert(0) += 1;
+        
+        dropped
+    }
+    
+    /// Take the next message to send, highest class first
+    pub fn pop(&mut self) -> Option<(PeerId, Message)> {
+        let entry = self.queues.iter_mut().find_map(|queue| queue.pop_front())?;
+        self.release(&entry.0);
+        Some(entry)
+    }
+    
+    /// Drop all messages queued for a peer
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        for queue in &mut self.queues {
+            queue.retain(|(queued_for, _)| queued_for != peer_id);
+        }
+        self.per_peer.remove(peer_id);
+    }
+    
+    /// Get the number of queued messages
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+    
+    /// Check if the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+    
+    /// Get the number of messages queued for a peer
+    pub fn queued_for(&self, peer_id: &PeerId) -> usize {
+        self.per_peer.get(peer_id).copied().unwrap_or(0)
+    }
+    
+    /// Evict the newest message of the lowest class strictly below `class`,
+    /// optionally only among a peer's messages
+    fn evict_below(&mut self, class: MessageClass, peer_id: Option<PeerId>) -> Option<MessageClass> {
+        for lower in MessageClass::ALL.iter().rev().take_while(|lower| **lower > class) {
+            let queue = &mut self.queues[lower.index()];
+            let position = queue.iter().rposition(|(queued_for, _)| peer_id.is_none_or(|peer| *queued_for == peer));
+            
+            if let Some((queued_for, _)) = position.and_then(|position| queue.remove(position)) {
+                self.release(&queued_for);
+                return Some(*lower);
+            }
+        }
+        
+        None
+    }
+    
+    /// Decrement a peer's queued count
+    fn release(&mut self, peer_id: &PeerId) {
+        if let Some(count) = self.per_peer.get_mut(peer_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_peer.remove(peer_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// A control message
+    fn ping() -> Message {
+        Message::new(MessageType::Ping { data: 0 }, 1)
+    }
+    
+    /// A transaction message
+    fn transaction_request() -> Message {
+        Message::new(MessageType::TransactionRequest { transaction_ids: Vec::new() }, 1)
+    }
+    
+    #[test]
+    fn limits_each_peer_and_class_to_its_burst() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            control: BucketConfig { rate: 0.0, burst: 2.0 },
+            ..RateLimitConfig::default()
+        });
+        let (peer, other) = (PeerId::random(), PeerId::random());
+        
+        assert!(limiter.allow(&peer, MessageClass::Control));
+        assert!(limiter.allow(&peer, MessageClass::Control));
+        assert!(!limiter.allow(&peer, MessageClass::Control));
+        assert!(limiter.allow(&peer, MessageClass::Transactions));
+        assert!(limiter.allow(&other, MessageClass::Control));
+        
+        limiter.remove_peer(&peer);
+        assert!(limiter.allow(&peer, MessageClass::Control));
+    }
+    
+    #[test]
+    fn sends_higher_classes_first() {
+        let mut queue = OutboundQueue::new(8, 8);
+        let peer = PeerId::random();
+        
+        queue.push(peer, transaction_request());
+        queue.push(peer, ping());
+        
+        assert!(matches!(queue.pop().unwrap().1.message_type, MessageType::Ping { .. }));
+        assert!(matches!(queue.pop().unwrap().1.message_type, MessageType::TransactionRequest { .. }));
+        assert!(queue.is_empty());
+    }
+    
+    #[test]
+    fn evicts_lower_classes_when_a_peer_queue_is_full() {
+        let mut queue = OutboundQueue::new(2, 8);
+        let (peer, other) = (PeerId::random(), PeerId::random());
+        
+        queue.push(peer, transaction_request());
+        queue.push(peer, transaction_request());
+        queue.push(other, transaction_request());
+        
+        assert_eq!(queue.push(peer, transaction_request()), Some(MessageClass::Transactions));
+        assert_eq!(queue.push(peer, ping()), Some(MessageClass::Transactions));
+        assert_eq!(queue.queued_for(&peer), 2);
+        assert_eq!(queue.queued_for(&other), 1);
+        
+        queue.remove_peer(&peer);
+        assert_eq!(queue.len(), 1);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::network::MessageId;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Time- and size-bounded LRU cache of recently seen message IDs
+pub struct SeenCache {
+    /// Maximum number of IDs to remember
+    capacity: usize,
+    /// How long an ID is remembered after it was last seen
+    ttl: Duration,
+    /// Time each ID was last seen
+    entries: HashMap<MessageId, Instant>,
+    /// IDs in order of use; entries whose time no longer matches are stale
+    order: VecDeque<(MessageId, Instant)>,
+}
+
+impl SeenCache {
+    /// Create a new cache
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        SeenCache {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+    
+    /// Record an ID, returning true if it was not already in the cache
+    pub fn insert(&mut self, message_id: MessageId) -> bool {
+        self.prune();
+        
+        let now = Instant::now();
+        let is_new = self.entries.insert(message_id.clone(), now).is_none();
+        self.order.push_back((message_id, now));
+        
+        while self.entries.len() > self.capacity {
+            self.evict_oldest();
+        }
+        
+        // Refreshed IDs leave stale entries behind; compact before they pile up
+        if self.order.len() > 2 * self.capacity.max(1) {
+            let entries = &self.entries;
+            self.order.retain(|(id, seen_at)| entries.get(id) == Some(seen_at));
+        }
+        
+        is_new
+    }
+    
+    /// Check if an ID was seen recently
+    pub fn contains(&self, message_id: &MessageId) -> bool {
+        self.entries.get(message_id)
+            .is_some_and(|seen_at| seen_at.elapsed() < self.ttl)
+    }
+    
+    /// Get the number of remembered IDs
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    
+    /// Check if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    
+    /// Forget IDs that were last seen longer ago than the TTL
+    pub fn prune(&mut self) {
+        while let Some((_, seen_at)) = self.order.front() {
+            if seen_at.elapsed() < self.ttl {
+                break;
+            }
+            
+            if let Some((message_id, seen_at)) = self.order.pop_front() {
+                if self.entries.get(&message_id) == Some(&seen_at) {
+                    self.entries.remove(&message_id);
+                }
+            }
+        }
+    }
+    
+    /// Remove the least recently seen ID
+    fn evict_oldest(&mut self) {
+        while let Some((message_id, seen_at)) = self.order.pop_front() {
+            if self.entries.get(&message_id) == Some(&seen_at) {
+                self.entries.remove(&message_id);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn id(byte: u8) -> MessageId {
+        MessageId([byte; 32])
+    }
+    
+    #[test]
+    fn reports_repeated_ids() {
+        let mut cache = SeenCache::new(10, Duration::from_secs(60));
+        
+        assert!(cache.insert(id(1)));
+        assert!(!cache.insert(id(1)));
+        assert!(cache.contains(&id(1)));
+        assert!(!cache.contains(&id(2)));
+    }
+    
+    #[test]
+    fn evicts_the_least_recently_seen_id_at_capacity() {
+        let mut cache = SeenCache::new(2, Duration::from_secs(60));
+        cache.insert(id(1));
+        cache.insert(id(2));
+        
+        // Seeing 1 again makes 2 the least recently seen
+        cache.insert(id(1));
+        cache.insert(id(3));
+        
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains(&id(1)));
+        assert!(!cache.contains(&id(2)));
+        assert!(cache.contains(&id(3)));
+    }
+    
+    #[test]
+    fn forgets_ids_after_the_ttl() {
+        let mut cache = SeenCache::new(10, Duration::from_millis(20));
+        cache.insert(id(1));
+        
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!cache.contains(&id(1)));
+        
+        cache.prune();
+        assert!(cache.is_empty());
+        assert!(cache.insert(id(1)));
+    }
+    
+    #[test]
+    fn stays_bounded_when_ids_are_refreshed() {
+        let mut cache = SeenCache::new(4, Duration::from_secs(60));
+        
+        for _ in 0..100 {
+            cache.insert(id(1));
+        }
+        
+        assert_eq!(cache.len(), 1);
+        assert!(cache.order.len() <= 8);
+    }
+}
@@ -0,0 +1,820 @@
+use crate::network::MessageType;
+use crate::storage::{Batch, Database, IteratorMode, KeyPrefix, StorageKey, AccountKey, StateKey, StateKeyPrefix, MetadataKey};
+use crate::types::{Account, BlockId, MerkleProof, MerkleTree, State, StateRoot, account_leaf, merkle_root};
+use libp2p::PeerId;
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+/// Metadata key under which the manifest of an unfinished state sync is kept
+const MANIFEST_KEY: &str = "state_sync_manifest";
+
+/// Prefix of state keys holding downloaded chunks
+const CHUNK_KEY_PREFIX: &[u8] = b"sync";
+
+/// Largest number of accounts a snapshot may claim
+const MAX_SNAPSHOT_ACCOUNTS: u64 = u32::MAX as u64;
+
+/// Largest number of accounts a chunk may hold
+const MAX_ACCOUNTS_PER_CHUNK: u32 = 1 << 16;
+
+/// Configuration for state synchronization
+#[derive(Debug, Clone)]
+pub struct StateSyncConfig {
+    /// Number of accounts per chunk (must be a power of two)
+    pub accounts_per_chunk: u32,
+    /// Maximum number of chunk requests in flight per peer
+    pub max_chunks_per_peer: usize,
+    /// Maximum number of manifest requests in flight
+    pub max_manifest_requests: usize,
+    /// Request timeout in seconds
+    pub request_timeout: u64,
+    /// Number of failures after which a peer is no longer used
+    pub max_peer_failures: u32,
+    /// Number of failed last chunks after which an unconfirmed manifest is dropped
+    pub max_manifest_failures: u32,
+}
+
+impl Default for StateSyncConfig {
+    fn default() -> Self {
+        StateSyncConfig {
+            accounts_per_chunk: 256,
+            max_chunks_per_peer: 4,
+            max_manifest_requests: 3,
+            request_timeout: 30,
+            max_peer_failures: 3,
+            max_manifest_failures: 3,
+        }
+    }
+}
+
+/// Description of a state snapshot offered by a peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateManifest {
+    /// Height of the block the snapshot was taken at
+    pub height: u64,
+    /// ID of the block the snapshot was taken at
+    pub block_id: BlockId,
+    /// State root of the snapshot
+    pub state_root: StateRoot,
+    /// Total number of accounts in the snapshot
+    pub account_count: u64,
+    /// Number of accounts per chunk
+    pub accounts_per_chunk: u32,
+}
+
+impl StateManifest {
+    /// Check that the chunk layout is well-formed
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.accounts_per_chunk.is_power_of_two() || self.accounts_per_chunk > MAX_ACCOUNTS_PER_CHUNK {
+            return Err(format!("Chunk size {} is not a power of two up to {}", self.accounts_per_chunk, MAX_ACCOUNTS_PER_CHUNK));
+        }
+        
+        if self.account_count > MAX_SNAPSHOT_ACCOUNTS {
+            return Err(format!("Snapshot claims {} accounts, at most {} are allowed", self.account_count, MAX_SNAPSHOT_ACCOUNTS));
+        }
+        
+        // Only the empty state has the all-zero root
+        if (self.account_count == 0) != (self.state_root.0 == [0; 32]) {
+            return Err("Account count does not match the state root".to_string());
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the number of chunks in the snapshot
+    pub fn chunk_count(&self) -> u32 {
+        let count = self.account_count.div_ceil(self.accounts_per_chunk.max(1) as u64);
+        u32::try_from(count).unwrap_or(u32::MAX)
+    }
+    
+    /// Get the number of accounts in a chunk
+    fn chunk_len(&self, index: u32) -> usize {
+        let start = index as u64 * self.accounts_per_chunk as u64;
+        self.account_count.saturating_sub(start).min(self.accounts_per_chunk as u64) as usize
+    }
+    
+    /// Get the tree level at which each chunk is a single node
+    fn chunk_level(&self) -> usize {
+        self.accounts_per_chunk.trailing_zeros() as usize
+    }
+}
+
+/// A contiguous range of accounts, ordered by ID, with a proof against the state root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateChunk {
+    /// State root the chunk belongs to
+    pub state_root: StateRoot,
+    /// Index of the chunk
+    pub index: u32,
+    /// Accounts in the chunk, including contract code and storage
+    pub accounts: Vec<Account>,
+    /// Proof of the chunk's subtree against the state root
+    pub proof: MerkleProof,
+}
+
+impl StateChunk {
+    /// Verify the chunk against a manifest
+    pub fn verify(&self, manifest: &StateManifest) -> Result<(), String> {
+        if self.state_root != manifest.state_root {
+            return Err("Chunk belongs to a different state root".to_string());
+        }
+        
+        if self.index >= manifest.chunk_count() {
+            return Err(format!("Chunk index {} out of range", self.index));
+        }
+        
+        if self.accounts.len() != manifest.chunk_len(self.index) {
+            return Err(format!(
+                "Chunk {} has {} accounts, expected {}",
+                self.index,
+                self.accounts.len(),
+                manifest.chunk_len(self.index)
+            ));
+        }
+        
+        if self.accounts.windows(2).any(|pair| pair[0].id.0 >= pair[1].id.0) {
+            return Err(format!("Accounts in chunk {} are not ordered by ID", self.index));
+        }
+        
+        if self.proof.index != self.index as u64 || self.proof.width != manifest.chunk_count() as u64 {
+            return Err(format!("Proof of chunk {} is for a different position", self.index));
+        }
+        
+        let leaves: Vec<_> = self.accounts.iter().map(account_leaf).collect();
+        if !self.proof.verify(merkle_root(&leaves), &manifest.state_root.0) {
+            return Err(format!("Chunk {} does not match the state root", self.index));
+        }
+        
+        Ok(())
+    }
+}
+
+/// A local state prepared for serving to syncing peers
+pub struct StateSnapshot {
+    /// Manifest describing the snapshot
+    manifest: StateManifest,
+    /// Accounts ordered by ID
+    accounts: Vec<Account>,
+    /// Merkle tree over the accounts
+    tree: MerkleTree,
+}
+
+impl StateSnapshot {
+    /// Create a snapshot of a state at a block
+    pub fn new(state: &State, height: u64, block_id: BlockId, accounts_per_chunk: u32) -> Result<Self, String> {
+        let accounts: Vec<Account> = state.sorted_accounts().into_iter().cloned().collect();
+        let tree = MerkleTree::new(accounts.iter().map(account_leaf).collect());
+        
+        let manifest = StateManifest {
+            height,
+            block_id,
+            state_root: StateRoot(tree.root()),
+            account_count: accounts.len() as u64,
+            accounts_per_chunk,
+        };
+        manifest.validate()?;
+        
+        Ok(StateSnapshot {
+            manifest,
+            accounts,
+            tree,
+        })
+    }
+    
+    /// Get the manifest of the snapshot
+    pub fn manifest(&self) -> &StateManifest {
+        &self.manifest
+    }
+    
+    /// Get a chunk of the snapshot
+    pub fn chunk(&self, index: u32) -> Option<StateChunk> {
+        if index >= self.manifest.chunk_count() {
+            return None;
+        }
+        
+        let start = index as usize * self.manifest.accounts_per_chunk as usize;
+        let end = start + self.manifest.chunk_len(index);
+        let proof = self.tree.subtree_proof(self.manifest.chunk_level(), index as usize)?;
+        
+        Some(StateChunk {
+            state_root: self.manifest.state_root.clone(),
+            index,
+            accounts: self.accounts[start..end].to_vec(),
+            proof,
+        })
+    }
+}
+
+/// Progress of a state synchronization
+#[derive(Debug, Clone)]
+pub struct StateSyncProgress {
+    /// Height being synced
+    pub height: u64,
+    /// Total number of chunks, once the manifest is known
+    pub chunks_total: Option<u32>,
+    /// Number of verified and persisted chunks
+    pub chunks_done: u32,
+    /// Number of peers serving the snapshot
+    pub peers: usize,
+}
+
+/// Whether a peer has been asked for the snapshot manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ManifestStatus {
+    /// Not asked yet
+    Unknown,
+    /// Asked and waiting for an answer
+    Requested(Instant),
+    /// Peer serves the snapshot
+    Serving,
+    /// Peer does not have the snapshot
+    Unavailable,
+}
+
+/// State sync status tracked per peer
+#[derive(Debug, Clone)]
+struct PeerState {
+    /// Manifest status
+    manifest: ManifestStatus,
+    /// Number of failed or timed-out requests
+    failures: u32,
+}
+
+/// Downloads, verifies and persists a state snapshot from multiple peers
+pub struct StateSync {
+    /// Configuration
+    config: StateSyncConfig,
+    /// Finalized height being synced
+    height: u64,
+    /// Trusted state root at that height
+    state_root: StateRoot,
+    /// Manifest, once a peer has provided one that matches the state root
+    manifest: Option<StateManifest>,
+    /// Whether the manifest's last chunk verified, binding its layout to the state root
+    manifest_confirmed: bool,
+    /// Failed last chunks since the manifest was adopted
+    manifest_failures: u32,
+    /// Manifests dropped because their last chunk never verified
+    rejected: Vec<StateManifest>,
+    /// Peers that may serve the snapshot
+    peers: HashMap<PeerId, PeerState>,
+    /// Chunks not yet requested
+    pending: BTreeSet<u32>,
+    /// Chunk requests in flight
+    in_flight: HashMap<u32, (PeerId, Instant)>,
+    /// Chunks verified and persisted
+    completed: BTreeSet<u32>,
+}
+
+impl StateSync {
+    /// Create a new state sync towards a trusted state root at a finalized height
+    pub fn new(config: StateSyncConfig, height: u64, state_root: StateRoot) -> Self {
+        StateSync {
+            config,
+            height,
+            state_root,
+            manifest: None,
+            manifest_confirmed: false,
+            manifest_failures: 0,
+            rejected: Vec::new(),
+            peers: HashMap::new(),
+            pending: BTreeSet::new(),
+            in_flight: HashMap::new(),
+            completed: BTreeSet::new(),
+        }
+    }
+    
+    /// Create a state sync, picking up chunks persisted by an interrupted sync of the same state
+    ///
+    /// A manifest is only persisted once its last chunk verified, and it is
+    /// checked again before its chunks are reused.
+    pub fn resume(
+        config: StateSyncConfig,
+        height: u64,
+        state_root: StateRoot,
+        database: &Database,
+    ) -> Result<Self, String> {
+        let mut sync = StateSync::new(config, height, state_root);
+        
+        let stored = database.get(&MetadataKey { key: MANIFEST_KEY.to_string() })
+            .map_err(|e| e.to_string())?;
+        
+        if let Some(bytes) = stored {
+            let manifest: StateManifest = bincode::deserialize(&bytes)
+                .map_err(|e| format!("Failed to decode stored manifest: {}", e))?;
+            
+            let last_chunk = manifest.chunk_count().checked_sub(1);
+            let usable = manifest.height == height
+                && manifest.state_root == sync.state_root
+                && manifest.validate().is_ok()
+                && match last_chunk {
+                    Some(index) => database.exists(&chunk_key(&manifest.state_root, index)).map_err(|e| e.to_string())?,
+                    None => true,
+                };
+            
+            if usable {
+                for index in 0..manifest.chunk_count() {
+                    if database.exists(&chunk_key(&manifest.state_root, index)).map_err(|e| e.to_string())? {
+                        sync.completed.insert(index);
+                    }
+                }
+                
+                log::info!(
+                    "Resuming state sync at height {} with {}/{} chunks",
+                    height,
+                    sync.completed.len(),
+                    manifest.chunk_count()
+                );
+                
+                sync.adopt_manifest(manifest);
+            } else {
+                // A different or unusable snapshot was being synced; its chunks are useless now
+                clear_sync_data(database)?;
+            }
+        }
+        
+        Ok(sync)
+    }
+    
+    /// Add a peer that may serve the snapshot
+    pub fn add_peer(&mut self, peer_id: PeerId) {
+        self.peers.entry(peer_id).or_insert(PeerState {
+            manifest: ManifestStatus::Unknown,
+            failures: 0,
+        });
+    }
+    
+    /// Remove a peer and reschedule its outstanding requests
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+        self.requeue_chunks_from(peer_id);
+    }
+    
+    /// Produce the next batch of requests to send
+    pub fn next_requests(&mut self) -> Vec<(PeerId, MessageType)> {
+        let mut requests = Vec::new();
+        let max_failures = self.config.max_peer_failures;
+        
+        // Ask more peers for the manifest so chunks can be spread over them
+        let outstanding = self.peers.values()
+            .filter(|peer| matches!(peer.manifest, ManifestStatus::Requested(_)))
+            .count();
+        let to_ask: Vec<PeerId> = self.peers.iter()
+            .filter(|(_, peer)| peer.manifest == ManifestStatus::Unknown && peer.failures < max_failures)
+            .map(|(peer_id, _)| *peer_id)
+            .take(self.config.max_manifest_requests.saturating_sub(outstanding))
+            .collect();
+        
+        for peer_id in to_ask {
+            if let Some(peer) = self.peers.get_mut(&peer_id) {
+                peer.manifest = ManifestStatus::Requested(Instant::now());
+            }
+            
+            requests.push((peer_id, MessageType::StateManifestRequest {
+                height: self.height,
+                state_root: self.state_root.clone(),
+            }));
+        }
+        
+        if self.manifest.is_none() {
+            return requests;
+        }
+        
+        let servers: Vec<PeerId> = self.peers.iter()
+            .filter(|(_, peer)| peer.manifest == ManifestStatus::Serving && peer.failures < max_failures)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        
+        for peer_id in servers {
+            let mut in_flight = self.in_flight.values().filter(|(owner, _)| *owner == peer_id).count();
+            
+            while in_flight < self.config.max_chunks_per_peer {
+                let next = if self.manifest_confirmed {
+                    self.pending.iter().next().copied()
+                } else {
+                    // Only the last chunk, until it binds the manifest's layout to the state root
+                    let last = self.manifest.as_ref().map(|manifest| manifest.chunk_count().saturating_sub(1));
+                    self.pending.iter().next_back().copied().filter(|index| Some(*index) == last)
+                };
+                let index = match next {
+                    Some(index) => index,
+                    None => return requests,
+                };
+                
+                self.pending.remove(&index);
+                self.in_flight.insert(index, (peer_id, Instant::now()));
+                in_flight += 1;
+                
+                requests.push((peer_id, MessageType::StateChunkRequest {
+                    state_root: self.state_root.clone(),
+                    index,
+                }));
+            }
+        }
+        
+        requests
+    }
+    
+    /// Handle a manifest answer from a peer
+    ///
+    /// The manifest is adopted but not persisted until its last chunk verifies.
+    pub fn on_manifest(&mut self, peer_id: &PeerId, manifest: Option<StateManifest>) -> Result<(), String> {
+        let manifest = match manifest {
+            Some(manifest) => manifest,
+            None => {
+                if let Some(peer) = self.peers.get_mut(peer_id) {
+                    peer.manifest = ManifestStatus::Unavailable;
+                }
+                return Ok(());
+            }
+        };
+        
+        let check = if manifest.height != self.height || manifest.state_root != self.state_root {
+            Err("Manifest does not match the requested state".to_string())
+        } else {
+            manifest.validate()
+        };
+        
+        let check = check.and_then(|()| match self.rejected.contains(&manifest) {
+            true => Err("Manifest was dropped after its last chunk failed to verify".to_string()),
+            false => Ok(()),
+        });
+        
+        if let Err(reason) = check {
+            if let Some(peer) = self.peers.get_mut(peer_id) {
+                peer.manifest = ManifestStatus::Unavailable;
+            }
+            self.record_failure(peer_id);
+            return Err(reason);
+        }
+        
+        if let Some(current) = &self.manifest {
+            // Peers must agree on the chunk layout to share the download
+            if *current != manifest {
+                if let Some(peer) = self.peers.get_mut(peer_id) {
+                    peer.manifest = ManifestStatus::Unavailable;
+                }
+                return Ok(());
+            }
+        } else {
+            self.adopt_manifest(manifest);
+        }
+        
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.manifest = ManifestStatus::Serving;
+        }
+        
+        Ok(())
+    }
+    
+    /// Handle a chunk received from a peer, persisting it once verified
+    ///
+    /// The manifest is persisted with its last chunk. If the last chunk fails
+    /// to verify too often the manifest is dropped, and another one is asked for.
+    pub fn on_chunk(&mut self, peer_id: &PeerId, chunk: StateChunk, database: &Database) -> Result<(), String> {
+        let manifest = self.manifest.clone().ok_or_else(|| "No manifest adopted yet".to_string())?;
+        
+        match self.in_flight.get(&chunk.index) {
+            Some((owner, _)) if owner == peer_id => {}
+            _ => {
+                self.record_failure(peer_id);
+                return Err(format!("Unrequested chunk {}", chunk.index));
+            }
+        }
+        
+        self.in_flight.remove(&chunk.index);
+        
+        if let Err(reason) = chunk.verify(&manifest) {
+            self.pending.insert(chunk.index);
+            self.record_failure(peer_id);
+            
+            if !self.manifest_confirmed {
+                self.manifest_failures += 1;
+                if self.manifest_failures >= self.config.max_manifest_failures {
+                    self.reject_manifest(database)?;
+                }
+            }
+            return Err(reason);
+        }
+        
+        let is_last = chunk.index + 1 == manifest.chunk_count();
+        let mut batch = Batch::new();
+        let value = bincode::serialize(&chunk.accounts)
+            .map_err(|e| format!("Failed to encode chunk: {}", e))?;
+        batch.put(StateKey::column_family(), chunk_key(&manifest.state_root, chunk.index).encode(), value);
+        if is_last {
+            let value = bincode::serialize(&manifest)
+                .map_err(|e| format!("Failed to encode manifest: {}", e))?;
+            batch.put(MetadataKey::column_family(), MetadataKey { key: MANIFEST_KEY.to_string() }.encode(), value);
+        }
+        
+        if let Err(e) = database.apply_batch(&batch) {
+            self.pending.insert(chunk.index);
+            return Err(e.to_string());
+        }
+        
+        self.completed.insert(chunk.index);
+        if is_last {
+            self.manifest_confirmed = true;
+        }
+        
+        Ok(())
+    }
+    
+    /// Reschedule timed-out requests, returning the peers that timed out
+    pub fn check_timeouts(&mut self) -> Vec<PeerId> {
+        let timeout = Duration::from_secs(self.config.request_timeout);
+        let mut timed_out = Vec::new();
+        
+        for (peer_id, peer) in &mut self.peers {
+            if let ManifestStatus::Requested(sent_at) = peer.manifest {
+                if sent_at.elapsed() > timeout {
+                    peer.manifest = ManifestStatus::Unavailable;
+                    timed_out.push(*peer_id);
+                }
+            }
+        }
+        
+        for (owner, sent_at) in self.in_flight.values() {
+            if sent_at.elapsed() > timeout && !timed_out.contains(owner) {
+                timed_out.push(*owner);
+            }
+        }
+        
+        for peer_id in &timed_out {
+            self.requeue_chunks_from(peer_id);
+            self.record_failure(peer_id);
+        }
+        
+        timed_out
+    }
+    
+    /// Get the synchronization progress
+    pub fn progress(&self) -> StateSyncProgress {
+        StateSyncProgress {
+            height: self.height,
+            chunks_total: self.manifest.as_ref().map(|manifest| manifest.chunk_count()),
+            chunks_done: self.completed.len() as u32,
+            peers: self.peers.values().filter(|peer| peer.manifest == ManifestStatus::Serving).count(),
+        }
+    }
+    
+    /// Check if every chunk has been downloaded
+    pub fn is_complete(&self) -> bool {
+        match &self.manifest {
+            Some(manifest) => self.completed.len() as u32 == manifest.chunk_count(),
+            None => false,
+        }
+    }
+    
+    /// Assemble the downloaded chunks, verify the full state and write it to the accounts column family
+    pub fn finalize(&self, database: &Database) -> Result<State, String> {
+        let manifest = self.manifest.as_ref().ok_or_else(|| "No manifest adopted yet".to_string())?;
+        
+        if !self.is_complete() {
+            return Err("State sync is not complete".to_string());
+        }
+        
+        let mut accounts = Vec::new();
+        
+        for index in 0..manifest.chunk_count() {
+            let bytes = database.get(&chunk_key(&manifest.state_root, index))
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("Chunk {} is missing from the database", index))?;
+            
+            let chunk: Vec<Account> = bincode::deserialize(&bytes)
+                .map_err(|e| format!("Failed to decode chunk {}: {}", index, e))?;
+            accounts.extend(chunk);
+        }
+        
+        let state = State::from_accounts(accounts);
+        if state.root != manifest.state_root {
+            return Err("Assembled state does not match the state root".to_string());
+        }
+        
+        let mut batch = Batch::new();
+        for account in &state.accounts {
+            let key = AccountKey { account_id: account.id.0 };
+            let value = bincode::serialize(account)
+                .map_err(|e| format!("Failed to encode account: {}", e))?;
+            batch.put(AccountKey::column_family(), key.encode(), value);
+        }
+        database.apply_batch(&batch).map_err(|e| e.to_string())?;
+        
+        clear_sync_data(database)?;
+        
+        log::info!("State sync completed at height {} with {} accounts", self.height, state.accounts.len());
+        
+        Ok(state)
+    }
+    
+    /// Start downloading the chunks described by a manifest
+    fn adopt_manifest(&mut self, manifest: StateManifest) {
+        self.pending = (0..manifest.chunk_count())
+            .filter(|index| !self.completed.contains(index))
+            .collect();
+        self.manifest_confirmed = match manifest.chunk_count().checked_sub(1) {
+            Some(last) => self.completed.contains(&last),
+            None => true,
+        };
+        self.manifest_failures = 0;
+        self.manifest = Some(manifest);
+    }
+    
+    /// Drop the adopted manifest and everything downloaded for it, and ask peers again
+    fn reject_manifest(&mut self, database: &Database) -> Result<(), String> {
+        if let Some(manifest) = self.manifest.take() {
+            log::warn!(
+                "Dropping state manifest with {} accounts after {} failed chunks",
+                manifest.account_count, self.manifest_failures
+            );
+            self.rejected.push(manifest);
+        }
+        
+        self.manifest_confirmed = false;
+        self.manifest_failures = 0;
+        self.pending.clear();
+        self.in_flight.clear();
+        self.completed.clear();
+        
+        for peer in self.peers.values_mut() {
+            if peer.manifest == ManifestStatus::Serving {
+                peer.manifest = ManifestStatus::Unknown;
+            }
+        }
+        
+        clear_sync_data(database)
+    }
+    
+    /// Put a peer's in-flight chunk requests back into the queue
+    fn requeue_chunks_from(&mut self, peer_id: &PeerId) {
+        let indices: Vec<u32> = self.in_flight.iter()
+            .filter(|(_, (owner, _))| owner == peer_id)
+            .map(|(index, _)| *index)
+            .collect();
+        
+        for index in indices {
+            self.in_flight.remove(&index);
+            self.pending.insert(index);
+        }
+    }
+    
+    /// Record a failure for a peer
+    fn record_failure(&mut self, peer_id: &PeerId) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.failures += 1;
+        }
+    }
+}
+
+/// Get the storage key of a downloaded chunk
+fn chunk_key(state_root: &StateRoot, index: u32) -> StateKey {
+    let mut key = CHUNK_KEY_PREFIX.to_vec();
+    key.extend_from_slice(&state_root.0);
+    key.extend_from_slice(&index.to_be_bytes());
+    StateKey { key }
+}
+
+/// Remove downloaded chunks and the stored manifest
+fn clear_sync_data(database: &Database) -> Result<(), String> {
+    let mut prefix = StateKeyPrefix.encode();
+    prefix.extend_from_slice(CHUNK_KEY_PREFIX);
+    
+    let keys = database.iter_prefix(&StateKeyPrefix, IteratorMode::From(prefix.clone()))
+        .map_err(|e| e.to_string())?
+        .collect_keys();
+    
+    let mut batch = Batch::new();
+    for key in keys.into_iter().filter(|key| key.starts_with(&prefix)) {
+        batch.delete(StateKey::column_family(), key);
+    }
+    batch.delete(MetadataKey::column_family(), MetadataKey { key: MANIFEST_KEY.to_string() }.encode());
+    
+    database.apply_batch(&batch).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::temporary_database;
+    use crate::types::AccountId;
+    
+    /// A state with the given number of funded accounts
+    fn state(count: u8) -> State {
+        State::from_accounts((0..count).map(|i| {
+            let mut account = Account::new_user(AccountId([i; 32]));
+            account.balance.native = i as u64 * 10;
+            account
+        }).collect())
+    }
+    
+    /// Serve every request of a state sync from a snapshot until nothing is left to ask
+    fn serve(sync: &mut StateSync, snapshot: &StateSnapshot, database: &Database) {
+        loop {
+            let requests = sync.next_requests();
+            if requests.is_empty() {
+                return;
+            }
+            
+            for (peer_id, request) in requests {
+                match request {
+                    MessageType::StateManifestRequest { .. } => {
+                        sync.on_manifest(&peer_id, Some(snapshot.manifest().clone())).unwrap();
+                    }
+                    MessageType::StateChunkRequest { index, .. } => {
+                        sync.on_chunk(&peer_id, snapshot.chunk(index).unwrap(), database).unwrap();
+                    }
+                    request => panic!("Unexpected request {:?}", request),
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn snapshots_match_the_state_root() {
+        let state = state(10);
+        let snapshot = StateSnapshot::new(&state, 7, BlockId([1; 32]), 4).unwrap();
+        
+        assert_eq!(snapshot.manifest().state_root, state.root);
+        assert_eq!(snapshot.manifest().chunk_count(), 3);
+        assert_eq!(snapshot.chunk(2).unwrap().accounts.len(), 2);
+        assert!(snapshot.chunk(3).is_none());
+        
+        for index in 0..3 {
+            snapshot.chunk(index).unwrap().verify(snapshot.manifest()).unwrap();
+        }
+    }
+    
+    #[test]
+    fn syncs_a_snapshot_from_several_peers() {
+        let (_directory, database) = temporary_database();
+        let state = state(20);
+        let snapshot = StateSnapshot::new(&state, 7, BlockId([1; 32]), 4).unwrap();
+        
+        let mut sync = StateSync::new(StateSyncConfig::default(), 7, state.root.clone());
+        sync.add_peer(PeerId::random());
+        sync.add_peer(PeerId::random());
+        serve(&mut sync, &snapshot, &database);
+        
+        assert!(sync.is_complete());
+        assert_eq!(sync.progress().peers, 2);
+        let synced = sync.finalize(&database).unwrap();
+        assert_eq!(synced.root, state.root);
+        assert_eq!(synced.accounts.len(), 20);
+    }
+    
+    #[test]
+    fn rejects_tampered_chunks_and_mismatched_manifests() {
+        let (_directory, database) = temporary_database();
+        let state = state(8);
+        let snapshot = StateSnapshot::new(&state, 7, BlockId([1; 32]), 4).unwrap();
+        let peer_id = PeerId::random();
+        
+        let mut sync = StateSync::new(StateSyncConfig::default(), 7, state.root.clone());
+        sync.add_peer(peer_id);
+        sync.next_requests();
+        
+        let mut wrong = snapshot.manifest().clone();
+        wrong.height = 8;
+        assert!(sync.on_manifest(&peer_id, Some(wrong)).is_err());
+        
+        let other = PeerId::random();
+        sync.add_peer(other);
+        sync.next_requests();
+        sync.on_manifest(&other, Some(snapshot.manifest().clone())).unwrap();
+        
+        let index = match &sync.next_requests()[..] {
+            [(_, MessageType::StateChunkRequest { index, .. })] => *index,
+            requests => panic!("Unexpected requests {:?}", requests),
+        };
+        let mut chunk = snapshot.chunk(index).unwrap();
+        chunk.accounts[0].balance.native += 1;
+        assert!(sync.on_chunk(&other, chunk, &database).is_err());
+        assert_eq!(sync.progress().chunks_done, 0);
+    }
+    
+    #[test]
+    fn resumes_with_persisted_chunks() {
+        let (_directory, database) = temporary_database();
+        let state = state(16);
+        let snapshot = StateSnapshot::new(&state, 7, BlockId([1; 32]), 4).unwrap();
+        let peer_id = PeerId::random();
+        
+        let mut sync = StateSync::new(StateSyncConfig { max_chunks_per_peer: 1, ..StateSyncConfig::default() }, 7, state.root.clone());
+        sync.add_peer(peer_id);
+        sync.next_requests();
+        sync.on_manifest(&peer_id, Some(snapshot.manifest().clone())).unwrap();
+        for _ in 0..2 {
+            for (_, request) in sync.next_requests() {
+                if let MessageType::StateChunkRequest { index, .. } = request {
+                    sync.on_chunk(&peer_id, snapshot.chunk(index).unwrap(), &database).unwrap();
+                }
+            }
+        }
+        
+        let resumed = StateSync::resume(StateSyncConfig::default(), 7, state.root.clone(), &database).unwrap();
+        assert_eq!(resumed.progress().chunks_done, 2);
+        assert_eq!(resumed.progress().chunks_total, Some(4));
+    }
+}
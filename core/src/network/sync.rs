@@ -0,0 +1,862 @@
+use crate::network::{CompleteBlock, MessageType};
+use crate::types::{Block, BlockHeader, BlockId, Transaction};
+use libp2p::PeerId;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Configuration for block synchronization
+#[derive(Debug, Clone)]
+pub struct SyncConfig {
+    /// Maximum number of headers requested at once
+    pub max_headers_per_request: u32,
+    /// Maximum number of blocks requested at once
+    pub max_blocks_per_request: usize,
+    /// Maximum number of block requests in flight per peer
+    pub max_requests_per_peer: usize,
+    /// Request timeout in seconds
+    pub request_timeout: u64,
+    /// Number of failures after which a peer is no longer used for sync
+    pub max_peer_failures: u32,
+    /// Number of times a peer may restart header sync on a fork before it is penalized
+    pub max_fork_restarts: u32,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        SyncConfig {
+            max_headers_per_request: 512,
+            max_blocks_per_request: 64,
+            max_requests_per_peer: 2,
+            request_timeout: 10,
+            max_peer_failures: 3,
+            max_fork_restarts: 4,
+        }
+    }
+}
+
+/// Phase of the synchronization process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncState {
+    /// No peer is ahead of us
+    Idle,
+    /// Downloading and verifying the header chain
+    DownloadingHeaders,
+    /// Headers are known, downloading block bodies
+    DownloadingBlocks,
+}
+
+/// Snapshot of synchronization progress
+#[derive(Debug, Clone)]
+pub struct SyncProgress {
+    /// Current phase
+    pub state: SyncState,
+    /// Height of the local chain
+    pub local_height: u64,
+    /// Best height announced by our peers
+    pub target_height: u64,
+    /// Highest verified header
+    pub headers_height: u64,
+    /// Number of blocks downloaded but not yet imported
+    pub blocks_queued: usize,
+    /// Number of peers usable for sync
+    pub peers: usize,
+}
+
+impl SyncProgress {
+    /// Get the percentage of the chain that has been synced
+    pub fn percentage(&self) -> f64 {
+        if self.target_height == 0 || self.local_height >= self.target_height {
+            return 100.0;
+        }
+        
+        self.local_height as f64 / self.target_height as f64 * 100.0
+    }
+}
+
+/// Events emitted by the block synchronizer
+#[derive(Debug)]
+pub enum SyncEvent {
+    /// Blocks ready to be imported, in ascending height order
+    BlocksReady {
+        /// Downloaded blocks with their transactions and the peers that served them
+        blocks: Vec<CompleteBlock>,
+    },
+    /// A peer sent an invalid or unrequested response
+    PeerMisbehaved {
+        /// Offending peer
+        peer_id: PeerId,
+        /// Description of the problem
+        reason: String,
+    },
+    /// A peer did not answer a request in time
+    PeerTimedOut {
+        /// Peer that timed out
+        peer_id: PeerId,
+    },
+    /// A peer follows a chain that does not contain our local head
+    ConflictingChain {
+        /// Peer on the conflicting chain
+        peer_id: PeerId,
+    },
+}
+
+/// How a well-formed header range relates to our header chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeaderLink {
+    /// The range extends the header chain
+    Extends,
+    /// The first header does not link to the header chain
+    Diverges,
+}
+
+/// Sync state tracked per peer
+#[derive(Debug, Clone)]
+struct PeerSyncState {
+    /// Best height announced by the peer
+    best_height: u64,
+    /// Best block announced by the peer
+    best_block_id: BlockId,
+    /// Number of failed or timed-out requests
+    failures: u32,
+    /// Number of times the peer's headers forked off above our local head
+    fork_restarts: u32,
+}
+
+/// Outstanding header request
+#[derive(Debug, Clone)]
+struct HeaderRequest {
+    /// Peer the request was sent to
+    peer_id: PeerId,
+    /// First requested height
+    start_height: u64,
+    /// Number of requested headers
+    count: u32,
+    /// When the request was sent
+    sent_at: Instant,
+}
+
+/// Outstanding block request
+#[derive(Debug, Clone)]
+struct BlockRequest {
+    /// Peer the request was sent to
+    peer_id: PeerId,
+    /// Heights of the requested blocks
+    heights: Vec<u64>,
+    /// When the request was sent
+    sent_at: Instant,
+}
+
+/// Outstanding request for the transactions of a downloaded block
+#[derive(Debug, Clone)]
+struct BodyRequest {
+    /// Peer the request was sent to
+    peer_id: PeerId,
+    /// The block whose transactions were requested
+    block: Block,
+    /// When the request was sent
+    sent_at: Instant,
+}
+
+/// Headers-first block synchronizer
+pub struct BlockSync {
+    /// Configuration
+    config: SyncConfig,
+    /// Height of the local chain
+    local_height: u64,
+    /// ID of the local best block
+    local_best: BlockId,
+    /// Peers available for sync
+    peers: HashMap<PeerId, PeerSyncState>,
+    /// Verified headers above the local height
+    headers: BTreeMap<u64, (BlockHeader, BlockId)>,
+    /// Outstanding header request
+    header_request: Option<HeaderRequest>,
+    /// Heights whose header is verified but whose block is not yet requested
+    queued_blocks: BTreeSet<u64>,
+    /// Block requests in flight
+    block_requests: Vec<BlockRequest>,
+    /// Downloaded blocks waiting for their transactions, by block ID
+    body_requests: HashMap<BlockId, BodyRequest>,
+    /// Body requests not yet handed out
+    outgoing: Vec<(PeerId, MessageType)>,
+    /// Downloaded blocks waiting for their predecessors
+    downloaded: BTreeMap<u64, CompleteBlock>,
+    /// Keys of the validators allowed to produce blocks; headers are not requested while empty
+    validators: HashSet<[u8; 32]>,
+}
+
+impl BlockSync {
+    /// Create a new block synchronizer
+    pub fn new(config: SyncConfig, local_height: u64, local_best: BlockId) -> Self {
+        BlockSync {
+            config,
+            local_height,
+            local_best,
+            peers: HashMap::new(),
+            headers: BTreeMap::new(),
+            header_request: None,
+            queued_blocks: BTreeSet::new(),
+            block_requests: Vec::new(),
+            body_requests: HashMap::new(),
+            outgoing: Vec::new(),
+            downloaded: BTreeMap::new(),
+            validators: HashSet::new(),
+        }
+    }
+    
+    /// Set the validator keys headers must be signed with
+    pub fn set_validators(&mut self, validators: HashSet<[u8; 32]>) {
+        self.validators = validators;
+    }
+    
+    /// Set the local chain head after blocks have been imported
+    pub fn set_local_head(&mut self, height: u64, block_id: BlockId) {
+        // Progress forgives earlier forks
+        if height > self.local_height {
+            for peer in self.peers.values_mut() {
+                peer.fork_restarts = 0;
+            }
+        }
+        
+        self.local_height = height;
+        self.local_best = block_id;
+        
+        // Drop state that is now below the local head
+        self.headers = self.headers.split_off(&(height + 1));
+        self.queued_blocks = self.queued_blocks.split_off(&(height + 1));
+        self.downloaded = self.downloaded.split_off(&(height + 1));
+        self.body_requests.retain(|_, request| request.block.header.height > height);
+    }
+    
+    /// Update a peer's announced chain head from its status message
+    pub fn update_peer_status(&mut self, peer_id: PeerId, best_height: u64, best_block_id: BlockId) {
+        let peer = self.peers.entry(peer_id).or_insert(PeerSyncState {
+            best_height,
+            best_block_id: best_block_id.clone(),
+            failures: 0,
+            fork_restarts: 0,
+        });
+        
+        peer.best_height = best_height;
+        peer.best_block_id = best_block_id;
+    }
+    
+    /// Remove a peer and reschedule its outstanding requests
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+        
+        if self.header_request.as_ref().is_some_and(|request| request.peer_id == *peer_id) {
+            self.header_request = None;
+        }
+        
+        self.requeue_blocks_from(peer_id);
+    }
+    
+    /// Get the best height announced by any usable peer
+    pub fn target_height(&self) -> u64 {
+        self.usable_peers()
+            .map(|(_, peer)| peer.best_height)
+            .max()
+            .unwrap_or(0)
+            .max(self.local_height)
+    }
+    
+    /// Get the current synchronization progress
+    pub fn progress(&self) -> SyncProgress {
+        let target_height = self.target_height();
+        let headers_height = self.headers_height();
+        
+        let state = if target_height <= self.local_height {
+            SyncState::Idle
+        } else if headers_height < target_height {
+            SyncState::DownloadingHeaders
+        } else {
+            SyncState::DownloadingBlocks
+        };
+        
+        SyncProgress {
+            state,
+            local_height: self.local_height,
+            target_height,
+            headers_height,
+            blocks_queued: self.downloaded.len(),
+            peers: self.usable_peers().count(),
+        }
+    }
+    
+    /// Check if we are behind our peers
+    pub fn is_syncing(&self) -> bool {
+        self.target_height() > self.local_height
+    }
+    
+    /// Produce the next batch of requests to send
+    pub fn next_requests(&mut self) -> Vec<(PeerId, MessageType)> {
+        let mut requests = std::mem::take(&mut self.outgoing);
+        
+        if let Some(request) = self.next_header_request() {
+            requests.push(request);
+        }
+        
+        requests.extend(self.next_block_requests());
+        
+        requests
+    }
+    
+    /// Handle a header range received from a peer
+    pub fn on_headers(&mut self, peer_id: &PeerId, headers: Vec<BlockHeader>) -> Vec<SyncEvent> {
+        let request = match self.header_request.take() {
+            Some(request) if request.peer_id == *peer_id => request,
+            other => {
+                self.header_request = other;
+                return vec![self.penalize(peer_id, "Unrequested headers".to_string())];
+            }
+        };
+        
+        match self.verify_headers(&request, &headers) {
+            Err(reason) => return vec![self.penalize(peer_id, reason)],
+            Ok(HeaderLink::Diverges) if request.start_height > self.local_height + 1 => {
+                // The peer is on another branch above our local head; fetch the header chain again from there
+                let max_restarts = self.config.max_fork_restarts;
+                let restarts = self.peers.get_mut(peer_id).map_or(0, |peer| {
+                    peer.fork_restarts += 1;
+                    peer.fork_restarts
+                });
+                if restarts > max_restarts {
+                    return vec![self.penalize(peer_id, format!("Headers forked off {} times without progress", restarts))];
+                }
+                
+                log::debug!("Headers from {} fork off above height {}; restarting header sync", peer_id, self.local_height);
+                self.truncate_headers(self.local_height + 1);
+                return Vec::new();
+            }
+            Ok(HeaderLink::Diverges) => {
+                log::warn!("Peer {} follows a chain without our block at height {}", peer_id, self.local_height);
+                self.remove_peer(peer_id);
+                return vec![SyncEvent::ConflictingChain { peer_id: *peer_id }];
+            }
+            Ok(HeaderLink::Extends) => {}
+        }
+        
+        for header in headers {
+            let height = header.height;
+            let block_id = header.id();
+            self.headers.insert(height, (header, block_id));
+            self.queued_blocks.insert(height);
+        }
+        
+        Vec::new()
+    }
+    
+    /// Handle blocks received from a peer
+    pub fn on_blocks(&mut self, peer_id: &PeerId, blocks: Vec<Block>) -> Vec<SyncEvent> {
+        // Match the response to the request it answers
+        let first_height = blocks.first().map(|block| block.header.height);
+        let index = self.block_requests.iter().position(|request| {
+            request.peer_id == *peer_id
+                && first_height.is_none_or(|height| request.heights.contains(&height))
+        });
+        
+        let request = match index {
+            Some(index) => self.block_requests.remove(index),
+            None => return vec![self.penalize(peer_id, "Unrequested blocks".to_string())],
+        };
+        
+        let mut events = Vec::new();
+        
+        for block in blocks {
+            let height = block.header.height;
+            
+            if !request.heights.contains(&height) {
+                events.push(self.penalize(peer_id, format!("Unrequested block at height {}", height)));
+                continue;
+            }
+            
+            // The header may have been dropped for another branch while the request was in flight
+            let expected = match self.headers.get(&height) {
+                Some((_, block_id)) => block_id.clone(),
+                None => continue,
+            };
+            if expected != block.id() || !block.has_valid_transactions_root() {
+                events.push(self.penalize(peer_id, format!("Block at height {} does not match its header", height)));
+                continue;
+            }
+            
+            if block.transactions.is_empty() {
+                self.downloaded.insert(height, CompleteBlock { peer_id: *peer_id, block, transactions: Vec::new() });
+                continue;
+            }
+            
+            // Blocks carry transaction IDs only; their bodies are fetched from the same peer
+            self.outgoing.push((*peer_id, MessageType::BlockBodyRequest { block_id: expected.clone() }));
+            self.body_requests.insert(expected, BodyRequest {
+                peer_id: *peer_id,
+                block,
+                sent_at: Instant::now(),
+            });
+        }
+        
+        // Blocks that the peer did not return are requested again elsewhere
+        for height in request.heights {
+            if self.headers.contains_key(&height) && !self.is_downloading(height) {
+                self.queued_blocks.insert(height);
+            }
+        }
+        
+        let ready = self.drain_ready_blocks();
+        if !ready.is_empty() {
+            events.push(SyncEvent::BlocksReady { blocks: ready });
+        }
+        
+        events
+    }
+    
+    /// Check if a block body response from a peer answers one of our body requests
+    pub fn is_awaiting_body(&self, peer_id: &PeerId, block_id: &BlockId) -> bool {
+        self.body_requests.get(block_id).is_some_and(|request| request.peer_id == *peer_id)
+    }
+    
+    /// Handle the transactions of a downloaded block
+    ///
+    /// The transactions must be exactly the ones the block lists, in block order.
+    pub fn on_block_body(&mut self, peer_id: &PeerId, block_id: &BlockId, transactions: Vec<Transaction>) -> Vec<SyncEvent> {
+        let request = match self.body_requests.remove(block_id) {
+            Some(request) if request.peer_id == *peer_id => request,
+            Some(request) => {
+                self.body_requests.insert(block_id.clone(), request);
+                return vec![self.penalize(peer_id, "Unrequested block body".to_string())];
+            }
+            None => return vec![self.penalize(peer_id, "Unrequested block body".to_string())],
+        };
+        
+        let height = request.block.header.height;
+        let matches = transactions.len() == request.block.transactions.len()
+            && transactions.iter().zip(&request.block.transactions).all(|(transaction, id)| transaction.id() == *id);
+        if !matches {
+            self.queued_blocks.insert(height);
+            return vec![self.penalize(peer_id, format!("Body of block at height {} does not match its transactions", height))];
+        }
+        
+        self.downloaded.insert(height, CompleteBlock { peer_id: *peer_id, block: request.block, transactions });
+        
+        let ready = self.drain_ready_blocks();
+        if ready.is_empty() {
+            Vec::new()
+        } else {
+            vec![SyncEvent::BlocksReady { blocks: ready }]
+        }
+    }
+    
+    /// Drop headers and blocks from a height up after a synced block failed to import
+    ///
+    /// The range is downloaded again, from other peers if the sender gets banned.
+    pub fn discard_from(&mut self, height: u64) {
+        self.truncate_headers(height);
+    }
+    
+    /// Reschedule requests that have timed out
+    pub fn check_timeouts(&mut self) -> Vec<SyncEvent> {
+        let timeout = Duration::from_secs(self.config.request_timeout);
+        let mut timed_out = Vec::new();
+        
+        if let Some(request) = &self.header_request {
+            if request.sent_at.elapsed() > timeout {
+                timed_out.push(request.peer_id);
+                self.header_request = None;
+            }
+        }
+        
+        for request in &self.block_requests {
+            if request.sent_at.elapsed() > timeout && !timed_out.contains(&request.peer_id) {
+                timed_out.push(request.peer_id);
+            }
+        }
+        
+        for request in self.body_requests.values() {
+            if request.sent_at.elapsed() > timeout && !timed_out.contains(&request.peer_id) {
+                timed_out.push(request.peer_id);
+            }
+        }
+        
+        let mut events = Vec::new();
+        
+        for peer_id in timed_out {
+            self.requeue_blocks_from(&peer_id);
+            
+            if let Some(peer) = self.peers.get_mut(&peer_id) {
+                peer.failures += 1;
+            }
+            
+            log::debug!("Sync request to {} timed out", peer_id);
+            events.push(SyncEvent::PeerTimedOut { peer_id });
+        }
+        
+        events
+    }
+    
+    /// Build a header request if the header chain is behind the target
+    fn next_header_request(&mut self) -> Option<(PeerId, MessageType)> {
+        // Headers could not be checked against their producers
+        if self.header_request.is_some() || self.validators.is_empty() {
+            return None;
+        }
+        
+        let headers_height = self.headers_height();
+        let (peer_id, best_height) = self.usable_peers()
+            .filter(|(_, peer)| peer.best_height > headers_height)
+            .min_by_key(|(_, peer)| (peer.failures, Reverse(peer.best_height)))
+            .map(|(peer_id, peer)| (*peer_id, peer.best_height))?;
+        
+        let start_height = headers_height + 1;
+        let count = (best_height - headers_height).min(self.config.max_headers_per_request as u64) as u32;
+        
+        self.header_request = Some(HeaderRequest {
+            peer_id,
+            start_height,
+            count,
+            sent_at: Instant::now(),
+        });
+        
+        Some((peer_id, MessageType::HeadersRequest { start_height, count }))
+    }
+    
+    /// Spread queued block downloads over the available peers
+    fn next_block_requests(&mut self) -> Vec<(PeerId, MessageType)> {
+        let mut requests = Vec::new();
+        
+        let mut peers: Vec<_> = self.usable_peers()
+            .map(|(peer_id, peer)| (*peer_id, peer.best_height))
+            .collect();
+        peers.sort_by_key(|(_, best_height)| Reverse(*best_height));
+        
+        for (peer_id, best_height) in peers {
+            loop {
+                let in_flight = self.block_requests.iter()
+                    .filter(|request| request.peer_id == peer_id)
+                    .count();
+                if in_flight >= self.config.max_requests_per_peer {
+                    break;
+                }
+                
+                let heights: Vec<u64> = self.queued_blocks.iter()
+                    .copied()
+                    .filter(|height| *height <= best_height)
+                    .take(self.config.max_blocks_per_request)
+                    .collect();
+                
+                if heights.is_empty() {
+                    break;
+                }
+                
+                let mut block_ids = Vec::with_capacity(heights.len());
+                
+                for height in &heights {
+                    self.queued_blocks.remove(height);
+                    
+                    if let Some((_, block_id)) = self.headers.get(height) {
+                        block_ids.push(block_id.clone());
+                    }
+                }
+                
+                self.block_requests.push(BlockRequest {
+                    peer_id,
+                    heights,
+                    sent_at: Instant::now(),
+                });
+                
+                requests.push((peer_id, MessageType::BlocksRequest { block_ids }));
+            }
+        }
+        
+        requests
+    }
+    
+    /// Verify that a header range answers the request and is a chain of its own
+    ///
+    /// Every header must be signed by a known validator. A range whose first
+    /// header does not link to our header chain is not an error: the peer may
+    /// be on another branch.
+    fn verify_headers(&self, request: &HeaderRequest, headers: &[BlockHeader]) -> Result<HeaderLink, String> {
+        if headers.is_empty() {
+            return Err("Empty header response".to_string());
+        }
+        
+        if headers.len() > request.count as usize {
+            return Err(format!("Expected at most {} headers, got {}", request.count, headers.len()));
+        }
+        
+        let (mut prev_id, mut prev_timestamp) = match self.headers.get(&(request.start_height - 1)) {
+            Some((header, block_id)) => (block_id.clone(), header.timestamp),
+            None if request.start_height - 1 == self.local_height => (self.local_best.clone(), 0),
+            None => return Err("Header request no longer extends the local chain".to_string()),
+        };
+        
+        let mut link = HeaderLink::Extends;
+        
+        for (offset, header) in headers.iter().enumerate() {
+            let expected_height = request.start_height + offset as u64;
+            
+            if header.height != expected_height {
+                return Err(format!("Expected header at height {}, got {}", expected_height, header.height));
+            }
+            
+            if offset == 0 && header.prev_block != prev_id {
+                link = HeaderLink::Diverges;
+                prev_id = header.prev_block.clone();
+                prev_timestamp = 0;
+            }
+            
+            if header.prev_block != prev_id {
+                return Err(format!("Header at height {} does not link to its parent", header.height));
+            }
+            
+            if header.timestamp < prev_timestamp {
+                return Err(format!("Header at height {} goes back in time", header.height));
+            }
+            
+            if !self.validators.contains(&header.validator.to_bytes()) {
+                return Err(format!("Header at height {} is not produced by a validator", header.height));
+            }
+            
+            if !header.has_valid_signature() {
+                return Err(format!("Header at height {} has an invalid signature", header.height));
+            }
+            
+            prev_id = header.id();
+            prev_timestamp = header.timestamp;
+            
+            // The header at the peer's announced head must be the block it announced
+            if let Some(peer) = self.peers.get(&request.peer_id) {
+                if header.height == peer.best_height && prev_id != peer.best_block_id {
+                    return Err(format!("Header at height {} does not match the announced best block", header.height));
+                }
+            }
+        }
+        
+        Ok(link)
+    }
+    
+    /// Drop verified headers and queued or downloaded blocks from a height up
+    ///
+    /// Block requests in flight stay, and their blocks are ignored when they arrive.
+    fn truncate_headers(&mut self, from_height: u64) {
+        self.headers.retain(|height, _| *height < from_height);
+        self.queued_blocks.retain(|height| *height < from_height);
+        self.downloaded.retain(|height, _| *height < from_height);
+        self.body_requests.retain(|_, request| request.block.header.height < from_height);
+    }
+    
+    /// Take downloaded blocks that directly extend the local chain
+    fn drain_ready_blocks(&mut self) -> Vec<CompleteBlock> {
+        let mut ready = Vec::new();
+        let mut next_height = self.local_height + 1;
+        
+        while let Some(block) = self.downloaded.remove(&next_height) {
+            ready.push(block);
+            next_height += 1;
+        }
+        
+        ready
+    }
+    
+    /// Put a peer's in-flight block requests back into the queue
+    fn requeue_blocks_from(&mut self, peer_id: &PeerId) {
+        let (requeued, remaining): (Vec<_>, Vec<_>) = self.block_requests
+            .drain(..)
+            .partition(|request| request.peer_id == *peer_id);
+        self.block_requests = remaining;
+        
+        let local_height = self.local_height;
+        for request in requeued {
+            self.queued_blocks.extend(request.heights.into_iter().filter(|height| *height > local_height));
+        }
+        
+        let bodies: Vec<BlockId> = self.body_requests.iter()
+            .filter(|(_, request)| request.peer_id == *peer_id)
+            .map(|(block_id, _)| block_id.clone())
+            .collect();
+        for block_id in bodies {
+            if let Some(request) = self.body_requests.remove(&block_id) {
+                self.queued_blocks.insert(request.block.header.height);
+            }
+        }
+    }
+    
+    /// Check if a block at a height was downloaded or is waiting for its body
+    fn is_downloading(&self, height: u64) -> bool {
+        self.downloaded.contains_key(&height)
+            || self.body_requests.values().any(|request| request.block.header.height == height)
+    }
+    
+    /// Record a failure for a peer
+    fn penalize(&mut self, peer_id: &PeerId, reason: String) -> SyncEvent {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.failures += 1;
+        }
+        
+        log::warn!("Sync peer {} misbehaved: {}", peer_id, reason);
+        
+        SyncEvent::PeerMisbehaved {
+            peer_id: *peer_id,
+            reason,
+        }
+    }
+    
+    /// Get the height of the highest verified header
+    fn headers_height(&self) -> u64 {
+        self.headers.keys().next_back().copied().unwrap_or(self.local_height)
+    }
+    
+    /// Iterate over peers that have not failed too often
+    fn usable_peers(&self) -> impl Iterator<Item = (&PeerId, &PeerSyncState)> {
+        let max_failures = self.config.max_peer_failures;
+        self.peers.iter().filter(move |(_, peer)| peer.failures < max_failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{StateRoot, TransactionType};
+    use crate::utils::crypto::KeyPair;
+    use ed25519_dalek::VerifyingKey;
+    
+    /// Signed blocks from a height on, each with one transaction
+    fn chain(keypair: &KeyPair, start_height: u64, prev_block: BlockId, count: u64) -> Vec<(Block, Vec<Transaction>)> {
+        let validator = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+        let mut prev_block = prev_block;
+        
+        (start_height..start_height + count).map(|height| {
+            let transaction = Transaction::new(TransactionType::Stake { amount: height }, validator, height, 1, 1);
+            let mut block = Block::new(height, prev_block.clone(), vec![transaction.id()], StateRoot([0; 32]), validator, 0);
+            block.header.sign(keypair).unwrap();
+            prev_block = block.id();
+            (block, vec![transaction])
+        }).collect()
+    }
+    
+    /// A synchronizer at genesis that accepts blocks from the key pair
+    fn synchronizer(keypair: &KeyPair, config: SyncConfig) -> BlockSync {
+        let mut sync = BlockSync::new(config, 0, BlockId([0; 32]));
+        sync.set_validators([keypair.public_key()].into_iter().collect());
+        sync
+    }
+    
+    fn headers(blocks: &[(Block, Vec<Transaction>)]) -> Vec<BlockHeader> {
+        blocks.iter().map(|(block, _)| block.header.clone()).collect()
+    }
+    
+    #[test]
+    fn downloads_headers_blocks_and_bodies_in_order() {
+        let keypair = KeyPair::generate();
+        let blocks = chain(&keypair, 1, BlockId([0; 32]), 3);
+        let peer_id = PeerId::random();
+        let mut sync = synchronizer(&keypair, SyncConfig::default());
+        sync.update_peer_status(peer_id, 3, blocks[2].0.id());
+        
+        let requests = sync.next_requests();
+        assert!(matches!(requests[..], [(_, MessageType::HeadersRequest { start_height: 1, count: 3 })]));
+        assert!(sync.on_headers(&peer_id, headers(&blocks)).is_empty());
+        
+        let requests = sync.next_requests();
+        assert!(matches!(&requests[..], [(_, MessageType::BlocksRequest { block_ids })] if block_ids.len() == 3));
+        let events = sync.on_blocks(&peer_id, blocks.iter().map(|(block, _)| block.clone()).collect());
+        assert!(events.is_empty());
+        
+        let body_requests = sync.next_requests();
+        assert_eq!(body_requests.len(), 3);
+        
+        let mut imported = Vec::new();
+        for (block, transactions) in blocks.iter().rev() {
+            assert!(sync.is_awaiting_body(&peer_id, &block.id()));
+            for event in sync.on_block_body(&peer_id, &block.id(), transactions.clone()) {
+                match event {
+                    SyncEvent::BlocksReady { blocks } => imported.extend(blocks),
+                    event => panic!("Unexpected event {:?}", event),
+                }
+            }
+        }
+        
+        let heights: Vec<u64> = imported.iter().map(|complete| complete.block.header.height).collect();
+        assert_eq!(heights, vec![1, 2, 3]);
+        assert!(imported.iter().all(|complete| complete.peer_id == peer_id && complete.transactions.len() == 1));
+    }
+    
+    #[test]
+    fn requests_no_headers_until_validators_are_known() {
+        let keypair = KeyPair::generate();
+        let mut sync = BlockSync::new(SyncConfig::default(), 0, BlockId([0; 32]));
+        sync.update_peer_status(PeerId::random(), 3, BlockId([1; 32]));
+        assert!(sync.next_requests().is_empty());
+        
+        sync.set_validators([keypair.public_key()].into_iter().collect());
+        assert_eq!(sync.next_requests().len(), 1);
+    }
+    
+    #[test]
+    fn rejects_headers_from_unknown_producers_or_with_bad_signatures() {
+        let keypair = KeyPair::generate();
+        let stranger = KeyPair::generate();
+        let peer_id = PeerId::random();
+        
+        let mut sync = synchronizer(&keypair, SyncConfig::default());
+        let blocks = chain(&stranger, 1, BlockId([0; 32]), 2);
+        sync.update_peer_status(peer_id, 2, blocks[1].0.id());
+        sync.next_requests();
+        let events = sync.on_headers(&peer_id, headers(&blocks));
+        assert!(matches!(&events[..], [SyncEvent::PeerMisbehaved { reason, .. }] if reason.contains("not produced by a validator")));
+        
+        let mut sync = synchronizer(&keypair, SyncConfig::default());
+        let mut blocks = chain(&keypair, 1, BlockId([0; 32]), 1);
+        blocks[0].0.header.signature = stranger.sign(b"forged");
+        sync.update_peer_status(peer_id, 1, blocks[0].0.id());
+        sync.next_requests();
+        let events = sync.on_headers(&peer_id, headers(&blocks));
+        assert!(matches!(&events[..], [SyncEvent::PeerMisbehaved { reason, .. }] if reason.contains("invalid signature")));
+    }
+    
+    #[test]
+    fn penalizes_bodies_that_do_not_match_the_block() {
+        let keypair = KeyPair::generate();
+        let blocks = chain(&keypair, 1, BlockId([0; 32]), 1);
+        let other = chain(&keypair, 5, BlockId([0; 32]), 1);
+        let peer_id = PeerId::random();
+        let mut sync = synchronizer(&keypair, SyncConfig::default());
+        sync.update_peer_status(peer_id, 1, blocks[0].0.id());
+        
+        sync.next_requests();
+        sync.on_headers(&peer_id, headers(&blocks));
+        sync.next_requests();
+        sync.on_blocks(&peer_id, vec![blocks[0].0.clone()]);
+        sync.next_requests();
+        
+        let events = sync.on_block_body(&peer_id, &blocks[0].0.id(), other[0].1.clone());
+        assert!(matches!(&events[..], [SyncEvent::PeerMisbehaved { .. }]));
+        assert!(!sync.is_awaiting_body(&peer_id, &blocks[0].0.id()));
+        
+        // The block is downloaded again
+        let requests = sync.next_requests();
+        assert!(matches!(&requests[..], [(_, MessageType::BlocksRequest { .. })]));
+    }
+    
+    #[test]
+    fn penalizes_peers_that_keep_forking_above_the_local_head() {
+        let keypair = KeyPair::generate();
+        let blocks = chain(&keypair, 1, BlockId([0; 32]), 2);
+        let fork = chain(&keypair, 3, BlockId([9; 32]), 2);
+        let peer_id = PeerId::random();
+        let config = SyncConfig { max_headers_per_request: 2, max_fork_restarts: 1, ..SyncConfig::default() };
+        let mut sync = synchronizer(&keypair, config);
+        sync.update_peer_status(peer_id, 10, BlockId([1; 32]));
+        
+        let mut last_events = Vec::new();
+        for _ in 0..2 {
+            sync.next_requests();
+            assert!(sync.on_headers(&peer_id, headers(&blocks)).is_empty());
+            sync.next_requests();
+            last_events = sync.on_headers(&peer_id, headers(&fork));
+        }
+        
+        assert!(matches!(&last_events[..], [SyncEvent::PeerMisbehaved { reason, .. }] if reason.contains("forked off")));
+    }
+}
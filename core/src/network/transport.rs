@@ -1,11 +1,83 @@
+use futures_timer::Delay;
 use libp2p::{
-    core::{muxing::StreamMuxerBox, transport::Boxed},
+    core::{
+        muxing::StreamMuxerBox,
+        transport::{Boxed, MemoryTransport},
+        upgrade::Version,
+    },
+    futures::{AsyncRead, AsyncWrite},
     identity::Keypair,
-    PeerId,
+    multiaddr::Protocol,
+    noise, tcp, yamux, Multiaddr, PeerId, Transport as _,
 };
-// Removed unused imports
+use rand::Rng;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::time::Duration;
 
+/// Kind of transport carrying connections
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransportKind {
+    /// TCP sockets
+    Tcp,
+    /// In-process channels with simulated link conditions, for tests
+    Memory(LinkConditions),
+}
+
+/// Simulated link conditions of the in-memory transport
+///
+/// Conditions are applied by stalling each write before it reaches the
+/// channel, so writes on a connection are not pipelined: every write waits
+/// out the full latency, and many small writes are slower than they would
+/// be on a real link with the same latency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkConditions {
+    /// Stall added before every write goes through
+    pub latency: Duration,
+    /// Bandwidth in bytes per second, or `None` for unlimited
+    pub bandwidth: Option<u64>,
+    /// Probability in `0.0..=0.99` that a write needs a retransmission
+    ///
+    /// No bytes are ever dropped. Connections are reliable streams, so loss
+    /// only shows up as an extra round trip of delay per retransmission, as
+    /// it would on TCP. Higher values are capped so a write always goes through.
+    pub loss: f64,
+}
+
+impl Default for LinkConditions {
+    fn default() -> Self {
+        LinkConditions {
+            latency: Duration::ZERO,
+            bandwidth: None,
+            loss: 0.0,
+        }
+    }
+}
+
+impl LinkConditions {
+    /// Time it takes to deliver a write of `len` bytes
+    fn delay_for(&self, len: usize) -> Duration {
+        let mut delay = self.latency;
+        
+        if let Some(bandwidth) = self.bandwidth.filter(|bandwidth| *bandwidth > 0) {
+            delay += Duration::from_secs_f64(len as f64 / bandwidth as f64);
+        }
+        
+        // Each loss costs a retransmission round trip
+        if self.loss > 0.0 {
+            let mut rng = rand::thread_rng();
+            let retransmit = (2 * self.latency).max(Duration::from_millis(1));
+            while rng.gen_bool(self.loss.min(0.99)) {
+                delay += retransmit;
+            }
+        }
+        
+        delay
+    }
+}
+
 /// Configuration for the network transport
 #[derive(Debug, Clone)]
 pub struct TransportConfig {
@@ -19,6 +91,8 @@ pub struct TransportConfig {
     pub max_message_size: usize,
     /// Enable or disable NAT traversal
     pub enable_nat_traversal: bool,
+    /// Transport carrying the connections
+    pub kind: TransportKind,
 }
 
 impl Default for TransportConfig {
@@ -29,10 +103,27 @@ impl Default for TransportConfig {
             max_connections: 100,
             max_message_size: 10 * 1024 * 1024, // 10 MB
             enable_nat_traversal: true,
+            kind: TransportKind::Tcp,
+        }
+    }
+}
+
+impl TransportConfig {
+    /// Create a configuration for the in-memory transport
+    pub fn memory(conditions: LinkConditions) -> Self {
+        TransportConfig {
+            enable_nat_traversal: false,
+            kind: TransportKind::Memory(conditions),
+            ..TransportConfig::default()
         }
     }
 }
 
+/// Get the address of an in-memory listener; port 0 picks a free one
+pub fn memory_address(port: u64) -> Multiaddr {
+    Multiaddr::empty().with(Protocol::Memory(port))
+}
+
 /// Network transport for the blockchain
 pub struct Transport {
     /// Configuration
@@ -66,15 +157,36 @@ impl Transport {
     }
     
     /// Build the libp2p transport
+    ///
+    /// Both kinds are upgraded with the same noise encryption and yamux
+    /// multiplexing, so everything above the raw connection is identical.
     pub fn build_transport(&self) -> Result<Boxed<(PeerId, StreamMuxerBox)>, String> {
-        // In a real implementation, this would create a libp2p transport
-        // with the configured parameters
-        
-        // For now, just log that we're building a transport
         log::info!("Building transport with peer ID: {}", self.local_peer_id);
         
-        // Return an error since we're not actually implementing the transport
-        Err("Transport implementation is not complete".to_string())
+        let noise = noise::Config::new(&self.local_keypair)
+            .map_err(|e| format!("Failed to create noise config: {}", e))?;
+        let timeout = Duration::from_secs(self.config.connection_timeout);
+        
+        let transport = match &self.config.kind {
+            TransportKind::Tcp => tcp::async_io::Transport::new(tcp::Config::default().nodelay(true))
+                .upgrade(Version::V1)
+                .authenticate(noise)
+                .multiplex(yamux::Config::default())
+                .timeout(timeout)
+                .boxed(),
+            TransportKind::Memory(conditions) => {
+                let conditions = conditions.clone();
+                MemoryTransport::default()
+                    .map(move |channel, _| ConditionedStream::new(channel, conditions.clone()))
+                    .upgrade(Version::V1)
+                    .authenticate(noise)
+                    .multiplex(yamux::Config::default())
+                    .timeout(timeout)
+                    .boxed()
+            }
+        };
+        
+        Ok(transport)
     }
     
     /// Get the configuration
@@ -82,3 +194,298 @@ impl Transport {
         &self.config
     }
 }
+
+/// Stream that delays writes according to simulated link conditions
+pub struct ConditionedStream<S> {
+    /// Underlying stream
+    inner: S,
+    /// Link conditions
+    conditions: LinkConditions,
+    /// Delay before the pending write goes through
+    delay: Option<Delay>,
+}
+
+impl<S> ConditionedStream<S> {
+    /// Wrap a stream
+    pub fn new(inner: S, conditions: LinkConditions) -> Self {
+        ConditionedStream {
+            inner,
+            conditions,
+            delay: None,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ConditionedStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ConditionedStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        
+        // The caller retries the same buffer until it is accepted, so the delay
+        // is armed once per write and cleared when the write goes through
+        if this.delay.is_none() {
+            let delay = this.conditions.delay_for(buf.len());
+            if !delay.is_zero() {
+                this.delay = Some(Delay::new(delay));
+            }
+        }
+        
+        if let Some(delay) = this.delay.as_mut() {
+            if Pin::new(delay).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+        
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if result.is_ready() {
+            this.delay = None;
+        }
+        result
+    }
+    
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+    
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{Discovery, DiscoveryConfig, Protocol, ProtocolConfig, ProtocolEvent};
+    use crate::types::BlockId;
+    use libp2p::core::muxing::{StreamMuxerExt, SubstreamBox};
+    use libp2p::core::transport::{ListenerId, TransportEvent};
+    use libp2p::futures::executor::block_on;
+    use libp2p::futures::future::{self, poll_fn, Either};
+    use libp2p::futures::{AsyncReadExt, AsyncWriteExt, StreamExt};
+    use libp2p::identify;
+    use libp2p::swarm::{self, Swarm, SwarmEvent};
+    use std::time::Instant;
+    
+    /// A node on the in-memory transport
+    fn node(conditions: LinkConditions) -> Transport {
+        Transport::new(TransportConfig::memory(conditions), Keypair::generate_ed25519())
+    }
+    
+    /// Poll a connection in the background so its streams make progress
+    fn drive(mut muxer: StreamMuxerBox) {
+        std::thread::spawn(move || {
+            block_on(poll_fn(|cx| loop {
+                match muxer.poll_unpin(cx) {
+                    Poll::Ready(Ok(_)) => continue,
+                    Poll::Ready(Err(_)) => return Poll::Ready(()),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }))
+        });
+    }
+    
+    /// Connect two nodes and open a stream between them
+    ///
+    /// Returns the peer ID each side authenticated and its end of the stream.
+    /// The dialer writes `first` so that the stream is announced to the listener.
+    fn open_stream(dialer: &Transport, listener: &Transport, first: &[u8]) -> ((PeerId, SubstreamBox), (PeerId, SubstreamBox)) {
+        let mut dialer_transport = dialer.build_transport().unwrap();
+        let mut listener_transport = listener.build_transport().unwrap();
+        listener_transport.listen_on(ListenerId::next(), memory_address(0)).unwrap();
+        
+        block_on(async {
+            let address = match listener_transport.select_next_some().await {
+                TransportEvent::NewAddress { listen_addr, .. } => listen_addr,
+                event => panic!("Unexpected listener event {:?}", event),
+            };
+            
+            // The dial only reaches the listener once it is polled, so both run together
+            let dial = dialer_transport.dial(address).unwrap();
+            let accept = async {
+                match listener_transport.select_next_some().await {
+                    TransportEvent::Incoming { upgrade, .. } => upgrade.await,
+                    event => panic!("Unexpected listener event {:?}", event),
+                }
+            };
+            
+            let (dialed, accepted) = future::join(dial, accept).await;
+            let (listener_id, mut dialer_muxer) = dialed.unwrap();
+            let (dialer_id, mut listener_muxer) = accepted.unwrap();
+            
+            let mut outbound = poll_fn(|cx| dialer_muxer.poll_outbound_unpin(cx)).await.unwrap();
+            drive(dialer_muxer);
+            outbound.write_all(first).await.unwrap();
+            outbound.flush().await.unwrap();
+            
+            let inbound = poll_fn(|cx| listener_muxer.poll_inbound_unpin(cx)).await.unwrap();
+            drive(listener_muxer);
+            
+            ((listener_id, outbound), (dialer_id, inbound))
+        })
+    }
+    
+    /// A protocol on a chain with the given genesis block
+    fn protocol(genesis: u8) -> Protocol {
+        let mut protocol = Protocol::new(ProtocolConfig::default());
+        protocol.set_genesis(BlockId([genesis; 32]));
+        protocol
+    }
+    
+    /// Take every frame the protocol queued for a peer
+    fn outbound_frames(protocol: &mut Protocol, peer_id: &PeerId) -> Vec<u8> {
+        let mut frames = Vec::new();
+        while let Some((to, frame)) = protocol.next_outbound_frame() {
+            assert_eq!(to, *peer_id);
+            frames.extend(frame);
+        }
+        frames
+    }
+    
+    /// Write every frame the protocol queued for a peer to the stream
+    fn send(protocol: &mut Protocol, peer_id: &PeerId, stream: &mut SubstreamBox) {
+        let frames = outbound_frames(protocol, peer_id);
+        block_on(async {
+            stream.write_all(&frames).await.unwrap();
+            stream.flush().await.unwrap();
+        });
+    }
+    
+    /// Read from the stream until the protocol raises events
+    fn receive(protocol: &mut Protocol, peer_id: PeerId, stream: &mut SubstreamBox) -> Vec<ProtocolEvent> {
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 4096];
+        
+        loop {
+            let read = block_on(stream.read(&mut chunk)).unwrap();
+            assert!(read > 0, "Stream closed before a whole frame arrived");
+            buffer.extend_from_slice(&chunk[..read]);
+            
+            let (consumed, events) = protocol.handle_frames(peer_id, &buffer);
+            buffer.drain(..consumed);
+            if !events.is_empty() {
+                return events;
+            }
+        }
+    }
+    
+    /// Run a handshake between two protocols and return the events of each side
+    fn handshake(conditions: LinkConditions, genesis: (u8, u8)) -> (Vec<ProtocolEvent>, Vec<ProtocolEvent>) {
+        let (dialer, listener) = (node(conditions.clone()), node(conditions));
+        let (dialer_id, listener_id) = (*dialer.local_peer_id(), *listener.local_peer_id());
+        let (mut local, mut remote) = (protocol(genesis.0), protocol(genesis.1));
+        
+        assert!(local.add_peer(listener_id));
+        assert!(remote.add_peer(dialer_id));
+        
+        let first = outbound_frames(&mut local, &listener_id);
+        let ((authenticated_listener, mut outbound), (authenticated_dialer, mut inbound)) =
+            open_stream(&dialer, &listener, &first);
+        assert_eq!(authenticated_listener, listener_id);
+        assert_eq!(authenticated_dialer, dialer_id);
+        
+        let remote_events = receive(&mut remote, dialer_id, &mut inbound);
+        send(&mut remote, &dialer_id, &mut inbound);
+        let local_events = receive(&mut local, listener_id, &mut outbound);
+        
+        (local_events, remote_events)
+    }
+    
+    #[test]
+    fn protocols_complete_a_handshake() {
+        let (local, remote) = handshake(LinkConditions::default(), (1, 1));
+        
+        assert!(matches!(local.as_slice(), [ProtocolEvent::PeerHandshaked { .. }]));
+        assert!(matches!(remote.as_slice(), [ProtocolEvent::PeerHandshaked { .. }]));
+    }
+    
+    #[test]
+    fn protocols_reject_a_different_genesis() {
+        let (local, remote) = handshake(LinkConditions::default(), (1, 2));
+        
+        assert!(matches!(remote.as_slice(), [ProtocolEvent::HandshakeFailed { .. }]));
+        assert!(matches!(local.as_slice(), [ProtocolEvent::HandshakeFailed { .. }]));
+    }
+    
+    #[test]
+    fn lossy_links_delay_but_deliver_every_byte() {
+        let conditions = LinkConditions {
+            latency: Duration::from_millis(5),
+            loss: 0.5,
+            ..LinkConditions::default()
+        };
+        let (local, remote) = handshake(conditions, (1, 1));
+        
+        assert!(matches!(local.as_slice(), [ProtocolEvent::PeerHandshaked { .. }]));
+        assert!(matches!(remote.as_slice(), [ProtocolEvent::PeerHandshaked { .. }]));
+    }
+    
+    #[test]
+    fn latency_stalls_writes() {
+        let latency = Duration::from_millis(100);
+        let (dialer, listener) = (node(LinkConditions { latency, ..LinkConditions::default() }), node(LinkConditions::default()));
+        
+        let started = Instant::now();
+        let (_, (_, mut inbound)) = open_stream(&dialer, &listener, b"ping");
+        let mut received = [0u8; 4];
+        block_on(inbound.read_exact(&mut received)).unwrap();
+        
+        assert_eq!(&received, b"ping");
+        assert!(started.elapsed() >= latency);
+    }
+    
+    /// A swarm exchanging identify records over the in-memory transport
+    fn identify_swarm(transport: &Transport) -> Swarm<identify::Behaviour> {
+        let config = identify::Config::new("/optimachain/1.0.0".to_string(), transport.local_keypair().public());
+        Swarm::new(
+            transport.build_transport().unwrap(),
+            identify::Behaviour::new(config),
+            *transport.local_peer_id(),
+            swarm::Config::with_async_std_executor().with_idle_connection_timeout(Duration::from_secs(10)),
+        )
+    }
+    
+    #[test]
+    fn discovery_learns_dialable_addresses_from_identify() {
+        let (dialer, listener) = (node(LinkConditions::default()), node(LinkConditions::default()));
+        let listener_id = *listener.local_peer_id();
+        let mut dialer_swarm = identify_swarm(&dialer);
+        let mut listener_swarm = identify_swarm(&listener);
+        let mut discovery = Discovery::new(DiscoveryConfig { min_peers: 1, ..DiscoveryConfig::default() });
+        
+        listener_swarm.listen_on(memory_address(0)).unwrap();
+        let address = block_on(async {
+            loop {
+                if let SwarmEvent::NewListenAddr { address, .. } = listener_swarm.select_next_some().await {
+                    return address;
+                }
+            }
+        });
+        dialer_swarm.dial(address.clone()).unwrap();
+        
+        block_on(async {
+            loop {
+                let event = match future::select(dialer_swarm.select_next_some(), listener_swarm.select_next_some()).await {
+                    Either::Left((event, _)) => event,
+                    Either::Right(_) => continue,
+                };
+                
+                if let SwarmEvent::Behaviour(event @ identify::Event::Received { .. }) = event {
+                    discovery.handle_identify_event(event);
+                    break;
+                }
+            }
+        });
+        
+        let info = discovery.get_peer(&listener_id.to_string()).unwrap();
+        assert_eq!(info.addresses, vec![address.clone()]);
+        assert_eq!(discovery.peers_to_dial(), vec![(listener_id, vec![address])]);
+        
+        discovery.peer_connected(listener_id);
+        assert!(discovery.peers_to_dial().is_empty());
+    }
+}
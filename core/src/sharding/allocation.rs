@@ -1,4 +1,6 @@
 use crate::sharding::{Shard, ShardId, ShardConfig};
+use crate::sharding::placement::PlacementStats;
+use crate::sharding::ring::{HashRing, DEFAULT_VIRTUAL_NODES};
 use crate::types::AccountId;
 use serde::{Serialize, Deserialize};
 use std::collections::{HashMap, HashSet};
@@ -8,14 +10,30 @@ use std::collections::{HashMap, HashSet};
 pub enum AllocationStrategy {
     /// Allocate based on account ID hash
     Hash,
-    /// Allocate based on account balance
+    /// Allocate to spread the value held across shards
     Balance,
-    /// Allocate based on account activity
+    /// Allocate to spread transaction activity across shards
     Activity,
-    /// Allocate based on geographic location
+    /// Allocate based on geographic location; no location data exists yet, so this hashes
     Geographic,
-    /// Allocate based on smart contract dependencies
+    /// Allocate next to the accounts and contracts an account interacts with
     ContractDependency,
+    /// Allocate on a consistent hash ring weighted by shard capacity
+    ConsistentHash,
+}
+
+impl AllocationStrategy {
+    /// Parse a strategy from its configuration name, defaulting to hash allocation
+    pub fn from_config(strategy: &str) -> Self {
+        match strategy {
+            "balance" => AllocationStrategy::Balance,
+            "activity" => AllocationStrategy::Activity,
+            "geographic" => AllocationStrategy::Geographic,
+            "contract_dependency" => AllocationStrategy::ContractDependency,
+            "consistent_hash" => AllocationStrategy::ConsistentHash,
+            _ => AllocationStrategy::Hash,
+        }
+    }
 }
 
 impl Default for AllocationStrategy {
@@ -37,6 +55,30 @@ pub struct ShardAllocation {
     pub strategy: AllocationStrategy,
 }
 
+/// An account that changes shard when the shard set changes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountMove {
+    /// Account ID
+    pub account_id: AccountId,
+    /// Shard the account is in now
+    pub from: ShardId,
+    /// Shard the account belongs to afterwards
+    pub to: ShardId,
+}
+
+impl AccountMove {
+    /// Group moves by source and destination shard, one batch per migration
+    pub fn group(moves: &[AccountMove]) -> HashMap<(ShardId, ShardId), Vec<AccountId>> {
+        let mut groups: HashMap<(ShardId, ShardId), Vec<AccountId>> = HashMap::new();
+        for account_move in moves {
+            groups.entry((account_move.from, account_move.to))
+                .or_default()
+                .push(account_move.account_id.clone());
+        }
+        groups
+    }
+}
+
 /// Allocator for assigning accounts to shards
 pub struct ShardAllocator {
     /// Current allocation strategy
@@ -47,6 +89,12 @@ pub struct ShardAllocator {
     shard_accounts: HashMap<ShardId, HashSet<AccountId>>,
     /// Available shards
     shards: HashMap<ShardId, ShardConfig>,
+    /// Consistent hash ring over the available shards
+    ring: HashRing,
+    /// Usage statistics for data-driven strategies
+    stats: PlacementStats,
+    /// Accounts deliberately moved off their default shard, left out of ring plans
+    relocated: HashSet<AccountId>,
 }
 
 impl ShardAllocator {
@@ -57,15 +105,25 @@ impl ShardAllocator {
             allocations: HashMap::new(),
             shard_accounts: HashMap::new(),
             shards: HashMap::new(),
+            ring: HashRing::new(DEFAULT_VIRTUAL_NODES),
+            stats: PlacementStats::new(),
+            relocated: HashSet::new(),
         }
     }
     
     /// Add a shard to the allocator
     pub fn add_shard(&mut self, shard_id: ShardId, config: ShardConfig) {
+        self.ring.remove_shard(&shard_id);
+        self.ring.add_shard(shard_id, &config);
         self.shards.insert(shard_id, config);
         self.shard_accounts.entry(shard_id).or_insert_with(HashSet::new);
     }
     
+    /// Check if a shard is available
+    pub fn has_shard(&self, shard_id: &ShardId) -> bool {
+        self.shards.contains_key(shard_id)
+    }
+    
     /// Remove a shard from the allocator
     pub fn remove_shard(&mut self, shard_id: &ShardId) -> Vec<AccountId> {
         // Get accounts in the shard
@@ -78,6 +136,7 @@ impl ShardAllocator {
         
         // Remove shard config
         self.shards.remove(shard_id);
+        self.ring.remove_shard(shard_id);
         
         accounts.into_iter().collect()
     }
@@ -151,6 +210,48 @@ impl ShardAllocator {
         }
     }
     
+    /// Record that an account is on a shard, whether or not it was allocated before
+    pub fn assign_account(&mut self, account_id: &AccountId, shard_id: ShardId) -> Result<ShardAllocation, String> {
+        if self.allocations.contains_key(account_id) {
+            return self.reallocate_account(account_id, shard_id);
+        }
+        if !self.shards.contains_key(&shard_id) {
+            return Err(format!("Shard {:?} does not exist", shard_id));
+        }
+        
+        self.allocations.insert(account_id.clone(), shard_id);
+        self.shard_accounts.entry(shard_id).or_default().insert(account_id.clone());
+        
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        
+        Ok(ShardAllocation {
+            account_id: account_id.clone(),
+            shard_id,
+            timestamp,
+            strategy: self.strategy,
+        })
+    }
+    
+    /// Get the shard an account goes to before it is allocated
+    ///
+    /// Only the shard set decides the result, so every node agrees on it.
+    /// Data-driven strategies rely on statistics each node gathers itself,
+    /// so they place new accounts on the hash ring and act through
+    /// `propose_migrations` instead.
+    pub fn default_shard(&self, account_id: &AccountId) -> Result<ShardId, String> {
+        if self.shards.is_empty() {
+            return Err("No shards available".to_string());
+        }
+        
+        match self.strategy {
+            AllocationStrategy::Hash | AllocationStrategy::Geographic => self.find_best_shard_hash(account_id),
+            _ => self.ring.locate(account_id).ok_or_else(|| "No shards available".to_string()),
+        }
+    }
+    
     /// Get the shard for an account
     pub fn get_shard(&self, account_id: &AccountId) -> Option<ShardId> {
         self.allocations.get(account_id).copied()
@@ -166,6 +267,200 @@ impl ShardAllocator {
         self.shard_accounts.get(shard_id).map_or(0, |accounts| accounts.len())
     }
     
+    /// Mark an account as deliberately moved, so ring plans leave it where it is
+    pub fn mark_relocated(&mut self, account_id: &AccountId) {
+        self.relocated.insert(account_id.clone());
+    }
+    
+    /// Work out which accounts change shard if the shard set becomes `shards`
+    ///
+    /// Under consistent hashing only the accounts on arcs that change owner
+    /// are reported, so resharding moves the minimum set. Accounts placed by
+    /// other strategies are compared against the ring as well, except those
+    /// a migration moved on purpose.
+    pub fn plan_shard_set(&self, shards: &HashMap<ShardId, ShardConfig>) -> Result<Vec<AccountMove>, String> {
+        if shards.is_empty() {
+            return Err("No shards available".to_string());
+        }
+        
+        let ring = HashRing::from_shards(DEFAULT_VIRTUAL_NODES, shards);
+        
+        let mut moves: Vec<AccountMove> = self.allocations.iter()
+            .filter(|(account_id, _)| !self.relocated.contains(*account_id))
+            .filter_map(|(account_id, from)| {
+                let to = ring.locate(account_id)?;
+                (to != *from).then(|| AccountMove {
+                    account_id: account_id.clone(),
+                    from: *from,
+                    to,
+                })
+            })
+            .collect();
+        moves.sort_by_key(|account_move| account_move.account_id.0);
+        
+        Ok(moves)
+    }
+    
+    /// Work out which accounts sit away from their ring owner in the current shard set
+    ///
+    /// After a shard is added or removed, accounts pinned to the shard holding
+    /// their state are moved towards the shard the ring now gives them.
+    pub fn plan_rebalance(&self) -> Result<Vec<AccountMove>, String> {
+        self.plan_shard_set(&self.shards)
+    }
+    
+    /// Get the usage statistics
+    pub fn stats(&self) -> &PlacementStats {
+        &self.stats
+    }
+    
+    /// Get mutable access to the usage statistics, to record executed transactions and balances
+    pub fn stats_mut(&mut self) -> &mut PlacementStats {
+        &mut self.stats
+    }
+    
+    /// Get the share of recorded interactions that cross shards
+    pub fn cross_shard_ratio(&self) -> f64 {
+        let mut total = 0;
+        let mut cross = 0;
+        for (account, neighbour, weight) in self.stats.interactions() {
+            if let (Some(a), Some(b)) = (self.get_shard(account), self.get_shard(neighbour)) {
+                total += weight;
+                if a != b {
+                    cross += weight;
+                }
+            }
+        }
+        
+        if total == 0 {
+            0.0
+        } else {
+            cross as f64 / total as f64
+        }
+    }
+    
+    /// Get the load of a shard under the current strategy, relative to its capacity weight
+    pub fn shard_load(&self, shard_id: &ShardId) -> f64 {
+        let weight = self.shards.get(shard_id).map_or(1.0, |config| config.effective_capacity_weight());
+        let load: f64 = self.shard_accounts.get(shard_id)
+            .map(|accounts| accounts.iter().map(|account_id| self.account_load(account_id)).sum())
+            .unwrap_or(0.0);
+        
+        load / weight
+    }
+    
+    /// Propose account moves that cut cross-shard interactions, then even out load
+    ///
+    /// Accounts are first moved towards the shard holding most of their
+    /// interactions, as long as that keeps the target within
+    /// `imbalance_tolerance` of the average load. Load is then evened out
+    /// only with moves that do not add cross-shard interactions, so balance
+    /// never comes at the cost of more cross-shard transactions.
+    pub fn propose_migrations(&self, max_moves: usize, imbalance_tolerance: f64) -> Vec<AccountMove> {
+        if self.shards.len() < 2 {
+            return Vec::new();
+        }
+        
+        let mut loads: HashMap<ShardId, f64> = self.shards.keys()
+            .map(|shard_id| (*shard_id, self.shard_load(shard_id)))
+            .collect();
+        let average = loads.values().sum::<f64>() / loads.len() as f64;
+        let limit = average * (1.0 + imbalance_tolerance.max(0.0));
+        let weight_of = |shard_id: &ShardId| self.shards.get(shard_id).map_or(1.0, |config| config.effective_capacity_weight());
+        
+        let mut moves: Vec<AccountMove> = Vec::new();
+        let mut moved: HashSet<AccountId> = HashSet::new();
+        let mut placement: HashMap<AccountId, ShardId> = HashMap::new();
+        let shard_of = |placement: &HashMap<AccountId, ShardId>, account_id: &AccountId| {
+            placement.get(account_id).copied().or_else(|| self.get_shard(account_id))
+        };
+        
+        // Cut cross-shard interactions, biggest gains first
+        let mut candidates: Vec<(u64, AccountId, ShardId, ShardId)> = self.allocations.iter()
+            .filter_map(|(account_id, current)| {
+                let affinity = self.affinity(account_id, &placement);
+                let here = affinity.get(current).copied().unwrap_or(0);
+                let (best, weight) = affinity.into_iter().max_by_key(|(shard_id, weight)| (*weight, std::cmp::Reverse(shard_id.0)))?;
+                (best != *current && weight > here).then(|| (weight - here, account_id.clone(), *current, best))
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.0.cmp(&b.1.0)));
+        
+        for (_, account_id, from, to) in candidates {
+            if moves.len() >= max_moves {
+                return moves;
+            }
+            
+            // Earlier moves may have changed the picture
+            let affinity = self.affinity(&account_id, &placement);
+            if affinity.get(&to).copied().unwrap_or(0) <= affinity.get(&from).copied().unwrap_or(0) {
+                continue;
+            }
+            
+            let load = self.account_load(&account_id);
+            let target_load = loads[&to] + load / weight_of(&to);
+            if target_load > limit && target_load > loads[&from] {
+                continue;
+            }
+            
+            *loads.get_mut(&from).expect("Shard has a load") -= load / weight_of(&from);
+            *loads.get_mut(&to).expect("Shard has a load") = target_load;
+            placement.insert(account_id.clone(), to);
+            moved.insert(account_id.clone());
+            moves.push(AccountMove { account_id, from, to });
+        }
+        
+        // Even out load without adding cross-shard interactions
+        while moves.len() < max_moves {
+            let (heaviest, heaviest_load) = loads.iter()
+                .max_by(|a, b| a.1.total_cmp(b.1).then_with(|| b.0.0.cmp(&a.0.0)))
+                .map(|(shard_id, load)| (*shard_id, *load))
+                .expect("There are shards");
+            let (lightest, lightest_load) = loads.iter()
+                .min_by(|a, b| a.1.total_cmp(b.1).then_with(|| a.0.0.cmp(&b.0.0)))
+                .map(|(shard_id, load)| (*shard_id, *load))
+                .expect("There are shards");
+            
+            if heaviest_load <= limit || heaviest == lightest {
+                break;
+            }
+            
+            let gap = (heaviest_load - lightest_load) / 2.0;
+            let mut accounts: Vec<&AccountId> = self.allocations.keys()
+                .filter(|account_id| shard_of(&placement, account_id) == Some(heaviest))
+                .filter(|account_id| !moved.contains(*account_id))
+                .collect();
+            accounts.sort_by(|a, b| self.account_load(b).total_cmp(&self.account_load(a)).then_with(|| a.0.cmp(&b.0)));
+            
+            let chosen = accounts.into_iter().find(|account_id| {
+                let load = self.account_load(account_id);
+                let affinity = self.affinity(account_id, &placement);
+                load > 0.0
+                    && load / weight_of(&heaviest) <= gap
+                    && affinity.get(&lightest).copied().unwrap_or(0) >= affinity.get(&heaviest).copied().unwrap_or(0)
+            });
+            
+            let account_id = match chosen {
+                Some(account_id) => account_id.clone(),
+                None => break,
+            };
+            
+            let load = self.account_load(&account_id);
+            *loads.get_mut(&heaviest).expect("Shard has a load") -= load / weight_of(&heaviest);
+            *loads.get_mut(&lightest).expect("Shard has a load") += load / weight_of(&lightest);
+            placement.insert(account_id.clone(), lightest);
+            moved.insert(account_id.clone());
+            moves.push(AccountMove { account_id, from: heaviest, to: lightest });
+        }
+        
+        moves
+    }
+    
+    /// Get the allocation strategy
+    pub fn strategy(&self) -> AllocationStrategy {
+        self.strategy
+    }
+    
     /// Set the allocation strategy
     pub fn set_strategy(&mut self, strategy: AllocationStrategy) {
         self.strategy = strategy;
@@ -180,44 +475,67 @@ impl ShardAllocator {
         match self.strategy {
             AllocationStrategy::Hash => {
                 // Simple hash-based allocation
-                let hash = self.hash_account_id(account_id);
-                let shard_count = self.shards.len() as u32;
-                let shard_index = hash % shard_count;
-                
-                // Find the shard with this index
-                let shards: Vec<_> = self.shards.keys().collect();
-                if let Some(shard_id) = shards.get(shard_index as usize) {
-                    Ok(**shard_id)
-                } else {
-                    // Fallback to first shard
-                    Ok(*shards[0])
+                self.find_best_shard_hash(account_id)
+            }
+            AllocationStrategy::Balance | AllocationStrategy::Activity => {
+                // Allocate to the least loaded shard
+                self.least_loaded_shard()
+            }
+            AllocationStrategy::ContractDependency => {
+                // Allocate next to the accounts it interacts with most, if any are placed
+                match self.affinity(account_id, &HashMap::new()).into_iter().max_by_key(|(shard_id, weight)| (*weight, std::cmp::Reverse(shard_id.0))) {
+                    Some((shard_id, _)) => Ok(shard_id),
+                    None => self.least_loaded_shard(),
                 }
             }
-            AllocationStrategy::Balance => {
-                // Allocate to the shard with the fewest accounts
-                let (shard_id, _) = self.shards.keys()
-                    .map(|id| (*id, self.shard_account_count(id)))
-                    .min_by_key(|(_, count)| *count)
-                    .ok_or_else(|| "No shards available".to_string())?;
-                
-                Ok(shard_id)
+            AllocationStrategy::ConsistentHash => {
+                self.ring.locate(account_id).ok_or_else(|| "No shards available".to_string())
             }
-            AllocationStrategy::Activity | AllocationStrategy::Geographic | AllocationStrategy::ContractDependency => {
-                // These strategies require additional data that we don't have here
-                // For now, fall back to hash-based allocation
+            AllocationStrategy::Geographic => {
+                // Peers do not report locations, so fall back to hash-based allocation
                 self.find_best_shard_hash(account_id)
             }
         }
     }
     
+    /// Find the shard with the lowest load, breaking ties by account count
+    fn least_loaded_shard(&self) -> Result<ShardId, String> {
+        self.shards.keys()
+            .map(|shard_id| (*shard_id, self.shard_load(shard_id), self.shard_account_count(shard_id)))
+            .min_by(|a, b| a.1.total_cmp(&b.1).then(a.2.cmp(&b.2)).then(a.0.0.cmp(&b.0.0)))
+            .map(|(shard_id, _, _)| shard_id)
+            .ok_or_else(|| "No shards available".to_string())
+    }
+    
+    /// Get the load an account puts on its shard under the current strategy
+    fn account_load(&self, account_id: &AccountId) -> f64 {
+        match self.strategy {
+            AllocationStrategy::Balance => self.stats.balance(account_id) as f64,
+            AllocationStrategy::Activity | AllocationStrategy::ContractDependency => self.stats.activity(account_id) as f64,
+            _ => 1.0,
+        }
+    }
+    
+    /// Sum an account's interactions per shard, with `placement` overriding current allocations
+    fn affinity(&self, account_id: &AccountId, placement: &HashMap<AccountId, ShardId>) -> HashMap<ShardId, u64> {
+        let mut affinity: HashMap<ShardId, u64> = HashMap::new();
+        for (neighbour, weight) in self.stats.neighbours(account_id) {
+            if let Some(shard_id) = placement.get(neighbour).copied().or_else(|| self.get_shard(neighbour)) {
+                *affinity.entry(shard_id).or_insert(0) += weight;
+            }
+        }
+        affinity
+    }
+    
     /// Find the best shard using hash-based allocation
     fn find_best_shard_hash(&self, account_id: &AccountId) -> Result<ShardId, String> {
         let hash = self.hash_account_id(account_id);
         let shard_count = self.shards.len() as u32;
         let shard_index = hash % shard_count;
         
-        // Find the shard with this index
-        let shards: Vec<_> = self.shards.keys().collect();
+        // Find the shard with this index, in shard ID order so every node picks the same one
+        let mut shards: Vec<_> = self.shards.keys().collect();
+        shards.sort();
         if let Some(shard_id) = shards.get(shard_index as usize) {
             Ok(**shard_id)
         } else {
@@ -236,3 +554,70 @@ impl ShardAllocator {
         hash
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Transaction, TransactionType};
+    use crate::utils::crypto::KeyPair;
+    use ed25519_dalek::VerifyingKey;
+    
+    /// A fresh sender key and its account
+    fn account() -> (VerifyingKey, AccountId) {
+        let key = VerifyingKey::from_bytes(&KeyPair::generate().public_key()).unwrap();
+        (key, AccountId(key.to_bytes()))
+    }
+    
+    /// An activity-driven allocator over two shards of equal weight
+    fn allocator() -> ShardAllocator {
+        let mut allocator = ShardAllocator::new(AllocationStrategy::Activity);
+        allocator.add_shard(ShardId(0), ShardConfig::default());
+        allocator.add_shard(ShardId(1), ShardConfig::default());
+        allocator
+    }
+    
+    #[test]
+    fn moves_interacting_accounts_together_within_the_load_limit() {
+        let mut allocator = allocator();
+        let (key, sender) = account();
+        let (busy_key, busy) = account();
+        let recipient = AccountId([1; 32]);
+        allocator.assign_account(&sender, ShardId(0)).unwrap();
+        allocator.assign_account(&busy, ShardId(0)).unwrap();
+        allocator.assign_account(&recipient, ShardId(1)).unwrap();
+        
+        for nonce in 0..3 {
+            let transfer = TransactionType::Transfer { recipient: recipient.0, amount: 1 };
+            allocator.stats_mut().record_transaction(&Transaction::new(transfer, key, nonce, 21_000, 1));
+        }
+        for nonce in 0..6 {
+            allocator.stats_mut().record_transaction(&Transaction::new(TransactionType::Stake { amount: 1 }, busy_key, nonce, 21_000, 1));
+        }
+        
+        // The recipient's shard is the lighter one, so the sender joins it rather than the other way round
+        let moves = allocator.propose_migrations(10, 0.2);
+        
+        assert_eq!(moves, vec![AccountMove { account_id: sender, from: ShardId(0), to: ShardId(1) }]);
+    }
+    
+    #[test]
+    fn moves_each_account_at_most_once() {
+        let mut allocator = allocator();
+        let mut keys = Vec::new();
+        for _ in 0..8 {
+            let (key, account_id) = account();
+            allocator.assign_account(&account_id, ShardId(0)).unwrap();
+            keys.push(key);
+        }
+        for (nonce, key) in keys.iter().enumerate() {
+            allocator.stats_mut().record_transaction(&Transaction::new(TransactionType::Stake { amount: 1 }, *key, nonce as u64, 21_000, 1));
+        }
+        
+        let moves = allocator.propose_migrations(10, 0.0);
+        
+        let moved: HashSet<&AccountId> = moves.iter().map(|account_move| &account_move.account_id).collect();
+        assert_eq!(moved.len(), moves.len());
+        assert_eq!(moves.len(), 4);
+        assert!(moves.iter().all(|account_move| account_move.from == ShardId(0) && account_move.to == ShardId(1)));
+    }
+}
@@ -0,0 +1,794 @@
+use crate::consensus::{CommitteeSchedule, FinalityProof};
+use crate::sharding::{journal, ReshardingStrategy, ShardId};
+use crate::storage::{Batch, Database};
+use crate::types::{hash_leaf, AccountId, BlockHeader, BlockId, MerkleProof, MerkleTree, StateRoot};
+use crate::utils::crypto::{verify_signature, Signature};
+use ed25519_dalek::VerifyingKey;
+use serde::{Serialize, Deserialize};
+use sha3::{Sha3_256, Digest};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Journal record kind for imported beacon blocks
+const BLOCK_RECORD: &[u8] = b"beacon_block";
+
+/// Journal record kind for the finalized beacon height
+const FINALITY_RECORD: &[u8] = b"beacon_finality";
+
+/// Configuration for the beacon chain
+#[derive(Debug, Clone)]
+pub struct BeaconConfig {
+    /// Fraction of validators that must sign a beacon block to finalize it
+    pub finality_threshold: f64,
+    /// Maximum number of shard headers a single cross-link may carry
+    pub max_cross_link_headers: usize,
+    /// Genesis block every shard chain starts from
+    pub genesis_block_id: BlockId,
+    /// Minimum number of seconds between beacon blocks
+    pub block_interval: u64,
+}
+
+impl Default for BeaconConfig {
+    fn default() -> Self {
+        BeaconConfig {
+            finality_threshold: 0.67,
+            max_cross_link_headers: 64,
+            genesis_block_id: BlockId([0; 32]),
+            block_interval: 6,
+        }
+    }
+}
+
+/// Latest shard block committed to by the beacon chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardCommitment {
+    /// Shard the block belongs to
+    pub shard_id: ShardId,
+    /// Height of the shard block
+    pub height: u64,
+    /// ID of the shard block
+    pub block_id: BlockId,
+    /// State root after the shard block
+    pub state_root: StateRoot,
+}
+
+impl ShardCommitment {
+    /// Get the leaf of this commitment in the global state commitment
+    ///
+    /// The leaf hashes the fields in a fixed layout, so it never depends on
+    /// how the commitment is serialized.
+    pub fn leaf(&self) -> [u8; 32] {
+        let mut bytes = Vec::with_capacity(4 + 8 + 32 + 32);
+        bytes.extend_from_slice(&self.shard_id.0.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.extend_from_slice(&self.block_id.0);
+        bytes.extend_from_slice(&self.state_root.0);
+        hash_leaf(&bytes)
+    }
+}
+
+/// Shard headers linking a shard's last committed block to its new tip
+///
+/// The first header extends the shard's previous commitment, or the genesis
+/// block for a shard's first link, and each following header extends the
+/// one before, so the beacon chain can check the shard chain without seeing
+/// the blocks themselves. Every header must be signed by a member of the
+/// shard's committee.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossLink {
+    /// Shard the headers belong to
+    pub shard_id: ShardId,
+    /// Headers in ascending height order
+    pub headers: Vec<BlockHeader>,
+}
+
+impl CrossLink {
+    /// Get the commitment to the newest header
+    pub fn commitment(&self) -> Option<ShardCommitment> {
+        self.headers.last().map(|header| ShardCommitment {
+            shard_id: self.shard_id,
+            height: header.height,
+            block_id: header.id(),
+            state_root: header.state_root.clone(),
+        })
+    }
+}
+
+/// Resharding agreed on by the beacon chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledResharding {
+    /// Resharding strategy
+    pub strategy: ReshardingStrategy,
+    /// Shards involved
+    pub shards: Vec<ShardId>,
+    /// Beacon height at which the resharding starts
+    pub activation_height: u64,
+    /// Accounts moving from the first shard to the second, for a rebalance
+    pub accounts: Vec<AccountId>,
+}
+
+/// Block of the beacon chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BeaconBlock {
+    /// Height in the beacon chain
+    pub height: u64,
+    /// ID of the previous beacon block
+    pub prev_block: BlockId,
+    /// Timestamp when the block was created
+    pub timestamp: u64,
+    /// New shard blocks committed to by this block
+    pub cross_links: Vec<CrossLink>,
+    /// Resharding scheduled by this block
+    pub resharding: Vec<ScheduledResharding>,
+    /// Merkle root over the latest commitment of every shard, by shard ID
+    pub state_commitment: [u8; 32],
+}
+
+impl BeaconBlock {
+    /// Get the ID of the block
+    ///
+    /// Cross-links are hashed through their header IDs, and every field in a
+    /// fixed layout with lengths before lists.
+    pub fn id(&self) -> BlockId {
+        let mut hasher = Sha3_256::new();
+        hasher.update(self.height.to_be_bytes());
+        hasher.update(self.prev_block.0);
+        hasher.update(self.timestamp.to_be_bytes());
+        hasher.update(self.state_commitment);
+        
+        hasher.update((self.cross_links.len() as u64).to_be_bytes());
+        for link in &self.cross_links {
+            hasher.update(link.shard_id.0.to_be_bytes());
+            hasher.update((link.headers.len() as u64).to_be_bytes());
+            for header in &link.headers {
+                hasher.update(header.id().0);
+            }
+        }
+        
+        hasher.update((self.resharding.len() as u64).to_be_bytes());
+        for resharding in &self.resharding {
+            hasher.update([resharding.strategy as u8]);
+            hasher.update(resharding.activation_height.to_be_bytes());
+            hasher.update((resharding.shards.len() as u64).to_be_bytes());
+            for shard_id in &resharding.shards {
+                hasher.update(shard_id.0.to_be_bytes());
+            }
+            hasher.update((resharding.accounts.len() as u64).to_be_bytes());
+            for account_id in &resharding.accounts {
+                hasher.update(account_id.0);
+            }
+        }
+        
+        let mut id = [0u8; 32];
+        id.copy_from_slice(&hasher.finalize());
+        BlockId(id)
+    }
+}
+
+/// Proof that a shard commitment is part of a global state commitment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardRootProof {
+    /// The proven commitment
+    pub commitment: ShardCommitment,
+    /// Path from the commitment's leaf to the global state commitment
+    pub proof: MerkleProof,
+}
+
+impl ShardRootProof {
+    /// Verify the proof against a global state commitment
+    pub fn verify(&self, state_commitment: &[u8; 32]) -> bool {
+        self.proof.verify(self.commitment.leaf(), state_commitment)
+    }
+}
+
+/// Coordination chain tying shard chains together
+///
+/// Each beacon block carries cross-links for the shards that produced
+/// blocks since the last one and commits to the latest state root of every
+/// shard. Finalizing a beacon block finalizes every shard block it links, and
+/// resharding scheduled in finalized blocks starts at its agreed height.
+pub struct BeaconChain {
+    /// Configuration
+    config: BeaconConfig,
+    /// Blocks by height, starting with genesis
+    blocks: Vec<BeaconBlock>,
+    /// Shard commitments after each block, by height
+    commitments: Vec<BTreeMap<ShardId, ShardCommitment>>,
+    /// Shards that cross-links are accepted for
+    shards: BTreeSet<ShardId>,
+    /// Validated cross-links waiting for the next block
+    pending_links: BTreeMap<ShardId, CrossLink>,
+    /// Resharding waiting for the next block
+    pending_resharding: Vec<ScheduledResharding>,
+    /// Height of the latest finalized beacon block
+    finalized_height: u64,
+    /// Finality signatures on unfinalized blocks, by height and validator
+    votes: BTreeMap<u64, BTreeMap<[u8; 32], Signature>>,
+}
+
+impl BeaconChain {
+    /// Create a beacon chain containing only the genesis block
+    pub fn new(config: BeaconConfig) -> Self {
+        let genesis = BeaconBlock {
+            height: 0,
+            prev_block: BlockId([0; 32]),
+            timestamp: 0,
+            cross_links: Vec::new(),
+            resharding: Vec::new(),
+            state_commitment: [0; 32],
+        };
+        
+        BeaconChain {
+            config,
+            blocks: vec![genesis],
+            commitments: vec![BTreeMap::new()],
+            shards: BTreeSet::new(),
+            pending_links: BTreeMap::new(),
+            pending_resharding: Vec::new(),
+            finalized_height: 0,
+            votes: BTreeMap::new(),
+        }
+    }
+    
+    /// Restore the beacon chain from its journal
+    pub fn resume(config: BeaconConfig, shards: &[ShardId], database: &Database) -> Result<Self, String> {
+        let mut chain = Self::new(config);
+        for shard_id in shards {
+            chain.add_shard(*shard_id);
+        }
+        
+        // Journaled blocks had their headers checked against the committees when imported
+        let blocks: Vec<BeaconBlock> = journal::load(database, BLOCK_RECORD)?;
+        for block in blocks {
+            chain.apply_block(block, None)?;
+        }
+        
+        let finalized: Vec<u64> = journal::load(database, FINALITY_RECORD)?;
+        chain.finalized_height = finalized.into_iter().next().unwrap_or(0).min(chain.head().height);
+        
+        if chain.head().height > 0 {
+            log::info!("Resumed beacon chain at height {} (finalized {})", chain.head().height, chain.finalized_height);
+        }
+        
+        Ok(chain)
+    }
+    
+    /// Set the genesis block the first cross-link of every shard must extend
+    pub fn set_genesis(&mut self, genesis_block_id: BlockId) {
+        self.config.genesis_block_id = genesis_block_id;
+    }
+    
+    /// Get the configuration
+    pub fn config(&self) -> &BeaconConfig {
+        &self.config
+    }
+    
+    /// Accept cross-links for a shard
+    pub fn add_shard(&mut self, shard_id: ShardId) {
+        self.shards.insert(shard_id);
+    }
+    
+    /// Stop accepting cross-links for a shard
+    pub fn remove_shard(&mut self, shard_id: &ShardId) {
+        self.shards.remove(shard_id);
+        self.pending_links.remove(shard_id);
+    }
+    
+    /// Get the latest block
+    pub fn head(&self) -> &BeaconBlock {
+        self.blocks.last().expect("Beacon chain has a genesis block")
+    }
+    
+    /// Get a block by height
+    pub fn block(&self, height: u64) -> Option<&BeaconBlock> {
+        self.blocks.get(height as usize)
+    }
+    
+    /// Get the height of the latest finalized block
+    pub fn finalized_height(&self) -> u64 {
+        self.finalized_height
+    }
+    
+    /// Get the global state commitment after a block
+    pub fn state_commitment(&self, height: u64) -> Option<[u8; 32]> {
+        self.block(height).map(|block| block.state_commitment)
+    }
+    
+    /// Get the latest commitment of a shard as of a block
+    pub fn shard_commitment(&self, height: u64, shard_id: &ShardId) -> Option<&ShardCommitment> {
+        self.commitments.get(height as usize).and_then(|commitments| commitments.get(shard_id))
+    }
+    
+    /// Get the latest finalized commitment of a shard
+    pub fn finalized_commitment(&self, shard_id: &ShardId) -> Option<&ShardCommitment> {
+        self.shard_commitment(self.finalized_height, shard_id)
+    }
+    
+    /// Check if a shard block height is covered by a finalized beacon block
+    pub fn is_finalized(&self, shard_id: &ShardId, height: u64) -> bool {
+        self.finalized_commitment(shard_id).is_some_and(|commitment| commitment.height >= height)
+    }
+    
+    /// Prove a shard's commitment against the global state commitment of a block
+    pub fn prove_shard_root(&self, height: u64, shard_id: &ShardId) -> Option<ShardRootProof> {
+        let commitments = self.commitments.get(height as usize)?;
+        let index = commitments.keys().position(|id| id == shard_id)?;
+        let tree = MerkleTree::new(commitments.values().map(ShardCommitment::leaf).collect());
+        
+        Some(ShardRootProof {
+            commitment: commitments[shard_id].clone(),
+            proof: tree.proof(index)?,
+        })
+    }
+    
+    /// Validate a cross-link against the chain and the shard committees, and queue it for the next block
+    ///
+    /// A later link for the same shard replaces a queued one, as it covers
+    /// the same headers and more.
+    pub fn submit_cross_link(&mut self, link: CrossLink, committees: &CommitteeSchedule) -> Result<(), String> {
+        let commitments = self.commitments.last().expect("Beacon chain has a genesis block");
+        validate_cross_link(&self.config, &self.shards, commitments.get(&link.shard_id), &link, Some(committees))?;
+        
+        let height = link.headers.last().map_or(0, |header| header.height);
+        let replaces = self.pending_links.get(&link.shard_id)
+            .is_none_or(|pending| pending.headers.last().map_or(0, |header| header.height) < height);
+        if replaces {
+            self.pending_links.insert(link.shard_id, link);
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the cross-link queued for a shard
+    pub fn pending_link(&self, shard_id: &ShardId) -> Option<&CrossLink> {
+        self.pending_links.get(shard_id)
+    }
+    
+    /// Check if cross-links or resharding are waiting for the next block
+    pub fn has_pending(&self) -> bool {
+        !self.pending_links.is_empty() || !self.pending_resharding.is_empty()
+    }
+    
+    /// Queue resharding to be agreed on in the next block
+    pub fn schedule_resharding(&mut self, resharding: ScheduledResharding) -> Result<(), String> {
+        validate_resharding(&self.shards, self.head().height + 1, &resharding)?;
+        self.pending_resharding.push(resharding);
+        Ok(())
+    }
+    
+    /// Build a block from the queued cross-links and resharding, and import it
+    ///
+    /// Queued links whose signers are no longer in a retained committee are dropped.
+    pub fn produce_block(&mut self, timestamp: u64, committees: &CommitteeSchedule, database: &Database) -> Result<BeaconBlock, String> {
+        let (height, prev_block, head_timestamp) = (self.head().height + 1, self.head().id(), self.head().timestamp);
+        
+        let previous = self.commitments.last().expect("Beacon chain has a genesis block");
+        let cross_links: Vec<CrossLink> = std::mem::take(&mut self.pending_links)
+            .into_values()
+            .filter(|link| validate_cross_link(&self.config, &self.shards, previous.get(&link.shard_id), link, Some(committees)).is_ok())
+            .collect();
+        let resharding: Vec<ScheduledResharding> = std::mem::take(&mut self.pending_resharding)
+            .into_iter()
+            .filter(|resharding| validate_resharding(&self.shards, height, resharding).is_ok())
+            .collect();
+        
+        let mut commitments = self.commitments.last().expect("Beacon chain has a genesis block").clone();
+        for link in &cross_links {
+            if let Some(commitment) = link.commitment() {
+                commitments.insert(link.shard_id, commitment);
+            }
+        }
+        
+        let block = BeaconBlock {
+            height,
+            prev_block,
+            timestamp: timestamp.max(head_timestamp),
+            cross_links,
+            resharding,
+            state_commitment: commitment_root(&commitments),
+        };
+        
+        self.import_block(block.clone(), committees, database)?;
+        Ok(block)
+    }
+    
+    /// Validate a block, including the committee signatures on its shard headers, and append it
+    pub fn import_block(&mut self, block: BeaconBlock, committees: &CommitteeSchedule, database: &Database) -> Result<(), String> {
+        let mut batch = Batch::new();
+        journal::put(&mut batch, BLOCK_RECORD, &block.height.to_be_bytes(), &block)?;
+        
+        self.apply_block(block, Some(committees))?;
+        journal::commit(database, &batch)?;
+        
+        // Drop queued links the new block already covers; their signatures were checked on submission
+        let commitments = self.commitments.last().expect("Beacon chain has a genesis block");
+        let (config, shards) = (&self.config, &self.shards);
+        self.pending_links.retain(|shard_id, link| {
+            validate_cross_link(config, shards, commitments.get(shard_id), link, None).is_ok()
+        });
+        
+        Ok(())
+    }
+    
+    /// Count a validator's finality signature on a block
+    ///
+    /// Returns the finality proof once enough of `validators` signed the
+    /// block; the block is finalized by passing the proof to `finalize`.
+    /// Votes for unknown or already finalized blocks are ignored.
+    pub fn add_finality_vote(
+        &mut self,
+        validator: &[u8; 32],
+        block_id: &BlockId,
+        height: u64,
+        signature: Signature,
+        validators: &[[u8; 32]],
+    ) -> Result<Option<FinalityProof>, String> {
+        if height <= self.finalized_height || self.block(height).is_none_or(|block| block.id() != *block_id) {
+            return Ok(None);
+        }
+        if !validators.contains(validator) {
+            return Err(format!("Finality vote from {} who is not a validator", hex::encode(validator)));
+        }
+        if !verify_signature(validator, &FinalityProof::signing_bytes(block_id, height), &signature) {
+            return Err(format!("Invalid finality signature from validator {}", hex::encode(validator)));
+        }
+        
+        let votes = self.votes.entry(height).or_default();
+        votes.insert(*validator, signature);
+        let required = (validators.len() as f64 * self.config.finality_threshold).ceil() as usize;
+        if votes.len() < required {
+            return Ok(None);
+        }
+        
+        let mut proof = FinalityProof::new(block_id.clone(), height);
+        for (key, signature) in votes.iter() {
+            let key = VerifyingKey::from_bytes(key).map_err(|e| format!("Invalid validator key: {}", e))?;
+            proof.add_signature(key, signature.clone());
+        }
+        Ok(Some(proof))
+    }
+    
+    /// Finalize a block, and with it the shard blocks it links
+    ///
+    /// Returns the shard commitments that became final.
+    pub fn finalize(
+        &mut self,
+        proof: &FinalityProof,
+        validators: &[[u8; 32]],
+        database: &Database,
+    ) -> Result<Vec<ShardCommitment>, String> {
+        if proof.height <= self.finalized_height {
+            return Err(format!("Beacon block {} is already finalized", proof.height));
+        }
+        
+        let block = self.block(proof.height)
+            .ok_or_else(|| format!("Unknown beacon block at height {}", proof.height))?;
+        if block.id() != proof.block_id {
+            return Err(format!("Finality proof is for a different beacon block at height {}", proof.height));
+        }
+        
+        proof.verify(validators, self.config.finality_threshold)?;
+        
+        let mut batch = Batch::new();
+        journal::put(&mut batch, FINALITY_RECORD, b"height", &proof.height)?;
+        journal::commit(database, &batch)?;
+        
+        let previous = self.commitments[self.finalized_height as usize].clone();
+        self.finalized_height = proof.height;
+        self.votes.retain(|height, _| *height > proof.height);
+        
+        Ok(self.commitments[proof.height as usize].values()
+            .filter(|commitment| previous.get(&commitment.shard_id) != Some(*commitment))
+            .cloned()
+            .collect())
+    }
+    
+    /// Get the finalized resharding activating after `after` and up to `up_to`
+    pub fn due_resharding(&self, after: u64, up_to: u64) -> Vec<&ScheduledResharding> {
+        self.blocks.iter()
+            .take(self.finalized_height as usize + 1)
+            .flat_map(|block| block.resharding.iter())
+            .filter(|resharding| resharding.activation_height > after && resharding.activation_height <= up_to)
+            .collect()
+    }
+    
+    /// Get the resharding scheduled in finalized blocks that activates after a height
+    pub fn upcoming_resharding(&self, after: u64) -> Vec<&ScheduledResharding> {
+        self.due_resharding(after, u64::MAX)
+    }
+    
+    /// Validate a block against the head and append it
+    ///
+    /// Shard headers are checked against `committees` when given.
+    fn apply_block(&mut self, block: BeaconBlock, committees: Option<&CommitteeSchedule>) -> Result<(), String> {
+        let head = self.head();
+        if block.height != head.height + 1 {
+            return Err(format!("Expected beacon block {}, got {}", head.height + 1, block.height));
+        }
+        if block.prev_block != head.id() {
+            return Err(format!("Beacon block {} does not extend the head", block.height));
+        }
+        if block.timestamp < head.timestamp {
+            return Err(format!("Beacon block {} is older than its parent", block.height));
+        }
+        
+        let mut commitments = self.commitments.last().expect("Beacon chain has a genesis block").clone();
+        let mut linked = BTreeSet::new();
+        for link in &block.cross_links {
+            if !linked.insert(link.shard_id) {
+                return Err(format!("Beacon block {} links shard {:?} twice", block.height, link.shard_id));
+            }
+            validate_cross_link(&self.config, &self.shards, commitments.get(&link.shard_id), link, committees)?;
+            if let Some(commitment) = link.commitment() {
+                commitments.insert(link.shard_id, commitment);
+            }
+        }
+        
+        for resharding in &block.resharding {
+            validate_resharding(&self.shards, block.height, resharding)?;
+        }
+        
+        if block.state_commitment != commitment_root(&commitments) {
+            return Err(format!("Beacon block {} has an invalid state commitment", block.height));
+        }
+        
+        self.blocks.push(block);
+        self.commitments.push(commitments);
+        Ok(())
+    }
+}
+
+/// Check that a cross-link extends a shard's previous commitment, signed by the shard's committee
+///
+/// Signatures are only checked when `committees` is given.
+fn validate_cross_link(
+    config: &BeaconConfig,
+    shards: &BTreeSet<ShardId>,
+    previous: Option<&ShardCommitment>,
+    link: &CrossLink,
+    committees: Option<&CommitteeSchedule>,
+) -> Result<(), String> {
+    if !shards.contains(&link.shard_id) {
+        return Err(format!("Unknown shard {:?}", link.shard_id));
+    }
+    if link.headers.is_empty() {
+        return Err(format!("Cross-link for shard {:?} has no headers", link.shard_id));
+    }
+    if link.headers.len() > config.max_cross_link_headers {
+        return Err(format!(
+            "Cross-link for shard {:?} has {} headers, at most {} are allowed",
+            link.shard_id, link.headers.len(), config.max_cross_link_headers
+        ));
+    }
+    
+    // The first link of a shard extends the genesis block
+    let (mut height, mut block_id) = previous
+        .map_or((0, config.genesis_block_id.clone()), |commitment| (commitment.height, commitment.block_id.clone()));
+    for header in &link.headers {
+        if header.height != height + 1 || header.prev_block != block_id {
+            return Err(format!(
+                "Shard {:?} header at height {} does not extend block {}",
+                link.shard_id, header.height, height
+            ));
+        }
+        if let Some(committees) = committees {
+            committees.verify_header(&link.shard_id, header)?;
+        }
+        (height, block_id) = (header.height, header.id());
+    }
+    
+    Ok(())
+}
+
+/// Check that resharding can be scheduled in a block
+fn validate_resharding(shards: &BTreeSet<ShardId>, height: u64, resharding: &ScheduledResharding) -> Result<(), String> {
+    if resharding.activation_height <= height {
+        return Err(format!("Resharding must activate after beacon height {}", height));
+    }
+    if resharding.shards.is_empty() {
+        return Err("Resharding must involve at least one shard".to_string());
+    }
+    
+    if let Some(shard_id) = resharding.shards.iter().find(|shard_id| !shards.contains(shard_id)) {
+        return Err(format!("Unknown shard {:?}", shard_id));
+    }
+    
+    let moves_accounts = resharding.strategy == ReshardingStrategy::Rebalance && !resharding.accounts.is_empty();
+    if moves_accounts && (resharding.shards.len() != 2 || resharding.shards[0] == resharding.shards[1]) {
+        return Err("Account moves need a source and a different destination shard".to_string());
+    }
+    if !moves_accounts && !resharding.accounts.is_empty() {
+        return Err("Only rebalancing moves accounts".to_string());
+    }
+    
+    Ok(())
+}
+
+/// Compute the global state commitment over shard commitments
+fn commitment_root(commitments: &BTreeMap<ShardId, ShardCommitment>) -> [u8; 32] {
+    MerkleTree::new(commitments.values().map(ShardCommitment::leaf).collect()).root()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::{CommitteeConfig, Validator, ValidatorSet};
+    use crate::storage::temporary_database;
+    use crate::types::Block;
+    use crate::utils::crypto::KeyPair;
+    use ed25519_dalek::VerifyingKey;
+    
+    /// Committees for shard 0 made of the key pair alone
+    fn committees(keypair: &KeyPair) -> CommitteeSchedule {
+        let mut validators = ValidatorSet::new();
+        let public_key = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+        validators.add_validator(Validator::new(public_key, 100, "validator".to_string(), None, None, None));
+        
+        let mut committees = CommitteeSchedule::new(CommitteeConfig { min_committee_size: 1, ..CommitteeConfig::default() });
+        committees.reshuffle(0, &BlockId([0; 32]), &validators, &[ShardId(0)]).unwrap();
+        committees
+    }
+    
+    /// Signed shard 0 headers following a parent
+    fn headers(keypair: &KeyPair, parent: (u64, BlockId), count: u64) -> Vec<BlockHeader> {
+        let validator = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+        let (mut height, mut prev) = parent;
+        
+        (0..count).map(|_| {
+            let mut block = Block::new(height + 1, prev.clone(), Vec::new(), StateRoot([height as u8 + 1; 32]), validator, 0);
+            block.header.sign(keypair).unwrap();
+            (height, prev) = (block.header.height, block.header.id());
+            block.header
+        }).collect()
+    }
+    
+    /// A beacon chain accepting links for shard 0
+    fn beacon_chain() -> BeaconChain {
+        let mut chain = BeaconChain::new(BeaconConfig::default());
+        chain.add_shard(ShardId(0));
+        chain
+    }
+    
+    /// A finality proof for a beacon block signed by the key pairs
+    fn finality_proof(keypairs: &[&KeyPair], block: &BeaconBlock) -> FinalityProof {
+        let mut proof = FinalityProof::new(block.id(), block.height);
+        for keypair in keypairs {
+            let validator = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+            proof.add_signature(validator, keypair.sign(&FinalityProof::signing_bytes(&block.id(), block.height)));
+        }
+        proof
+    }
+    
+    #[test]
+    fn produces_blocks_that_commit_to_linked_shard_headers() {
+        let (_dir, database) = temporary_database();
+        let keypair = KeyPair::generate();
+        let committees = committees(&keypair);
+        let mut chain = beacon_chain();
+        
+        let headers = headers(&keypair, (0, BlockId([0; 32])), 2);
+        chain.submit_cross_link(CrossLink { shard_id: ShardId(0), headers: headers.clone() }, &committees).unwrap();
+        assert!(chain.has_pending());
+        
+        let block = chain.produce_block(10, &committees, &database).unwrap();
+        assert_eq!(chain.head().height, 1);
+        assert!(!chain.has_pending());
+        
+        let commitment = chain.shard_commitment(1, &ShardId(0)).unwrap();
+        assert_eq!(commitment.height, 2);
+        assert_eq!(commitment.block_id, headers[1].id());
+        assert!(chain.prove_shard_root(1, &ShardId(0)).unwrap().verify(&block.state_commitment));
+        
+        // Another node follows the chain by importing the block
+        let (_other_dir, other_database) = temporary_database();
+        let mut other = beacon_chain();
+        other.import_block(block.clone(), &committees, &other_database).unwrap();
+        assert_eq!(other.head().id(), block.id());
+    }
+    
+    #[test]
+    fn rejects_links_and_blocks_that_do_not_extend_the_chain() {
+        let (_dir, database) = temporary_database();
+        let keypair = KeyPair::generate();
+        let committees = committees(&keypair);
+        let mut chain = beacon_chain();
+        
+        let outsider = headers(&KeyPair::generate(), (0, BlockId([0; 32])), 1);
+        assert!(chain.submit_cross_link(CrossLink { shard_id: ShardId(0), headers: outsider }, &committees).is_err());
+        
+        let headers = headers(&keypair, (0, BlockId([0; 32])), 3);
+        let gap = vec![headers[0].clone(), headers[2].clone()];
+        assert!(chain.submit_cross_link(CrossLink { shard_id: ShardId(0), headers: gap }, &committees).is_err());
+        assert!(chain.submit_cross_link(CrossLink { shard_id: ShardId(1), headers: headers.clone() }, &committees).is_err());
+        
+        chain.submit_cross_link(CrossLink { shard_id: ShardId(0), headers }, &committees).unwrap();
+        let block = chain.produce_block(10, &committees, &database).unwrap();
+        assert!(chain.import_block(block.clone(), &committees, &database).is_err());
+        
+        let mut fork = block;
+        fork.prev_block = BlockId([1; 32]);
+        let mut other = beacon_chain();
+        assert!(other.import_block(fork, &committees, &database).is_err());
+        assert_eq!(other.head().height, 0);
+    }
+    
+    #[test]
+    fn block_ids_and_leaves_cover_every_field() {
+        let keypair = KeyPair::generate();
+        let block = BeaconBlock {
+            height: 1,
+            prev_block: BlockId([0; 32]),
+            timestamp: 10,
+            cross_links: vec![CrossLink { shard_id: ShardId(0), headers: headers(&keypair, (0, BlockId([0; 32])), 1) }],
+            resharding: Vec::new(),
+            state_commitment: [0; 32],
+        };
+        let decoded: BeaconBlock = bincode::deserialize(&bincode::serialize(&block).unwrap()).unwrap();
+        assert_eq!(decoded.id(), block.id());
+        
+        let mut unlinked = block.clone();
+        unlinked.cross_links.clear();
+        assert_ne!(unlinked.id(), block.id());
+        
+        let mut resharded = block.clone();
+        resharded.resharding.push(ScheduledResharding {
+            strategy: ReshardingStrategy::Merge,
+            shards: vec![ShardId(0)],
+            activation_height: 2,
+            accounts: Vec::new(),
+        });
+        assert_ne!(resharded.id(), block.id());
+        
+        let commitment = block.cross_links[0].commitment().unwrap();
+        let mut moved = commitment.clone();
+        moved.state_root = StateRoot([9; 32]);
+        assert_ne!(moved.leaf(), commitment.leaf());
+    }
+    
+    #[test]
+    fn finalizes_blocks_signed_by_the_validators_and_resumes_them() {
+        let (_dir, database) = temporary_database();
+        let keypair = KeyPair::generate();
+        let committees = committees(&keypair);
+        let mut chain = beacon_chain();
+        
+        let headers = headers(&keypair, (0, BlockId([0; 32])), 2);
+        chain.submit_cross_link(CrossLink { shard_id: ShardId(0), headers }, &committees).unwrap();
+        let block = chain.produce_block(10, &committees, &database).unwrap();
+        
+        let validators = [keypair.public_key()];
+        assert!(chain.finalize(&finality_proof(&[&KeyPair::generate()], &block), &validators, &database).is_err());
+        
+        let finalized = chain.finalize(&finality_proof(&[&keypair], &block), &validators, &database).unwrap();
+        assert_eq!(finalized.len(), 1);
+        assert!(chain.is_finalized(&ShardId(0), 2));
+        assert!(chain.finalize(&finality_proof(&[&keypair], &block), &validators, &database).is_err());
+        
+        let resumed = BeaconChain::resume(BeaconConfig::default(), &[ShardId(0)], &database).unwrap();
+        assert_eq!(resumed.head().id(), block.id());
+        assert_eq!(resumed.finalized_height(), 1);
+    }
+    
+    #[test]
+    fn collects_finality_votes_into_a_proof() {
+        let (_dir, database) = temporary_database();
+        let keypairs: Vec<KeyPair> = (0..3).map(|_| KeyPair::generate()).collect();
+        let validators: Vec<[u8; 32]> = keypairs.iter().map(KeyPair::public_key).collect();
+        let mut chain = beacon_chain();
+        let block = chain.produce_block(10, &committees(&keypairs[0]), &database).unwrap();
+        let vote = |keypair: &KeyPair| keypair.sign(&FinalityProof::signing_bytes(&block.id(), block.height));
+        
+        let outsider = KeyPair::generate();
+        assert!(chain.add_finality_vote(&outsider.public_key(), &block.id(), 1, vote(&outsider), &validators).is_err());
+        assert!(chain.add_finality_vote(&validators[0], &block.id(), 1, vote(&outsider), &validators).is_err());
+        assert!(chain.add_finality_vote(&validators[0], &BlockId([1; 32]), 1, vote(&keypairs[0]), &validators).unwrap().is_none());
+        
+        assert!(chain.add_finality_vote(&validators[0], &block.id(), 1, vote(&keypairs[0]), &validators).unwrap().is_none());
+        assert!(chain.add_finality_vote(&validators[0], &block.id(), 1, vote(&keypairs[0]), &validators).unwrap().is_none());
+        assert!(chain.add_finality_vote(&validators[1], &block.id(), 1, vote(&keypairs[1]), &validators).unwrap().is_none());
+        let proof = chain.add_finality_vote(&validators[2], &block.id(), 1, vote(&keypairs[2]), &validators).unwrap().unwrap();
+        assert_eq!(proof.signature_count(), 3);
+        
+        chain.finalize(&proof, &validators, &database).unwrap();
+        assert!(chain.add_finality_vote(&validators[0], &block.id(), 1, vote(&keypairs[0]), &validators).unwrap().is_none());
+    }
+}
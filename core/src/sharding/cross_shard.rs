@@ -1,8 +1,97 @@
-use crate::types::{Transaction, TransactionId, Block, BlockId};
-use crate::sharding::ShardId;
+use crate::types::{Account, AccountId, BlockId, State, StateRoot, StateUpdate, StorageProof, Transaction, TransactionId, TransactionType};
+use crate::sharding::{journal, ShardId, ShardRootProof};
+use crate::storage::{Batch, Database};
 use serde::{Serialize, Deserialize};
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::{Arc, Mutex};
+use sha3::{Sha3_256, Digest};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+/// Domain separator for outbox account IDs
+const OUTBOX_DOMAIN: &[u8] = b"optimachain-cross-shard-outbox";
+
+/// Domain separator for inbox account IDs
+const INBOX_DOMAIN: &[u8] = b"optimachain-cross-shard-inbox";
+
+/// Journal record kind for in-flight transactions
+const TRANSACTION_RECORD: &[u8] = b"cross_shard_transaction";
+
+/// Journal record kind for finalized state roots
+const ROOT_RECORD: &[u8] = b"cross_shard_root";
+
+/// Configuration for cross-shard transfers
+#[derive(Debug, Clone)]
+pub struct CrossShardConfig {
+    /// Number of destination shard blocks a receipt stays valid for
+    ///
+    /// The deadline is counted from the destination's latest finalized height
+    /// known when the sender is debited, so it is in the same units as the
+    /// destination heights it is checked against when crediting and refunding.
+    pub timeout_blocks: u64,
+    /// Number of finalized state roots kept per shard
+    pub max_finalized_roots: usize,
+}
+
+impl Default for CrossShardConfig {
+    fn default() -> Self {
+        CrossShardConfig {
+            timeout_blocks: 100,
+            max_finalized_roots: 1024,
+        }
+    }
+}
+
+/// A transfer of native tokens to an account in another shard
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrossShardTransfer {
+    /// Sending account, in the source shard
+    pub sender: AccountId,
+    /// Receiving account, in the destination shard
+    pub recipient: AccountId,
+    /// Amount of native tokens
+    pub amount: u64,
+}
+
+impl CrossShardTransfer {
+    /// Get the transfer described by a transaction
+    pub fn from_transaction(transaction: &Transaction) -> Result<Self, String> {
+        match &transaction.transaction_type {
+            TransactionType::Transfer { recipient, amount } => Ok(CrossShardTransfer {
+                sender: AccountId(transaction.sender.to_bytes()),
+                recipient: AccountId(*recipient),
+                amount: *amount,
+            }),
+            _ => Err("Only transfers can cross shards".to_string()),
+        }
+    }
+}
+
+/// Receipt written by the source shard once it has debited the sender
+///
+/// The receipt is stored in the source shard's outbox account for the
+/// destination shard, so it can be proven against the source's state root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrossShardReceipt {
+    /// Original transaction ID
+    pub transaction_id: TransactionId,
+    /// Source shard ID
+    pub source_shard: ShardId,
+    /// Destination shard ID
+    pub destination_shard: ShardId,
+    /// The transfer
+    pub transfer: CrossShardTransfer,
+    /// Source shard height at which the sender was debited
+    pub source_height: u64,
+    /// Last destination shard height at which the receipt can be credited: the
+    /// destination's finalized height when the sender was debited plus the
+    /// configured timeout
+    pub deadline: u64,
+}
+
+impl CrossShardReceipt {
+    /// Storage key of the receipt in outbox and inbox accounts
+    pub fn key(&self) -> Vec<u8> {
+        self.transaction_id.0.to_vec()
+    }
+}
 
 /// A cross-shard transaction
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,8 +102,10 @@ pub struct CrossShardTransaction {
     pub source_shard: ShardId,
     /// Destination shard ID
     pub destination_shard: ShardId,
-    /// Transaction data
-    pub data: Vec<u8>,
+    /// The transfer
+    pub transfer: CrossShardTransfer,
+    /// Receipt, once the source shard has debited the sender
+    pub receipt: Option<CrossShardReceipt>,
     /// Status of the cross-shard transaction
     pub status: CrossShardTransactionStatus,
     /// Block ID in the source shard
@@ -23,19 +114,37 @@ pub struct CrossShardTransaction {
     pub destination_block_id: Option<BlockId>,
 }
 
+impl CrossShardTransaction {
+    /// Build the transaction for a receipt we were not tracking
+    fn from_receipt(receipt: &CrossShardReceipt) -> Self {
+        CrossShardTransaction {
+            transaction_id: receipt.transaction_id.clone(),
+            source_shard: receipt.source_shard,
+            destination_shard: receipt.destination_shard,
+            transfer: receipt.transfer.clone(),
+            receipt: Some(receipt.clone()),
+            status: CrossShardTransactionStatus::PendingDestination,
+            source_block_id: None,
+            destination_block_id: None,
+        }
+    }
+}
+
 /// Status of a cross-shard transaction
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CrossShardTransactionStatus {
     /// Transaction is pending in the source shard
     PendingSource,
-    /// Transaction is committed in the source shard
+    /// Sender is debited in the source shard, waiting for the source block to be finalized
     CommittedSource,
-    /// Transaction is pending in the destination shard
+    /// Receipt is finalized and can be credited in the destination shard
     PendingDestination,
-    /// Transaction is committed in the destination shard
+    /// Recipient is credited in the destination shard
     CommittedDestination,
     /// Transaction has been finalized in both shards
     Finalized,
+    /// Receipt timed out and the sender was refunded
+    Refunded,
     /// Transaction has failed
     Failed {
         /// Reason for failure
@@ -43,8 +152,109 @@ pub enum CrossShardTransactionStatus {
     },
 }
 
+/// Get the account holding a shard's receipts for a destination shard
+pub fn outbox_account(destination_shard: ShardId) -> AccountId {
+    system_account(OUTBOX_DOMAIN, destination_shard)
+}
+
+/// Get the account recording receipts a shard has credited from a source shard
+pub fn inbox_account(source_shard: ShardId) -> AccountId {
+    system_account(INBOX_DOMAIN, source_shard)
+}
+
+/// Check if an account is the outbox or inbox of one of the given shards
+pub(crate) fn is_system_account<'a, I>(account_id: &AccountId, shards: I) -> bool
+where
+    I: IntoIterator<Item = &'a ShardId>,
+{
+    shards.into_iter().any(|shard_id| *account_id == outbox_account(*shard_id) || *account_id == inbox_account(*shard_id))
+}
+
+/// Derive a system account ID that no key pair controls
+fn system_account(domain: &[u8], shard_id: ShardId) -> AccountId {
+    let mut hasher = Sha3_256::new();
+    hasher.update(domain);
+    hasher.update(shard_id.0.to_le_bytes());
+    
+    let mut id = [0u8; 32];
+    id.copy_from_slice(&hasher.finalize());
+    AccountId(id)
+}
+
+/// Get a storage value of an account
+fn storage_value(state: &State, account_id: &AccountId, key: &[u8]) -> Option<Vec<u8>> {
+    state.accounts.iter()
+        .find(|account| account.id == *account_id)
+        .and_then(|account| account.storage.iter().find(|(k, _)| k == key))
+        .map(|(_, value)| value.clone())
+}
+
+/// Change an account's balance and storage, creating it if it does not exist
+pub(crate) fn update_account(
+    state: &mut State,
+    account_id: &AccountId,
+    balance_delta: i64,
+    nonce_delta: u64,
+    storage_updates: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+) {
+    if !state.accounts.iter().any(|account| account.id == *account_id) {
+        state.apply_update(StateUpdate::CreateAccount(Account::new_user(account_id.clone())));
+    }
+    
+    state.apply_update(StateUpdate::UpdateAccount {
+        id: account_id.0,
+        balance_delta,
+        nonce_delta,
+        storage_updates,
+    });
+}
+
+/// In-flight transaction changes for accounts changing shard, journaled but not yet applied
+#[derive(Debug, Clone)]
+pub struct PreparedMove {
+    /// Receipts settled directly between the states being merged
+    settled: Vec<CrossShardTransaction>,
+    /// Transfers pointed at their accounts' new shards
+    rerouted: Vec<CrossShardTransaction>,
+    /// Transfers that became intra-shard
+    failed: Vec<TransactionId>,
+}
+
+/// A finalized state root of a shard, as journaled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FinalizedRoot {
+    /// Shard ID
+    shard_id: ShardId,
+    /// Finalized height
+    height: u64,
+    /// State root at that height
+    state_root: StateRoot,
+}
+
+/// Get the journal ID of a finalized root
+fn root_record_id(shard_id: ShardId, height: u64) -> Vec<u8> {
+    let mut id = shard_id.0.to_be_bytes().to_vec();
+    id.extend_from_slice(&height.to_be_bytes());
+    id
+}
+
 /// Communicator for cross-shard transactions
+///
+/// A transfer is executed as a receipt protocol. The source shard debits the
+/// sender and writes a receipt into its outbox account. Once that block is
+/// finalized, the destination shard credits the recipient after checking a
+/// storage proof of the receipt against the source's finalized state root,
+/// and records the receipt in its inbox account so it cannot be credited
+/// twice. A receipt not credited by its deadline is refunded in the source
+/// shard, which requires proof from the destination's finalized state that
+/// the receipt is absent from its inbox.
+///
+/// Every step is journaled to the database together with the accounts it
+/// changes before it is applied, and in-flight transactions are picked up
+/// again by `resume`.
 pub struct CrossShardCommunicator {
+    /// Configuration
+    config: CrossShardConfig,
     /// Pending cross-shard transactions by source shard
     pending_by_source: HashMap<ShardId, VecDeque<CrossShardTransaction>>,
     /// Pending cross-shard transactions by destination shard
@@ -53,6 +263,8 @@ pub struct CrossShardCommunicator {
     completed: HashMap<TransactionId, CrossShardTransaction>,
     /// Transactions waiting for finality
     waiting_for_finality: HashMap<TransactionId, CrossShardTransaction>,
+    /// Finalized state roots by shard and height
+    finalized_roots: HashMap<ShardId, BTreeMap<u64, StateRoot>>,
     /// Callback for when a transaction is ready for the destination shard
     ready_for_destination_callback: Option<Box<dyn Fn(CrossShardTransaction) + Send + Sync>>,
     /// Callback for when a transaction is finalized
@@ -62,41 +274,79 @@ pub struct CrossShardCommunicator {
 impl CrossShardCommunicator {
     /// Create a new cross-shard communicator
     pub fn new() -> Self {
+        Self::with_config(CrossShardConfig::default())
+    }
+    
+    /// Create a new cross-shard communicator with the given configuration
+    pub fn with_config(config: CrossShardConfig) -> Self {
         CrossShardCommunicator {
+            config,
             pending_by_source: HashMap::new(),
             pending_by_destination: HashMap::new(),
             completed: HashMap::new(),
             waiting_for_finality: HashMap::new(),
+            finalized_roots: HashMap::new(),
             ready_for_destination_callback: None,
             finalized_callback: None,
         }
     }
     
+    /// Create a communicator, picking up the transactions in flight when the node stopped
+    pub fn resume(config: CrossShardConfig, database: &Database) -> Result<Self, String> {
+        let mut communicator = Self::with_config(config);
+        
+        let roots: Vec<FinalizedRoot> = journal::load(database, ROOT_RECORD)?;
+        for root in roots {
+            communicator.finalized_roots
+                .entry(root.shard_id)
+                .or_insert_with(BTreeMap::new)
+                .insert(root.height, root.state_root);
+        }
+        
+        let transactions: Vec<CrossShardTransaction> = journal::load(database, TRANSACTION_RECORD)?;
+        let count = transactions.len();
+        for transaction in transactions {
+            communicator.track(transaction);
+        }
+        
+        if count > 0 {
+            log::info!("Resuming {} in-flight cross-shard transactions", count);
+        }
+        
+        Ok(communicator)
+    }
+    
     /// Submit a cross-shard transaction
     pub fn submit_transaction(
         &mut self,
         transaction_id: TransactionId,
         source_shard: ShardId,
         destination_shard: ShardId,
-        data: Vec<u8>,
-    ) -> CrossShardTransaction {
+        transfer: CrossShardTransfer,
+        database: &Database,
+    ) -> Result<CrossShardTransaction, String> {
         let transaction = CrossShardTransaction {
             transaction_id: transaction_id.clone(),
             source_shard,
             destination_shard,
-            data,
+            transfer,
+            receipt: None,
             status: CrossShardTransactionStatus::PendingSource,
             source_block_id: None,
             destination_block_id: None,
         };
         
+        let mut batch = Batch::new();
+        journal::put(&mut batch, TRANSACTION_RECORD, &transaction_id.0, &transaction)?;
+        journal::commit(database, &batch)?;
+        
         // Add to pending by source
         self.pending_by_source
             .entry(source_shard)
             .or_insert_with(VecDeque::new)
             .push_back(transaction.clone());
         
-        transaction
+        Ok(transaction)
     }
     
     /// Get pending transactions for a source shard
@@ -115,70 +365,261 @@ impl CrossShardCommunicator {
             .unwrap_or_default()
     }
     
-    /// Mark a transaction as committed in the source shard
+    /// Debit the sender in the source shard and write the receipt to its outbox
+    ///
+    /// `source_state` is the source shard's state for the block at `height`.
+    /// The transaction fails if the sender cannot cover the amount.
     pub fn commit_in_source(
         &mut self,
         transaction_id: &TransactionId,
         block_id: BlockId,
+        height: u64,
+        source_state: &mut State,
+        database: &Database,
     ) -> Result<CrossShardTransaction, String> {
-        // Find the transaction
-        for (shard_id, queue) in &mut self.pending_by_source {
-            if let Some(pos) = queue.iter().position(|tx| tx.transaction_id == *transaction_id) {
-                let mut transaction = queue.remove(pos).unwrap();
-                
-                // Update status
-                transaction.status = CrossShardTransactionStatus::CommittedSource;
-                transaction.source_block_id = Some(block_id);
-                
-                // Add to pending by destination
-                self.pending_by_destination
-                    .entry(transaction.destination_shard)
-                    .or_insert_with(VecDeque::new)
-                    .push_back(transaction.clone());
-                
-                // Call the callback if set
-                if let Some(callback) = &self.ready_for_destination_callback {
-                    callback(transaction.clone());
-                }
-                
-                return Ok(transaction);
+        let mut transaction = self.pending_by_source.values()
+            .flat_map(|queue| queue.iter())
+            .find(|tx| tx.transaction_id == *transaction_id && tx.status == CrossShardTransactionStatus::PendingSource)
+            .cloned()
+            .ok_or_else(|| format!("Transaction {:?} not found", transaction_id))?;
+        
+        let transfer = &transaction.transfer;
+        let balance = source_state.accounts.iter()
+            .find(|account| account.id == transfer.sender)
+            .map_or(0, |account| account.balance.native);
+        if balance < transfer.amount || transfer.amount > i64::MAX as u64 {
+            let reason = "Insufficient balance for cross-shard transfer".to_string();
+            self.fail_transaction(transaction_id, reason.clone(), database)?;
+            return Err(reason);
+        }
+        
+        let receipt = CrossShardReceipt {
+            transaction_id: transaction_id.clone(),
+            source_shard: transaction.source_shard,
+            destination_shard: transaction.destination_shard,
+            transfer: transfer.clone(),
+            source_height: height,
+            deadline: self.finalized_height(&transaction.destination_shard).saturating_add(self.config.timeout_blocks),
+        };
+        let encoded = bincode::serialize(&receipt).map_err(|e| e.to_string())?;
+        
+        transaction.status = CrossShardTransactionStatus::CommittedSource;
+        transaction.source_block_id = Some(block_id);
+        transaction.receipt = Some(receipt.clone());
+        
+        let outbox = outbox_account(receipt.destination_shard);
+        let mut updated = source_state.clone();
+        update_account(&mut updated, &transfer.sender, -(transfer.amount as i64), 1, Vec::new());
+        update_account(&mut updated, &outbox, 0, 0, vec![(receipt.key(), Some(encoded))]);
+        
+        let mut batch = Batch::new();
+        journal::put(&mut batch, TRANSACTION_RECORD, &transaction_id.0, &transaction)?;
+        journal::put_accounts(&mut batch, transaction.source_shard, &updated, [&transfer.sender, &outbox])?;
+        journal::commit(database, &batch)?;
+        *source_state = updated;
+        
+        let queue = self.pending_by_source.get_mut(&transaction.source_shard).expect("Transaction was found in this queue");
+        let entry = queue.iter_mut()
+            .find(|tx| tx.transaction_id == *transaction_id)
+            .expect("Transaction was found in this queue");
+        *entry = transaction.clone();
+        
+        Ok(transaction)
+    }
+    
+    /// Record a finalized state root of a shard
+    ///
+    /// Receipts committed in the shard at or below `height` become ready for
+    /// their destination shard.
+    pub fn on_shard_finalized(
+        &mut self,
+        shard_id: ShardId,
+        height: u64,
+        state_root: StateRoot,
+        database: &Database,
+    ) -> Result<Vec<CrossShardTransaction>, String> {
+        let is_ready = |transaction: &CrossShardTransaction| {
+            transaction.status == CrossShardTransactionStatus::CommittedSource
+                && transaction.receipt.as_ref().is_some_and(|receipt| receipt.source_height <= height)
+        };
+        
+        let mut ready: Vec<CrossShardTransaction> = self.pending_by_source.get(&shard_id)
+            .map(|queue| queue.iter().filter(|tx| is_ready(tx)).cloned().collect())
+            .unwrap_or_default();
+        for transaction in ready.iter_mut() {
+            transaction.status = CrossShardTransactionStatus::PendingDestination;
+        }
+        
+        let mut roots = self.finalized_roots.get(&shard_id).cloned().unwrap_or_default();
+        roots.insert(height, state_root.clone());
+        let pruned: Vec<u64> = roots.keys()
+            .take(roots.len().saturating_sub(self.config.max_finalized_roots))
+            .copied()
+            .collect();
+        
+        let mut batch = Batch::new();
+        journal::put(&mut batch, ROOT_RECORD, &root_record_id(shard_id, height), &FinalizedRoot {
+            shard_id,
+            height,
+            state_root,
+        })?;
+        for height in &pruned {
+            journal::delete(&mut batch, ROOT_RECORD, &root_record_id(shard_id, *height));
+            roots.remove(height);
+        }
+        for transaction in &ready {
+            journal::put(&mut batch, TRANSACTION_RECORD, &transaction.transaction_id.0, transaction)?;
+        }
+        journal::commit(database, &batch)?;
+        
+        self.finalized_roots.insert(shard_id, roots);
+        if let Some(queue) = self.pending_by_source.get_mut(&shard_id) {
+            queue.retain(|tx| !is_ready(tx));
+        }
+        
+        for transaction in &ready {
+            // Add to pending by destination
+            self.pending_by_destination
+                .entry(transaction.destination_shard)
+                .or_insert_with(VecDeque::new)
+                .push_back(transaction.clone());
+            
+            // Call the callback if set
+            if let Some(callback) = &self.ready_for_destination_callback {
+                callback(transaction.clone());
             }
         }
         
-        Err(format!("Transaction {:?} not found", transaction_id))
+        Ok(ready)
     }
     
-    /// Mark a transaction as committed in the destination shard
+    /// Record a shard state root proven against a finalized beacon state commitment
+    pub fn on_beacon_finalized(
+        &mut self,
+        proof: &ShardRootProof,
+        state_commitment: &[u8; 32],
+        database: &Database,
+    ) -> Result<Vec<CrossShardTransaction>, String> {
+        if !proof.verify(state_commitment) {
+            return Err(format!("Invalid state root proof for shard {:?}", proof.commitment.shard_id));
+        }
+        
+        let commitment = &proof.commitment;
+        if self.finalized_root(&commitment.shard_id, commitment.height).is_some() {
+            return Ok(Vec::new());
+        }
+        
+        self.on_shard_finalized(commitment.shard_id, commitment.height, commitment.state_root.clone(), database)
+    }
+    
+    /// Get a finalized state root of a shard
+    pub fn finalized_root(&self, shard_id: &ShardId, height: u64) -> Option<&StateRoot> {
+        self.finalized_roots.get(shard_id).and_then(|roots| roots.get(&height))
+    }
+    
+    /// Get the latest finalized height of a shard
+    pub fn finalized_height(&self, shard_id: &ShardId) -> u64 {
+        self.finalized_roots.get(shard_id)
+            .and_then(|roots| roots.keys().next_back().copied())
+            .unwrap_or(0)
+    }
+    
+    /// Verify a receipt against the source shard's finalized state
+    ///
+    /// `proof` is a storage proof of the receipt in the source's outbox
+    /// account for the destination shard, taken at the finalized `proof_height`.
+    pub fn verify_receipt(&self, receipt: &CrossShardReceipt, proof: &StorageProof, proof_height: u64) -> Result<(), String> {
+        if proof_height < receipt.source_height {
+            return Err("Receipt proof predates the receipt".to_string());
+        }
+        
+        let state_root = self.finalized_root(&receipt.source_shard, proof_height)
+            .ok_or_else(|| format!("No finalized state root for shard {:?} at height {}", receipt.source_shard, proof_height))?;
+        
+        if proof.key != receipt.key() {
+            return Err("Proof is for a different receipt".to_string());
+        }
+        
+        let value = proof.verify(&outbox_account(receipt.destination_shard), state_root)?
+            .ok_or_else(|| "Receipt is not in the source shard's outbox".to_string())?;
+        
+        let proven: CrossShardReceipt = bincode::deserialize(&value).map_err(|e| e.to_string())?;
+        if proven != *receipt {
+            return Err("Receipt does not match the source shard's outbox".to_string());
+        }
+        
+        Ok(())
+    }
+    
+    /// Credit the recipient of a proven receipt in the destination shard
+    ///
+    /// `destination_state` is the destination shard's state for the block at
+    /// `height`. The receipt is recorded in the inbox so it cannot be credited
+    /// again.
     pub fn commit_in_destination(
         &mut self,
-        transaction_id: &TransactionId,
+        receipt: &CrossShardReceipt,
+        proof: &StorageProof,
+        proof_height: u64,
         block_id: BlockId,
+        height: u64,
+        destination_state: &mut State,
+        database: &Database,
     ) -> Result<CrossShardTransaction, String> {
-        // Find the transaction
-        for (shard_id, queue) in &mut self.pending_by_destination {
-            if let Some(pos) = queue.iter().position(|tx| tx.transaction_id == *transaction_id) {
-                let mut transaction = queue.remove(pos).unwrap();
-                
-                // Update status
-                transaction.status = CrossShardTransactionStatus::CommittedDestination;
-                transaction.destination_block_id = Some(block_id);
-                
-                // Add to waiting for finality
-                self.waiting_for_finality.insert(transaction_id.clone(), transaction.clone());
-                
-                return Ok(transaction);
-            }
+        if height > receipt.deadline {
+            return Err(format!("Receipt {:?} expired at height {}", receipt.transaction_id, receipt.deadline));
         }
         
-        Err(format!("Transaction {:?} not found", transaction_id))
+        let inbox = inbox_account(receipt.source_shard);
+        if storage_value(destination_state, &inbox, &receipt.key()).is_some() {
+            return Err(format!("Receipt {:?} was already credited", receipt.transaction_id));
+        }
+        
+        self.verify_receipt(receipt, proof, proof_height)?;
+        
+        let mut transaction = self.pending_by_destination.get(&receipt.destination_shard)
+            .and_then(|queue| queue.iter().find(|tx| tx.transaction_id == receipt.transaction_id))
+            .cloned()
+            .unwrap_or_else(|| CrossShardTransaction::from_receipt(receipt));
+        
+        // Update status
+        transaction.status = CrossShardTransactionStatus::CommittedDestination;
+        transaction.destination_block_id = Some(block_id);
+        
+        let mut updated = destination_state.clone();
+        update_account(&mut updated, &receipt.transfer.recipient, receipt.transfer.amount as i64, 0, Vec::new());
+        update_account(&mut updated, &inbox, 0, 0, vec![(receipt.key(), Some(vec![1]))]);
+        
+        let mut batch = Batch::new();
+        journal::put(&mut batch, TRANSACTION_RECORD, &receipt.transaction_id.0, &transaction)?;
+        journal::put_accounts(&mut batch, receipt.destination_shard, &updated, [&receipt.transfer.recipient, &inbox])?;
+        journal::commit(database, &batch)?;
+        *destination_state = updated;
+        
+        // Drop it from the destination queue if we were tracking it
+        if let Some(queue) = self.pending_by_destination.get_mut(&receipt.destination_shard) {
+            queue.retain(|tx| tx.transaction_id != receipt.transaction_id);
+        }
+        
+        // Add to waiting for finality
+        self.waiting_for_finality.insert(receipt.transaction_id.clone(), transaction.clone());
+        
+        Ok(transaction)
     }
     
     /// Mark a transaction as finalized
     pub fn finalize_transaction(
         &mut self,
         transaction_id: &TransactionId,
+        database: &Database,
     ) -> Result<CrossShardTransaction, String> {
-        if let Some(mut transaction) = self.waiting_for_finality.remove(transaction_id) {
+        if self.waiting_for_finality.contains_key(transaction_id) {
+            let mut batch = Batch::new();
+            journal::delete(&mut batch, TRANSACTION_RECORD, &transaction_id.0);
+            journal::commit(database, &batch)?;
+            
+            let mut transaction = self.waiting_for_finality.remove(transaction_id).expect("Transaction is waiting for finality");
+            
             // Update status
             transaction.status = CrossShardTransactionStatus::Finalized;
             
@@ -196,44 +637,305 @@ impl CrossShardCommunicator {
         }
     }
     
+    /// Finalize the transactions credited in a destination block that became final
+    pub fn on_block_finalized(
+        &mut self,
+        block_id: &BlockId,
+        database: &Database,
+    ) -> Result<Vec<CrossShardTransaction>, String> {
+        let credited: Vec<TransactionId> = self.waiting_for_finality.values()
+            .filter(|tx| tx.destination_block_id.as_ref() == Some(block_id))
+            .map(|tx| tx.transaction_id.clone())
+            .collect();
+        
+        credited.iter()
+            .map(|transaction_id| self.finalize_transaction(transaction_id, database))
+            .collect()
+    }
+    
+    /// Get the receipts headed for a destination shard whose deadline has passed
+    ///
+    /// These need a proof of absence from the destination's inbox to be refunded.
+    pub fn expired_receipts(&self, destination_shard: &ShardId) -> Vec<CrossShardReceipt> {
+        let finalized_height = self.finalized_height(destination_shard);
+        
+        self.pending_by_destination.get(destination_shard)
+            .map(|queue| {
+                queue.iter()
+                    .filter_map(|tx| tx.receipt.clone())
+                    .filter(|receipt| receipt.deadline < finalized_height)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    
+    /// Refund the sender of a receipt that was never credited
+    ///
+    /// `proof` must show that the receipt is absent from the destination's
+    /// inbox at a finalized `proof_height` past the receipt's deadline, after
+    /// which the destination can no longer credit it. The receipt is removed
+    /// from the outbox so it cannot be refunded twice.
+    pub fn refund(
+        &mut self,
+        receipt: &CrossShardReceipt,
+        proof: &StorageProof,
+        proof_height: u64,
+        source_state: &mut State,
+        database: &Database,
+    ) -> Result<CrossShardTransaction, String> {
+        if proof_height <= receipt.deadline {
+            return Err(format!("Receipt {:?} has not expired", receipt.transaction_id));
+        }
+        
+        let outbox = outbox_account(receipt.destination_shard);
+        let stored = storage_value(source_state, &outbox, &receipt.key())
+            .ok_or_else(|| format!("Receipt {:?} is not in the outbox", receipt.transaction_id))?;
+        if stored != bincode::serialize(receipt).map_err(|e| e.to_string())? {
+            return Err("Receipt does not match the outbox".to_string());
+        }
+        
+        let state_root = self.finalized_root(&receipt.destination_shard, proof_height)
+            .ok_or_else(|| format!("No finalized state root for shard {:?} at height {}", receipt.destination_shard, proof_height))?;
+        if proof.key != receipt.key() {
+            return Err("Proof is for a different receipt".to_string());
+        }
+        if proof.verify(&inbox_account(receipt.source_shard), state_root)?.is_some() {
+            return Err(format!("Receipt {:?} was credited in the destination shard", receipt.transaction_id));
+        }
+        
+        let mut updated = source_state.clone();
+        update_account(&mut updated, &receipt.transfer.sender, receipt.transfer.amount as i64, 0, Vec::new());
+        update_account(&mut updated, &outbox, 0, 0, vec![(receipt.key(), None)]);
+        
+        let mut batch = Batch::new();
+        journal::delete(&mut batch, TRANSACTION_RECORD, &receipt.transaction_id.0);
+        journal::put_accounts(&mut batch, receipt.source_shard, &updated, [&receipt.transfer.sender, &outbox])?;
+        journal::commit(database, &batch)?;
+        *source_state = updated;
+        
+        let mut transaction = self.remove_pending(&receipt.transaction_id)
+            .unwrap_or_else(|| CrossShardTransaction::from_receipt(receipt));
+        transaction.status = CrossShardTransactionStatus::Refunded;
+        self.completed.insert(receipt.transaction_id.clone(), transaction.clone());
+        
+        Ok(transaction)
+    }
+    
     /// Mark a transaction as failed
+    ///
+    /// Only transactions that have not debited the sender can fail; later
+    /// ones are refunded instead.
     pub fn fail_transaction(
         &mut self,
         transaction_id: &TransactionId,
         reason: String,
+        database: &Database,
     ) -> Result<CrossShardTransaction, String> {
-        // Check pending by source
-        for (shard_id, queue) in &mut self.pending_by_source {
-            if let Some(pos) = queue.iter().position(|tx| tx.transaction_id == *transaction_id) {
-                let mut transaction = queue.remove(pos).unwrap();
-                
-                // Update status
-                transaction.status = CrossShardTransactionStatus::Failed { reason: reason.clone() };
-                
-                // Add to completed
-                self.completed.insert(transaction_id.clone(), transaction.clone());
-                
-                return Ok(transaction);
+        let debited = self.get_transaction(transaction_id)
+            .is_some_and(|transaction| transaction.receipt.is_some());
+        if debited {
+            return Err(format!("Transaction {:?} has a receipt and must be refunded", transaction_id));
+        }
+        
+        self.force_fail(transaction_id, reason, database)
+    }
+    
+    /// Stage the adjustment of in-flight transactions for accounts moving from one shard to another
+    ///
+    /// Transfers that have not debited their sender yet are pointed at the
+    /// account's new shard, or failed if that makes them intra-shard. A
+    /// receipt that still has to be credited to a moving recipient, or may
+    /// still be refunded to a moving sender, cannot follow the account, so the
+    /// move is refused until the receipt is credited or refunded.
+    ///
+    /// The changes are journaled into `batch`; apply them with `apply_move`
+    /// once the batch is written.
+    pub fn prepare_migration(
+        &self,
+        accounts: &HashSet<AccountId>,
+        from: ShardId,
+        to: ShardId,
+        batch: &mut Batch,
+    ) -> Result<PreparedMove, String> {
+        let blocking = self.in_flight().into_iter()
+            .filter(|tx| tx.receipt.is_some() && tx.status != CrossShardTransactionStatus::CommittedDestination)
+            .filter(|tx| {
+                (tx.destination_shard == from && accounts.contains(&tx.transfer.recipient))
+                    || (tx.source_shard == from && accounts.contains(&tx.transfer.sender))
+            })
+            .count();
+        if blocking > 0 {
+            return Err(format!("{} cross-shard receipts of moving accounts are still to be credited or refunded", blocking));
+        }
+        
+        self.plan_reroute(
+            |account_id, shard_id| if shard_id == from && accounts.contains(account_id) { to } else { shard_id },
+            Vec::new(),
+            batch,
+        )
+    }
+    
+    /// Settle in-flight transactions of a shard being merged into another
+    ///
+    /// Receipts between the two shards are credited directly in the
+    /// surviving or retiring state and removed from the outbox, since both
+    /// states are about to become one. Transfers that have not debited their
+    /// sender are pointed at the surviving shard, or failed if they become
+    /// intra-shard. Receipts between the retiring shard and any other shard
+    /// must be credited first, as the retiring shard can no longer prove or
+    /// refund them, so the merge is refused while any are left.
+    ///
+    /// The changes are journaled into `batch`; apply them with `apply_move`
+    /// once the batch is written.
+    pub fn prepare_merge(
+        &self,
+        retiring: ShardId,
+        surviving: ShardId,
+        retiring_state: &mut State,
+        surviving_state: &mut State,
+        batch: &mut Batch,
+    ) -> Result<PreparedMove, String> {
+        let is_pair = |tx: &CrossShardTransaction| {
+            (tx.source_shard == retiring && tx.destination_shard == surviving)
+                || (tx.source_shard == surviving && tx.destination_shard == retiring)
+        };
+        let uncredited = |tx: &CrossShardTransaction| {
+            tx.receipt.is_some() && tx.status != CrossShardTransactionStatus::CommittedDestination
+        };
+        
+        let in_flight = self.in_flight();
+        let blocking = in_flight.iter()
+            .filter(|tx| uncredited(tx) && !is_pair(tx))
+            .filter(|tx| tx.source_shard == retiring || tx.destination_shard == retiring)
+            .count();
+        if blocking > 0 {
+            return Err(format!("{} cross-shard receipts of shard {:?} are still to be credited", blocking, retiring));
+        }
+        
+        let settled: Vec<CrossShardTransaction> = in_flight.into_iter()
+            .filter(|tx| uncredited(tx) && is_pair(tx))
+            .collect();
+        
+        for transaction in &settled {
+            let receipt = transaction.receipt.as_ref().expect("Uncredited transactions have a receipt");
+            let (source_state, destination_state) = if receipt.source_shard == retiring {
+                (&mut *retiring_state, &mut *surviving_state)
+            } else {
+                (&mut *surviving_state, &mut *retiring_state)
+            };
+            
+            update_account(destination_state, &receipt.transfer.recipient, receipt.transfer.amount as i64, 0, Vec::new());
+            update_account(source_state, &outbox_account(receipt.destination_shard), 0, 0, vec![(receipt.key(), None)]);
+            journal::delete(batch, TRANSACTION_RECORD, &transaction.transaction_id.0);
+        }
+        
+        self.plan_reroute(
+            |_, shard_id| if shard_id == retiring { surviving } else { shard_id },
+            settled,
+            batch,
+        )
+    }
+    
+    /// Apply in-flight transaction changes staged by `prepare_migration` or `prepare_merge`
+    ///
+    /// Returns the transactions the move settled.
+    pub fn apply_move(&mut self, prepared: PreparedMove) -> Vec<CrossShardTransaction> {
+        let mut completed = Vec::new();
+        for mut transaction in prepared.settled {
+            self.remove_pending(&transaction.transaction_id);
+            transaction.status = CrossShardTransactionStatus::Finalized;
+            self.completed.insert(transaction.transaction_id.clone(), transaction.clone());
+            
+            if let Some(callback) = &self.finalized_callback {
+                callback(transaction.clone());
             }
+            completed.push(transaction);
         }
         
-        // Check pending by destination
-        for (shard_id, queue) in &mut self.pending_by_destination {
-            if let Some(pos) = queue.iter().position(|tx| tx.transaction_id == *transaction_id) {
-                let mut transaction = queue.remove(pos).unwrap();
-                
-                // Update status
-                transaction.status = CrossShardTransactionStatus::Failed { reason: reason.clone() };
-                
-                // Add to completed
-                self.completed.insert(transaction_id.clone(), transaction.clone());
-                
-                return Ok(transaction);
+        for transaction_id in prepared.failed {
+            if let Some(mut transaction) = self.remove_pending(&transaction_id) {
+                transaction.status = CrossShardTransactionStatus::Failed {
+                    reason: "Sender and recipient are in the same shard after resharding".to_string(),
+                };
+                self.completed.insert(transaction_id, transaction);
             }
         }
+        for transaction in prepared.rerouted {
+            self.remove_pending(&transaction.transaction_id);
+            self.track(transaction);
+        }
         
-        // Check waiting for finality
-        if let Some(mut transaction) = self.waiting_for_finality.remove(transaction_id) {
+        completed
+    }
+    
+    /// Stage pointing transfers that have not debited their sender at their accounts' new shards
+    ///
+    /// `new_shard` maps an account and its current shard to its shard after
+    /// the move. Transfers left within one shard are failed.
+    fn plan_reroute<F>(&self, new_shard: F, settled: Vec<CrossShardTransaction>, batch: &mut Batch) -> Result<PreparedMove, String>
+    where
+        F: Fn(&AccountId, ShardId) -> ShardId,
+    {
+        let mut rerouted = Vec::new();
+        let mut failed = Vec::new();
+        for transaction in self.pending_by_source.values().flat_map(|queue| queue.iter()) {
+            if transaction.receipt.is_some() {
+                continue;
+            }
+            
+            let mut updated = transaction.clone();
+            updated.source_shard = new_shard(&updated.transfer.sender, updated.source_shard);
+            updated.destination_shard = new_shard(&updated.transfer.recipient, updated.destination_shard);
+            
+            if updated.source_shard == updated.destination_shard {
+                failed.push(updated.transaction_id);
+            } else if updated.source_shard != transaction.source_shard || updated.destination_shard != transaction.destination_shard {
+                rerouted.push(updated);
+            }
+        }
+        
+        for transaction in &rerouted {
+            journal::put(batch, TRANSACTION_RECORD, &transaction.transaction_id.0, transaction)?;
+        }
+        for transaction_id in &failed {
+            journal::delete(batch, TRANSACTION_RECORD, &transaction_id.0);
+        }
+        
+        Ok(PreparedMove { settled, rerouted, failed })
+    }
+    
+    /// Get the transactions that are still in flight
+    pub fn in_flight(&self) -> Vec<CrossShardTransaction> {
+        self.pending_by_source.values()
+            .chain(self.pending_by_destination.values())
+            .flat_map(|queue| queue.iter())
+            .chain(self.waiting_for_finality.values())
+            .cloned()
+            .collect()
+    }
+    
+    /// Fail an in-flight transaction at any stage, for operators clearing stuck transfers
+    ///
+    /// Unlike `fail_transaction` this also fails transactions whose sender was
+    /// already debited; the receipt stays in the outbox and can still be
+    /// refunded once it expires.
+    pub fn force_fail(
+        &mut self,
+        transaction_id: &TransactionId,
+        reason: String,
+        database: &Database,
+    ) -> Result<CrossShardTransaction, String> {
+        if !self.in_flight().iter().any(|tx| tx.transaction_id == *transaction_id) {
+            return Err(format!("Transaction {:?} not found", transaction_id));
+        }
+        
+        let mut batch = Batch::new();
+        journal::delete(&mut batch, TRANSACTION_RECORD, &transaction_id.0);
+        journal::commit(database, &batch)?;
+        
+        let removed = self.remove_pending(transaction_id)
+            .or_else(|| self.waiting_for_finality.remove(transaction_id));
+        if let Some(mut transaction) = removed {
             // Update status
             transaction.status = CrossShardTransactionStatus::Failed { reason };
             
@@ -258,21 +960,12 @@ impl CrossShardCommunicator {
             return Some(transaction.clone());
         }
         
-        // Check pending by source
-        for (shard_id, queue) in &self.pending_by_source {
-            if let Some(transaction) = queue.iter().find(|tx| tx.transaction_id == *transaction_id) {
-                return Some(transaction.clone());
-            }
-        }
-        
-        // Check pending by destination
-        for (shard_id, queue) in &self.pending_by_destination {
-            if let Some(transaction) = queue.iter().find(|tx| tx.transaction_id == *transaction_id) {
-                return Some(transaction.clone());
-            }
-        }
-        
-        None
+        // Check pending by source and destination
+        self.pending_by_source.values()
+            .chain(self.pending_by_destination.values())
+            .flat_map(|queue| queue.iter())
+            .find(|tx| tx.transaction_id == *transaction_id)
+            .cloned()
     }
     
     /// Set the callback for when a transaction is ready for the destination shard
@@ -290,4 +983,206 @@ impl CrossShardCommunicator {
     {
         self.finalized_callback = Some(Box::new(callback));
     }
+    
+    /// Put a journaled transaction back in the queue for its status
+    fn track(&mut self, transaction: CrossShardTransaction) {
+        match transaction.status {
+            CrossShardTransactionStatus::PendingSource | CrossShardTransactionStatus::CommittedSource => {
+                self.pending_by_source
+                    .entry(transaction.source_shard)
+                    .or_insert_with(VecDeque::new)
+                    .push_back(transaction);
+            }
+            CrossShardTransactionStatus::PendingDestination => {
+                self.pending_by_destination
+                    .entry(transaction.destination_shard)
+                    .or_insert_with(VecDeque::new)
+                    .push_back(transaction);
+            }
+            CrossShardTransactionStatus::CommittedDestination => {
+                self.waiting_for_finality.insert(transaction.transaction_id.clone(), transaction);
+            }
+            _ => {}
+        }
+    }
+    
+    /// Take a transaction out of the source or destination queues
+    fn remove_pending(&mut self, transaction_id: &TransactionId) -> Option<CrossShardTransaction> {
+        for queue in self.pending_by_source.values_mut().chain(self.pending_by_destination.values_mut()) {
+            if let Some(pos) = queue.iter().position(|tx| tx.transaction_id == *transaction_id) {
+                return queue.remove(pos);
+            }
+        }
+        
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::temporary_database;
+    
+    const SOURCE: ShardId = ShardId(0);
+    const DESTINATION: ShardId = ShardId(1);
+    
+    fn sender() -> AccountId {
+        AccountId([1; 32])
+    }
+    
+    fn recipient() -> AccountId {
+        AccountId([2; 32])
+    }
+    
+    /// A state holding one account with a native balance
+    fn funded_state(account_id: AccountId, balance: u64) -> State {
+        let mut account = Account::new_user(account_id);
+        account.balance.native = balance;
+        State::from_accounts(vec![account])
+    }
+    
+    fn balance(state: &State, account_id: &AccountId) -> u64 {
+        state.accounts.iter()
+            .find(|account| account.id == *account_id)
+            .map_or(0, |account| account.balance.native)
+    }
+    
+    /// Submit a transfer of 40 from the sender and debit it at a source height
+    fn debit(
+        communicator: &mut CrossShardCommunicator,
+        source_state: &mut State,
+        height: u64,
+        database: &Database,
+    ) -> CrossShardReceipt {
+        let transaction_id = TransactionId([9; 32]);
+        let transfer = CrossShardTransfer { sender: sender(), recipient: recipient(), amount: 40 };
+        communicator.submit_transaction(transaction_id.clone(), SOURCE, DESTINATION, transfer, database).unwrap();
+        
+        let transaction = communicator.commit_in_source(&transaction_id, BlockId([height as u8; 32]), height, source_state, database).unwrap();
+        assert_eq!(transaction.status, CrossShardTransactionStatus::CommittedSource);
+        transaction.receipt.unwrap()
+    }
+    
+    #[test]
+    fn debits_credits_and_finalizes_a_transfer() {
+        let (_dir, database) = temporary_database();
+        let mut communicator = CrossShardCommunicator::new();
+        let mut source_state = funded_state(sender(), 100);
+        let mut destination_state = State::new();
+        
+        let receipt = debit(&mut communicator, &mut source_state, 5, &database);
+        assert_eq!(balance(&source_state, &sender()), 60);
+        assert!(communicator.get_pending_for_destination(&DESTINATION).is_empty());
+        
+        let ready = communicator.on_shard_finalized(SOURCE, 5, source_state.root.clone(), &database).unwrap();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(communicator.get_pending_for_destination(&DESTINATION).len(), 1);
+        
+        let proof = source_state.prove_storage(&outbox_account(DESTINATION), receipt.key());
+        let block_id = BlockId([7; 32]);
+        communicator.commit_in_destination(&receipt, &proof, 5, block_id.clone(), 3, &mut destination_state, &database).unwrap();
+        assert_eq!(balance(&destination_state, &recipient()), 40);
+        assert!(communicator.commit_in_destination(&receipt, &proof, 5, block_id.clone(), 3, &mut destination_state, &database).is_err());
+        
+        let finalized = communicator.on_block_finalized(&block_id, &database).unwrap();
+        assert_eq!(finalized.len(), 1);
+        assert_eq!(communicator.get_transaction(&receipt.transaction_id).unwrap().status, CrossShardTransactionStatus::Finalized);
+        assert!(communicator.in_flight().is_empty());
+    }
+    
+    #[test]
+    fn refunds_receipts_that_expire_uncredited() {
+        let (_dir, database) = temporary_database();
+        let mut communicator = CrossShardCommunicator::with_config(CrossShardConfig {
+            timeout_blocks: 2,
+            ..CrossShardConfig::default()
+        });
+        let mut source_state = funded_state(sender(), 100);
+        let destination_state = State::new();
+        
+        // The deadline counts from the destination's finalized height, not the source height
+        communicator.on_shard_finalized(DESTINATION, 10, destination_state.root.clone(), &database).unwrap();
+        let receipt = debit(&mut communicator, &mut source_state, 50, &database);
+        assert_eq!(receipt.deadline, 12);
+        communicator.on_shard_finalized(SOURCE, 50, source_state.root.clone(), &database).unwrap();
+        
+        let proof = source_state.prove_storage(&outbox_account(DESTINATION), receipt.key());
+        assert!(communicator.commit_in_destination(&receipt, &proof, 50, BlockId([7; 32]), 13, &mut destination_state.clone(), &database).is_err());
+        
+        let absence = destination_state.prove_storage(&inbox_account(SOURCE), receipt.key());
+        assert!(communicator.refund(&receipt, &absence, 10, &mut source_state, &database).is_err());
+        assert!(communicator.expired_receipts(&DESTINATION).is_empty());
+        
+        communicator.on_shard_finalized(DESTINATION, 13, destination_state.root.clone(), &database).unwrap();
+        assert_eq!(communicator.expired_receipts(&DESTINATION), vec![receipt.clone()]);
+        
+        let refunded = communicator.refund(&receipt, &absence, 13, &mut source_state, &database).unwrap();
+        assert_eq!(refunded.status, CrossShardTransactionStatus::Refunded);
+        assert_eq!(balance(&source_state, &sender()), 100);
+        assert!(communicator.refund(&receipt, &absence, 13, &mut source_state, &database).is_err());
+    }
+    
+    #[test]
+    fn refuses_refunds_of_credited_receipts() {
+        let (_dir, database) = temporary_database();
+        let mut communicator = CrossShardCommunicator::with_config(CrossShardConfig {
+            timeout_blocks: 2,
+            ..CrossShardConfig::default()
+        });
+        let mut source_state = funded_state(sender(), 100);
+        let mut destination_state = State::new();
+        
+        let receipt = debit(&mut communicator, &mut source_state, 1, &database);
+        communicator.on_shard_finalized(SOURCE, 1, source_state.root.clone(), &database).unwrap();
+        let proof = source_state.prove_storage(&outbox_account(DESTINATION), receipt.key());
+        communicator.commit_in_destination(&receipt, &proof, 1, BlockId([7; 32]), 1, &mut destination_state, &database).unwrap();
+        
+        communicator.on_shard_finalized(DESTINATION, 5, destination_state.root.clone(), &database).unwrap();
+        let presence = destination_state.prove_storage(&inbox_account(SOURCE), receipt.key());
+        assert!(communicator.refund(&receipt, &presence, 5, &mut source_state, &database).is_err());
+        assert_eq!(balance(&source_state, &sender()), 60);
+    }
+    
+    #[test]
+    fn rejects_receipts_that_do_not_match_the_source_outbox() {
+        let (_dir, database) = temporary_database();
+        let mut communicator = CrossShardCommunicator::new();
+        let mut source_state = funded_state(sender(), 100);
+        
+        let receipt = debit(&mut communicator, &mut source_state, 1, &database);
+        communicator.on_shard_finalized(SOURCE, 1, source_state.root.clone(), &database).unwrap();
+        let proof = source_state.prove_storage(&outbox_account(DESTINATION), receipt.key());
+        
+        let mut forged = receipt.clone();
+        forged.transfer.amount = 1_000;
+        assert!(communicator.commit_in_destination(&forged, &proof, 1, BlockId([7; 32]), 1, &mut State::new(), &database).is_err());
+        assert!(communicator.commit_in_destination(&receipt, &proof, 2, BlockId([7; 32]), 1, &mut State::new(), &database).is_err());
+    }
+    
+    #[test]
+    fn fails_transfers_the_sender_cannot_cover() {
+        let (_dir, database) = temporary_database();
+        let mut communicator = CrossShardCommunicator::new();
+        let mut source_state = funded_state(sender(), 10);
+        let transaction_id = TransactionId([9; 32]);
+        let transfer = CrossShardTransfer { sender: sender(), recipient: recipient(), amount: 40 };
+        
+        communicator.submit_transaction(transaction_id.clone(), SOURCE, DESTINATION, transfer, &database).unwrap();
+        assert!(communicator.commit_in_source(&transaction_id, BlockId([1; 32]), 1, &mut source_state, &database).is_err());
+        assert!(matches!(communicator.get_transaction(&transaction_id).unwrap().status, CrossShardTransactionStatus::Failed { .. }));
+        assert_eq!(balance(&source_state, &sender()), 10);
+    }
+    
+    #[test]
+    fn resumes_in_flight_transfers() {
+        let (_dir, database) = temporary_database();
+        let mut communicator = CrossShardCommunicator::new();
+        let mut source_state = funded_state(sender(), 100);
+        let receipt = debit(&mut communicator, &mut source_state, 1, &database);
+        communicator.on_shard_finalized(SOURCE, 1, source_state.root.clone(), &database).unwrap();
+        
+        let resumed = CrossShardCommunicator::resume(CrossShardConfig::default(), &database).unwrap();
+        assert_eq!(resumed.get_pending_for_destination(&DESTINATION)[0].receipt, Some(receipt));
+        assert_eq!(resumed.finalized_root(&SOURCE, 1), Some(&source_state.root));
+    }
 }
@@ -0,0 +1,223 @@
+use crate::sharding::cross_shard::update_account;
+use crate::sharding::{journal, Route, Shard, ShardRouter};
+use crate::storage::{Batch, Database};
+use crate::types::{AccountId, State, Transaction, TransactionId, TransactionType};
+use std::time::{Duration, Instant};
+
+/// Gas used by a transfer
+pub const TRANSFER_GAS: u64 = 21_000;
+
+/// Gas used by staking or unstaking
+pub const STAKE_GAS: u64 = 40_000;
+
+/// Storage key of the amount an account has staked
+pub const STAKE_KEY: &[u8] = b"stake";
+
+/// Outcome of executing a block in its shard
+#[derive(Debug, Clone, Default)]
+pub struct BlockExecution {
+    /// Gas used by the executed transactions
+    pub gas_used: u64,
+    /// Time spent executing the transactions
+    pub execution_time: Duration,
+    /// Number of transactions executed
+    pub executed: usize,
+    /// Transactions that failed and changed nothing, with the reason
+    pub failed: Vec<(TransactionId, String)>,
+}
+
+/// Execute the transfers and staking of a block in its shard
+///
+/// Each executed transaction uses a fixed amount of gas and bumps its
+/// sender's nonce. Transfers to other shards are left to the receipt
+/// protocol of `CrossShardCommunicator`, and contract deployments and calls
+/// are not run here. A transaction whose gas limit or sender's funds do not
+/// cover it fails without changing the state. The changed accounts are
+/// journaled with the shard.
+pub fn execute_block(
+    shard: &mut Shard,
+    transactions: &[Transaction],
+    router: &ShardRouter,
+    database: &Database,
+) -> Result<BlockExecution, String> {
+    let started = Instant::now();
+    let shard_id = shard.id();
+    let state = shard.account_state_mut();
+    let mut execution = BlockExecution::default();
+    let mut changed: Vec<AccountId> = Vec::new();
+    
+    for transaction in transactions {
+        if router.route(transaction) != Ok(Route::IntraShard(shard_id)) {
+            continue;
+        }
+        
+        match execute_transaction(state, transaction) {
+            Ok(Some((gas_used, accounts))) => {
+                execution.gas_used = execution.gas_used.saturating_add(gas_used);
+                execution.executed += 1;
+                changed.extend(accounts);
+            }
+            Ok(None) => {}
+            Err(reason) => execution.failed.push((transaction.id(), reason)),
+        }
+    }
+    execution.execution_time = started.elapsed();
+    
+    let mut batch = Batch::new();
+    journal::put_accounts(&mut batch, shard_id, state, &changed)?;
+    journal::commit(database, &batch)?;
+    
+    Ok(execution)
+}
+
+/// Apply one transaction, returning the gas it used and the accounts it changed, or `None` if it is not run here
+fn execute_transaction(state: &mut State, transaction: &Transaction) -> Result<Option<(u64, Vec<AccountId>)>, String> {
+    let sender = AccountId(transaction.sender.to_bytes());
+    let (gas, amount) = match &transaction.transaction_type {
+        TransactionType::Transfer { amount, .. } => (TRANSFER_GAS, *amount),
+        TransactionType::Stake { amount } | TransactionType::Unstake { amount } => (STAKE_GAS, *amount),
+        TransactionType::DeployContract { .. } | TransactionType::CallContract { .. } => return Ok(None),
+    };
+    if transaction.gas_limit < gas {
+        return Err(format!("Gas limit {} is below the {} gas the transaction uses", transaction.gas_limit, gas));
+    }
+    if amount > i64::MAX as u64 {
+        return Err("Amount is too large".to_string());
+    }
+    
+    let balance = state.accounts.iter()
+        .find(|account| account.id == sender)
+        .map_or(0, |account| account.balance.native);
+    let staked = staked(state, &sender);
+    
+    match &transaction.transaction_type {
+        TransactionType::Transfer { recipient, .. } => {
+            if balance < amount {
+                return Err("Insufficient balance for transfer".to_string());
+            }
+            let recipient = AccountId(*recipient);
+            update_account(state, &sender, -(amount as i64), 1, Vec::new());
+            update_account(state, &recipient, amount as i64, 0, Vec::new());
+            Ok(Some((gas, vec![sender, recipient])))
+        }
+        TransactionType::Stake { .. } => {
+            if balance < amount {
+                return Err("Insufficient balance for stake".to_string());
+            }
+            let staked = staked.checked_add(amount).ok_or_else(|| "Stake overflows".to_string())?;
+            update_account(state, &sender, -(amount as i64), 1, vec![(STAKE_KEY.to_vec(), Some(staked.to_be_bytes().to_vec()))]);
+            Ok(Some((gas, vec![sender])))
+        }
+        TransactionType::Unstake { .. } => {
+            if staked < amount {
+                return Err("Cannot unstake more than is staked".to_string());
+            }
+            if balance.checked_add(amount).is_none() {
+                return Err("Balance overflows".to_string());
+            }
+            let remaining = staked - amount;
+            let stake = (remaining > 0).then(|| remaining.to_be_bytes().to_vec());
+            update_account(state, &sender, amount as i64, 1, vec![(STAKE_KEY.to_vec(), stake)]);
+            Ok(Some((gas, vec![sender])))
+        }
+        TransactionType::DeployContract { .. } | TransactionType::CallContract { .. } => Ok(None),
+    }
+}
+
+/// Get the amount an account has staked
+pub fn staked(state: &State, account_id: &AccountId) -> u64 {
+    state.accounts.iter()
+        .find(|account| account.id == *account_id)
+        .and_then(|account| account.storage.iter().find(|(key, _)| key == STAKE_KEY))
+        .and_then(|(_, value)| value.as_slice().try_into().ok())
+        .map_or(0, u64::from_be_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sharding::{AllocationStrategy, ShardAllocator, ShardConfig, ShardId};
+    use crate::storage::temporary_database;
+    use crate::utils::crypto::KeyPair;
+    use ed25519_dalek::VerifyingKey;
+    
+    /// A router over two shards and shard 0 holding a funded sender
+    fn setup(balance: u64) -> (ShardRouter, Shard, VerifyingKey) {
+        let mut router = ShardRouter::new(ShardAllocator::new(AllocationStrategy::Hash));
+        router.add_shard(ShardId(0), ShardConfig::default());
+        router.add_shard(ShardId(1), ShardConfig::default());
+        
+        let sender = VerifyingKey::from_bytes(&KeyPair::generate().public_key()).unwrap();
+        router.allocator_mut().assign_account(&AccountId(sender.to_bytes()), ShardId(0)).unwrap();
+        router.allocator_mut().assign_account(&AccountId([1; 32]), ShardId(0)).unwrap();
+        router.allocator_mut().assign_account(&AccountId([2; 32]), ShardId(1)).unwrap();
+        
+        let mut shard = Shard::new(ShardId(0), ShardConfig::default());
+        update_account(shard.account_state_mut(), &AccountId(sender.to_bytes()), balance as i64, 0, Vec::new());
+        (router, shard, sender)
+    }
+    
+    /// Get an account's native balance in a shard
+    fn balance(shard: &Shard, account_id: &AccountId) -> u64 {
+        shard.account_state().accounts.iter()
+            .find(|account| account.id == *account_id)
+            .map_or(0, |account| account.balance.native)
+    }
+    
+    #[test]
+    fn applies_transfers_and_staking_with_their_gas() {
+        let (_dir, database) = temporary_database();
+        let (router, mut shard, sender) = setup(100);
+        let transactions = vec![
+            Transaction::new(TransactionType::Transfer { recipient: [1; 32], amount: 30 }, sender, 0, TRANSFER_GAS, 1),
+            Transaction::new(TransactionType::Stake { amount: 20 }, sender, 1, STAKE_GAS, 1),
+            Transaction::new(TransactionType::Unstake { amount: 5 }, sender, 2, STAKE_GAS, 1),
+        ];
+        
+        let execution = execute_block(&mut shard, &transactions, &router, &database).unwrap();
+        
+        let sender_id = AccountId(sender.to_bytes());
+        assert_eq!(execution.executed, 3);
+        assert_eq!(execution.gas_used, TRANSFER_GAS + 2 * STAKE_GAS);
+        assert_eq!(balance(&shard, &sender_id), 55);
+        assert_eq!(balance(&shard, &AccountId([1; 32])), 30);
+        assert_eq!(staked(shard.account_state(), &sender_id), 15);
+        
+        let journaled = journal::load_states(&database).unwrap();
+        assert_eq!(journaled[&ShardId(0)].root, shard.account_state().root);
+    }
+    
+    #[test]
+    fn fails_transactions_without_funds_or_gas() {
+        let (_dir, database) = temporary_database();
+        let (router, mut shard, sender) = setup(10);
+        let root = shard.account_state().root.clone();
+        let transactions = vec![
+            Transaction::new(TransactionType::Transfer { recipient: [1; 32], amount: 30 }, sender, 0, TRANSFER_GAS, 1),
+            Transaction::new(TransactionType::Stake { amount: 5 }, sender, 0, STAKE_GAS - 1, 1),
+            Transaction::new(TransactionType::Unstake { amount: 5 }, sender, 0, STAKE_GAS, 1),
+        ];
+        
+        let execution = execute_block(&mut shard, &transactions, &router, &database).unwrap();
+        
+        assert_eq!(execution.executed, 0);
+        assert_eq!(execution.gas_used, 0);
+        assert_eq!(execution.failed.len(), 3);
+        assert_eq!(shard.account_state().root, root);
+    }
+    
+    #[test]
+    fn leaves_cross_shard_transfers_to_the_receipt_protocol() {
+        let (_dir, database) = temporary_database();
+        let (router, mut shard, sender) = setup(100);
+        let transactions = vec![
+            Transaction::new(TransactionType::Transfer { recipient: [2; 32], amount: 30 }, sender, 0, TRANSFER_GAS, 1),
+        ];
+        
+        let execution = execute_block(&mut shard, &transactions, &router, &database).unwrap();
+        
+        assert_eq!(execution.executed, 0);
+        assert!(execution.failed.is_empty());
+        assert_eq!(balance(&shard, &AccountId(sender.to_bytes())), 100);
+    }
+}
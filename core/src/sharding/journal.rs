@@ -0,0 +1,95 @@
+//! Write-ahead records of in-flight sharding work
+//!
+//! Records are written before the in-memory state they describe is changed,
+//! so that a restarted node resumes from the last step it started. Account
+//! changes made by a step are written in the same batch as its records.
+
+use crate::sharding::ShardId;
+use crate::storage::{Batch, Database, IteratorMode, KeyPrefix, ShardingKey, ShardingKeyPrefix, StorageKey};
+use crate::types::{Account, AccountId, State};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+
+/// Get the storage key of a record
+fn record_key(kind: &[u8], id: &[u8]) -> ShardingKey {
+    let mut key = kind.to_vec();
+    key.push(b'/');
+    key.extend_from_slice(id);
+    ShardingKey { key }
+}
+
+/// Add a record to a batch
+pub(crate) fn put<T: Serialize>(batch: &mut Batch, kind: &[u8], id: &[u8], record: &T) -> Result<(), String> {
+    let bytes = bincode::serialize(record)
+        .map_err(|e| format!("Failed to encode sharding record: {}", e))?;
+    batch.put(ShardingKey::column_family(), record_key(kind, id).encode(), bytes);
+    Ok(())
+}
+
+/// Add the removal of a record to a batch
+pub(crate) fn delete(batch: &mut Batch, kind: &[u8], id: &[u8]) {
+    batch.delete(ShardingKey::column_family(), record_key(kind, id).encode());
+}
+
+/// Write a batch of records
+pub(crate) fn commit(database: &Database, batch: &Batch) -> Result<(), String> {
+    database.apply_batch(batch).map_err(|e| e.to_string())
+}
+
+/// Load all records of a kind
+pub(crate) fn load<T: DeserializeOwned>(database: &Database, kind: &[u8]) -> Result<Vec<T>, String> {
+    let mut prefix = ShardingKeyPrefix.encode();
+    prefix.extend_from_slice(&record_key(kind, &[]).key);
+    
+    database.iter_prefix(&ShardingKeyPrefix, IteratorMode::From(prefix.clone()))
+        .map_err(|e| e.to_string())?
+        .collect()
+        .into_iter()
+        .take_while(|(key, _)| key.starts_with(&prefix))
+        .map(|(_, value)| {
+            bincode::deserialize(&value).map_err(|e| format!("Failed to decode sharding record: {}", e))
+        })
+        .collect()
+}
+
+/// Journal record kind for the accounts of a shard
+const ACCOUNT_RECORD: &[u8] = b"shard_account";
+
+/// Get the journal ID of an account in a shard
+fn account_record_id(shard_id: ShardId, account_id: &AccountId) -> Vec<u8> {
+    let mut id = shard_id.0.to_be_bytes().to_vec();
+    id.extend_from_slice(&account_id.0);
+    id
+}
+
+/// Add accounts of a shard, as they are in `state`, to a batch
+///
+/// Accounts `state` no longer holds are removed, so once the batch is written
+/// the journaled accounts of the shard match `state`.
+pub(crate) fn put_accounts<'a, I>(batch: &mut Batch, shard_id: ShardId, state: &State, account_ids: I) -> Result<(), String>
+where
+    I: IntoIterator<Item = &'a AccountId>,
+{
+    for account_id in account_ids {
+        let id = account_record_id(shard_id, account_id);
+        match state.accounts.iter().find(|account| account.id == *account_id) {
+            Some(account) => put(batch, ACCOUNT_RECORD, &id, &(shard_id, account))?,
+            None => delete(batch, ACCOUNT_RECORD, &id),
+        }
+    }
+    Ok(())
+}
+
+/// Load the journaled accounts of every shard
+pub(crate) fn load_states(database: &Database) -> Result<HashMap<ShardId, State>, String> {
+    let records: Vec<(ShardId, Account)> = load(database, ACCOUNT_RECORD)?;
+    
+    let mut accounts: HashMap<ShardId, Vec<Account>> = HashMap::new();
+    for (shard_id, account) in records {
+        accounts.entry(shard_id).or_default().push(account);
+    }
+    
+    Ok(accounts.into_iter()
+        .map(|(shard_id, accounts)| (shard_id, State::from_accounts(accounts)))
+        .collect())
+}
@@ -0,0 +1,51 @@
+use crate::sharding::ShardId;
+use crate::types::{AccountId, State, StateRoot, StateUpdate};
+use serde::{Serialize, Deserialize};
+
+/// Record of accounts moved between shards at a block boundary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountMigration {
+    /// Shard the accounts left
+    pub source_shard: ShardId,
+    /// Shard the accounts joined
+    pub destination_shard: ShardId,
+    /// Source shard height after which the accounts moved
+    pub source_height: u64,
+    /// Destination shard height after which the accounts moved
+    pub destination_height: u64,
+    /// Accounts moved
+    pub accounts: Vec<AccountId>,
+    /// Source state root before the move
+    pub source_root_before: StateRoot,
+    /// Source state root after the move
+    pub source_root_after: StateRoot,
+    /// Destination state root before the move
+    pub destination_root_before: StateRoot,
+    /// Destination state root after the move
+    pub destination_root_after: StateRoot,
+}
+
+/// Move accounts, with their balances and contract storage, from one state to another
+///
+/// Returns the resulting source and destination states, leaving the inputs
+/// untouched so the move can be recorded before it is applied.
+pub(crate) fn move_accounts(source: &State, destination: &State, accounts: &[AccountId]) -> Result<(State, State), String> {
+    let mut source = source.clone();
+    let mut destination = destination.clone();
+    
+    for account_id in accounts {
+        let account = source.accounts.iter()
+            .find(|account| account.id == *account_id)
+            .cloned()
+            .ok_or_else(|| format!("Account {:?} is not in the source shard", account_id))?;
+        
+        if destination.accounts.iter().any(|existing| existing.id == *account_id) {
+            return Err(format!("Account {:?} already exists in the destination shard", account_id));
+        }
+        
+        source.apply_update(StateUpdate::DeleteAccount { id: account_id.0 });
+        destination.apply_update(StateUpdate::CreateAccount(account));
+    }
+    
+    Ok((source, destination))
+}
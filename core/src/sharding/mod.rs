@@ -6,8 +6,24 @@ mod shard;
 mod allocation;
 mod cross_shard;
 mod resharding;
+mod journal;
+mod router;
+mod migration;
+mod ring;
+mod placement;
+mod beacon;
+mod executor;
 
-pub use shard::{Shard, ShardId, ShardConfig, ShardState};
-pub use allocation::{ShardAllocation, ShardAllocator, AllocationStrategy};
-pub use cross_shard::{CrossShardTransaction, CrossShardCommunicator};
-pub use resharding::{ReshardingManager, ReshardingEvent, ReshardingStrategy};
+pub use shard::{Shard, ShardId, ShardConfig, ShardState, ShardLoadMetrics};
+pub use allocation::{ShardAllocation, ShardAllocator, AllocationStrategy, AccountMove};
+pub use ring::HashRing;
+pub use placement::PlacementStats;
+pub use cross_shard::{
+    CrossShardTransaction, CrossShardTransactionStatus, CrossShardCommunicator, CrossShardConfig,
+    CrossShardReceipt, CrossShardTransfer, PreparedMove, inbox_account, outbox_account,
+};
+pub use migration::AccountMigration;
+pub use executor::{BlockExecution, execute_block, staked, STAKE_GAS, STAKE_KEY, TRANSFER_GAS};
+pub use router::{Route, ShardRouter};
+pub use beacon::{BeaconBlock, BeaconChain, BeaconConfig, CrossLink, ScheduledResharding, ShardCommitment, ShardRootProof};
+pub use resharding::{ReshardingManager, ReshardingEvent, ReshardingStrategy, ReshardingOperation, ReshardingStatus, ReshardingPolicy};
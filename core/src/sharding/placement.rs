@@ -0,0 +1,261 @@
+use crate::storage::{Database, MetadataKey};
+use crate::types::{AccountId, Transaction, TransactionType};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+/// Default number of accounts usage is tracked for
+pub const DEFAULT_MAX_TRACKED_ACCOUNTS: usize = 100_000;
+
+/// Share of the maximum that tracking is cut back to once it is exceeded
+const LOW_WATER_RATIO: f64 = 0.75;
+
+/// Metadata key the statistics are stored under
+const PLACEMENT_STATS_KEY: &str = "placement_stats";
+
+/// Observed usage that data-driven allocation strategies place accounts by
+///
+/// Every executed transaction counts towards its sender's activity. Contract
+/// calls and transfers add weight to an edge between the two accounts, since
+/// either becomes a cross-shard transaction if the accounts are split.
+/// Balances are kept for accounts with recorded activity. Once more accounts
+/// are tracked than allowed, the least active ones are forgotten until a
+/// quarter of the room is free again, so busy accounts keep their history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlacementStats {
+    /// Most accounts tracked at once
+    max_accounts: usize,
+    /// Executed transactions per account
+    activity: HashMap<AccountId, u64>,
+    /// Latest known native balance per account
+    balances: HashMap<AccountId, u64>,
+    /// Interaction counts between accounts, stored in both directions
+    interactions: HashMap<AccountId, HashMap<AccountId, u64>>,
+}
+
+impl Default for PlacementStats {
+    fn default() -> Self {
+        Self::with_max_accounts(DEFAULT_MAX_TRACKED_ACCOUNTS)
+    }
+}
+
+impl PlacementStats {
+    /// Create empty statistics
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Create empty statistics tracking at most `max_accounts` accounts
+    pub fn with_max_accounts(max_accounts: usize) -> Self {
+        PlacementStats {
+            max_accounts: max_accounts.max(1),
+            activity: HashMap::new(),
+            balances: HashMap::new(),
+            interactions: HashMap::new(),
+        }
+    }
+    
+    /// Record an executed transaction
+    pub fn record_transaction(&mut self, transaction: &Transaction) {
+        let sender = AccountId(transaction.sender.to_bytes());
+        *self.activity.entry(sender.clone()).or_insert(0) += 1;
+        
+        let counterpart = match &transaction.transaction_type {
+            TransactionType::CallContract { contract_id, .. } => Some(AccountId(*contract_id)),
+            TransactionType::Transfer { recipient, .. } => Some(AccountId(*recipient)),
+            _ => None,
+        };
+        
+        if let Some(counterpart) = counterpart.filter(|counterpart| *counterpart != sender) {
+            *self.activity.entry(counterpart.clone()).or_insert(0) += 1;
+            *self.interactions.entry(sender.clone()).or_default().entry(counterpart.clone()).or_insert(0) += 1;
+            *self.interactions.entry(counterpart).or_default().entry(sender).or_insert(0) += 1;
+        }
+        
+        if self.activity.len() > self.max_accounts {
+            self.evict_least_active();
+        }
+    }
+    
+    /// Record an account's current native balance, if its activity is tracked
+    pub fn record_balance(&mut self, account_id: AccountId, balance: u64) {
+        if self.activity.contains_key(&account_id) {
+            self.balances.insert(account_id, balance);
+        }
+    }
+    
+    /// Get the number of accounts tracked
+    pub fn tracked_accounts(&self) -> usize {
+        self.activity.len()
+    }
+    
+    /// Forget the least active accounts until tracking is down to the low-water mark
+    fn evict_least_active(&mut self) {
+        let low_water = (self.max_accounts as f64 * LOW_WATER_RATIO) as usize;
+        let excess = self.activity.len().saturating_sub(low_water);
+        if excess == 0 {
+            return;
+        }
+        
+        let mut accounts: Vec<(u64, AccountId)> = self.activity.iter()
+            .map(|(account_id, count)| (*count, account_id.clone()))
+            .collect();
+        // Ties go by account ID, so every node forgets the same accounts
+        accounts.select_nth_unstable_by(excess - 1, |a, b| a.0.cmp(&b.0).then_with(|| a.1.0.cmp(&b.1.0)));
+        
+        for (_, account_id) in accounts.into_iter().take(excess) {
+            self.remove_account(&account_id);
+        }
+    }
+    
+    /// Get the number of transactions an account took part in
+    pub fn activity(&self, account_id: &AccountId) -> u64 {
+        self.activity.get(account_id).copied().unwrap_or(0)
+    }
+    
+    /// Get an account's last recorded balance
+    pub fn balance(&self, account_id: &AccountId) -> u64 {
+        self.balances.get(account_id).copied().unwrap_or(0)
+    }
+    
+    /// Get the accounts an account interacts with, and how often
+    pub fn neighbours(&self, account_id: &AccountId) -> impl Iterator<Item = (&AccountId, u64)> {
+        self.interactions.get(account_id)
+            .into_iter()
+            .flat_map(|neighbours| neighbours.iter().map(|(neighbour, weight)| (neighbour, *weight)))
+    }
+    
+    /// Iterate over all interactions, each pair once
+    pub fn interactions(&self) -> impl Iterator<Item = (&AccountId, &AccountId, u64)> {
+        self.interactions.iter()
+            .flat_map(|(account, neighbours)| {
+                neighbours.iter()
+                    .filter(move |(neighbour, _)| account.0 < neighbour.0)
+                    .map(move |(neighbour, weight)| (account, neighbour, *weight))
+            })
+    }
+    
+    /// Load statistics saved before a restart, keeping the current limit
+    pub fn load(&mut self, database: &Database) -> Result<(), String> {
+        let stored = database.get(&MetadataKey { key: PLACEMENT_STATS_KEY.to_string() })
+            .map_err(|e| e.to_string())?;
+        
+        if let Some(bytes) = stored {
+            let stats: PlacementStats = bincode::deserialize(&bytes)
+                .map_err(|e| format!("Failed to decode placement statistics: {}", e))?;
+            
+            self.activity = stats.activity;
+            self.balances = stats.balances;
+            self.interactions = stats.interactions;
+            if self.activity.len() > self.max_accounts {
+                self.evict_least_active();
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Persist the statistics
+    pub fn save(&self, database: &Database) -> Result<(), String> {
+        let bytes = bincode::serialize(self)
+            .map_err(|e| format!("Failed to encode placement statistics: {}", e))?;
+        
+        database.put(&MetadataKey { key: PLACEMENT_STATS_KEY.to_string() }, &bytes)
+            .map_err(|e| e.to_string())
+    }
+    
+    /// Forget an account
+    pub fn remove_account(&mut self, account_id: &AccountId) {
+        self.activity.remove(account_id);
+        self.balances.remove(account_id);
+        
+        if let Some(neighbours) = self.interactions.remove(account_id) {
+            for neighbour in neighbours.keys() {
+                if let Some(edges) = self.interactions.get_mut(neighbour) {
+                    edges.remove(account_id);
+                    if edges.is_empty() {
+                        self.interactions.remove(neighbour);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::crypto::KeyPair;
+    use ed25519_dalek::VerifyingKey;
+    
+    /// A fresh sender key and its account
+    fn account() -> (VerifyingKey, AccountId) {
+        let key = VerifyingKey::from_bytes(&KeyPair::generate().public_key()).unwrap();
+        (key, AccountId(key.to_bytes()))
+    }
+    
+    /// A transfer between two accounts
+    fn transfer(sender: &VerifyingKey, recipient: &AccountId) -> Transaction {
+        Transaction::new(TransactionType::Transfer { recipient: recipient.0, amount: 1 }, *sender, 0, 21_000, 1)
+    }
+    
+    #[test]
+    fn counts_activity_and_interactions() {
+        let mut stats = PlacementStats::new();
+        let (key, sender) = account();
+        let recipient = AccountId([1; 32]);
+        
+        stats.record_transaction(&transfer(&key, &recipient));
+        stats.record_transaction(&transfer(&key, &recipient));
+        stats.record_balance(recipient.clone(), 50);
+        stats.record_balance(AccountId([2; 32]), 70);
+        
+        assert_eq!(stats.activity(&sender), 2);
+        assert_eq!(stats.activity(&recipient), 2);
+        assert_eq!(stats.balance(&recipient), 50);
+        assert_eq!(stats.tracked_accounts(), 2);
+        assert_eq!(stats.neighbours(&sender).collect::<Vec<_>>(), vec![(&recipient, 2)]);
+        assert_eq!(stats.interactions().count(), 1);
+    }
+    
+    #[test]
+    fn evicts_the_least_active_accounts_down_to_the_low_water_mark() {
+        let mut stats = PlacementStats::with_max_accounts(4);
+        let (key, sender) = account();
+        let recipients: Vec<AccountId> = (1..=4).map(|byte| AccountId([byte; 32])).collect();
+        
+        for _ in 0..3 {
+            stats.record_transaction(&transfer(&key, &recipients[0]));
+        }
+        for recipient in &recipients[1..] {
+            stats.record_transaction(&transfer(&key, recipient));
+        }
+        
+        // Of the accounts with one transaction, the lowest IDs are forgotten
+        assert_eq!(stats.tracked_accounts(), 3);
+        assert_eq!(stats.activity(&sender), 6);
+        assert_eq!(stats.activity(&recipients[0]), 3);
+        assert_eq!(stats.activity(&recipients[1]), 0);
+        assert_eq!(stats.activity(&recipients[2]), 0);
+        assert_eq!(stats.activity(&recipients[3]), 1);
+        assert_eq!(stats.neighbours(&sender).count(), 2);
+        assert!(stats.neighbours(&recipients[1]).next().is_none());
+    }
+    
+    #[test]
+    fn reloads_saved_statistics() {
+        let (_dir, database) = crate::storage::temporary_database();
+        let mut stats = PlacementStats::new();
+        let (key, sender) = account();
+        let recipient = AccountId([1; 32]);
+        stats.record_transaction(&transfer(&key, &recipient));
+        stats.record_balance(sender.clone(), 10);
+        stats.save(&database).unwrap();
+        
+        let mut loaded = PlacementStats::new();
+        loaded.load(&database).unwrap();
+        
+        assert_eq!(loaded.activity(&sender), 1);
+        assert_eq!(loaded.balance(&sender), 10);
+        assert_eq!(loaded.neighbours(&recipient).collect::<Vec<_>>(), vec![(&sender, 1)]);
+    }
+}
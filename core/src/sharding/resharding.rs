@@ -1,8 +1,28 @@
-use crate::sharding::{Shard, ShardId, ShardConfig, ShardState};
-use crate::types::{Block, BlockId, Account, AccountId};
+use crate::sharding::cross_shard::{is_system_account, update_account};
+use crate::sharding::{journal, inbox_account, outbox_account, AccountMigration, CrossShardCommunicator, ScheduledResharding, Shard, ShardId, ShardConfig, ShardState};
+use crate::sharding::migration::move_accounts;
+use crate::storage::{Batch, Database};
+use crate::types::{Block, BlockId, Account, AccountId, State, StateUpdate};
 use serde::{Serialize, Deserialize};
 use std::collections::{HashMap, HashSet};
-use std::time::{Duration, Instant};
+use std::time::Duration;
+
+/// Journal record kind for active resharding operations
+const OPERATION_RECORD: &[u8] = b"resharding_operation";
+
+/// Journal record kind for accounts a migration moved off their default shard
+const RELOCATED_RECORD: &[u8] = b"relocated_account";
+
+/// Journal record kind for shards retired by a merge
+const RETIRED_RECORD: &[u8] = b"retired_shard";
+
+/// Get the current time in seconds since the Unix epoch
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
 
 /// Strategy for resharding
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -62,9 +82,52 @@ pub enum ReshardingEvent {
         /// Destination shard
         destination_shard: ShardId,
     },
+    /// Accounts moved between shards at a block boundary
+    AccountsMigrated {
+        /// The move, with both shards' state roots before and after
+        migration: AccountMigration,
+    },
+}
+
+/// Thresholds deciding when a shard's load calls for resharding
+#[derive(Debug, Clone)]
+pub struct ReshardingPolicy {
+    /// Load score below which a shard is underloaded and may be merged
+    pub merge_threshold: f64,
+    /// How far the load must fall back past a threshold before a trend resets
+    pub hysteresis_margin: f64,
+    /// Consecutive evaluations a shard must stay past a threshold
+    pub required_observations: u32,
+    /// Minimum time between recommendations for a shard, in seconds
+    pub cooldown: u64,
+}
+
+impl Default for ReshardingPolicy {
+    fn default() -> Self {
+        ReshardingPolicy {
+            merge_threshold: 0.2,
+            hysteresis_margin: 0.1,
+            required_observations: 5,
+            cooldown: 600,
+        }
+    }
+}
+
+/// How long a shard has been over- or underloaded
+#[derive(Debug, Clone, Default)]
+struct LoadTrend {
+    /// Consecutive evaluations above the split threshold
+    overloaded: u32,
+    /// Consecutive evaluations below the merge threshold
+    underloaded: u32,
+    /// When a strategy was last recommended for the shard
+    last_recommendation: Option<u64>,
 }
 
 /// Manager for resharding operations
+///
+/// Active operations are journaled to the database before they change, so a
+/// node restarted mid-operation picks them up again with `resume`.
 pub struct ReshardingManager {
     /// Active resharding operations
     active_operations: HashMap<String, ReshardingOperation>,
@@ -74,28 +137,36 @@ pub struct ReshardingManager {
     shard_configs: HashMap<ShardId, ShardConfig>,
     /// Callback for resharding events
     event_callback: Option<Box<dyn Fn(ReshardingEvent) + Send + Sync>>,
+    /// Thresholds for recommendations
+    policy: ReshardingPolicy,
+    /// Load trend per shard
+    trends: HashMap<ShardId, LoadTrend>,
+    /// Accounts migrations moved off their default shard
+    relocated: HashSet<AccountId>,
+    /// Shards retired by a merge
+    retired: HashSet<ShardId>,
 }
 
 /// A resharding operation
-#[derive(Debug, Clone)]
-struct ReshardingOperation {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReshardingOperation {
     /// Unique ID for the operation
-    id: String,
+    pub id: String,
     /// Strategy being used
-    strategy: ReshardingStrategy,
+    pub strategy: ReshardingStrategy,
     /// Shards involved
-    shards: Vec<ShardId>,
-    /// Start time
-    start_time: Instant,
+    pub shards: Vec<ShardId>,
+    /// Start time, in seconds since the Unix epoch
+    pub started_at: u64,
     /// Status of the operation
-    status: ReshardingStatus,
+    pub status: ReshardingStatus,
     /// Events emitted during the operation
-    events: Vec<ReshardingEvent>,
+    pub events: Vec<ReshardingEvent>,
 }
 
 /// Status of a resharding operation
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum ReshardingStatus {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReshardingStatus {
     /// Operation is in progress
     InProgress,
     /// Operation has completed successfully
@@ -112,7 +183,69 @@ impl ReshardingManager {
             completed_operations: Vec::new(),
             shard_configs: HashMap::new(),
             event_callback: None,
+            policy: ReshardingPolicy::default(),
+            trends: HashMap::new(),
+            relocated: HashSet::new(),
+            retired: HashSet::new(),
+        }
+    }
+    
+    /// Create a resharding manager, picking up the operations active when the node stopped
+    ///
+    /// The journaled account states of `shards` are restored, so accounts
+    /// moved before the restart stay where the move put them. Operations are
+    /// continued by calling `migrate_accounts` or `merge_shards` again.
+    /// Retired shards must not be among `shards`.
+    pub fn resume(database: &Database, shards: &mut [Shard]) -> Result<Self, String> {
+        let mut manager = Self::new();
+        manager.retired = Self::load_retired(database)?;
+        if let Some(shard) = shards.iter().find(|shard| manager.retired.contains(&shard.id())) {
+            return Err(format!("Shard {:?} was retired by a merge", shard.id()));
+        }
+        
+        let mut states = journal::load_states(database)?;
+        let shard_ids: Vec<ShardId> = shards.iter().map(|shard| shard.id()).chain(manager.retired.iter().copied()).collect();
+        for shard in shards.iter_mut() {
+            let state = match states.remove(&shard.id()) {
+                Some(state) => state,
+                None => continue,
+            };
+            
+            for account in &state.accounts {
+                if !is_system_account(&account.id, &shard_ids) {
+                    shard.add_account(account.id.0)?;
+                }
+            }
+            log::info!("Restored {} accounts of shard {:?}", state.accounts.len(), shard.id());
+            *shard.account_state_mut() = state;
+        }
+        
+        let relocated: Vec<AccountId> = journal::load(database, RELOCATED_RECORD)?;
+        manager.relocated.extend(relocated);
+        
+        let operations: Vec<ReshardingOperation> = journal::load(database, OPERATION_RECORD)?;
+        for operation in operations {
+            log::info!("Resuming resharding operation {} ({:?} of {:?})", operation.id, operation.strategy, operation.shards);
+            manager.active_operations.insert(operation.id.clone(), operation);
         }
+        
+        Ok(manager)
+    }
+    
+    /// Load the shards retired by a merge, which must not be created again on startup
+    pub fn load_retired(database: &Database) -> Result<HashSet<ShardId>, String> {
+        let retired: Vec<ShardId> = journal::load(database, RETIRED_RECORD)?;
+        Ok(retired.into_iter().collect())
+    }
+    
+    /// Get the shards retired by a merge
+    pub fn retired_shards(&self) -> &HashSet<ShardId> {
+        &self.retired
+    }
+    
+    /// Get the accounts migrations moved off their default shard
+    pub fn relocated_accounts(&self) -> &HashSet<AccountId> {
+        &self.relocated
     }
     
     /// Add a shard configuration
@@ -120,11 +253,21 @@ impl ReshardingManager {
         self.shard_configs.insert(shard_id, config);
     }
     
+    /// Start a resharding operation agreed on by the beacon chain
+    pub fn start_scheduled(&mut self, scheduled: &ScheduledResharding, database: &Database) -> Result<String, String> {
+        log::info!(
+            "Starting {:?} of {:?} scheduled for beacon height {}",
+            scheduled.strategy, scheduled.shards, scheduled.activation_height
+        );
+        self.start_resharding(scheduled.strategy, scheduled.shards.clone(), database)
+    }
+    
     /// Start a resharding operation
     pub fn start_resharding(
         &mut self,
         strategy: ReshardingStrategy,
         shards: Vec<ShardId>,
+        database: &Database,
     ) -> Result<String, String> {
         // Validate shards
         for shard_id in &shards {
@@ -164,32 +307,34 @@ impl ReshardingManager {
         // Create operation ID
         let operation_id = format!("{:x}", rand::random::<u64>());
         
+        let timestamp = now();
+        let event = ReshardingEvent::Started {
+            strategy,
+            shards: shards.clone(),
+            timestamp,
+        };
+        
         // Create operation
         let operation = ReshardingOperation {
             id: operation_id.clone(),
             strategy,
-            shards: shards.clone(),
-            start_time: Instant::now(),
+            shards,
+            started_at: timestamp,
             status: ReshardingStatus::InProgress,
-            events: Vec::new(),
+            events: vec![event.clone()],
         };
         
+        let mut batch = Batch::new();
+        journal::put(&mut batch, OPERATION_RECORD, operation_id.as_bytes(), &operation)?;
+        journal::commit(database, &batch)?;
+        
         // Add to active operations
         self.active_operations.insert(operation_id.clone(), operation);
         
-        // Emit started event
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
-        
-        let event = ReshardingEvent::Started {
-            strategy,
-            shards: shards.clone(),
-            timestamp,
-        };
-        
-        self.emit_event(operation_id.clone(), event);
+        // Call the callback if set
+        if let Some(callback) = &self.event_callback {
+            callback(event);
+        }
         
         Ok(operation_id)
     }
@@ -199,18 +344,21 @@ impl ReshardingManager {
         &mut self,
         operation_id: &str,
         resulting_shards: Vec<ShardId>,
+        database: &Database,
     ) -> Result<(), String> {
-        if let Some(mut operation) = self.active_operations.remove(operation_id) {
+        if self.active_operations.contains_key(operation_id) {
+            let mut batch = Batch::new();
+            journal::delete(&mut batch, OPERATION_RECORD, operation_id.as_bytes());
+            journal::commit(database, &batch)?;
+            
+            let mut operation = self.active_operations.remove(operation_id).expect("Operation is active");
+            
             // Update status
             operation.status = ReshardingStatus::Completed;
             
             // Emit completed event
-            let timestamp = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_secs();
-            
-            let duration_seconds = operation.start_time.elapsed().as_secs();
+            let timestamp = now();
+            let duration_seconds = timestamp.saturating_sub(operation.started_at);
             
             let event = ReshardingEvent::Completed {
                 strategy: operation.strategy,
@@ -236,17 +384,20 @@ impl ReshardingManager {
         &mut self,
         operation_id: &str,
         reason: String,
+        database: &Database,
     ) -> Result<(), String> {
-        if let Some(mut operation) = self.active_operations.remove(operation_id) {
+        if self.active_operations.contains_key(operation_id) {
+            let mut batch = Batch::new();
+            journal::delete(&mut batch, OPERATION_RECORD, operation_id.as_bytes());
+            journal::commit(database, &batch)?;
+            
+            let mut operation = self.active_operations.remove(operation_id).expect("Operation is active");
+            
             // Update status
             operation.status = ReshardingStatus::Failed(reason.clone());
             
             // Emit failed event
-            let timestamp = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_secs();
-            
+            let timestamp = now();
             let event = ReshardingEvent::Failed {
                 strategy: operation.strategy,
                 shards: operation.shards.clone(),
@@ -272,8 +423,9 @@ impl ReshardingManager {
         account_id: AccountId,
         source_shard: ShardId,
         destination_shard: ShardId,
+        database: &Database,
     ) -> Result<(), String> {
-        if let Some(operation) = self.active_operations.get_mut(operation_id) {
+        if let Some(operation) = self.active_operations.get(operation_id) {
             // Emit account moved event
             let event = ReshardingEvent::AccountMoved {
                 account_id,
@@ -281,6 +433,13 @@ impl ReshardingManager {
                 destination_shard,
             };
             
+            let mut updated = operation.clone();
+            updated.events.push(event.clone());
+            
+            let mut batch = Batch::new();
+            journal::put(&mut batch, OPERATION_RECORD, operation_id.as_bytes(), &updated)?;
+            journal::commit(database, &batch)?;
+            
             self.emit_event(operation_id.to_string(), event);
             
             Ok(())
@@ -289,6 +448,255 @@ impl ReshardingManager {
         }
     }
     
+    /// Freeze the shards of a move so they stop accepting transactions
+    ///
+    /// Shards are marked `Merging` for a merge and `Splitting` otherwise.
+    pub fn begin_move(&self, operation_id: &str, source: &mut Shard, destination: &mut Shard) -> Result<(), String> {
+        let operation = self.active_operations.get(operation_id)
+            .ok_or_else(|| format!("Operation {} not found", operation_id))?;
+        
+        if !operation.shards.contains(&source.id()) {
+            return Err(format!("Shard {:?} is not part of operation {}", source.id(), operation_id));
+        }
+        
+        let state = match operation.strategy {
+            ReshardingStrategy::Merge => ShardState::Merging,
+            _ => ShardState::Splitting,
+        };
+        source.set_state(state.clone());
+        destination.set_state(state);
+        
+        Ok(())
+    }
+    
+    /// Let the shards of a finished move accept transactions again
+    pub fn finish_move(&self, source: &mut Shard, destination: &mut Shard) {
+        source.set_state(ShardState::Active);
+        destination.set_state(ShardState::Active);
+    }
+    
+    /// Move accounts with their state and contract storage to another shard
+    ///
+    /// Meant to run at the beacon height the move was scheduled for, with the
+    /// accounts refusing new transactions since the schedule was finalized.
+    /// The accounts move after each shard's latest block. The adjusted
+    /// in-flight cross-shard transfers, both shards' moved accounts and the
+    /// operation record with the state roots before and after are written in
+    /// one batch. The caller relocates the accounts in the router.
+    pub fn migrate_accounts(
+        &mut self,
+        operation_id: &str,
+        accounts: Vec<AccountId>,
+        source: &mut Shard,
+        destination: &mut Shard,
+        communicator: &mut CrossShardCommunicator,
+        database: &Database,
+    ) -> Result<AccountMigration, String> {
+        let operation = self.active_operations.get(operation_id)
+            .ok_or_else(|| format!("Operation {} not found", operation_id))?;
+        
+        if destination.account_count() + accounts.len() > destination.config().max_accounts {
+            return Err(format!("Shard {:?} has no room for {} accounts", destination.id(), accounts.len()));
+        }
+        
+        let (source_state, destination_state) = move_accounts(source.account_state(), destination.account_state(), &accounts)?;
+        
+        let mut batch = Batch::new();
+        let account_set: HashSet<AccountId> = accounts.iter().cloned().collect();
+        let prepared = communicator.prepare_migration(&account_set, source.id(), destination.id(), &mut batch)?;
+        
+        let migration = AccountMigration {
+            source_shard: source.id(),
+            destination_shard: destination.id(),
+            source_height: source.latest_block_height(),
+            destination_height: destination.latest_block_height(),
+            accounts: accounts.clone(),
+            source_root_before: source.state_root().clone(),
+            source_root_after: source_state.root.clone(),
+            destination_root_before: destination.state_root().clone(),
+            destination_root_after: destination_state.root.clone(),
+        };
+        
+        let mut events = vec![ReshardingEvent::AccountsMigrated { migration: migration.clone() }];
+        events.extend(accounts.iter().map(|account_id| ReshardingEvent::AccountMoved {
+            account_id: account_id.clone(),
+            source_shard: source.id(),
+            destination_shard: destination.id(),
+        }));
+        
+        let mut updated = operation.clone();
+        updated.events.extend(events.iter().cloned());
+        
+        journal::put(&mut batch, OPERATION_RECORD, operation_id.as_bytes(), &updated)?;
+        journal::put_accounts(&mut batch, source.id(), &source_state, &accounts)?;
+        journal::put_accounts(&mut batch, destination.id(), &destination_state, &accounts)?;
+        for account_id in &accounts {
+            journal::put(&mut batch, RELOCATED_RECORD, &account_id.0, account_id)?;
+        }
+        journal::commit(database, &batch)?;
+        
+        communicator.apply_move(prepared);
+        self.relocated.extend(accounts.iter().cloned());
+        *source.account_state_mut() = source_state;
+        *destination.account_state_mut() = destination_state;
+        for account_id in &accounts {
+            source.remove_account(&account_id.0);
+            destination.add_account(account_id.0)?;
+        }
+        
+        for event in events {
+            self.emit_event(operation_id.to_string(), event);
+        }
+        
+        Ok(migration)
+    }
+    
+    /// Merge a shard into another and retire it
+    ///
+    /// Both shards must be frozen with `begin_move` under a merge operation.
+    /// Cross-shard transactions between them are settled, every account of
+    /// the retiring shard moves with its state into the surviving shard after
+    /// each shard's latest block, and the retiring shard is marked
+    /// `Inactive` and recorded as retired. Receipts the retiring shard already
+    /// credited stay recorded in the surviving shard's inboxes, so they cannot
+    /// be credited again. The settled transfers, both states, the retirement
+    /// and the operation record are written in one batch. The caller retires
+    /// the shard in the router.
+    pub fn merge_shards(
+        &mut self,
+        operation_id: &str,
+        retiring: &mut Shard,
+        surviving: &mut Shard,
+        communicator: &mut CrossShardCommunicator,
+        database: &Database,
+    ) -> Result<AccountMigration, String> {
+        let operation = self.active_operations.get(operation_id)
+            .ok_or_else(|| format!("Operation {} not found", operation_id))?;
+        
+        if operation.strategy != ReshardingStrategy::Merge
+            || !operation.shards.contains(&retiring.id())
+            || !operation.shards.contains(&surviving.id())
+        {
+            return Err(format!("Operation {} is not a merge of shards {:?} and {:?}", operation_id, retiring.id(), surviving.id()));
+        }
+        
+        if retiring.state() != &ShardState::Merging || surviving.state() != &ShardState::Merging {
+            return Err("Shards must be frozen before they are merged".to_string());
+        }
+        
+        // The retiring shard's outboxes are dropped, and its inboxes are merged below
+        let known_shards: Vec<ShardId> = self.shard_configs.keys().chain(self.retired.iter()).copied().collect();
+        let accounts: Vec<AccountId> = retiring.account_state().accounts.iter()
+            .map(|account| account.id.clone())
+            .filter(|account_id| !is_system_account(account_id, &known_shards))
+            .collect();
+        
+        let mut incoming: HashSet<[u8; 32]> = retiring.account_ids().into_iter().collect();
+        incoming.extend(accounts.iter().map(|account_id| account_id.0));
+        if surviving.account_count() + incoming.len() > surviving.config().max_accounts {
+            return Err(format!("Shard {:?} has no room for the accounts of shard {:?}", surviving.id(), retiring.id()));
+        }
+        
+        // Check the move before in-flight transactions are settled for it
+        move_accounts(retiring.account_state(), surviving.account_state(), &accounts)?;
+        
+        let mut batch = Batch::new();
+        let mut retiring_state = retiring.account_state().clone();
+        let mut surviving_state = surviving.account_state().clone();
+        let prepared = communicator.prepare_merge(retiring.id(), surviving.id(), &mut retiring_state, &mut surviving_state, &mut batch)?;
+        
+        // The surviving shard no longer sends receipts to the retired one
+        if surviving_state.accounts.iter().any(|account| account.id == outbox_account(retiring.id())) {
+            surviving_state.apply_update(StateUpdate::DeleteAccount { id: outbox_account(retiring.id()).0 });
+        }
+        
+        let (_, mut merged_state) = move_accounts(&retiring_state, &surviving_state, &accounts)?;
+        
+        // Keep the retiring shard's record of credited receipts from every other live shard
+        for source_shard in self.shard_configs.keys().filter(|shard_id| **shard_id != surviving.id()) {
+            let inbox = inbox_account(*source_shard);
+            let credited = match retiring_state.accounts.iter().find(|account| account.id == inbox) {
+                Some(account) if !account.storage.is_empty() => account.storage.iter()
+                    .map(|(key, value)| (key.clone(), Some(value.clone())))
+                    .collect(),
+                _ => continue,
+            };
+            update_account(&mut merged_state, &inbox, 0, 0, credited);
+        }
+        
+        let migration = AccountMigration {
+            source_shard: retiring.id(),
+            destination_shard: surviving.id(),
+            source_height: retiring.latest_block_height(),
+            destination_height: surviving.latest_block_height(),
+            accounts: accounts.clone(),
+            source_root_before: retiring.state_root().clone(),
+            source_root_after: State::new().root,
+            destination_root_before: surviving.state_root().clone(),
+            destination_root_after: merged_state.root.clone(),
+        };
+        
+        let mut events = vec![ReshardingEvent::AccountsMigrated { migration: migration.clone() }];
+        events.extend(accounts.iter().map(|account_id| ReshardingEvent::AccountMoved {
+            account_id: account_id.clone(),
+            source_shard: retiring.id(),
+            destination_shard: surviving.id(),
+        }));
+        
+        let mut updated = operation.clone();
+        updated.events.extend(events.iter().cloned());
+        
+        let retired_state = State::new();
+        let retiring_accounts = retiring.account_state().accounts.iter().map(|account| &account.id);
+        let surviving_accounts = surviving.account_state().accounts.iter()
+            .chain(merged_state.accounts.iter())
+            .map(|account| &account.id);
+        
+        journal::put(&mut batch, OPERATION_RECORD, operation_id.as_bytes(), &updated)?;
+        journal::put(&mut batch, RETIRED_RECORD, &retiring.id().0.to_be_bytes(), &retiring.id())?;
+        journal::put_accounts(&mut batch, retiring.id(), &retired_state, retiring_accounts)?;
+        journal::put_accounts(&mut batch, surviving.id(), &merged_state, surviving_accounts)?;
+        journal::commit(database, &batch)?;
+        
+        communicator.apply_move(prepared);
+        *surviving.account_state_mut() = merged_state;
+        *retiring.account_state_mut() = retired_state;
+        for account_id in incoming {
+            retiring.remove_account(&account_id);
+            surviving.add_account(account_id)?;
+        }
+        
+        retiring.set_state(ShardState::Inactive);
+        self.shard_configs.remove(&retiring.id());
+        self.trends.remove(&retiring.id());
+        self.retired.insert(retiring.id());
+        
+        for event in events {
+            self.emit_event(operation_id.to_string(), event);
+        }
+        
+        log::info!("Merged shard {:?} into shard {:?} ({} accounts)", retiring.id(), surviving.id(), accounts.len());
+        
+        Ok(migration)
+    }
+    
+    /// Pick the shard an underloaded shard should be merged into
+    ///
+    /// This is the least loaded other active shard that can take the
+    /// shard's accounts while staying clear of its own split threshold.
+    pub fn merge_partner(&self, shard: &Shard, shards: &[Shard]) -> Option<ShardId> {
+        shards.iter()
+            .filter(|candidate| candidate.id() != shard.id() && candidate.is_active())
+            .filter(|candidate| {
+                self.shard_configs.get(&candidate.id()).is_some_and(|config| {
+                    candidate.load_score() + shard.load_score() < config.resharding_threshold - self.policy.hysteresis_margin
+                        && candidate.account_count() + shard.account_count() <= config.max_accounts
+                })
+            })
+            .min_by(|a, b| a.load_score().total_cmp(&b.load_score()).then(a.id().cmp(&b.id())))
+            .map(|candidate| candidate.id())
+    }
+    
     /// Get an active resharding operation
     pub fn get_active_operation(&self, operation_id: &str) -> Option<&ReshardingOperation> {
         self.active_operations.get(operation_id)
@@ -299,6 +707,14 @@ impl ReshardingManager {
         &self.active_operations
     }
     
+    /// Get the active operations that have been running for longer than `max_age`
+    pub fn stuck_operations(&self, max_age: Duration) -> Vec<&ReshardingOperation> {
+        let now = now();
+        self.active_operations.values()
+            .filter(|operation| now.saturating_sub(operation.started_at) > max_age.as_secs())
+            .collect()
+    }
+    
     /// Get all completed resharding operations
     pub fn get_completed_operations(&self) -> &[ReshardingOperation] {
         &self.completed_operations
@@ -327,15 +743,54 @@ impl ReshardingManager {
         }
     }
     
-    /// Recommend a resharding strategy for a shard
-    pub fn recommend_strategy(&self, shard_id: &ShardId) -> Option<ReshardingStrategy> {
-        // Get shard config
-        let config = self.shard_configs.get(shard_id)?;
+    /// Set the thresholds for recommendations
+    pub fn set_policy(&mut self, policy: ReshardingPolicy) {
+        self.policy = policy;
+    }
+    
+    /// Recommend a resharding strategy for a shard from its measured load
+    ///
+    /// Meant to be called periodically. A shard must stay above its
+    /// resharding threshold, or below the merge threshold, for several
+    /// evaluations in a row, a trend only resets once the load is back past
+    /// the threshold by the hysteresis margin, and a shard gets no new
+    /// recommendation during the cooldown, so noisy load does not make
+    /// shards flap between splits and merges.
+    pub fn recommend_strategy(&mut self, shard: &Shard) -> Option<ReshardingStrategy> {
+        let split_threshold = self.shard_configs.get(&shard.id())?.resharding_threshold;
+        let policy = self.policy.clone();
+        let score = shard.load_score();
+        let trend = self.trends.entry(shard.id()).or_default();
+        
+        if score >= split_threshold {
+            trend.overloaded += 1;
+        } else if score < split_threshold - policy.hysteresis_margin {
+            trend.overloaded = 0;
+        }
+        
+        if score <= policy.merge_threshold {
+            trend.underloaded += 1;
+        } else if score > policy.merge_threshold + policy.hysteresis_margin {
+            trend.underloaded = 0;
+        }
+        
+        let now = now();
+        if trend.last_recommendation.is_some_and(|last| now.saturating_sub(last) < policy.cooldown) {
+            return None;
+        }
+        
+        let strategy = if trend.overloaded >= policy.required_observations {
+            ReshardingStrategy::Split
+        } else if trend.underloaded >= policy.required_observations && self.shard_configs.len() > 1 {
+            ReshardingStrategy::Merge
+        } else {
+            return None;
+        };
         
-        // In a real implementation, we would analyze the shard's load metrics
-        // and recommend a strategy based on that
+        trend.overloaded = 0;
+        trend.underloaded = 0;
+        trend.last_recommendation = Some(now);
         
-        // For now, just return a simple recommendation
-        Some(ReshardingStrategy::Split)
+        Some(strategy)
     }
 }
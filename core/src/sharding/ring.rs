@@ -0,0 +1,177 @@
+use crate::sharding::{ShardConfig, ShardId};
+use crate::types::AccountId;
+use sha3::{Sha3_256, Digest};
+use std::collections::{BTreeMap, HashMap};
+
+/// Number of virtual nodes a shard of weight 1.0 gets on the ring
+pub const DEFAULT_VIRTUAL_NODES: u32 = 128;
+
+/// Consistent hash ring mapping accounts to shards
+///
+/// Each shard owns a number of points on the ring proportional to its
+/// capacity weight, and an account belongs to the shard owning the first
+/// point at or after the account's hash. Adding a shard only takes accounts
+/// from the arcs its new points cover, and removing one only hands its own
+/// accounts to the next points, so every other account stays put.
+#[derive(Debug, Clone)]
+pub struct HashRing {
+    /// Virtual nodes per unit of weight
+    virtual_nodes: u32,
+    /// Ring points and the shard owning each
+    points: BTreeMap<u64, ShardId>,
+}
+
+impl HashRing {
+    /// Create an empty ring
+    pub fn new(virtual_nodes: u32) -> Self {
+        HashRing {
+            virtual_nodes,
+            points: BTreeMap::new(),
+        }
+    }
+    
+    /// Create a ring over a set of shards
+    pub fn from_shards(virtual_nodes: u32, shards: &HashMap<ShardId, ShardConfig>) -> Self {
+        let mut ring = HashRing::new(virtual_nodes);
+        for (shard_id, config) in shards {
+            ring.add_shard(*shard_id, config);
+        }
+        ring
+    }
+    
+    /// Add a shard's virtual nodes
+    pub fn add_shard(&mut self, shard_id: ShardId, config: &ShardConfig) {
+        let count = (self.virtual_nodes as f64 * config.effective_capacity_weight()).round().max(1.0) as u32;
+        
+        for index in 0..count {
+            let point = hash_u64(&[&shard_id.0.to_be_bytes()[..], &index.to_be_bytes()[..]]);
+            // On the rare collision the lower shard ID keeps the point, so the ring is order independent
+            let owner = self.points.entry(point).or_insert(shard_id);
+            if shard_id.0 < owner.0 {
+                *owner = shard_id;
+            }
+        }
+    }
+    
+    /// Remove a shard's virtual nodes
+    pub fn remove_shard(&mut self, shard_id: &ShardId) {
+        self.points.retain(|_, owner| owner != shard_id);
+    }
+    
+    /// Check if the ring has no shards
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+    
+    /// Get the shard an account belongs to
+    pub fn locate(&self, account_id: &AccountId) -> Option<ShardId> {
+        let hash = hash_u64(&[&account_id.0[..]]);
+        
+        self.points.range(hash..)
+            .next()
+            .or_else(|| self.points.iter().next())
+            .map(|(_, shard_id)| *shard_id)
+    }
+}
+
+/// Hash byte strings to a position on the ring
+fn hash_u64(parts: &[&[u8]]) -> u64 {
+    let mut hasher = Sha3_256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hasher.finalize()[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Accounts spread over the ring
+    fn accounts(count: u32) -> Vec<AccountId> {
+        (0..count)
+            .map(|index| {
+                let mut id = [0u8; 32];
+                id[..4].copy_from_slice(&index.to_be_bytes());
+                AccountId(id)
+            })
+            .collect()
+    }
+    
+    /// A ring over shards with the given capacity weights
+    fn ring(weights: &[(u32, f64)]) -> HashRing {
+        let shards = weights.iter()
+            .map(|(shard_id, weight)| (ShardId(*shard_id), ShardConfig { capacity_weight: *weight, ..ShardConfig::default() }))
+            .collect();
+        HashRing::from_shards(DEFAULT_VIRTUAL_NODES, &shards)
+    }
+    
+    #[test]
+    fn locates_nothing_on_an_empty_ring() {
+        let ring = HashRing::new(DEFAULT_VIRTUAL_NODES);
+        
+        assert!(ring.is_empty());
+        assert_eq!(ring.locate(&accounts(1)[0]), None);
+    }
+    
+    #[test]
+    fn adding_a_shard_only_moves_accounts_onto_it() {
+        let before = ring(&[(0, 1.0), (1, 1.0), (2, 1.0)]);
+        let after = ring(&[(0, 1.0), (1, 1.0), (2, 1.0), (3, 1.0)]);
+        
+        let mut moved = 0;
+        for account_id in accounts(2000) {
+            let (from, to) = (before.locate(&account_id).unwrap(), after.locate(&account_id).unwrap());
+            if from != to {
+                assert_eq!(to, ShardId(3));
+                moved += 1;
+            }
+        }
+        // About a quarter of the accounts belong to the new shard
+        assert!((300..700).contains(&moved), "{} accounts moved", moved);
+    }
+    
+    #[test]
+    fn removing_a_shard_only_moves_its_own_accounts() {
+        let mut ring = ring(&[(0, 1.0), (1, 1.0), (2, 1.0)]);
+        let before: Vec<ShardId> = accounts(2000).iter().map(|account_id| ring.locate(account_id).unwrap()).collect();
+        
+        ring.remove_shard(&ShardId(1));
+        
+        for (account_id, from) in accounts(2000).iter().zip(before) {
+            let to = ring.locate(account_id).unwrap();
+            assert_ne!(to, ShardId(1));
+            if from != ShardId(1) {
+                assert_eq!(to, from);
+            }
+        }
+    }
+    
+    #[test]
+    fn gives_shards_accounts_in_proportion_to_their_weight() {
+        let ring = ring(&[(0, 1.0), (1, 2.0)]);
+        
+        let heavy = accounts(3000).iter().filter(|account_id| ring.locate(account_id) == Some(ShardId(1))).count();
+        
+        // Two thirds of the accounts, give or take the spread of the virtual nodes
+        assert!((1700..2300).contains(&heavy), "{} accounts on the heavier shard", heavy);
+    }
+    
+    #[test]
+    fn does_not_depend_on_the_order_shards_are_added() {
+        let config = ShardConfig::default();
+        let mut forward = HashRing::new(DEFAULT_VIRTUAL_NODES);
+        let mut backward = HashRing::new(DEFAULT_VIRTUAL_NODES);
+        for shard_id in 0..4 {
+            forward.add_shard(ShardId(shard_id), &config);
+            backward.add_shard(ShardId(3 - shard_id), &config);
+        }
+        
+        for account_id in accounts(500) {
+            assert_eq!(forward.locate(&account_id), backward.locate(&account_id));
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use crate::mempool::Mempool;
+use crate::sharding::cross_shard::is_system_account;
+use crate::sharding::{CrossShardCommunicator, CrossShardTransactionStatus, CrossShardTransfer, Shard, ShardAllocator, ShardConfig, ShardId};
+use crate::storage::Database;
+use crate::types::{AccountId, State, Transaction, TransactionId, TransactionType};
+use std::collections::HashSet;
+
+/// Where a transaction is executed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    /// All accounts it touches are in one shard
+    IntraShard(ShardId),
+    /// It moves funds from the sender's shard to the recipient's
+    CrossShard {
+        /// Shard of the sender
+        source_shard: ShardId,
+        /// Shard of the recipient
+        destination_shard: ShardId,
+        /// The transfer
+        transfer: CrossShardTransfer,
+    },
+}
+
+/// Routes transactions to the shards of the accounts they touch
+///
+/// An account is on the shard whose state holds it. Accounts no shard holds
+/// yet go to the allocator's default shard, which only depends on the shard
+/// set, so every node routes them alike; routing itself records nothing.
+/// Routed transactions are pooled with the shard that executes them; transfers
+/// between shards also become cross-shard transactions of the source shard.
+pub struct ShardRouter {
+    /// Allocator deciding which shard an account is on
+    allocator: ShardAllocator,
+    /// Accounts scheduled to change shard, which take no new transactions
+    frozen: HashSet<AccountId>,
+    /// Shards retired by a merge, whose inboxes and outboxes may remain in other shards
+    retired: HashSet<ShardId>,
+}
+
+impl ShardRouter {
+    /// Create a new router
+    pub fn new(allocator: ShardAllocator) -> Self {
+        ShardRouter {
+            allocator,
+            frozen: HashSet::new(),
+            retired: HashSet::new(),
+        }
+    }
+    
+    /// Add a shard that transactions can be routed to
+    pub fn add_shard(&mut self, shard_id: ShardId, config: ShardConfig) {
+        self.allocator.add_shard(shard_id, config);
+    }
+    
+    /// Get the allocator
+    pub fn allocator(&self) -> &ShardAllocator {
+        &self.allocator
+    }
+    
+    /// Get mutable access to the allocator
+    pub fn allocator_mut(&mut self) -> &mut ShardAllocator {
+        &mut self.allocator
+    }
+    
+    /// Get the shard an account is on or goes to, if there are any shards
+    pub fn shard_of(&self, account_id: &AccountId) -> Option<ShardId> {
+        self.locate(account_id).ok()
+    }
+    
+    /// Get the shard an account is on, or the shard it goes to if no shard holds it yet
+    pub fn locate(&self, account_id: &AccountId) -> Result<ShardId, String> {
+        match self.allocator.get_shard(account_id) {
+            Some(shard_id) => Ok(shard_id),
+            None => self.allocator.default_shard(account_id),
+        }
+    }
+    
+    /// Record the accounts the shards' states hold
+    ///
+    /// Called on startup and before the shard set changes, so accounts stay on
+    /// the shard holding them when their default shard moves.
+    pub fn place_from_states(&mut self, shards: &[Shard]) -> Result<(), String> {
+        let shard_ids: Vec<ShardId> = shards.iter().map(|shard| shard.id()).chain(self.retired.iter().copied()).collect();
+        for shard in shards {
+            for account in &shard.account_state().accounts {
+                if !is_system_account(&account.id, &shard_ids) && self.allocator.get_shard(&account.id) != Some(shard.id()) {
+                    self.allocator.assign_account(&account.id, shard.id())?;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Record a shard retired before a restart
+    pub fn mark_retired(&mut self, shard_id: ShardId) {
+        self.retired.insert(shard_id);
+    }
+    
+    /// Hand a retired shard's accounts and pending transactions to another shard
+    pub fn retire_shard(&mut self, retiring: &ShardId, surviving: ShardId, mempool: &mut Mempool) -> Result<(), String> {
+        if !self.allocator.has_shard(&surviving) {
+            return Err(format!("Unknown shard {:?}", surviving));
+        }
+        
+        let accounts: Vec<AccountId> = self.allocator.get_shard_accounts(retiring)
+            .map(|accounts| accounts.iter().cloned().collect())
+            .unwrap_or_default();
+        for account_id in &accounts {
+            self.allocator.reallocate_account(account_id, surviving)?;
+        }
+        self.allocator.remove_shard(retiring);
+        self.retired.insert(*retiring);
+        
+        mempool.move_shard(retiring, surviving);
+        
+        Ok(())
+    }
+    
+    /// Set the accounts scheduled to change shard
+    ///
+    /// Transactions touching them are refused until they are unfrozen, so
+    /// their in-flight transfers can drain before the move.
+    pub fn set_frozen_accounts(&mut self, accounts: HashSet<AccountId>) {
+        self.frozen = accounts;
+    }
+    
+    /// Check if an account is scheduled to change shard
+    pub fn is_frozen(&self, account_id: &AccountId) -> bool {
+        self.frozen.contains(account_id)
+    }
+    
+    /// Route the pooled transactions of moved accounts again
+    ///
+    /// Transactions that now cross shards become cross-shard transfers, and
+    /// ones that can no longer be routed are dropped.
+    pub fn reroute_pooled(
+        &mut self,
+        accounts: &HashSet<AccountId>,
+        mempool: &mut Mempool,
+        communicator: &mut CrossShardCommunicator,
+        database: &Database,
+    ) -> Result<(), String> {
+        let touching: Vec<TransactionId> = mempool.transactions().into_iter()
+            .filter(|transaction| touched_accounts(transaction).iter().any(|account_id| accounts.contains(account_id)))
+            .map(|transaction| transaction.id())
+            .collect();
+        let moved: Vec<Transaction> = touching.iter().filter_map(|transaction_id| mempool.remove(transaction_id)).collect();
+        
+        for transaction in moved {
+            let route = match self.route(&transaction) {
+                Ok(route) => route,
+                Err(e) => {
+                    log::debug!("Dropping pooled transaction {:?} after its accounts moved: {}", transaction.id(), e);
+                    continue;
+                }
+            };
+            self.enqueue(&route, transaction, mempool, communicator, database)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Record an executed transaction and the resulting balances of its accounts for data-driven placement
+    pub fn record_executed(&mut self, transaction: &Transaction, state: &State) {
+        let stats = self.allocator.stats_mut();
+        stats.record_transaction(transaction);
+        
+        for account_id in touched_accounts(transaction) {
+            if let Some(account) = state.accounts.iter().find(|account| account.id == account_id) {
+                stats.record_balance(account_id, account.balance.native);
+            }
+        }
+    }
+    
+    /// Record that a migration moved an account to another shard
+    ///
+    /// The account is left out of later ring rebalancing, so the move is not undone.
+    pub fn relocate(&mut self, account_id: &AccountId, shard_id: ShardId) -> Result<(), String> {
+        if self.allocator.get_shard(account_id) != Some(shard_id) {
+            self.allocator.assign_account(account_id, shard_id)?;
+        }
+        self.allocator.mark_relocated(account_id);
+        Ok(())
+    }
+    
+    /// Decide where a transaction is executed
+    pub fn route(&self, transaction: &Transaction) -> Result<Route, String> {
+        let accounts = touched_accounts(transaction);
+        let source_shard = self.locate(&accounts[0])?;
+        let destination_shard = match accounts.get(1) {
+            Some(target) => self.locate(target)?,
+            None => source_shard,
+        };
+        
+        if destination_shard == source_shard {
+            return Ok(Route::IntraShard(source_shard));
+        }
+        
+        match transaction.transaction_type {
+            TransactionType::Transfer { .. } => Ok(Route::CrossShard {
+                source_shard,
+                destination_shard,
+                transfer: CrossShardTransfer::from_transaction(transaction)?,
+            }),
+            _ => Err(format!(
+                "Contract calls from shard {:?} to shard {:?} are not supported",
+                source_shard, destination_shard
+            )),
+        }
+    }
+    
+    /// Route a transaction and queue it where it will be executed
+    ///
+    /// Transactions are pooled with the shard that executes them, and
+    /// cross-shard transfers are submitted to the communicator as well, unless
+    /// it already tracks them. Transactions already pooled are left alone.
+    /// Transactions touching a shard that
+    /// is being split or merged, or an account scheduled to change shard, are
+    /// refused until the move is over.
+    pub fn submit(
+        &mut self,
+        transaction: Transaction,
+        shards: &[Shard],
+        mempool: &mut Mempool,
+        communicator: &mut CrossShardCommunicator,
+        database: &Database,
+    ) -> Result<Route, String> {
+        if let Some(account_id) = touched_accounts(&transaction).into_iter().find(|account_id| self.is_frozen(account_id)) {
+            return Err(format!("Account {} is being moved to another shard", hex::encode(account_id.0)));
+        }
+        
+        let route = self.route(&transaction)?;
+        
+        let involved = match &route {
+            Route::IntraShard(shard_id) => vec![*shard_id],
+            Route::CrossShard { source_shard, destination_shard, .. } => vec![*source_shard, *destination_shard],
+        };
+        for shard_id in involved {
+            if let Some(shard) = shards.iter().find(|shard| shard.id() == shard_id) {
+                if !shard.accepts_transactions() {
+                    return Err(format!("Shard {:?} is not accepting transactions ({:?})", shard_id, shard.state()));
+                }
+            }
+        }
+        
+        self.enqueue(&route, transaction, mempool, communicator, database)?;
+        Ok(route)
+    }
+    
+    /// Pool a routed transaction with its executing shard and track cross-shard transfers
+    fn enqueue(
+        &mut self,
+        route: &Route,
+        transaction: Transaction,
+        mempool: &mut Mempool,
+        communicator: &mut CrossShardCommunicator,
+        database: &Database,
+    ) -> Result<(), String> {
+        let transaction_id = transaction.id();
+        if mempool.contains(&transaction_id) {
+            return Ok(());
+        }
+        
+        let shard_id = match route {
+            Route::IntraShard(shard_id) => *shard_id,
+            Route::CrossShard { source_shard, destination_shard, transfer } => {
+                if communicator.get_transaction(&transaction_id).is_none() {
+                    communicator.submit_transaction(
+                        transaction_id,
+                        *source_shard,
+                        *destination_shard,
+                        transfer.clone(),
+                        database,
+                    )?;
+                }
+                *source_shard
+            }
+        };
+        mempool.insert(transaction, shard_id);
+        
+        Ok(())
+    }
+    
+    /// Get the cross-shard references for the next block of a shard
+    ///
+    /// These are the transfers waiting to be debited in the shard, paired with
+    /// their destination shard, as recorded in `Block::cross_shard_txs`.
+    pub fn cross_shard_references(
+        &self,
+        shard_id: &ShardId,
+        communicator: &CrossShardCommunicator,
+    ) -> Vec<(u32, TransactionId)> {
+        communicator.get_pending_for_source(shard_id)
+            .into_iter()
+            .filter(|transaction| transaction.status == CrossShardTransactionStatus::PendingSource)
+            .map(|transaction| (transaction.destination_shard.0, transaction.transaction_id))
+            .collect()
+    }
+}
+
+/// Get the accounts a transaction touches, the sender first
+fn touched_accounts(transaction: &Transaction) -> Vec<AccountId> {
+    let mut accounts = vec![AccountId(transaction.sender.to_bytes())];
+    match &transaction.transaction_type {
+        TransactionType::Transfer { recipient, .. } => accounts.push(AccountId(*recipient)),
+        TransactionType::CallContract { contract_id, .. } => accounts.push(AccountId(*contract_id)),
+        // Deployments and staking only touch the sender's shard
+        TransactionType::DeployContract { .. }
+        | TransactionType::Stake { .. }
+        | TransactionType::Unstake { .. } => {}
+    }
+    accounts
+}
@@ -1,9 +1,10 @@
 use crate::types::{Block, BlockId, Transaction, TransactionId, State, StateRoot};
 use serde::{Serialize, Deserialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
 
 /// Unique identifier for a shard
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct ShardId(pub u32);
 
 /// Configuration for a shard
@@ -19,6 +20,36 @@ pub struct ShardConfig {
     pub max_accounts: usize,
     /// Threshold for resharding (load percentage)
     pub resharding_threshold: f64,
+    /// Relative capacity, scaling the share of accounts consistent hashing gives the shard
+    #[serde(default = "default_capacity_weight")]
+    pub capacity_weight: f64,
+    /// Maximum gas per block
+    #[serde(default = "default_max_gas_per_block")]
+    pub max_gas_per_block: u64,
+    /// Number of recent blocks load metrics are measured over
+    #[serde(default = "default_metrics_window")]
+    pub metrics_window: usize,
+}
+
+/// Smallest capacity weight a shard can have
+pub const MIN_CAPACITY_WEIGHT: f64 = 0.01;
+
+/// Largest capacity weight a shard can have
+pub const MAX_CAPACITY_WEIGHT: f64 = 100.0;
+
+/// Default capacity weight of a shard
+fn default_capacity_weight() -> f64 {
+    1.0
+}
+
+/// Default maximum gas per block
+fn default_max_gas_per_block() -> u64 {
+    100_000_000
+}
+
+/// Default number of blocks in the metrics window
+fn default_metrics_window() -> usize {
+    32
 }
 
 impl Default for ShardConfig {
@@ -29,10 +60,35 @@ impl Default for ShardConfig {
             target_block_time_ms: 1000, // 1 second
             max_accounts: 1000000, // 1 million accounts
             resharding_threshold: 0.8, // 80% load
+            capacity_weight: default_capacity_weight(),
+            max_gas_per_block: default_max_gas_per_block(),
+            metrics_window: default_metrics_window(),
         }
     }
 }
 
+impl ShardConfig {
+    /// Check that the configuration is usable
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_CAPACITY_WEIGHT..=MAX_CAPACITY_WEIGHT).contains(&self.capacity_weight) {
+            return Err(format!(
+                "Capacity weight must be between {} and {}, got {}",
+                MIN_CAPACITY_WEIGHT, MAX_CAPACITY_WEIGHT, self.capacity_weight
+            ));
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the capacity weight, clamped to the supported range
+    pub fn effective_capacity_weight(&self) -> f64 {
+        if self.capacity_weight.is_nan() {
+            return default_capacity_weight();
+        }
+        self.capacity_weight.clamp(MIN_CAPACITY_WEIGHT, MAX_CAPACITY_WEIGHT)
+    }
+}
+
 /// State of a shard
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ShardState {
@@ -70,6 +126,8 @@ pub struct Shard {
     shard_state: State,
     /// Load metrics
     load_metrics: ShardLoadMetrics,
+    /// Measurements of the most recent blocks, oldest first
+    block_samples: VecDeque<BlockSample>,
 }
 
 /// Metrics for shard load
@@ -81,12 +139,33 @@ pub struct ShardLoadMetrics {
     pub avg_block_time_ms: f64,
     /// Number of accounts in the shard
     pub account_count: usize,
-    /// Storage usage in bytes
+    /// Size of the shard's account state and blocks when stored, in bytes
     pub storage_usage: u64,
-    /// CPU usage percentage
+    /// Share of block time spent executing blocks, as a percentage
     pub cpu_usage: f64,
-    /// Memory usage in bytes
+    /// Size of the shard's account state in memory, in bytes
     pub memory_usage: u64,
+    /// Average time to execute a block, in milliseconds
+    pub avg_execution_time_ms: f64,
+    /// Average gas used per block
+    pub avg_gas_used: f64,
+    /// Number of transactions waiting in the shard's pool
+    pub mempool_backlog: usize,
+}
+
+/// Measurements of one block
+#[derive(Debug, Clone)]
+struct BlockSample {
+    /// Block height
+    height: u64,
+    /// Block timestamp, in seconds
+    timestamp: u64,
+    /// Number of transactions
+    transactions: usize,
+    /// Gas used executing the block
+    gas_used: u64,
+    /// Time spent executing the block
+    execution_time: Duration,
 }
 
 impl Shard {
@@ -102,6 +181,7 @@ impl Shard {
             latest_block_height: 0,
             shard_state: State::new(),
             load_metrics: ShardLoadMetrics::default(),
+            block_samples: VecDeque::new(),
         }
     }
     
@@ -130,6 +210,11 @@ impl Shard {
         self.state == ShardState::Active
     }
     
+    /// Check if the shard accepts new transactions; it does not while accounts are being moved
+    pub fn accepts_transactions(&self) -> bool {
+        self.is_active()
+    }
+    
     /// Add an account to the shard
     pub fn add_account(&mut self, account_id: [u8; 32]) -> Result<(), String> {
         if self.accounts.len() >= self.config.max_accounts {
@@ -156,6 +241,11 @@ impl Shard {
         self.accounts.contains(account_id)
     }
     
+    /// Get the IDs of the accounts in the shard
+    pub fn account_ids(&self) -> Vec<[u8; 32]> {
+        self.accounts.iter().copied().collect()
+    }
+    
     /// Get the number of accounts in the shard
     pub fn account_count(&self) -> usize {
         self.accounts.len()
@@ -179,9 +269,6 @@ impl Shard {
         // Add block to the shard
         self.blocks.insert(block_id, block);
         
-        // Update load metrics
-        self.update_load_metrics();
-        
         Ok(())
     }
     
@@ -210,62 +297,114 @@ impl Shard {
         &self.shard_state.root
     }
     
+    /// Get the accounts state of the shard
+    pub fn account_state(&self) -> &State {
+        &self.shard_state
+    }
+    
+    /// Get mutable access to the accounts state of the shard
+    pub fn account_state_mut(&mut self) -> &mut State {
+        &mut self.shard_state
+    }
+    
     /// Get the load metrics
     pub fn load_metrics(&self) -> &ShardLoadMetrics {
         &self.load_metrics
     }
     
-    /// Check if the shard needs resharding
-    pub fn needs_resharding(&self) -> bool {
-        // Check if account count is above threshold
-        let account_ratio = self.accounts.len() as f64 / self.config.max_accounts as f64;
-        if account_ratio >= self.config.resharding_threshold {
-            return true;
+    /// Sample an executed block for load metrics, with the gas it used and how long it took
+    ///
+    /// Only executed blocks are sampled, so blocks the shard merely stores do
+    /// not dilute the averages.
+    pub fn record_block_execution(&mut self, block: &Block, gas_used: u64, execution_time: Duration) {
+        let sample = BlockSample {
+            height: block.header.height,
+            timestamp: block.header.timestamp,
+            transactions: block.transactions.len(),
+            gas_used,
+            execution_time,
+        };
+        self.block_samples.retain(|s| s.height != sample.height);
+        let position = self.block_samples.iter().position(|s| s.height > sample.height).unwrap_or(self.block_samples.len());
+        self.block_samples.insert(position, sample);
+        while self.block_samples.len() > self.config.metrics_window.max(2) {
+            self.block_samples.pop_front();
         }
         
-        // Check if TPS is above threshold
-        let tps_capacity = 1000.0 / self.config.target_block_time_ms as f64 * self.config.max_transactions_per_block as f64;
-        let tps_ratio = self.load_metrics.tps / tps_capacity;
-        if tps_ratio >= self.config.resharding_threshold {
-            return true;
-        }
+        self.update_load_metrics();
+    }
+    
+    /// Measure the memory and storage the shard's account state and blocks use
+    ///
+    /// Walks the whole state, so it is meant for periodic evaluation rather than every block.
+    pub fn measure_state_usage(&mut self) {
+        let state_size: u64 = self.shard_state.accounts.iter()
+            .map(|account| bincode::serialized_size(account).unwrap_or(0))
+            .sum();
+        let block_size: u64 = self.blocks.values()
+            .map(|block| bincode::serialized_size(block).unwrap_or(0))
+            .sum();
         
-        // Check if CPU usage is above threshold
-        if self.load_metrics.cpu_usage >= self.config.resharding_threshold * 100.0 {
-            return true;
-        }
+        self.load_metrics.memory_usage = state_size;
+        self.load_metrics.storage_usage = state_size + block_size;
+    }
+    
+    /// Record the number of transactions waiting in the shard's pool
+    pub fn record_mempool_backlog(&mut self, backlog: usize) {
+        self.load_metrics.mempool_backlog = backlog;
+    }
+    
+    /// Get the shard's load as a fraction of its capacity, by its most constrained resource
+    pub fn load_score(&self) -> f64 {
+        let metrics = &self.load_metrics;
+        let tps_capacity = 1000.0 / self.config.target_block_time_ms.max(1) as f64 * self.config.max_transactions_per_block as f64;
+        
+        let ratios = [
+            self.accounts.len() as f64 / self.config.max_accounts.max(1) as f64,
+            metrics.tps / tps_capacity.max(f64::EPSILON),
+            metrics.cpu_usage / 100.0,
+            metrics.avg_gas_used / self.config.max_gas_per_block.max(1) as f64,
+            // A backlog of a full block or more counts as saturation
+            metrics.mempool_backlog as f64 / self.config.max_transactions_per_block.max(1) as f64,
+        ];
         
-        false
+        ratios.iter().cloned().fold(0.0, f64::max)
+    }
+    
+    /// Check if the shard needs resharding
+    pub fn needs_resharding(&self) -> bool {
+        self.load_score() >= self.config.resharding_threshold
     }
     
-    /// Update the load metrics
+    /// Update the load metrics from the recent block samples
     fn update_load_metrics(&mut self) {
-        // In a real implementation, we would calculate these metrics based on actual performance
-        // For now, just use simple calculations based on the number of accounts and blocks
+        let samples = &self.block_samples;
         
-        // Calculate TPS based on recent blocks
-        if let Some(latest_block) = self.latest_block() {
-            // Assume 1 block per second for simplicity
-            self.load_metrics.tps = latest_block.transactions.len() as f64;
+        // Rates need the time between the first and last block of the window
+        if let (Some(first), Some(last)) = (samples.front(), samples.back()) {
+            let span_ms = last.timestamp.saturating_sub(first.timestamp) * 1000;
+            let intervals = samples.len() - 1;
+            if intervals > 0 && span_ms > 0 {
+                // The first block's transactions were executed before the window started
+                let transactions: usize = samples.iter().skip(1).map(|sample| sample.transactions).sum();
+                self.load_metrics.tps = transactions as f64 * 1000.0 / span_ms as f64;
+                self.load_metrics.avg_block_time_ms = span_ms as f64 / intervals as f64;
+            }
         }
         
-        // Update account count
-        self.load_metrics.account_count = self.accounts.len();
-        
-        // Estimate storage usage (1 KB per account, 10 KB per block)
-        self.load_metrics.storage_usage = (self.accounts.len() * 1024 + self.blocks.len() * 10 * 1024) as u64;
+        let count = samples.len().max(1) as f64;
+        self.load_metrics.avg_execution_time_ms = samples.iter()
+            .map(|sample| sample.execution_time.as_secs_f64() * 1000.0)
+            .sum::<f64>() / count;
+        self.load_metrics.avg_gas_used = samples.iter().map(|sample| sample.gas_used as f64).sum::<f64>() / count;
         
-        // Estimate CPU usage based on account count and TPS
-        let max_accounts = self.config.max_accounts;
-        let max_tps = 1000.0 / self.config.target_block_time_ms as f64 * self.config.max_transactions_per_block as f64;
+        let block_time_ms = if self.load_metrics.avg_block_time_ms > 0.0 {
+            self.load_metrics.avg_block_time_ms
+        } else {
+            self.config.target_block_time_ms as f64
+        };
+        self.load_metrics.cpu_usage = self.load_metrics.avg_execution_time_ms / block_time_ms.max(f64::EPSILON) * 100.0;
         
-        let account_ratio = self.accounts.len() as f64 / max_accounts as f64;
-        let tps_ratio = self.load_metrics.tps / max_tps;
-        
-        // CPU usage is a combination of account ratio and TPS ratio
-        self.load_metrics.cpu_usage = (account_ratio * 0.3 + tps_ratio * 0.7) * 100.0;
-        
-        // Estimate memory usage (10 KB per account, 1 KB per block)
-        self.load_metrics.memory_usage = (self.accounts.len() * 10 * 1024 + self.blocks.len() * 1024) as u64;
+        self.load_metrics.account_count = self.accounts.len();
     }
 }
@@ -159,6 +159,39 @@ impl StorageKey for StateKey {
     }
 }
 
+/// Sharding key prefix
+#[derive(Debug, Clone, Copy)]
+pub struct ShardingKeyPrefix;
+
+impl KeyPrefix for ShardingKeyPrefix {
+    fn column_family() -> String {
+        "sharding".to_string()
+    }
+    
+    fn encode(&self) -> Vec<u8> {
+        vec![0x06]
+    }
+}
+
+/// Sharding key, for in-flight cross-shard and resharding operations
+#[derive(Debug, Clone)]
+pub struct ShardingKey {
+    /// Sharding key
+    pub key: Vec<u8>,
+}
+
+impl StorageKey for ShardingKey {
+    fn column_family() -> String {
+        "sharding".to_string()
+    }
+    
+    fn encode(&self) -> Vec<u8> {
+        let mut key = vec![0x06]; // Prefix for sharding
+        key.extend_from_slice(&self.key);
+        key
+    }
+}
+
 /// Metadata key prefix
 #[derive(Debug, Clone, Copy)]
 pub struct MetadataKeyPrefix;
@@ -8,6 +8,22 @@ mod batch;
 mod iterator;
 
 pub use database::{Database, DatabaseConfig, StorageError};
-pub use keys::{KeyPrefix, KeyCodec, StorageKey};
+pub use keys::{KeyPrefix, KeyCodec, StorageKey, AccountKey, AccountKeyPrefix, BlockKey, BlockKeyPrefix, TransactionKey, StateKey, StateKeyPrefix, MetadataKey, ShardingKey, ShardingKeyPrefix};
 pub use batch::{Batch, BatchOperation};
 pub use iterator::{StorageIterator, IteratorMode};
+
+/// Open a database with every column family in a temporary directory, for tests
+#[cfg(test)]
+pub(crate) fn temporary_database() -> (tempfile::TempDir, Database) {
+    let directory = tempfile::tempdir().expect("Failed to create a temporary directory");
+    let mut database = Database::new(DatabaseConfig {
+        path: directory.path().to_path_buf(),
+        column_families: ["blocks", "transactions", "accounts", "state", "metadata", "sharding"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+        ..DatabaseConfig::default()
+    });
+    database.open().expect("Failed to open the test database");
+    (directory, database)
+}
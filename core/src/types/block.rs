@@ -1,11 +1,14 @@
 use crate::types::{TransactionId, StateRoot};
 use ed25519_dalek::VerifyingKey;
-use crate::utils::crypto::Signature;
+use crate::utils::crypto::{verify_signature, KeyPair, Signature};
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
 use serde::de::Error as DeError;
 use sha3::{Sha3_256, Digest};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Domain separator for block header signatures
+const HEADER_SIGNING_DOMAIN: &[u8] = b"optimachain-block-header";
+
 /// Unique identifier for a block
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct BlockId(pub [u8; 32]);
@@ -82,15 +85,18 @@ impl<'de> Deserialize<'de> for BlockHeader {
                     transactions_root: [u8; 32],
                     state_root: StateRoot,
                     validator: [u8; 32],
-                    signature: Vec<u8>,
+                    signature: String,
                 }
                 
                 let helper = Helper::deserialize(deserializer)?;
                 
-                if helper.signature.len() != 64 {
+                // The signature is serialized as a hex string
+                let signature = hex::decode(&helper.signature)
+                    .map_err(|e| DeError::custom(format!("Invalid signature encoding: {}", e)))?;
+                if signature.len() != 64 {
                     return Err(DeError::custom(format!(
                         "Expected signature of length 64, got {}",
-                        helper.signature.len()
+                        signature.len()
                     )));
                 }
                 
@@ -102,7 +108,7 @@ impl<'de> Deserialize<'de> for BlockHeader {
                     transactions_root: helper.transactions_root,
                     state_root: helper.state_root,
                     validator: helper.validator,
-                    signature: helper.signature,
+                    signature,
                 })
             }
         }
@@ -129,6 +135,50 @@ impl<'de> Deserialize<'de> for BlockHeader {
     }
 }
 
+impl BlockHeader {
+    /// Get the ID of the block this header belongs to
+    pub fn id(&self) -> BlockId {
+        let serialized = bincode::serialize(self).unwrap();
+        let mut hasher = Sha3_256::new();
+        hasher.update(&serialized);
+        let result = hasher.finalize();
+        
+        let mut id = [0u8; 32];
+        id.copy_from_slice(&result);
+        BlockId(id)
+    }
+    
+    /// Bytes covered by the producer's signature, i.e. every field but the signature
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = HEADER_SIGNING_DOMAIN.to_vec();
+        bytes.extend(bincode::serialize(&(
+            self.version,
+            self.height,
+            self.timestamp,
+            &self.prev_block,
+            self.transactions_root,
+            &self.state_root,
+            self.validator.to_bytes(),
+        )).unwrap());
+        bytes
+    }
+    
+    /// Sign the header as its producer
+    pub fn sign(&mut self, keypair: &KeyPair) -> Result<(), String> {
+        if keypair.public_key() != self.validator.to_bytes() {
+            return Err("Key pair does not belong to the block producer".to_string());
+        }
+        
+        self.signature = keypair.sign(&self.signing_bytes());
+        Ok(())
+    }
+    
+    /// Check the producer's signature
+    pub fn has_valid_signature(&self) -> bool {
+        verify_signature(&self.validator.to_bytes(), &self.signing_bytes(), &self.signature)
+    }
+}
+
 /// A block in the blockchain
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
@@ -180,14 +230,12 @@ impl Block {
     
     /// Get the ID of this block
     pub fn id(&self) -> BlockId {
-        let serialized = bincode::serialize(&self.header).unwrap();
-        let mut hasher = Sha3_256::new();
-        hasher.update(&serialized);
-        let result = hasher.finalize();
-        
-        let mut id = [0u8; 32];
-        id.copy_from_slice(&result);
-        BlockId(id)
+        self.header.id()
+    }
+    
+    /// Check that the transactions root in the header matches the transaction list
+    pub fn has_valid_transactions_root(&self) -> bool {
+        compute_merkle_root(&self.transactions) == self.header.transactions_root
     }
 }
 
@@ -208,3 +256,20 @@ fn compute_merkle_root(transactions: &[TransactionId]) -> [u8; 32] {
     root.copy_from_slice(&result);
     root
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn round_trips_signed_blocks() {
+        let keypair = KeyPair::generate();
+        let validator = VerifyingKey::from_bytes(&keypair.public_key()).unwrap();
+        let mut block = Block::new(1, BlockId([0; 32]), Vec::new(), StateRoot([0; 32]), validator, 0);
+        block.header.sign(&keypair).unwrap();
+        
+        let decoded: Block = bincode::deserialize(&bincode::serialize(&block).unwrap()).unwrap();
+        assert_eq!(decoded.id(), block.id());
+        assert!(decoded.header.has_valid_signature());
+    }
+}
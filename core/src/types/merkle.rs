@@ -0,0 +1,188 @@
+use serde::{Serialize, Deserialize};
+use sha3::{Sha3_256, Digest};
+
+/// Domain separator for leaf hashes
+const LEAF_PREFIX: u8 = 0x00;
+
+/// Domain separator for interior node hashes
+const NODE_PREFIX: u8 = 0x01;
+
+/// Hash arbitrary data into a Merkle leaf
+pub fn hash_leaf(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(data);
+    finalize(hasher)
+}
+
+/// Hash two child nodes into their parent
+fn hash_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    finalize(hasher)
+}
+
+/// Finish a hasher into a 32-byte array
+fn finalize(hasher: Sha3_256) -> [u8; 32] {
+    let result = hasher.finalize();
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&result);
+    hash
+}
+
+/// Compute the Merkle root of a list of leaf hashes
+///
+/// Nodes are paired left to right; an unpaired last node is carried up
+/// unchanged. The root of an empty list is all zeroes.
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return [0; 32];
+    }
+    
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    
+    level[0]
+}
+
+/// Compute the parent level of a tree level
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level.chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_node(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// A Merkle tree with all levels kept in memory
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// Tree levels, from the leaves up to the root
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Build a tree over a list of leaf hashes
+    pub fn new(leaves: Vec<[u8; 32]>) -> Self {
+        let mut levels = vec![leaves];
+        
+        while levels.last().map_or(false, |level| level.len() > 1) {
+            let next = next_level(levels.last().unwrap());
+            levels.push(next);
+        }
+        
+        MerkleTree { levels }
+    }
+    
+    /// Get the root of the tree
+    pub fn root(&self) -> [u8; 32] {
+        self.levels.last()
+            .and_then(|level| level.first().copied())
+            .unwrap_or([0; 32])
+    }
+    
+    /// Get the number of leaves
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+    
+    /// Build a proof for a leaf
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        self.subtree_proof(0, index)
+    }
+    
+    /// Build a proof for the node at `index` on `level`, i.e. for the subtree
+    /// covering leaves `index << level .. (index + 1) << level`
+    pub fn subtree_proof(&self, level: usize, index: usize) -> Option<MerkleProof> {
+        let width = self.level_width(level);
+        if index >= width {
+            return None;
+        }
+        
+        let mut siblings = Vec::new();
+        let mut position = index;
+        
+        for nodes in self.levels.iter().skip(level) {
+            if nodes.len() <= 1 {
+                break;
+            }
+            
+            if position % 2 == 1 {
+                siblings.push(nodes[position - 1]);
+            } else if position + 1 < nodes.len() {
+                siblings.push(nodes[position + 1]);
+            }
+            
+            position /= 2;
+        }
+        
+        Some(MerkleProof {
+            index: index as u64,
+            width: width as u64,
+            siblings,
+        })
+    }
+    
+    /// Get the number of nodes on a level, including levels above the root
+    fn level_width(&self, level: usize) -> usize {
+        match self.levels.get(level) {
+            Some(nodes) => nodes.len(),
+            None if self.leaf_count() > 0 => 1,
+            None => 0,
+        }
+    }
+}
+
+/// Proof that a node is part of a Merkle tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    /// Position of the proven node on its level
+    pub index: u64,
+    /// Number of nodes on the level of the proven node
+    pub width: u64,
+    /// Sibling hashes from the proven node up to the root
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    /// Compute the root implied by this proof for a node
+    pub fn compute_root(&self, node: [u8; 32]) -> Option<[u8; 32]> {
+        if self.index >= self.width {
+            return None;
+        }
+        
+        let mut hash = node;
+        let mut position = self.index;
+        let mut width = self.width;
+        let mut siblings = self.siblings.iter();
+        
+        while width > 1 {
+            if position % 2 == 1 {
+                hash = hash_node(siblings.next()?, &hash);
+            } else if position + 1 < width {
+                hash = hash_node(&hash, siblings.next()?);
+            }
+            
+            position /= 2;
+            width = (width + 1) / 2;
+        }
+        
+        // Every sibling must have been consumed
+        if siblings.next().is_some() {
+            return None;
+        }
+        
+        Some(hash)
+    }
+    
+    /// Verify that a node is part of the tree with the given root
+    pub fn verify(&self, node: [u8; 32], root: &[u8; 32]) -> bool {
+        self.compute_root(node).as_ref() == Some(root)
+    }
+}
@@ -4,8 +4,12 @@ mod block;
 mod transaction;
 mod account;
 mod state;
+mod merkle;
+mod proof;
 
 pub use block::{Block, BlockHeader, BlockId};
 pub use transaction::{Transaction, TransactionType, TransactionId, TransactionStatus};
 pub use account::{Account, AccountId, Balance};
-pub use state::{State, StateUpdate, StateRoot};
+pub use state::{State, StateUpdate, StateRoot, account_leaf};
+pub use merkle::{MerkleTree, MerkleProof, merkle_root, hash_leaf};
+pub use proof::{AccountProof, ProvenAccount, StorageProof};
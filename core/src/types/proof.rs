@@ -0,0 +1,108 @@
+use crate::types::{account_leaf, Account, AccountId, MerkleProof, StateRoot};
+use serde::{Serialize, Deserialize};
+
+/// An account and its inclusion proof
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProvenAccount {
+    /// The account
+    pub account: Account,
+    /// Proof of the account's leaf in the state tree
+    pub proof: MerkleProof,
+}
+
+impl ProvenAccount {
+    /// Check that the account is in the state with the given root
+    fn verify(&self, state_root: &StateRoot) -> bool {
+        self.proof.verify(account_leaf(&self.account), &state_root.0)
+    }
+}
+
+/// Proof that an account is or is not part of a state
+///
+/// Accounts are ordered by ID in the state tree, so an account's absence is
+/// proven by its two neighbours sitting in adjacent leaves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AccountProof {
+    /// The account exists
+    Present(ProvenAccount),
+    /// The account does not exist
+    Absent {
+        /// Account with the next lower ID, if any
+        left: Option<ProvenAccount>,
+        /// Account with the next higher ID, if any
+        right: Option<ProvenAccount>,
+    },
+}
+
+impl AccountProof {
+    /// Verify the proof for an account against a state root
+    ///
+    /// Returns the account if it exists and `None` if it provably does not.
+    pub fn verify(&self, account_id: &AccountId, state_root: &StateRoot) -> Result<Option<Account>, String> {
+        match self {
+            AccountProof::Present(proven) => {
+                if proven.account.id != *account_id {
+                    return Err("Proof is for a different account".to_string());
+                }
+                if !proven.verify(state_root) {
+                    return Err("Account proof does not match the state root".to_string());
+                }
+                Ok(Some(proven.account.clone()))
+            }
+            AccountProof::Absent { left, right } => {
+                for neighbour in left.iter().chain(right.iter()) {
+                    if !neighbour.verify(state_root) {
+                        return Err("Neighbour proof does not match the state root".to_string());
+                    }
+                }
+                
+                let adjacent = match (left, right) {
+                    (Some(left), Some(right)) => {
+                        left.account.id.0 < account_id.0
+                            && account_id.0 < right.account.id.0
+                            && left.proof.width == right.proof.width
+                            && left.proof.index + 1 == right.proof.index
+                    }
+                    // Below the lowest account
+                    (None, Some(right)) => account_id.0 < right.account.id.0 && right.proof.index == 0,
+                    // Above the highest account
+                    (Some(left), None) => {
+                        left.account.id.0 < account_id.0 && left.proof.index + 1 == left.proof.width
+                    }
+                    // Empty state
+                    (None, None) => state_root.0 == [0; 32],
+                };
+                
+                if !adjacent {
+                    return Err("Neighbours do not prove the account's absence".to_string());
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Proof of a contract storage value
+///
+/// The state tree commits to whole accounts, so a storage value is proven
+/// by proving the account that holds it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageProof {
+    /// Storage key
+    pub key: Vec<u8>,
+    /// Proof of the account holding the storage
+    pub account: AccountProof,
+}
+
+impl StorageProof {
+    /// Verify the proof against a state root, returning the value if it is set
+    pub fn verify(&self, account_id: &AccountId, state_root: &StateRoot) -> Result<Option<Vec<u8>>, String> {
+        let account = self.account.verify(account_id, state_root)?;
+        
+        Ok(account.and_then(|account| {
+            account.storage.into_iter()
+                .find(|(key, _)| *key == self.key)
+                .map(|(_, value)| value)
+        }))
+    }
+}
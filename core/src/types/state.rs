@@ -1,6 +1,5 @@
-use crate::types::Account;
+use crate::types::{Account, AccountId, AccountProof, MerkleProof, MerkleTree, ProvenAccount, StorageProof, hash_leaf};
 use serde::{Serialize, Deserialize};
-use sha3::Digest;
 
 /// Root hash of the state
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -42,6 +41,70 @@ impl State {
         }
     }
     
+    /// Create a state from a set of accounts
+    pub fn from_accounts(accounts: Vec<Account>) -> Self {
+        let mut state = State {
+            accounts,
+            root: StateRoot([0; 32]),
+        };
+        
+        state.recalculate_root();
+        state
+    }
+    
+    /// Get the accounts ordered by account ID, the order used for the state root
+    pub fn sorted_accounts(&self) -> Vec<&Account> {
+        let mut accounts: Vec<_> = self.accounts.iter().collect();
+        accounts.sort_by(|a, b| a.id.0.cmp(&b.id.0));
+        accounts
+    }
+    
+    /// Build the Merkle tree over the accounts
+    pub fn account_tree(&self) -> MerkleTree {
+        MerkleTree::new(self.sorted_accounts().into_iter().map(account_leaf).collect())
+    }
+    
+    /// Get an account together with a proof of its inclusion in the state root
+    pub fn account_proof(&self, id: &AccountId) -> Option<(Account, MerkleProof)> {
+        let accounts = self.sorted_accounts();
+        let index = accounts.iter().position(|account| account.id == *id)?;
+        let proof = self.account_tree().proof(index)?;
+        
+        Some((accounts[index].clone(), proof))
+    }
+    
+    /// Prove that an account is or is not part of the state
+    pub fn prove_account(&self, id: &AccountId) -> AccountProof {
+        let accounts = self.sorted_accounts();
+        let tree = self.account_tree();
+        
+        let proven = |index: usize| -> Option<ProvenAccount> {
+            Some(ProvenAccount {
+                account: accounts.get(index).map(|account| (*account).clone())?,
+                proof: tree.proof(index)?,
+            })
+        };
+        
+        match accounts.binary_search_by(|account| account.id.0.cmp(&id.0)) {
+            Ok(index) => match proven(index) {
+                Some(proven) => AccountProof::Present(proven),
+                None => AccountProof::Absent { left: None, right: None },
+            },
+            Err(index) => AccountProof::Absent {
+                left: index.checked_sub(1).and_then(proven),
+                right: proven(index),
+            },
+        }
+    }
+    
+    /// Prove a contract storage value, or its absence
+    pub fn prove_storage(&self, id: &AccountId, key: Vec<u8>) -> StorageProof {
+        StorageProof {
+            key,
+            account: self.prove_account(id),
+        }
+    }
+    
     /// Apply a state update
     pub fn apply_update(&mut self, update: StateUpdate) {
         match update {
@@ -83,15 +146,13 @@ impl State {
     
     /// Recalculate the state root
     fn recalculate_root(&mut self) {
-        // Simple implementation for now
-        // In a real implementation, this would use a Merkle Patricia Trie
-        let serialized = bincode::serialize(&self.accounts).unwrap();
-        let mut hasher = sha3::Sha3_256::new();
-        hasher.update(&serialized);
-        let result = hasher.finalize();
-        
-        let mut root = [0u8; 32];
-        root.copy_from_slice(&result);
-        self.root = StateRoot(root);
+        // Binary Merkle tree over the accounts ordered by ID, so that
+        // individual accounts and ranges of accounts can be proven
+        self.root = StateRoot(self.account_tree().root());
     }
 }
+
+/// Compute the Merkle leaf of an account, covering its balance, nonce, code and storage
+pub fn account_leaf(account: &Account) -> [u8; 32] {
+    hash_leaf(&bincode::serialize(account).unwrap())
+}
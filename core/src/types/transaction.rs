@@ -1,8 +1,11 @@
 use ed25519_dalek::VerifyingKey;
-use crate::utils::crypto::Signature;
+use crate::utils::crypto::{verify_signature, KeyPair, Signature};
 use serde::{Serialize, Deserialize, Deserializer};
 use sha3::{Sha3_256, Digest};
 
+/// Domain separator for transaction signatures
+const TRANSACTION_SIGNING_DOMAIN: &[u8] = b"optimachain-transaction";
+
 /// Unique identifier for a transaction
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TransactionId(pub [u8; 32]);
@@ -189,4 +192,60 @@ impl Transaction {
         id.copy_from_slice(&result);
         TransactionId(id)
     }
+    
+    /// Get the bytes covered by the sender's signature
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = TRANSACTION_SIGNING_DOMAIN.to_vec();
+        bytes.extend(bincode::serialize(&(
+            &self.transaction_type,
+            self.sender.to_bytes(),
+            self.nonce,
+            self.gas_limit,
+            self.gas_price,
+        )).unwrap());
+        bytes
+    }
+    
+    /// Sign the transaction as its sender
+    pub fn sign(&mut self, keypair: &KeyPair) -> Result<(), String> {
+        if keypair.public_key() != self.sender.to_bytes() {
+            return Err("Key pair does not belong to the sender".to_string());
+        }
+        
+        self.signature = keypair.sign(&self.signing_bytes());
+        Ok(())
+    }
+    
+    /// Check the sender's signature
+    pub fn has_valid_signature(&self) -> bool {
+        verify_signature(&self.sender.to_bytes(), &self.signing_bytes(), &self.signature)
+    }
+    
+    /// Check that the transaction is signed and well formed, without looking at state
+    pub fn validate(&self) -> Result<(), String> {
+        if self.gas_limit == 0 {
+            return Err("Gas limit is zero".to_string());
+        }
+        
+        match &self.transaction_type {
+            TransactionType::Transfer { amount, .. }
+            | TransactionType::Stake { amount }
+            | TransactionType::Unstake { amount } if *amount == 0 => {
+                return Err("Amount is zero".to_string());
+            }
+            TransactionType::DeployContract { code, .. } if code.is_empty() => {
+                return Err("Contract code is empty".to_string());
+            }
+            TransactionType::CallContract { method, .. } if method.is_empty() => {
+                return Err("Contract method is empty".to_string());
+            }
+            _ => {}
+        }
+        
+        if !self.has_valid_signature() {
+            return Err("Invalid sender signature".to_string());
+        }
+        
+        Ok(())
+    }
 }
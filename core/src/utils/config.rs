@@ -36,6 +36,36 @@ pub struct NetworkConfig {
     pub connection_timeout: u64,
     /// Enable NAT traversal
     pub enable_nat_traversal: bool,
+    /// Chain identifier; peers on a different chain are rejected
+    #[serde(default = "default_chain_id")]
+    pub chain_id: String,
+    /// Kind of network the chain is
+    #[serde(default)]
+    pub network_type: NetworkType,
+    /// Discover peers on the local network with mDNS; never enabled on mainnet
+    #[serde(default)]
+    pub enable_mdns: bool,
+    /// Relay our transactions privately before broadcasting them (Dandelion++)
+    #[serde(default)]
+    pub enable_dandelion: bool,
+}
+
+/// Default chain identifier for configurations that predate it
+fn default_chain_id() -> String {
+    "optimachain-devnet".to_string()
+}
+
+/// Kind of network a node runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkType {
+    /// Production network
+    Mainnet,
+    /// Public test network
+    Testnet,
+    /// Local development network, the default to match the default chain ID
+    #[default]
+    Devnet,
 }
 
 /// Consensus configuration
@@ -125,6 +155,10 @@ impl Default for Config {
                 max_peers: 50,
                 connection_timeout: 30,
                 enable_nat_traversal: true,
+                chain_id: default_chain_id(),
+                network_type: NetworkType::Devnet,
+                enable_mdns: false,
+                enable_dandelion: false,
             },
             consensus: ConsensusConfig {
                 algorithm: "apos".to_string(),
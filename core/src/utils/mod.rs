@@ -10,4 +10,4 @@ mod config;
 pub use logging::{init_logger, Logger, LogLevel};
 pub use errors::{Result, Error, ErrorKind};
 pub use crypto::{KeyPair, Signature, hash, verify_signature, generate_keypair, sign_message};
-pub use config::{Config, ConfigBuilder, load_config, save_config};
+pub use config::{Config, ConfigBuilder, NetworkType, load_config, save_config};